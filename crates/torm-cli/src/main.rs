@@ -0,0 +1,79 @@
+//! TORM command-line tools
+//!
+//! ```text
+//! torm snapshot-to-sql --collections user,post [--url redis://...] out.db
+//! ```
+
+mod snapshot;
+
+use torm::TormDb;
+
+const USAGE: &str = "\
+Usage: torm <command> [options]
+
+Commands:
+  snapshot-to-sql --collections <a,b,...> [--url <redis-url>] <out.db>
+      Copy collections into tables of a SQLite database (readable by DuckDB)
+
+The store URL defaults to $REDIS_URL, then redis://localhost:6379.";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    match args.next().as_deref() {
+        Some("snapshot-to-sql") => snapshot_to_sql(args.collect()).await,
+        Some("-h" | "--help") | None => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => anyhow::bail!("unknown command '{}'\n\n{}", other, USAGE),
+    }
+}
+
+async fn snapshot_to_sql(args: Vec<String>) -> anyhow::Result<()> {
+    let mut collections = Vec::new();
+    let mut url =
+        std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
+    let mut out = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--collections" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--collections needs a value"))?;
+                collections.extend(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|c| !c.is_empty())
+                        .map(str::to_string),
+                );
+            }
+            "--url" => {
+                url = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--url needs a value"))?;
+            }
+            flag if flag.starts_with("--") => anyhow::bail!("unknown option '{}'", flag),
+            path if out.is_none() => out = Some(path.to_string()),
+            extra => anyhow::bail!("unexpected argument '{}'", extra),
+        }
+    }
+
+    if collections.is_empty() {
+        anyhow::bail!("--collections is required\n\n{}", USAGE);
+    }
+    let out = out.ok_or_else(|| anyhow::anyhow!("missing output file\n\n{}", USAGE))?;
+
+    let db = TormDb::connect(&url).await?;
+    let summary = snapshot::export(&db, &collections, &out).await?;
+    for (collection, rows) in summary {
+        println!("{}: {} rows", collection, rows);
+    }
+    println!("Snapshot written to {}", out);
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Analytical snapshots into SQLite
+//!
+//! Each collection becomes a table named after it. Columns are inferred
+//! from the top-level fields of the stored documents: integers and booleans
+//! map to `INTEGER`, other numbers to `REAL`, and everything else
+//! (strings, nested objects and arrays as JSON) to `TEXT`. The live store is
+//! only read.
+
+use rusqlite::types::Value as SqlValue;
+use rusqlite::Connection;
+use serde_json::Value;
+use torm::TormDb;
+
+/// SQLite column affinity inferred for a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Integer,
+    Real,
+    Text,
+}
+
+impl ColumnType {
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(ColumnType::Integer),
+            Value::Number(n) if n.is_i64() || n.is_u64() => Some(ColumnType::Integer),
+            Value::Number(_) => Some(ColumnType::Real),
+            _ => Some(ColumnType::Text),
+        }
+    }
+
+    /// Widen two observed types into one that holds both
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Real) | (ColumnType::Real, ColumnType::Integer) => {
+                ColumnType::Real
+            }
+            _ => ColumnType::Text,
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+        }
+    }
+}
+
+/// Copy `collections` into the SQLite database at `path`
+///
+/// Existing tables with the same names are replaced. Returns the number of
+/// rows written per collection.
+pub async fn export(
+    db: &TormDb,
+    collections: &[String],
+    path: &str,
+) -> anyhow::Result<Vec<(String, usize)>> {
+    let mut conn = Connection::open(path)?;
+    let mut summary = Vec::with_capacity(collections.len());
+
+    for collection in collections {
+        let docs = db.collection(collection.as_str()).query().exec(db).await?;
+        let tx = conn.transaction()?;
+        write_table(&tx, collection, &docs)?;
+        tx.commit()?;
+        summary.push((collection.clone(), docs.len()));
+    }
+
+    Ok(summary)
+}
+
+/// Infer columns from documents, `id` first, then in first-seen order
+fn infer_columns(docs: &[Value]) -> Vec<(String, ColumnType)> {
+    let mut columns: Vec<(String, Option<ColumnType>)> = vec![("id".to_string(), None)];
+
+    for doc in docs {
+        let Some(fields) = doc.as_object() else {
+            continue;
+        };
+        for (name, value) in fields {
+            let observed = ColumnType::of(value);
+            match columns.iter_mut().find(|(n, _)| n == name) {
+                Some((_, ty)) => {
+                    *ty = match (*ty, observed) {
+                        (Some(a), Some(b)) => Some(a.merge(b)),
+                        (a, b) => a.or(b),
+                    }
+                }
+                None => columns.push((name.clone(), observed)),
+            }
+        }
+    }
+
+    columns
+        .into_iter()
+        .map(|(name, ty)| (name, ty.unwrap_or(ColumnType::Text)))
+        .collect()
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_value(value: Option<&Value>) -> SqlValue {
+    match value {
+        None | Some(Value::Null) => SqlValue::Null,
+        Some(Value::Bool(b)) => SqlValue::Integer(i64::from(*b)),
+        Some(Value::Number(n)) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Some(Value::String(s)) => SqlValue::Text(s.clone()),
+        Some(other) => SqlValue::Text(other.to_string()),
+    }
+}
+
+/// Replace table `name` with the given documents
+fn write_table(conn: &Connection, name: &str, docs: &[Value]) -> anyhow::Result<()> {
+    let columns = infer_columns(docs);
+    let table = quote_ident(name);
+
+    let definitions: Vec<String> = columns
+        .iter()
+        .map(|(column, ty)| {
+            let key = if column == "id" { " PRIMARY KEY" } else { "" };
+            format!("{} {}{}", quote_ident(column), ty.sql(), key)
+        })
+        .collect();
+    conn.execute_batch(&format!(
+        "DROP TABLE IF EXISTS {table}; CREATE TABLE {table} ({});",
+        definitions.join(", ")
+    ))?;
+
+    let names: Vec<String> = columns.iter().map(|(c, _)| quote_ident(c)).collect();
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let mut insert = conn.prepare(&format!(
+        "INSERT OR REPLACE INTO {table} ({}) VALUES ({})",
+        names.join(", "),
+        placeholders
+    ))?;
+
+    for doc in docs {
+        let row: Vec<SqlValue> = columns
+            .iter()
+            .map(|(column, _)| sql_value(doc.get(column)))
+            .collect();
+        insert.execute(rusqlite::params_from_iter(row))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_columns() {
+        let docs = vec![
+            json!({ "id": "1", "age": 30, "score": 1, "tags": ["a"] }),
+            json!({ "id": "2", "age": null, "score": 2.5, "active": true }),
+        ];
+        let columns = infer_columns(&docs);
+        assert_eq!(columns[0], ("id".to_string(), ColumnType::Text));
+        assert!(columns.contains(&("age".to_string(), ColumnType::Integer)));
+        assert!(columns.contains(&("score".to_string(), ColumnType::Real)));
+        assert!(columns.contains(&("tags".to_string(), ColumnType::Text)));
+        assert!(columns.contains(&("active".to_string(), ColumnType::Integer)));
+    }
+
+    #[test]
+    fn test_write_table() {
+        let conn = Connection::open_in_memory().unwrap();
+        let docs = vec![
+            json!({ "id": "1", "name": "Ann", "meta": { "k": 1 } }),
+            json!({ "id": "2", "name": "Bo" }),
+        ];
+        write_table(&conn, "user", &docs).unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM user", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+        let meta: String = conn
+            .query_row("SELECT meta FROM user WHERE id = '1'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(meta, r#"{"k":1}"#);
+    }
+}
@@ -4,24 +4,99 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{parse_macro_input, Data, DeriveInput, Fields};
 
+mod naming;
+mod query;
+
+use naming::{CasePolicy, NamingPolicy};
+
 /// Derive the Model trait for a struct
 ///
+/// The collection name defaults to the lowercased struct name. Use
+/// `#[collection = "..."]` to set it explicitly, or `#[model(...)]` to pick
+/// a naming policy:
+///
+/// - `case = "snake_case"` (also `lowercase`, `camelCase`, `kebab-case`)
+/// - `plural` to pluralize the last word
+/// - `prefix = "app_"` to prepend a fixed prefix
+///
+/// `#[model(codec = "msgpack")]` stores documents as MessagePack (requires
+/// the `msgpack` feature of `torm`) instead of JSON, and `codec = "cbor"`
+/// as CBOR (feature `cbor`).
+///
+/// `#[model(compression = "zstd")]` (or `"gzip"`, each behind the feature
+/// of the same name) compresses documents of at least 1 KiB; set another
+/// threshold in bytes with `compress_above = 4096`.
+///
+/// `#[model(ordered)]` keeps an insertion-order index so `find_all`
+/// returns documents in creation order.
+///
+/// `#[model(dto = "UserDto")]` also generates a `UserDto` struct with
+/// `From` conversions in both directions. Mark fields `#[dto(skip)]` to
+/// leave them out (they must implement `Default`) or
+/// `#[dto(rename = "...")]` to rename them in the DTO.
+///
+/// Fields of type `chrono::DateTime<Utc>` marked `#[created_at]` or
+/// `#[updated_at]` are managed timestamps, reset when a model is duplicated.
+///
+/// Fields marked `#[index]` get a secondary index that queries use for
+/// `eq`, `in` and numeric range filters once built with
+/// `Model::build_indexes`.
+///
+/// Fields of type `torm::GeoPoint` marked `#[geo]` are indexed the same
+/// way, with their points kept in a GEO key for `near` and `within_box`
+/// filters.
+///
+/// Fields marked `#[search]` make up the collection's RediSearch schema,
+/// see `Model::create_search_index`: numeric fields are indexed as
+/// `NUMERIC` and others as `TAG`. Override with `#[search(tag)]` or
+/// `#[search(numeric)]`, and add `sortable` to keep a field sortable.
+///
+/// Use `#[id(auto = "sequence")]` to generate increasing integer IDs
+/// (stored as strings) from a per-collection counter instead of UUIDs.
+///
+/// Fields marked `#[skip(compute = "path")]` are recomputed by calling
+/// `path(&self)` every time the model is loaded. They must also be excluded
+/// from storage with `#[serde(skip)]`.
+///
 /// # Example
 /// ```rust,ignore
 /// #[derive(Model, Serialize, Deserialize)]
-/// struct User {
+/// #[model(case = "snake_case", plural)] // collection: "user_profiles"
+/// struct UserProfile {
 ///     #[id]
 ///     id: String,
 ///     name: String,
 ///     email: String,
+///     #[serde(skip)]
+///     #[skip(compute = "Self::display_name")]
+///     label: String,
 /// }
 /// ```
-#[proc_macro_derive(Model, attributes(id, collection))]
+#[proc_macro_derive(
+    Model,
+    attributes(
+        id, collection, model, created_at, updated_at, skip, dto, index, geo, search
+    )
+)]
 pub fn derive_model(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
     let name = &input.ident;
-    let collection_name = name.to_string().to_lowercase();
+    let ModelAttrs {
+        collection: collection_name,
+        dto,
+        codec,
+        compression,
+        ordered,
+    } = match model_attrs(&input) {
+        Ok(attrs) => attrs,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let dto = match dto.map(|dto| dto_mapping(&input, &dto)).transpose() {
+        Ok(dto) => dto,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     // Find the field marked with #[id]
     let id_field = find_id_field(&input.data);
@@ -35,6 +110,85 @@ pub fn derive_model(input: TokenStream) -> TokenStream {
         }
     };
 
+    let id_strategy = match id_strategy(&input.data) {
+        Ok(strategy) => strategy,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let created_at = find_field_with_attr(&input.data, "created_at");
+    let updated_at = find_field_with_attr(&input.data, "updated_at");
+
+    let updated_at_field = updated_at.as_ref().map(|field| {
+        let field_name = field.to_string();
+        quote! {
+            fn updated_at_field() -> Option<&'static str> {
+                Some(#field_name)
+            }
+        }
+    });
+
+    let reset_timestamps = if created_at.is_some() || updated_at.is_some() {
+        let assignments = created_at.iter().chain(updated_at.iter());
+        Some(quote! {
+            fn reset_timestamps(&mut self, now: chrono::DateTime<chrono::Utc>) {
+                #(self.#assignments = now;)*
+            }
+        })
+    } else {
+        None
+    };
+
+    let after_load = match after_load(&input.data) {
+        Ok(after_load) => after_load,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let codec = codec.map(|codec| {
+        quote! {
+            fn codec() -> torm::Codec {
+                #codec
+            }
+        }
+    });
+
+    let compression = compression.map(|compression| {
+        quote! {
+            fn compression() -> Option<torm::Compression> {
+                Some(#compression)
+            }
+        }
+    });
+
+    let mut indexed = fields_with_attr(&input.data, "index");
+    for field in fields_with_attr(&input.data, "geo") {
+        if !indexed.contains(&field) {
+            indexed.push(field);
+        }
+    }
+    let indexes = (!indexed.is_empty()).then(|| {
+        let names = indexed.iter().map(|field| field.to_string());
+        quote! {
+            fn indexes() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+        }
+    });
+
+    let search_fields = match search_fields(&input.data) {
+        Ok(search_fields) => search_fields,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let accessors = field_accessors(name, &input.data);
+
+    let ordered = ordered.then(|| {
+        quote! {
+            fn ordered() -> bool {
+                true
+            }
+        }
+    });
+
     let expanded = quote! {
         #[async_trait::async_trait]
         impl torm::Model for #name {
@@ -49,20 +203,463 @@ pub fn derive_model(input: TokenStream) -> TokenStream {
             fn set_id(&mut self, id: String) {
                 self.#id_field_name = id;
             }
+
+            #id_strategy
+
+            #codec
+
+            #compression
+
+            #ordered
+
+            #indexes
+
+            #search_fields
+
+            #updated_at_field
+
+            #reset_timestamps
+
+            #after_load
         }
+
+        #accessors
+
+        #dto
     };
 
     TokenStream::from(expanded)
 }
 
+/// Build a query whose filters are checked against the model at compile time
+///
+/// Takes the model type and an expression of comparisons between fields
+/// (or dot paths into nested structs) and values, combined with `&&`, `||`
+/// and `!`. It expands to `Model::query()` with the matching filters, and
+/// fails to compile if a field does not exist or cannot be compared with
+/// its value in Rust; `Option` fields compare with `Some(...)`.
+///
+/// # Example
+/// ```rust,ignore
+/// let adults = torm::query!(User, age >= 18 && (active == true || role == "admin"))
+///     .limit(10)
+///     .exec(&db)
+///     .await?;
+/// ```
+#[proc_macro]
+pub fn query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as query::QueryInput);
+    match query::expand(input) {
+        Ok(expanded) => expanded.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Hidden accessors that let `query!` check fields from any module
+fn field_accessors(name: &syn::Ident, data: &Data) -> Option<proc_macro2::TokenStream> {
+    let Data::Struct(data_struct) = data else {
+        return None;
+    };
+    let Fields::Named(fields) = &data_struct.fields else {
+        return None;
+    };
+    let accessors = fields.named.iter().filter_map(|field| {
+        let ident = field.ident.as_ref()?;
+        let accessor = quote::format_ident!("__torm_field_{}", ident);
+        let ty = &field.ty;
+        Some(quote! {
+            #[doc(hidden)]
+            pub fn #accessor(&self) -> &#ty {
+                &self.#ident
+            }
+        })
+    });
+    Some(quote! {
+        #[doc(hidden)]
+        #[allow(dead_code, private_interfaces)]
+        impl #name {
+            #(#accessors)*
+        }
+    })
+}
+
+/// Struct-level settings from `#[collection]` / `#[model]` attributes
+struct ModelAttrs {
+    collection: String,
+    dto: Option<syn::Ident>,
+    codec: Option<proc_macro2::TokenStream>,
+    compression: Option<proc_macro2::TokenStream>,
+    ordered: bool,
+}
+
+/// Resolve the collection name, DTO and storage settings
+fn model_attrs(input: &DeriveInput) -> syn::Result<ModelAttrs> {
+    let mut explicit = None;
+    let mut dto = None;
+    let mut codec = None;
+    let mut compression = None;
+    let mut threshold = None;
+    let mut ordered = false;
+    let mut policy = NamingPolicy::default();
+
+    for attr in &input.attrs {
+        if attr.path().is_ident("collection") {
+            let nv = attr.meta.require_name_value()?;
+            match &nv.value {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(value),
+                    ..
+                }) => explicit = Some(value.value()),
+                other => {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "expected #[collection = \"name\"]",
+                    ))
+                }
+            }
+        } else if attr.path().is_ident("model") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("collection") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    explicit = Some(value.value());
+                } else if meta.path.is_ident("case") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    policy.case = CasePolicy::parse(&value.value())
+                        .ok_or_else(|| meta.error("unknown case policy"))?;
+                } else if meta.path.is_ident("plural") {
+                    policy.plural = true;
+                } else if meta.path.is_ident("ordered") {
+                    ordered = true;
+                } else if meta.path.is_ident("prefix") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    policy.prefix = value.value();
+                } else if meta.path.is_ident("dto") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    dto = Some(value.parse::<syn::Ident>()?);
+                } else if meta.path.is_ident("codec") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    codec = Some(match value.value().as_str() {
+                        "json" => quote! { torm::Codec::Json },
+                        "msgpack" => quote! { torm::Codec::MessagePack },
+                        "cbor" => quote! { torm::Codec::Cbor },
+                        _ => {
+                            return Err(
+                                meta.error("expected codec = \"json\", \"msgpack\" or \"cbor\"")
+                            )
+                        }
+                    });
+                } else if meta.path.is_ident("compression") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    compression = Some(match value.value().as_str() {
+                        "zstd" => quote! { torm::Compression::zstd() },
+                        "gzip" => quote! { torm::Compression::gzip() },
+                        _ => return Err(meta.error("expected compression = \"zstd\" or \"gzip\"")),
+                    });
+                } else if meta.path.is_ident("compress_above") {
+                    let value: syn::LitInt = meta.value()?.parse()?;
+                    threshold = Some(value.base10_parse::<usize>()?);
+                } else {
+                    return Err(meta.error("unsupported model attribute"));
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    let compression = match (compression, threshold) {
+        (Some(compression), Some(bytes)) => Some(quote! { #compression.threshold(#bytes) }),
+        (compression, None) => compression,
+        (None, Some(_)) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "compress_above requires compression = \"zstd\" or \"gzip\"",
+            ))
+        }
+    };
+
+    Ok(ModelAttrs {
+        collection: explicit.unwrap_or_else(|| policy.apply(&input.ident.to_string())),
+        dto,
+        codec,
+        compression,
+        ordered,
+    })
+}
+
+/// Generate the DTO struct named `dto` and conversions to and from the model
+///
+/// Fields marked `#[dto(skip)]` are left out of the DTO and filled with
+/// `Default::default()` when converting back; `#[dto(rename = "...")]`
+/// changes the DTO field name.
+fn dto_mapping(input: &DeriveInput, dto: &syn::Ident) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(name, "dto requires named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(name, "dto requires a struct")),
+    };
+
+    let mut dto_fields = Vec::new();
+    let mut to_dto = Vec::new();
+    let mut to_model = Vec::new();
+
+    for field in fields {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let mut skip = false;
+        let mut rename = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("dto")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                } else if meta.path.is_ident("rename") {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    rename = Some(value.parse::<syn::Ident>()?);
+                } else {
+                    return Err(meta.error("unsupported dto attribute"));
+                }
+                Ok(())
+            })?;
+        }
+
+        if skip {
+            to_model.push(quote! { #ident: ::core::default::Default::default() });
+            continue;
+        }
+
+        let ty = &field.ty;
+        let dto_ident = rename.unwrap_or_else(|| ident.clone());
+        dto_fields.push(quote! { #vis #dto_ident: #ty });
+        to_dto.push(quote! { #dto_ident: model.#ident });
+        to_model.push(quote! { #ident: dto.#dto_ident });
+    }
+
+    let doc = format!("API-facing view of [`{}`]", name);
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+        #vis struct #dto {
+            #(#dto_fields,)*
+        }
+
+        impl ::core::convert::From<#name> for #dto {
+            fn from(model: #name) -> Self {
+                Self {
+                    #(#to_dto,)*
+                }
+            }
+        }
+
+        impl ::core::convert::From<#dto> for #name {
+            fn from(dto: #dto) -> Self {
+                Self {
+                    #(#to_model,)*
+                }
+            }
+        }
+    })
+}
+
+/// Generate `id_strategy` from `#[id(auto = "...")]`
+fn id_strategy(data: &Data) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let Data::Struct(data_struct) = data else {
+        return Ok(None);
+    };
+
+    let Some(attr) = data_struct
+        .fields
+        .iter()
+        .flat_map(|field| &field.attrs)
+        .find(|attr| attr.path().is_ident("id"))
+    else {
+        return Ok(None);
+    };
+    if matches!(attr.meta, syn::Meta::Path(_)) {
+        return Ok(None);
+    }
+
+    let mut strategy = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("auto") {
+            let value: syn::LitStr = meta.value()?.parse()?;
+            strategy = Some(match value.value().as_str() {
+                "sequence" => quote! { torm::IdStrategy::Sequence },
+                "uuid" => quote! { torm::IdStrategy::Uuid },
+                _ => return Err(meta.error("expected auto = \"sequence\" or \"uuid\"")),
+            });
+            Ok(())
+        } else {
+            Err(meta.error("unsupported id attribute"))
+        }
+    })?;
+
+    Ok(strategy.map(|strategy| {
+        quote! {
+            fn id_strategy() -> torm::IdStrategy {
+                #strategy
+            }
+        }
+    }))
+}
+
+/// Generate `after_load` for fields marked `#[skip(compute = "...")]`
+fn after_load(data: &Data) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let Data::Struct(data_struct) = data else {
+        return Ok(None);
+    };
+    let Fields::Named(fields) = &data_struct.fields else {
+        return Ok(None);
+    };
+
+    let mut assignments = Vec::new();
+    for field in &fields.named {
+        let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("skip")) else {
+            continue;
+        };
+
+        let serde_skipped = field.attrs.iter().any(|a| {
+            a.path().is_ident("serde")
+                && a.meta
+                    .require_list()
+                    .map(|list| list.tokens.to_string().contains("skip"))
+                    .unwrap_or(false)
+        });
+        if !serde_skipped {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "#[skip] fields must also be marked #[serde(skip)]",
+            ));
+        }
+
+        // A bare #[skip] leaves the field at its default after loading
+        if matches!(attr.meta, syn::Meta::Path(_)) {
+            continue;
+        }
+
+        let mut compute = None;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("compute") {
+                let value: syn::LitStr = meta.value()?.parse()?;
+                compute = Some(value.parse::<syn::ExprPath>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported skip attribute"))
+            }
+        })?;
+
+        if let (Some(ident), Some(compute)) = (&field.ident, compute) {
+            assignments.push(quote! { self.#ident = #compute(self); });
+        }
+    }
+
+    if assignments.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(quote! {
+        fn after_load(&mut self) {
+            #(#assignments)*
+        }
+    }))
+}
+
+/// Generate `search_fields` for fields marked `#[search]`
+fn search_fields(data: &Data) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let Data::Struct(data_struct) = data else {
+        return Ok(None);
+    };
+    let Fields::Named(fields) = &data_struct.fields else {
+        return Ok(None);
+    };
+
+    let mut entries = Vec::new();
+    for field in &fields.named {
+        let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("search")) else {
+            continue;
+        };
+
+        let mut numeric = is_numeric(&field.ty);
+        let mut sortable = false;
+        if !matches!(attr.meta, syn::Meta::Path(_)) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    numeric = false;
+                } else if meta.path.is_ident("numeric") {
+                    numeric = true;
+                } else if meta.path.is_ident("sortable") {
+                    sortable = true;
+                } else {
+                    return Err(meta.error("expected tag, numeric or sortable"));
+                }
+                Ok(())
+            })?;
+        }
+
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let name = ident.to_string();
+        let kind = if numeric {
+            quote! { torm::SearchType::Numeric }
+        } else {
+            quote! { torm::SearchType::Tag }
+        };
+        entries.push(quote! {
+            torm::SearchField { name: #name, kind: #kind, sortable: #sortable }
+        });
+    }
+
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(quote! {
+        fn search_fields() -> &'static [torm::SearchField] {
+            &[#(#entries),*]
+        }
+    }))
+}
+
+/// Whether a field type is a number, or an `Option` of one
+fn is_numeric(ty: &syn::Type) -> bool {
+    let syn::Type::Path(path) = ty else {
+        return false;
+    };
+    let Some(segment) = path.path.segments.last() else {
+        return false;
+    };
+    if segment.ident == "Option" {
+        if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+            if let Some(syn::GenericArgument::Type(inner)) = args.args.first() {
+                return is_numeric(inner);
+            }
+        }
+        return false;
+    }
+    [
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+        "f32", "f64",
+    ]
+    .iter()
+    .any(|number| segment.ident == number)
+}
+
 fn find_id_field(data: &Data) -> Option<syn::Ident> {
+    find_field_with_attr(data, "id")
+}
+
+/// Find the first named field carrying `#[attr_name]`
+fn find_field_with_attr(data: &Data, attr_name: &str) -> Option<syn::Ident> {
     match data {
         Data::Struct(data_struct) => {
             if let Fields::Named(fields) = &data_struct.fields {
                 for field in &fields.named {
-                    // Check if field has #[id] attribute
                     for attr in &field.attrs {
-                        if attr.path().is_ident("id") {
+                        if attr.path().is_ident(attr_name) {
                             return field.ident.clone();
                         }
                     }
@@ -73,3 +670,18 @@ fn find_id_field(data: &Data) -> Option<syn::Ident> {
     }
     None
 }
+
+fn fields_with_attr(data: &Data, attr_name: &str) -> Vec<syn::Ident> {
+    match data {
+        Data::Struct(data_struct) => match &data_struct.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .filter(|field| field.attrs.iter().any(|a| a.path().is_ident(attr_name)))
+                .filter_map(|field| field.ident.clone())
+                .collect(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
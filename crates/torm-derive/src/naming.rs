@@ -0,0 +1,169 @@
+//! Collection naming policies
+
+/// How the struct name is cased
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CasePolicy {
+    /// `UserProfile` -> `userprofile` (legacy default)
+    #[default]
+    Lower,
+    /// `UserProfile` -> `user_profile`
+    Snake,
+    /// `UserProfile` -> `user-profile`
+    Kebab,
+    /// `UserProfile` -> `userProfile`
+    Camel,
+}
+
+impl CasePolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "lowercase" => Some(CasePolicy::Lower),
+            "snake_case" => Some(CasePolicy::Snake),
+            "kebab-case" => Some(CasePolicy::Kebab),
+            "camelCase" => Some(CasePolicy::Camel),
+            _ => None,
+        }
+    }
+}
+
+/// Naming policy built from `#[model(...)]`
+#[derive(Debug, Clone, Default)]
+pub struct NamingPolicy {
+    pub case: CasePolicy,
+    pub plural: bool,
+    pub prefix: String,
+}
+
+impl NamingPolicy {
+    /// Turn a struct name into a collection name
+    pub fn apply(&self, ident: &str) -> String {
+        let mut words = split_words(ident);
+        if self.plural {
+            if let Some(last) = words.last_mut() {
+                *last = pluralize(last);
+            }
+        }
+
+        let name = match self.case {
+            CasePolicy::Lower => words.concat(),
+            CasePolicy::Snake => words.join("_"),
+            CasePolicy::Kebab => words.join("-"),
+            CasePolicy::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                .collect(),
+        };
+
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+/// Split a CamelCase identifier into lowercase words
+///
+/// Acronyms stay together: `HTTPRequest` -> `["http", "request"]`.
+fn split_words(ident: &str) -> Vec<String> {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        if c.is_uppercase() && !current.is_empty() {
+            let prev_lower = chars[i - 1].is_lowercase() || chars[i - 1].is_ascii_digit();
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev_lower || (chars[i - 1].is_uppercase() && next_lower) {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+
+        current.extend(c.to_lowercase());
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+/// Pluralize a lowercase English word
+fn pluralize(word: &str) -> String {
+    const ES_SUFFIXES: &[&str] = &["s", "x", "z", "ch", "sh"];
+
+    if ES_SUFFIXES.iter().any(|s| word.ends_with(s)) {
+        return format!("{}es", word);
+    }
+
+    if let Some(stem) = word.strip_suffix('y') {
+        let before = stem.chars().last();
+        if before.is_some_and(|c| !"aeiou".contains(c)) {
+            return format!("{}ies", stem);
+        }
+    }
+
+    format!("{}s", word)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(case: CasePolicy, plural: bool) -> NamingPolicy {
+        NamingPolicy {
+            case,
+            plural,
+            prefix: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_default_is_lowercase() {
+        assert_eq!(NamingPolicy::default().apply("UserProfile"), "userprofile");
+    }
+
+    #[test]
+    fn test_snake_plural() {
+        let p = policy(CasePolicy::Snake, true);
+        assert_eq!(p.apply("UserProfile"), "user_profiles");
+        assert_eq!(p.apply("Category"), "categories");
+        assert_eq!(p.apply("Address"), "addresses");
+        assert_eq!(p.apply("HTTPRequest"), "http_requests");
+    }
+
+    #[test]
+    fn test_other_cases() {
+        assert_eq!(
+            policy(CasePolicy::Kebab, false).apply("UserProfile"),
+            "user-profile"
+        );
+        assert_eq!(
+            policy(CasePolicy::Camel, true).apply("UserProfile"),
+            "userProfiles"
+        );
+    }
+
+    #[test]
+    fn test_prefix() {
+        let p = NamingPolicy {
+            case: CasePolicy::Snake,
+            plural: false,
+            prefix: "app_".into(),
+        };
+        assert_eq!(p.apply("User"), "app_user");
+    }
+}
@@ -0,0 +1,177 @@
+//! The `query!` macro: filter expressions checked against the model
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::{BinOp, Expr, Token, UnOp};
+
+/// `Model, expression`
+pub struct QueryInput {
+    model: syn::Path,
+    expr: Expr,
+}
+
+impl Parse for QueryInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let model = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let expr = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(Self { model, expr })
+    }
+}
+
+/// Expand to the model's query builder with one filter per top-level `&&`
+/// term, plus a never-called closure that performs the same comparisons on
+/// the model so field names and value types are checked by the compiler
+pub fn expand(input: QueryInput) -> syn::Result<TokenStream> {
+    let QueryInput { model, expr } = input;
+
+    let mut terms = Vec::new();
+    flatten(&expr, BinOp::And(Default::default()), &mut terms);
+    let filters = terms
+        .into_iter()
+        .map(filter)
+        .collect::<syn::Result<Vec<_>>>()?;
+    let check = check(&expr)?;
+
+    Ok(quote! {
+        {
+            #[allow(clippy::all)]
+            let _ = |doc: &#model| -> bool { #check };
+            <#model as torm::Model>::query()
+                #(.where_filter(#filters))*
+        }
+    })
+}
+
+/// Collect the operands of a chain of `op`, looking through parentheses
+fn flatten<'a>(expr: &'a Expr, op: BinOp, out: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::Binary(binary) if same_op(&binary.op, &op) => {
+            flatten(&binary.left, op, out);
+            flatten(&binary.right, op, out);
+        }
+        Expr::Paren(paren) if matches!(&*paren.expr, Expr::Binary(b) if same_op(&b.op, &op)) => {
+            flatten(&paren.expr, op, out)
+        }
+        _ => out.push(expr),
+    }
+}
+
+fn same_op(a: &BinOp, b: &BinOp) -> bool {
+    matches!(
+        (a, b),
+        (BinOp::And(_), BinOp::And(_)) | (BinOp::Or(_), BinOp::Or(_))
+    )
+}
+
+/// Build the `torm::Filter` for an expression
+fn filter(expr: &Expr) -> syn::Result<TokenStream> {
+    match expr {
+        Expr::Paren(paren) => filter(&paren.expr),
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Not(_)) => {
+            let inner = filter(&unary.expr)?;
+            Ok(quote! { torm::Filter::Not(::std::boxed::Box::new(#inner)) })
+        }
+        Expr::Binary(binary) if matches!(binary.op, BinOp::And(_) | BinOp::Or(_)) => {
+            let mut terms = Vec::new();
+            flatten(expr, binary.op, &mut terms);
+            let filters = terms
+                .into_iter()
+                .map(filter)
+                .collect::<syn::Result<Vec<_>>>()?;
+            let group = match binary.op {
+                BinOp::And(_) => quote! { And },
+                _ => quote! { Or },
+            };
+            Ok(quote! { torm::Filter::#group(::std::vec![#(#filters),*]) })
+        }
+        Expr::Binary(binary) => {
+            let constructor = match binary.op {
+                BinOp::Eq(_) => quote! { eq },
+                BinOp::Ne(_) => quote! { ne },
+                BinOp::Lt(_) => quote! { lt },
+                BinOp::Le(_) => quote! { lte },
+                BinOp::Gt(_) => quote! { gt },
+                BinOp::Ge(_) => quote! { gte },
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        binary.op,
+                        "expected a comparison: ==, !=, <, <=, > or >=",
+                    ))
+                }
+            };
+            let field = field_path(&binary.left)?
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(".");
+            let value = &binary.right;
+            Ok(quote! { torm::Filter::field(#field, torm::Query::#constructor(#value)) })
+        }
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            "expected a comparison like `age >= 18`, combined with &&, || and !",
+        )),
+    }
+}
+
+/// The same expression with fields read from `doc`
+fn check(expr: &Expr) -> syn::Result<TokenStream> {
+    match expr {
+        Expr::Paren(paren) => {
+            let inner = check(&paren.expr)?;
+            Ok(quote! { (#inner) })
+        }
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Not(_)) => {
+            let inner = check(&unary.expr)?;
+            Ok(quote! { !(#inner) })
+        }
+        Expr::Binary(binary) if matches!(binary.op, BinOp::And(_) | BinOp::Or(_)) => {
+            let (left, op, right) = (check(&binary.left)?, binary.op, check(&binary.right)?);
+            Ok(quote! { #left #op #right })
+        }
+        Expr::Binary(binary) => {
+            let path = field_path(&binary.left)?;
+            let (root, nested) = path.split_first().expect("field path is never empty");
+            let accessor = format_ident!("__torm_field_{}", root);
+            let (op, value) = (binary.op, &binary.right);
+            let field = if nested.is_empty() {
+                quote! { (*doc.#accessor()) }
+            } else {
+                quote! { doc.#accessor() #(.#nested)* }
+            };
+            Ok(quote! { #field #op #value })
+        }
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            "unsupported query expression",
+        )),
+    }
+}
+
+/// Field names of `name` or `address.city`
+fn field_path(expr: &Expr) -> syn::Result<Vec<syn::Ident>> {
+    match expr {
+        Expr::Path(path) if path.qself.is_none() => match path.path.get_ident() {
+            Some(ident) => Ok(vec![ident.clone()]),
+            None => Err(syn::Error::new_spanned(path, "expected a field name")),
+        },
+        Expr::Field(field) => {
+            let syn::Member::Named(ident) = &field.member else {
+                return Err(syn::Error::new_spanned(
+                    &field.member,
+                    "expected a field name",
+                ));
+            };
+            let mut path = field_path(&field.base)?;
+            path.push(ident.clone());
+            Ok(path)
+        }
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            "expected a field name on the left of the comparison",
+        )),
+    }
+}
@@ -0,0 +1,738 @@
+//! HTTP handlers for the TORM REST API
+
+use crate::filter;
+use crate::format::{self, ResponseFormat};
+use crate::{AppState, ServerConfig, API_VERSION};
+use axum::{
+    extract::{OriginalUri, Path, Query, RawQuery, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use torm::QueryBuilder;
+use tracing::{error, info};
+
+// Root endpoint
+pub(crate) async fn root() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "name": "TORM Server",
+        "version": env!("CARGO_PKG_VERSION"),
+        "status": "running",
+        "description": "ToonStore ORM HTTP API",
+        "endpoints": {
+            "health": "GET /health",
+            "capabilities": "GET /capabilities",
+            "create": "POST /api/{collection}",
+            "find_all": "GET /api/{collection}?filter=age ge 18&orderby=name&include=relation,...&page=1&per_page=20",
+            "find_by_id": "GET /api/{collection}/{id}?include=relation,...",
+            "update": "PUT /api/{collection}/{id}",
+            "delete": "DELETE /api/{collection}/{id}",
+            "query": "POST /api/{collection}/query",
+            "batch_get": "POST /api/{collection}/batch-get",
+            "relations": "GET|POST /api/{collection}/relations",
+            "count": "GET /api/{collection}/count",
+            "config": "GET|PUT /admin/config"
+        }
+    }))
+}
+
+// Health check
+pub(crate) async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match state.db.health().await {
+        Ok(health) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "status": "ok",
+                "database": "connected",
+                "server": health
+            })),
+        ),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "error",
+                "database": "disconnected",
+                "error": e.to_string()
+            })),
+        ),
+    }
+}
+
+// Capability discovery
+//
+// Clients call this once on startup to find out which optional features the
+// server supports, so mixed-version deployments can degrade gracefully.
+pub(crate) async fn capabilities() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "server": "torm-server",
+        "version": env!("CARGO_PKG_VERSION"),
+        "api_version": API_VERSION,
+        "min_api_version": 1,
+        "features": features()
+    }))
+}
+
+// Optional features of this build. Those behind a Cargo feature follow
+// the features the server was compiled with.
+fn features() -> serde_json::Value {
+    serde_json::json!({
+        "search": false,
+        "transactions": false,
+        "watch": false,
+        "telemetry": cfg!(feature = "otel"),
+        "response_formats": ["json", "jsonapi", "hal"],
+        "codecs": ["json"]
+    })
+}
+
+// Create document
+#[derive(Deserialize)]
+pub(crate) struct CreateRequest {
+    data: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub(crate) struct CreateResponse {
+    success: bool,
+    id: String,
+    data: serde_json::Value,
+}
+
+pub(crate) async fn create_document(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+    Json(req): Json<CreateRequest>,
+) -> impl IntoResponse {
+    info!("Creating document in collection: {}", collection);
+
+    // Extract or generate ID
+    let id = if let Some(id_value) = req.data.get("id") {
+        id_value.as_str().unwrap_or_default().to_string()
+    } else {
+        format!("{}:{}", collection, uuid::Uuid::new_v4())
+    };
+
+    let key = format!("{}:{}", collection, id);
+
+    let value = serde_json::to_string(&req.data).unwrap();
+    let result = match torm::set_counted(&state.db, &collection, &key, value.as_bytes()).await {
+        Ok(_) => torm::track_insertion(&state.db, &collection, &id).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(_) => (
+            StatusCode::CREATED,
+            Json(CreateResponse {
+                success: true,
+                id,
+                data: req.data,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to create document: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "success": false,
+                    "error": e.to_string()
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+// Page size of a list given `page` but no `per_page`
+const DEFAULT_PER_PAGE: usize = 20;
+
+// Read parameters: relations to embed (`?include=author,comments`) and, for
+// lists, OData-style `filter` / `orderby` expressions and `page` / `per_page`
+#[derive(Deserialize)]
+pub(crate) struct ReadParams {
+    include: Option<String>,
+    filter: Option<String>,
+    orderby: Option<String>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+impl ReadParams {
+    // The requested page and its size, if the list is paged
+    fn paging(&self) -> Option<(usize, usize)> {
+        if self.page.is_none() && self.per_page.is_none() {
+            return None;
+        }
+        Some((
+            self.page.unwrap_or(1),
+            self.per_page.unwrap_or(DEFAULT_PER_PAGE),
+        ))
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.include
+            .as_deref()
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn query(&self, collection: &str) -> Result<QueryBuilder<serde_json::Value>, String> {
+        let mut query = QueryBuilder::new(collection);
+        if let Some(expr) = &self.filter {
+            for (field, op) in filter::parse_filter(expr)? {
+                query = query.filter(field, op);
+            }
+        }
+        if let Some(expr) = &self.orderby {
+            let (field, order) = filter::parse_orderby(expr)?;
+            query = query.sort_by(field, order);
+        } else {
+            query = query.insertion_order();
+        }
+        query.validate().map_err(|e| e.to_string())?;
+        Ok(query)
+    }
+}
+
+// Find all documents
+pub(crate) async fn find_all_documents(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+    Query(params): Query<ReadParams>,
+    RawQuery(raw_query): RawQuery,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    info!("Finding all documents in collection: {}", collection);
+
+    let format = ResponseFormat::negotiate(&headers, state.format);
+    if params.filter.is_some() {
+        // Refresh the registered schema so filters are checked against it
+        if let Err(e) = torm::Schema::load(&state.db, &collection).await {
+            error!("Failed to load schema for {}: {}", collection, e);
+        }
+    }
+    let query = match params.query(&collection) {
+        Ok(query) => query,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": format!("Invalid filter: {}", e),
+                    "documents": []
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    let listed = match params.paging() {
+        Some((page, per_page)) => query.paginate(&state.db, page, per_page).await.map(|page| {
+            let pagination = format::Pagination {
+                page: page.page,
+                per_page: page.per_page,
+                total: page.total,
+                query: String::new(),
+            };
+            (page.items, pagination)
+        }),
+        None => query.exec(&state.db).await.map(|documents| {
+            let pagination = format::Pagination::single(documents.len());
+            (documents, pagination)
+        }),
+    };
+
+    match listed {
+        Ok((mut documents, pagination)) => {
+            let relations =
+                match torm::populate(&state.db, &collection, &mut documents, &params.names()).await
+                {
+                    Ok(relations) => relations,
+                    Err(e) => {
+                        return (
+                            populate_status(&e),
+                            Json(serde_json::json!({
+                                "error": e.to_string(),
+                                "documents": []
+                            })),
+                        )
+                            .into_response();
+                    }
+                };
+
+            let included = included_map(&relations);
+            formatted(
+                format,
+                StatusCode::OK,
+                format::collection(
+                    format,
+                    mount_path(&original, &uri),
+                    &collection,
+                    documents,
+                    &included,
+                    &pagination.with_query(raw_query.as_deref()),
+                ),
+            )
+        }
+        Err(e) => {
+            error!("Failed to find documents: {}", e);
+            Json(serde_json::json!({
+                "error": e.to_string(),
+                "documents": []
+            }))
+            .into_response()
+        }
+    }
+}
+
+// Find by ID
+pub(crate) async fn find_by_id(
+    State(state): State<Arc<AppState>>,
+    Path((collection, id)): Path<(String, String)>,
+    Query(params): Query<ReadParams>,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    info!("Finding document {}:{}", collection, id);
+
+    let format = ResponseFormat::negotiate(&headers, state.format);
+    let key = format!("{}:{}", collection, id);
+
+    match redis::cmd("GET")
+        .arg(&key)
+        .query_async::<Option<String>>(&mut state.db.connection().clone())
+        .await
+    {
+        Ok(Some(value)) => match serde_json::from_str::<serde_json::Value>(&value) {
+            Ok(doc) => {
+                let mut docs = [doc];
+                match torm::populate(&state.db, &collection, &mut docs, &params.names()).await {
+                    Ok(relations) => {
+                        let [doc] = docs;
+                        let included = included_map(&relations);
+                        formatted(
+                            format,
+                            StatusCode::OK,
+                            format::document(
+                                format,
+                                mount_path(&original, &uri),
+                                &collection,
+                                doc,
+                                &included,
+                            ),
+                        )
+                    }
+                    Err(e) => (
+                        populate_status(&e),
+                        Json(serde_json::json!({
+                            "error": e.to_string()
+                        })),
+                    )
+                        .into_response(),
+                }
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": format!("Failed to parse document: {}", e)
+                })),
+            )
+                .into_response(),
+        },
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "Document not found"
+            })),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "error": e.to_string()
+            })),
+        )
+            .into_response(),
+    }
+}
+
+// Map applied relations to their target collections for envelope rendering
+/// Path the API router is nested under, such as `/torm`, or empty when it
+/// is served from the root
+///
+/// A nested router sees the request path without its mount point, so the
+/// mount point is what the original path has in front of it.
+fn mount_path<'a>(original: &'a Uri, uri: &Uri) -> &'a str {
+    original
+        .path()
+        .strip_suffix(uri.path())
+        .unwrap_or_default()
+        .trim_end_matches('/')
+}
+
+fn included_map(relations: &[torm::Relation]) -> format::Included<'_> {
+    relations
+        .iter()
+        .map(|r| (r.name.as_str(), r.target.as_str()))
+        .collect()
+}
+
+// An unknown relation in `include` is the client's mistake; anything else
+// failed while fetching the related documents
+fn populate_status(e: &torm::Error) -> StatusCode {
+    match e {
+        torm::Error::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// Send a body with the content type of the negotiated format
+fn formatted(format: ResponseFormat, status: StatusCode, body: serde_json::Value) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, format.content_type())],
+        Json(body),
+    )
+        .into_response()
+}
+
+// Update document
+#[derive(Deserialize)]
+pub(crate) struct UpdateRequest {
+    data: serde_json::Value,
+}
+
+pub(crate) async fn update_document(
+    State(state): State<Arc<AppState>>,
+    Path((collection, id)): Path<(String, String)>,
+    Json(req): Json<UpdateRequest>,
+) -> impl IntoResponse {
+    info!("Updating document {}:{}", collection, id);
+
+    let key = format!("{}:{}", collection, id);
+
+    // Check if exists
+    match redis::cmd("EXISTS")
+        .arg(&key)
+        .query_async::<i32>(&mut state.db.connection().clone())
+        .await
+    {
+        Ok(1) => {
+            // Document exists, update it
+            match redis::cmd("SET")
+                .arg(&key)
+                .arg(serde_json::to_string(&req.data).unwrap())
+                .query_async::<()>(&mut state.db.connection().clone())
+                .await
+            {
+                Ok(_) => Json(serde_json::json!({
+                    "success": true,
+                    "id": id,
+                    "data": req.data
+                })),
+                Err(e) => Json(serde_json::json!({
+                    "success": false,
+                    "error": e.to_string()
+                })),
+            }
+        }
+        Ok(_) => Json(serde_json::json!({
+            "success": false,
+            "error": "Document not found"
+        })),
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "error": e.to_string()
+        })),
+    }
+}
+
+// Delete document
+pub(crate) async fn delete_document(
+    State(state): State<Arc<AppState>>,
+    Path((collection, id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    info!("Deleting document {}:{}", collection, id);
+
+    let key = format!("{}:{}", collection, id);
+
+    let result = match torm::del_counted(&state.db, &collection, &key).await {
+        Ok(Some(_)) => torm::untrack_insertion(&state.db, &collection, &id)
+            .await
+            .map(|_| 1),
+        Ok(None) => Ok(0),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(1) => Json(serde_json::json!({
+            "success": true,
+            "deleted": true
+        })),
+        Ok(_) => Json(serde_json::json!({
+            "success": false,
+            "error": "Document not found"
+        })),
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "error": e.to_string()
+        })),
+    }
+}
+
+// Query documents, with the body in the `torm::QuerySpec` format that
+// `QueryBuilder::to_spec` produces
+pub(crate) async fn query_documents(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+    Json(spec): Json<torm::QuerySpec>,
+) -> Response {
+    info!("Querying documents in collection: {}", collection);
+
+    if !spec.filters.is_empty() {
+        // Refresh the registered schema so filters are checked against it
+        if let Err(e) = torm::Schema::load(&state.db, &collection).await {
+            error!("Failed to load schema for {}: {}", collection, e);
+        }
+    }
+    let builder = QueryBuilder::<serde_json::Value>::from_spec(collection.as_str(), &spec);
+    if let Err(e) = builder.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": format!("Invalid query: {}", e),
+                "documents": []
+            })),
+        )
+            .into_response();
+    }
+
+    let result = match &spec.select {
+        Some(fields) => {
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            builder
+                .select(&fields)
+                .exec::<serde_json::Value>(&state.db)
+                .await
+        }
+        None => builder.exec(&state.db).await,
+    };
+    match result {
+        Ok(documents) => Json(serde_json::json!({
+            "collection": collection,
+            "count": documents.len(),
+            "documents": documents
+        }))
+        .into_response(),
+        Err(e) => Json(serde_json::json!({
+            "error": e.to_string(),
+            "documents": []
+        }))
+        .into_response(),
+    }
+}
+
+// Batch get documents
+#[derive(Deserialize)]
+pub(crate) struct BatchGetRequest {
+    ids: Vec<String>,
+}
+
+pub(crate) async fn batch_get_documents(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+    Json(req): Json<BatchGetRequest>,
+) -> impl IntoResponse {
+    info!(
+        "Batch getting {} documents in collection: {}",
+        req.ids.len(),
+        collection
+    );
+
+    if req.ids.is_empty() {
+        return (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "collection": collection,
+                "documents": []
+            })),
+        );
+    }
+
+    let keys: Vec<String> = req
+        .ids
+        .iter()
+        .map(|id| format!("{}:{}", collection, id))
+        .collect();
+
+    match redis::cmd("MGET")
+        .arg(&keys)
+        .query_async::<Vec<Option<String>>>(&mut state.db.connection().clone())
+        .await
+    {
+        Ok(values) => {
+            // Keep request order; misses and unparseable documents become null
+            let documents: Vec<serde_json::Value> = values
+                .into_iter()
+                .map(|value| {
+                    value
+                        .and_then(|v| serde_json::from_str(&v).ok())
+                        .unwrap_or(serde_json::Value::Null)
+                })
+                .collect();
+
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "collection": collection,
+                    "documents": documents
+                })),
+            )
+        }
+        Err(e) => {
+            error!("Failed to batch get documents: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": e.to_string(),
+                    "documents": []
+                })),
+            )
+        }
+    }
+}
+
+// List relations registered for a collection
+pub(crate) async fn list_relations(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+) -> impl IntoResponse {
+    match torm::Relation::for_collection(&state.db, &collection).await {
+        Ok(relations) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "collection": collection,
+                "relations": relations
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "error": e.to_string()
+            })),
+        ),
+    }
+}
+
+// Register a relation for a collection
+pub(crate) async fn register_relation(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+    Json(relation): Json<torm::Relation>,
+) -> impl IntoResponse {
+    info!(
+        "Registering relation {} on collection: {}",
+        relation.name, collection
+    );
+
+    match relation.register(&state.db, &collection).await {
+        Ok(()) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "success": true,
+                "relation": relation
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "error": e.to_string()
+            })),
+        ),
+    }
+}
+
+// Count documents
+pub(crate) async fn count_documents(
+    State(state): State<Arc<AppState>>,
+    Path(collection): Path<String>,
+) -> impl IntoResponse {
+    info!("Counting documents in collection: {}", collection);
+
+    match torm::QueryBuilder::<serde_json::Value>::new(collection.as_str())
+        .count(&state.db)
+        .await
+    {
+        Ok(count) => Json(serde_json::json!({
+            "collection": collection,
+            "count": count
+        })),
+        Err(e) => Json(serde_json::json!({
+            "error": e.to_string(),
+            "count": 0
+        })),
+    }
+}
+
+// Show the runtime configuration (API keys redacted)
+pub(crate) async fn get_config(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.config.get().redacted())
+}
+
+// Replace the runtime configuration without restarting; API keys that are
+// omitted or redacted are kept
+pub(crate) async fn update_config(
+    State(state): State<Arc<AppState>>,
+    Json(update): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    info!("Updating runtime configuration");
+
+    let config = match ServerConfig::from_update(&state.config.get(), update) {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "success": false,
+                    "error": e.to_string()
+                })),
+            )
+        }
+    };
+
+    crate::config::apply(&state, config, "admin").await;
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "config": state.config.get().redacted()
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_path() {
+        let mount = |original: &str, local: &str| {
+            let original: Uri = original.parse().unwrap();
+            mount_path(&original, &local.parse().unwrap()).to_string()
+        };
+        assert_eq!(mount("/api/user?page=2", "/api/user?page=2"), "");
+        assert_eq!(mount("/torm/api/user", "/api/user"), "/torm");
+        assert_eq!(mount("/a/b/api/user/1", "/api/user/1"), "/a/b");
+    }
+}
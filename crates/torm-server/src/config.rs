@@ -0,0 +1,305 @@
+//! Runtime configuration
+//!
+//! API keys, CORS, rate limits and read-only mode live in a [`ServerConfig`]
+//! that can be replaced while the server runs: either by editing the file
+//! passed to [`crate::ServerBuilder::watch_config`] or with
+//! `PUT /admin/config`, which needs a key set with
+//! [`crate::ServerBuilder::admin_key`]. Every change is recorded in the audit
+//! trail.
+
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use torm::{AuditEntry, ModelOp};
+use tracing::{error, info, warn};
+
+/// Collection name used for config changes in the audit trail
+pub const CONFIG_AUDIT_COLLECTION: &str = "torm:config";
+
+/// Shown in place of each API key by [`ServerConfig::redacted`]
+const REDACTED_KEY: &str = "***";
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Settings that can change without restarting the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Accepted API keys; authentication is disabled when empty
+    pub api_keys: Vec<String>,
+    /// Answer cross-origin requests with permissive CORS headers
+    pub cors: bool,
+    /// Reject writes to `/api` and `/studio` with 503
+    pub read_only: bool,
+    /// Maximum requests per minute per client (API key or address)
+    pub rate_limit: Option<u32>,
+    /// Take client addresses from `X-Forwarded-For`, which is only safe
+    /// behind a reverse proxy that sets it
+    pub trust_proxy: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            cors: true,
+            read_only: false,
+            rate_limit: None,
+            trust_proxy: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Load a JSON config file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let raw = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    /// Copy of this config that is safe to log or return to clients
+    pub fn redacted(&self) -> Self {
+        Self {
+            api_keys: self
+                .api_keys
+                .iter()
+                .map(|_| REDACTED_KEY.to_string())
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Read a `PUT /admin/config` body as the config replacing `current`
+    ///
+    /// API keys are write-only: the current keys stay when `api_keys` is
+    /// left out, and each redacted placeholder keeps the current key at the
+    /// same position. A config read from `GET /admin/config` can thus be
+    /// edited and sent back without losing the keys.
+    pub(crate) fn from_update(current: &Self, update: serde_json::Value) -> anyhow::Result<Self> {
+        let keys_given = update.get("api_keys").is_some();
+        let mut config: Self = serde_json::from_value(update)?;
+        if !keys_given {
+            config.api_keys = current.api_keys.clone();
+            return Ok(config);
+        }
+
+        for (i, key) in config.api_keys.iter_mut().enumerate() {
+            if key == REDACTED_KEY {
+                match current.api_keys.get(i) {
+                    Some(existing) => key.clone_from(existing),
+                    None => anyhow::bail!("no current API key at position {} to keep", i),
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The current config, shared by all requests
+#[derive(Clone, Default)]
+pub(crate) struct LiveConfig {
+    inner: Arc<RwLock<Arc<ServerConfig>>>,
+    /// API keys added to every config, e.g. from the environment
+    pinned_keys: Arc<Vec<String>>,
+}
+
+impl LiveConfig {
+    pub(crate) fn new(config: ServerConfig, pinned_keys: Vec<String>) -> Self {
+        let live = Self {
+            inner: Arc::default(),
+            pinned_keys: Arc::new(pinned_keys),
+        };
+        let config = live.pin(config);
+        *live.inner.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        live
+    }
+
+    /// Add the pinned API keys that `config` is missing
+    fn pin(&self, mut config: ServerConfig) -> ServerConfig {
+        for key in self.pinned_keys.iter() {
+            if !config.api_keys.contains(key) {
+                config.api_keys.push(key.clone());
+            }
+        }
+        config
+    }
+
+    pub(crate) fn get(&self) -> Arc<ServerConfig> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Swap in `config` with the pinned keys added, returning the previous
+    /// config or `None` if nothing changed
+    fn replace(&self, config: ServerConfig) -> Option<Arc<ServerConfig>> {
+        let config = self.pin(config);
+        let mut current = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        if **current == config {
+            return None;
+        }
+        Some(std::mem::replace(&mut *current, Arc::new(config)))
+    }
+}
+
+/// Apply a new config and record the change in the audit trail
+///
+/// `source` identifies where the change came from (e.g. `file` or `admin`).
+pub(crate) async fn apply(state: &AppState, config: ServerConfig, source: &str) {
+    let Some(previous) = state.config.replace(config) else {
+        return;
+    };
+    info!("Applied configuration change from {}", source);
+
+    let config = state.config.get();
+    if !previous.api_keys.is_empty() && config.api_keys.is_empty() {
+        warn!(
+            "Configuration change from {} has no API keys; authentication is now disabled",
+            source
+        );
+    }
+
+    let data = serde_json::to_value(config.redacted()).ok();
+    let entry = AuditEntry {
+        actor: format!("torm-server:{}", source),
+        action: ModelOp::Save,
+        collection: CONFIG_AUDIT_COLLECTION.to_string(),
+        id: "server".to_string(),
+        data,
+        at: chrono::Utc::now(),
+    };
+    if let Err(e) = entry.append(&state.db).await {
+        error!("Failed to audit configuration change: {}", e);
+    }
+}
+
+/// Poll `path` and apply its contents whenever the file changes
+///
+/// Invalid files are logged and ignored, keeping the previous config.
+pub(crate) fn spawn_watcher(state: Arc<AppState>, path: PathBuf) {
+    tokio::spawn(async move {
+        let mut last_modified = modified(&path);
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match ServerConfig::load(&path) {
+                Ok(config) => apply(&state, config, "file").await,
+                Err(e) => warn!("Ignoring invalid config {}: {}", path.display(), e),
+            }
+        }
+    });
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Fixed-window request counter per client
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    /// Count a request from `client`, returning false once `limit` is reached
+    pub(crate) fn check(&self, client: &str, limit: u32) -> bool {
+        self.check_at(client, limit, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, limit: u32, now: Instant) -> bool {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.len() > 10_000 {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < Self::WINDOW);
+        }
+
+        let (start, count) = windows.entry(client.to_string()).or_insert((now, 0));
+        if now.duration_since(*start) >= Self::WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= limit {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: ServerConfig = serde_json::from_str(r#"{ "read_only": true }"#).unwrap();
+        assert!(config.read_only);
+        assert!(config.cors);
+        assert_eq!(config.rate_limit, None);
+        assert_eq!(config.redacted().api_keys.len(), 0);
+    }
+
+    #[test]
+    fn test_update_keeps_redacted_keys() {
+        let current = ServerConfig {
+            api_keys: vec!["a".into(), "b".into()],
+            ..ServerConfig::default()
+        };
+
+        let omitted =
+            ServerConfig::from_update(&current, serde_json::json!({ "read_only": true })).unwrap();
+        assert!(omitted.read_only);
+        assert_eq!(omitted.api_keys, ["a", "b"]);
+
+        let mut round_trip = serde_json::to_value(current.redacted()).unwrap();
+        round_trip["api_keys"]
+            .as_array_mut()
+            .unwrap()
+            .push("c".into());
+        let round_trip = ServerConfig::from_update(&current, round_trip).unwrap();
+        assert_eq!(round_trip.api_keys, ["a", "b", "c"]);
+
+        let replaced =
+            ServerConfig::from_update(&current, serde_json::json!({ "api_keys": ["x"] })).unwrap();
+        assert_eq!(replaced.api_keys, ["x"]);
+
+        let extra = serde_json::json!({ "api_keys": ["***", "***", "***"] });
+        assert!(ServerConfig::from_update(&current, extra).is_err());
+    }
+
+    #[test]
+    fn test_pinned_keys_survive_reload() {
+        let live = LiveConfig::new(ServerConfig::default(), vec!["env".into()]);
+        assert_eq!(live.get().api_keys, ["env"]);
+
+        let reloaded = ServerConfig {
+            api_keys: vec!["file".into()],
+            ..ServerConfig::default()
+        };
+        assert!(live.replace(reloaded).is_some());
+        assert_eq!(live.get().api_keys, ["file", "env"]);
+
+        assert!(live.replace(ServerConfig::default()).is_some());
+        assert_eq!(live.get().api_keys, ["env"]);
+    }
+
+    #[test]
+    fn test_rate_limiter_window() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        assert!(limiter.check_at("a", 2, start));
+        assert!(limiter.check_at("a", 2, start));
+        assert!(!limiter.check_at("a", 2, start));
+        assert!(limiter.check_at("b", 2, start));
+        assert!(limiter.check_at("a", 2, start + RateLimiter::WINDOW));
+    }
+}
@@ -0,0 +1,601 @@
+//! Startup self-check
+//!
+//! Verifies the store before the server accepts traffic: connectivity,
+//! required modules, the migration record, registered schemas and index
+//! health. The resulting [`Report`] renders as a table for the startup log.
+//!
+//! Schema and index checks sample up to 20 collections that have a
+//! document counter, so they stay quick on large stores.
+//! Problems they find are warnings, since the server can still run.
+
+use std::fmt;
+use torm::{KeyScanner, MigrationManager, Schema, TormDb};
+
+/// Collections sampled by the schema and index checks
+const SAMPLE_COLLECTIONS: usize = 20;
+
+/// Keys counted per collection before the counter comparison gives up
+const SCAN_BUDGET: usize = 10_000;
+
+/// Index entries and documents sampled per collection
+const SAMPLE_ENTRIES: usize = 100;
+
+/// Problems listed in a check's detail before the rest are summarized
+const SHOWN_PROBLEMS: usize = 3;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// Check passed
+    Ok,
+    /// Something looks off, but the server can run
+    Warn,
+    /// Check failed
+    Fail,
+    /// Check did not run
+    Skip,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "OK",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skip => "SKIP",
+        }
+    }
+}
+
+/// Result of one diagnostic check
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Short check name
+    pub name: &'static str,
+    /// Outcome
+    pub status: CheckStatus,
+    /// Human-readable details
+    pub detail: String,
+    /// Whether a failure should stop a strict startup
+    pub critical: bool,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            critical: false,
+        }
+    }
+
+    fn critical(mut self) -> Self {
+        self.critical = true;
+        self
+    }
+}
+
+/// Results of a [`SelfCheck`] run
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Checks in the order they ran
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// True unless a critical check failed
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|c| c.critical && c.status == CheckStatus::Fail)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self
+            .checks
+            .iter()
+            .map(|c| c.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("CHECK".len());
+
+        writeln!(f, "{:<name_width$}  {:<6}DETAIL", "CHECK", "STATUS")?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "{:<name_width$}  {:<6}{}",
+                check.name,
+                check.status.label(),
+                check.detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Startup diagnostics for a ToonStore connection
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use torm::TormDb;
+/// # use torm_server::SelfCheck;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let report = SelfCheck::new().require_module("json").run(&db).await;
+/// println!("{}", report);
+/// if !report.passed() {
+///     anyhow::bail!("self-check failed");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SelfCheck {
+    required_modules: Vec<String>,
+}
+
+impl SelfCheck {
+    /// Create a self-check with no required modules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require a server module such as `json` or `search`
+    pub fn require_module(mut self, name: impl Into<String>) -> Self {
+        self.required_modules.push(name.into());
+        self
+    }
+
+    /// Run all checks
+    pub async fn run(&self, db: &TormDb) -> Report {
+        let mut checks = Vec::new();
+
+        match db.health().await {
+            Ok(health) => checks.push(
+                Check::new(
+                    "connectivity",
+                    CheckStatus::Ok,
+                    format!("PING in {} ms", health.latency.as_millis()),
+                )
+                .critical(),
+            ),
+            Err(e) => {
+                checks
+                    .push(Check::new("connectivity", CheckStatus::Fail, e.to_string()).critical());
+                for name in ["modules", "migrations", "schema", "indexes"] {
+                    checks.push(Check::new(name, CheckStatus::Skip, "store unreachable"));
+                }
+                return Report { checks };
+            }
+        }
+
+        checks.push(self.check_modules(db).await);
+        checks.push(check_migrations(db).await);
+
+        let collections = match sample_collections(db).await {
+            Ok(collections) => collections,
+            Err(e) => {
+                let detail = format!("can't list collections: {}", e);
+                checks.push(Check::new("schema", CheckStatus::Warn, detail.clone()));
+                checks.push(Check::new("indexes", CheckStatus::Warn, detail));
+                return Report { checks };
+            }
+        };
+        if collections.is_empty() {
+            for name in ["schema", "indexes"] {
+                checks.push(Check::new(name, CheckStatus::Skip, "no collections"));
+            }
+            return Report { checks };
+        }
+        checks.push(check_schemas(db, &collections).await);
+        checks.push(check_indexes(db, &collections).await);
+
+        Report { checks }
+    }
+
+    async fn check_modules(&self, db: &TormDb) -> Check {
+        if self.required_modules.is_empty() {
+            return Check::new("modules", CheckStatus::Skip, "none required");
+        }
+
+        let loaded = match redis::cmd("MODULE")
+            .arg("LIST")
+            .query_async::<redis::Value>(&mut db.connection().clone())
+            .await
+        {
+            Ok(value) => module_names(&value),
+            Err(e) => {
+                return Check::new(
+                    "modules",
+                    CheckStatus::Fail,
+                    format!("MODULE LIST failed: {}", e),
+                )
+                .critical()
+            }
+        };
+
+        let missing: Vec<&str> = self
+            .required_modules
+            .iter()
+            .filter(|required| !loaded.iter().any(|name| module_matches(required, name)))
+            .map(String::as_str)
+            .collect();
+
+        if missing.is_empty() {
+            Check::new(
+                "modules",
+                CheckStatus::Ok,
+                format!("loaded: {}", loaded.join(", ")),
+            )
+            .critical()
+        } else {
+            Check::new(
+                "modules",
+                CheckStatus::Fail,
+                format!("missing: {}", missing.join(", ")),
+            )
+            .critical()
+        }
+    }
+}
+
+async fn check_migrations(db: &TormDb) -> Check {
+    match MigrationManager::applied(db).await {
+        Ok(applied) => match applied.last() {
+            Some(latest) => Check::new(
+                "migrations",
+                CheckStatus::Ok,
+                format!("{} applied, latest {}", applied.len(), latest.id),
+            ),
+            None => Check::new("migrations", CheckStatus::Ok, "none applied"),
+        },
+        Err(e) => Check::new(
+            "migrations",
+            CheckStatus::Fail,
+            format!("unreadable migration record: {}", e),
+        )
+        .critical(),
+    }
+}
+
+/// Collections with a document counter, at most [`SAMPLE_COLLECTIONS`]
+async fn sample_collections(db: &TormDb) -> torm::Result<Vec<String>> {
+    let prefix = db.key(&torm::counter_key(""));
+    let mut conn = db.connection().clone();
+    let mut scanner = KeyScanner::new(format!("{}*", prefix));
+    let mut collections = Vec::new();
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        collections.extend(keys.iter().filter_map(|key| collection_of(&prefix, key)));
+        if collections.len() >= SAMPLE_COLLECTIONS {
+            break;
+        }
+    }
+    collections.sort();
+    collections.dedup();
+    collections.truncate(SAMPLE_COLLECTIONS);
+    Ok(collections)
+}
+
+/// Collection named by counter key `key`
+fn collection_of(prefix: &str, key: &str) -> Option<String> {
+    key.strip_prefix(prefix)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Summarize the problems a check found as a warning, or pass with `ok`
+fn summarize(name: &'static str, problems: &[String], ok: String) -> Check {
+    if problems.is_empty() {
+        return Check::new(name, CheckStatus::Ok, ok);
+    }
+    let mut detail = problems
+        .iter()
+        .take(SHOWN_PROBLEMS)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("; ");
+    if problems.len() > SHOWN_PROBLEMS {
+        detail.push_str(&format!(" (+{} more)", problems.len() - SHOWN_PROBLEMS));
+    }
+    Check::new(name, CheckStatus::Warn, detail)
+}
+
+/// Check that stored schemas load and that sampled documents only use
+/// fields they declare
+async fn check_schemas(db: &TormDb, collections: &[String]) -> Check {
+    let mut problems = Vec::new();
+    let mut registered = 0;
+    for collection in collections {
+        let schema = match Schema::load(db, collection).await {
+            Ok(Some(schema)) => schema,
+            Ok(None) => continue,
+            Err(e) => {
+                problems.push(format!("{}: unreadable schema ({})", collection, e));
+                continue;
+            }
+        };
+        registered += 1;
+
+        match sample_documents(db, collection).await {
+            Ok(documents) => {
+                let mut unknown: Vec<String> = documents
+                    .iter()
+                    .filter_map(serde_json::Value::as_object)
+                    .flat_map(|doc| doc.keys())
+                    .filter(|field| schema.field_type(field).is_none())
+                    .cloned()
+                    .collect();
+                unknown.sort();
+                unknown.dedup();
+                if !unknown.is_empty() {
+                    problems.push(format!(
+                        "{}: fields not in schema: {}",
+                        collection,
+                        unknown.join(", ")
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("{}: {}", collection, e)),
+        }
+    }
+
+    summarize(
+        "schema",
+        &problems,
+        format!(
+            "{} of {} collections have a schema",
+            registered,
+            collections.len()
+        ),
+    )
+}
+
+/// Read up to [`SAMPLE_ENTRIES`] documents of a collection as JSON,
+/// skipping ones stored in another codec
+async fn sample_documents(db: &TormDb, collection: &str) -> torm::Result<Vec<serde_json::Value>> {
+    let mut conn = db.connection().clone();
+    let mut scanner = KeyScanner::new(db.key(&format!("{}:*", collection)));
+    let mut documents = Vec::new();
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        for key in keys {
+            let raw: Option<Vec<u8>> = redis::cmd("GET")
+                .arg(&key)
+                .query_async(&mut conn)
+                .await
+                .unwrap_or(None);
+            if let Some(doc) = raw.and_then(|raw| serde_json::from_slice(&raw).ok()) {
+                documents.push(doc);
+            }
+            if documents.len() >= SAMPLE_ENTRIES {
+                return Ok(documents);
+            }
+        }
+    }
+    Ok(documents)
+}
+
+/// Compare each collection's counter with a SCAN of its keys, and check
+/// that sampled entries of its built indexes point to existing documents
+async fn check_indexes(db: &TormDb, collections: &[String]) -> Check {
+    let mut problems = Vec::new();
+    let mut indexes = 0;
+    for collection in collections {
+        if let Err(e) = check_counter(db, collection, &mut problems).await {
+            problems.push(format!("{}: counter unreadable ({})", collection, e));
+        }
+        match check_built_indexes(db, collection, &mut problems).await {
+            Ok(built) => indexes += built,
+            Err(e) => problems.push(format!("{}: indexes unreadable ({})", collection, e)),
+        }
+    }
+
+    summarize(
+        "indexes",
+        &problems,
+        format!(
+            "{} collections, {} built indexes consistent",
+            collections.len(),
+            indexes
+        ),
+    )
+}
+
+async fn check_counter(
+    db: &TormDb,
+    collection: &str,
+    problems: &mut Vec<String>,
+) -> torm::Result<()> {
+    let mut conn = db.connection().clone();
+    let counted: Option<usize> = redis::cmd("GET")
+        .arg(db.key(&torm::counter_key(collection)))
+        .query_async(&mut conn)
+        .await?;
+    let Some(counted) = counted else {
+        return Ok(());
+    };
+
+    let mut scanner = KeyScanner::new(db.key(&format!("{}:*", collection)));
+    let mut found = 0;
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        found += keys.len();
+        if found > SCAN_BUDGET {
+            return Ok(());
+        }
+    }
+    if found != counted {
+        problems.push(format!(
+            "{}: counter says {} documents, found {}",
+            collection, counted, found
+        ));
+    }
+    Ok(())
+}
+
+/// Sample the entries of each built index, returning how many indexes
+/// were checked
+async fn check_built_indexes(
+    db: &TormDb,
+    collection: &str,
+    problems: &mut Vec<String>,
+) -> torm::Result<usize> {
+    let mut conn = db.connection().clone();
+    let fields: Vec<String> = redis::cmd("SMEMBERS")
+        .arg(db.key(&torm::built_key(collection)))
+        .query_async(&mut conn)
+        .await?;
+
+    for field in &fields {
+        let index = db.key(&torm::index_key(collection, field));
+        let mut scanner = KeyScanner::new(format!("{}:*", index));
+        let mut ids: Vec<String> = Vec::new();
+        while let Some(keys) = scanner.next_batch(&mut conn).await? {
+            for key in keys.iter().filter(|key| !key.ends_with(":hll")) {
+                let members: Vec<String> = redis::cmd("SRANDMEMBER")
+                    .arg(key)
+                    .arg(SAMPLE_ENTRIES - ids.len())
+                    .query_async(&mut conn)
+                    .await?;
+                ids.extend(members);
+                if ids.len() >= SAMPLE_ENTRIES {
+                    break;
+                }
+            }
+            if ids.len() >= SAMPLE_ENTRIES {
+                break;
+            }
+        }
+
+        let mut dangling = 0;
+        for id in &ids {
+            let exists: bool = redis::cmd("EXISTS")
+                .arg(db.key(&format!("{}:{}", collection, id)))
+                .query_async(&mut conn)
+                .await?;
+            if !exists {
+                dangling += 1;
+            }
+        }
+        if dangling > 0 {
+            problems.push(format!(
+                "{}.{}: {} of {} sampled entries point to missing documents",
+                collection,
+                field,
+                dangling,
+                ids.len()
+            ));
+        }
+    }
+    Ok(fields.len())
+}
+
+/// Extract module names from a `MODULE LIST` reply (RESP2 or RESP3)
+fn module_names(value: &redis::Value) -> Vec<String> {
+    let redis::Value::Array(modules) = value else {
+        return Vec::new();
+    };
+
+    modules
+        .iter()
+        .filter_map(|module| {
+            let fields: Vec<(&redis::Value, &redis::Value)> = match module {
+                redis::Value::Array(items) => items
+                    .chunks(2)
+                    .filter_map(|pair| Some((pair.first()?, pair.get(1)?)))
+                    .collect(),
+                redis::Value::Map(pairs) => pairs.iter().map(|(k, v)| (k, v)).collect(),
+                _ => return None,
+            };
+            fields.into_iter().find_map(|(key, value)| {
+                (as_string(key)?.eq_ignore_ascii_case("name"))
+                    .then(|| as_string(value))
+                    .flatten()
+            })
+        })
+        .collect()
+}
+
+fn as_string(value: &redis::Value) -> Option<String> {
+    match value {
+        redis::Value::BulkString(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        redis::Value::SimpleString(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Match a friendly module name (`json`, `search`) against a loaded module
+fn module_matches(required: &str, loaded: &str) -> bool {
+    let aliases: &[&str] = match required.to_ascii_lowercase().as_str() {
+        "json" => &["rejson", "json"],
+        "search" => &["search", "ft"],
+        _ => &[],
+    };
+    loaded.eq_ignore_ascii_case(required) || aliases.iter().any(|a| loaded.eq_ignore_ascii_case(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> redis::Value {
+        redis::Value::BulkString(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_module_names() {
+        let reply = redis::Value::Array(vec![redis::Value::Array(vec![
+            bulk("name"),
+            bulk("ReJSON"),
+            bulk("ver"),
+            redis::Value::Int(20607),
+        ])]);
+        let names = module_names(&reply);
+        assert_eq!(names, vec!["ReJSON".to_string()]);
+        assert!(module_matches("json", &names[0]));
+        assert!(!module_matches("search", &names[0]));
+    }
+
+    #[test]
+    fn test_collection_of() {
+        assert_eq!(
+            collection_of("torm:count:", "torm:count:user"),
+            Some("user".to_string())
+        );
+        assert_eq!(collection_of("torm:count:", "torm:count:"), None);
+        assert_eq!(collection_of("torm:count:", "user:1"), None);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let check = summarize("indexes", &[], "fine".to_string());
+        assert_eq!(check.status, CheckStatus::Ok);
+
+        let problems: Vec<String> = (0..5).map(|i| format!("p{}", i)).collect();
+        let check = summarize("indexes", &problems, "fine".to_string());
+        assert_eq!(check.status, CheckStatus::Warn);
+        assert!(!check.critical);
+        assert_eq!(check.detail, "p0; p1; p2 (+2 more)");
+    }
+
+    #[test]
+    fn test_report_passed() {
+        let report = Report {
+            checks: vec![
+                Check::new("modules", CheckStatus::Ok, "loaded: ReJSON").critical(),
+                Check::new("indexes", CheckStatus::Warn, "stale"),
+            ],
+        };
+        assert!(report.passed());
+        assert!(report.to_string().starts_with("CHECK"));
+
+        let report = Report {
+            checks: vec![Check::new("connectivity", CheckStatus::Fail, "refused").critical()],
+        };
+        assert!(!report.passed());
+    }
+}
@@ -0,0 +1,269 @@
+//! OData-style `filter` and `orderby` query parameters
+//!
+//! Supports expressions such as `age ge 18 and active eq true` and
+//! `contains(name,'jo')` (also `startswith`, `endswith` and
+//! `matchesPattern`), joined with `and`, plus `orderby=name desc`.
+
+use torm::{Query, SortOrder};
+
+/// Parse a filter expression into field/query pairs (implicitly ANDed)
+pub fn parse_filter(input: &str) -> Result<Vec<(String, Query)>, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut filters = vec![parser.condition()?];
+
+    while let Some(token) = parser.next() {
+        match token {
+            Token::Word(w) if w.eq_ignore_ascii_case("and") => filters.push(parser.condition()?),
+            other => return Err(format!("expected 'and', found {}", other.describe())),
+        }
+    }
+
+    Ok(filters)
+}
+
+/// Parse an `orderby` value such as `name` or `age desc`
+pub fn parse_orderby(input: &str) -> Result<(String, SortOrder), String> {
+    let mut parts = input.split_whitespace();
+    let field = parts
+        .next()
+        .ok_or_else(|| "orderby requires a field".to_string())?;
+    let order = match parts.next() {
+        None => SortOrder::Asc,
+        Some(dir) if dir.eq_ignore_ascii_case("asc") => SortOrder::Asc,
+        Some(dir) if dir.eq_ignore_ascii_case("desc") => SortOrder::Desc,
+        Some(dir) => return Err(format!("unknown sort direction '{}'", dir)),
+    };
+    if parts.next().is_some() {
+        return Err("orderby accepts a single field".to_string());
+    }
+    Ok((field.to_string(), order))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Number(serde_json::Number),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(w) => format!("'{}'", w),
+            Token::Str(s) => format!("'{}'", s),
+            Token::Number(n) => n.to_string(),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::Comma => "','".to_string(),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '\'' => {
+                // OData escapes a quote inside a string by doubling it
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            value.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                        None => return Err("unterminated string literal".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse::<i64>()
+                    .map(serde_json::Number::from)
+                    .ok()
+                    .or_else(|| {
+                        text.parse::<f64>()
+                            .ok()
+                            .and_then(serde_json::Number::from_f64)
+                    })
+                    .ok_or_else(|| format!("invalid number '{}'", text))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[start..i].iter().collect()));
+            }
+            other => return Err(format!("unexpected character '{}'", other)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!(
+                "expected {}, found {}",
+                expected.describe(),
+                token.describe()
+            )),
+            None => Err(format!("expected {}", expected.describe())),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w),
+            Some(token) => Err(format!("expected a field name, found {}", token.describe())),
+            None => Err("expected a field name".to_string()),
+        }
+    }
+
+    fn value(&mut self) -> Result<serde_json::Value, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(serde_json::Value::String(s)),
+            Some(Token::Number(n)) => Ok(serde_json::Value::Number(n)),
+            Some(Token::Word(w)) if w == "true" => Ok(serde_json::Value::Bool(true)),
+            Some(Token::Word(w)) if w == "false" => Ok(serde_json::Value::Bool(false)),
+            Some(Token::Word(w)) if w == "null" => Ok(serde_json::Value::Null),
+            Some(token) => Err(format!("expected a value, found {}", token.describe())),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    /// `field op value` or a string function such as `contains(field,'text')`
+    fn condition(&mut self) -> Result<(String, Query), String> {
+        let head = self.word()?;
+
+        let function: Option<fn(String) -> Query> = match head.to_ascii_lowercase().as_str() {
+            "contains" => Some(Query::contains),
+            "startswith" => Some(Query::starts_with),
+            "endswith" => Some(Query::ends_with),
+            "matchespattern" => Some(Query::matches),
+            _ => None,
+        };
+        if let Some(function) = function {
+            self.expect(Token::LParen)?;
+            let field = self.word()?;
+            self.expect(Token::Comma)?;
+            let value = match self.value()? {
+                serde_json::Value::String(s) => s,
+                other => return Err(format!("{} expects a string, found {}", head, other)),
+            };
+            self.expect(Token::RParen)?;
+            return Ok((field, function(value)));
+        }
+
+        let op = self.word()?;
+        let value = self.value()?;
+        let query = match op.to_ascii_lowercase().as_str() {
+            "eq" => Query::Eq(value),
+            "ne" => Query::Ne(value),
+            "gt" => Query::Gt(value),
+            "ge" => Query::Gte(value),
+            "lt" => Query::Lt(value),
+            "le" => Query::Lte(value),
+            other => return Err(format!("unknown operator '{}'", other)),
+        };
+
+        Ok((head, query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_chain() {
+        let filters = parse_filter("age ge 18 and active eq true").unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].0, "age");
+        assert!(matches!(&filters[0].1, Query::Gte(v) if v == 18));
+        assert!(matches!(&filters[1].1, Query::Eq(v) if v == true));
+    }
+
+    #[test]
+    fn test_parse_strings_and_contains() {
+        let filters = parse_filter("name eq 'O''Brien' and contains(email,'@example')").unwrap();
+        assert!(matches!(&filters[0].1, Query::Eq(v) if v == "O'Brien"));
+        assert!(matches!(&filters[1].1, Query::Contains(s) if s == "@example"));
+
+        let filters = parse_filter("startswith(name,'Jo') and endswith(email,'.org')").unwrap();
+        assert!(matches!(&filters[0].1, Query::StartsWith(s) if s == "Jo"));
+        assert!(matches!(&filters[1].1, Query::EndsWith(s) if s == ".org"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_filter("age between 1").is_err());
+        assert!(parse_filter("age ge").is_err());
+        assert!(parse_filter("name eq 'open").is_err());
+        assert!(parse_filter("age ge 1 or age le 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_orderby() {
+        assert!(matches!(parse_orderby("name"), Ok((f, SortOrder::Asc)) if f == "name"));
+        assert!(matches!(
+            parse_orderby("age desc"),
+            Ok((_, SortOrder::Desc))
+        ));
+        assert!(parse_orderby("age sideways").is_err());
+    }
+}
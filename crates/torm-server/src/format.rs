@@ -0,0 +1,528 @@
+//! Response envelope formats
+//!
+//! Read endpoints can answer in plain JSON (the default), JSON:API or HAL.
+//! The format is picked from the `Accept` header, by quality value, falling
+//! back to the `TORM_RESPONSE_FORMAT` setting. Lists carry their page,
+//! page size and total, and JSON:API and HAL add links to the neighbouring
+//! pages.
+
+use axum::http::{header, HeaderMap};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Media type for JSON:API
+pub const JSON_API: &str = "application/vnd.api+json";
+
+/// Media type for HAL
+pub const HAL: &str = "application/hal+json";
+
+/// Response envelope format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// Plain JSON documents
+    #[default]
+    Plain,
+    /// JSON:API envelopes
+    JsonApi,
+    /// HAL envelopes
+    Hal,
+}
+
+impl ResponseFormat {
+    /// Parse a format name from configuration
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" | "plain" => Some(ResponseFormat::Plain),
+            "jsonapi" | "json:api" => Some(ResponseFormat::JsonApi),
+            "hal" => Some(ResponseFormat::Hal),
+            _ => None,
+        }
+    }
+
+    /// Pick a format from the request's `Accept` header
+    ///
+    /// The acceptable media type with the highest `q` wins, the first
+    /// listed on a tie; `q=0` rules a type out. Wildcards and a header
+    /// naming no known type select `default`.
+    pub fn negotiate(headers: &HeaderMap, default: ResponseFormat) -> Self {
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        let mut best: Option<(f32, bool, ResponseFormat)> = None;
+        for range in accept.split(',') {
+            let mut parts = range.split(';');
+            let media = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(Some(1.0), |(_, q)| q.trim().parse::<f32>().ok());
+            let Some(quality) = quality.filter(|q| *q > 0.0) else {
+                continue;
+            };
+            let (specific, format) = match media.as_str() {
+                JSON_API => (true, ResponseFormat::JsonApi),
+                HAL => (true, ResponseFormat::Hal),
+                "application/json" => (true, ResponseFormat::Plain),
+                "*/*" | "application/*" => (false, default),
+                _ => continue,
+            };
+            if best.is_none_or(|(q, s, _)| (quality, specific) > (q, s)) {
+                best = Some((quality, specific, format));
+            }
+        }
+        best.map_or(default, |(_, _, format)| format)
+    }
+
+    /// Content type to send with responses in this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ResponseFormat::Plain => "application/json",
+            ResponseFormat::JsonApi => JSON_API,
+            ResponseFormat::Hal => HAL,
+        }
+    }
+}
+
+/// Describes embedded relations: relation name -> target collection
+pub type Included<'a> = HashMap<&'a str, &'a str>;
+
+/// Where a list sits among all the documents matching the request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pagination {
+    /// 1-based page number
+    pub page: usize,
+    /// Maximum number of documents per page
+    pub per_page: usize,
+    /// Number of matching documents across all pages
+    pub total: usize,
+    /// Query string of the request without `page` and `per_page`, kept in
+    /// the links
+    pub query: String,
+}
+
+impl Pagination {
+    /// One page holding all `total` documents
+    pub fn single(total: usize) -> Self {
+        Self {
+            page: 1,
+            per_page: total.max(1),
+            total,
+            query: String::new(),
+        }
+    }
+
+    /// Keep the parameters of the request's query string in links, other
+    /// than the paging ones
+    pub fn with_query(mut self, query: Option<&str>) -> Self {
+        self.query = query
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or_default();
+                !pair.is_empty() && name != "page" && name != "per_page"
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        self
+    }
+
+    /// Number of pages, at least one
+    pub fn total_pages(&self) -> usize {
+        self.total.div_ceil(self.per_page.max(1)).max(1)
+    }
+
+    fn meta(&self, count: usize) -> Map<String, Value> {
+        let mut meta = Map::new();
+        meta.insert("count".into(), count.into());
+        meta.insert("total".into(), self.total.into());
+        meta.insert("page".into(), self.page.into());
+        meta.insert("per_page".into(), self.per_page.into());
+        meta.insert("total_pages".into(), self.total_pages().into());
+        meta
+    }
+
+    fn href(&self, base: &str, collection: &str, page: usize) -> String {
+        let separator = if self.query.is_empty() { "" } else { "&" };
+        format!(
+            "{}/api/{}?{}{}page={}&per_page={}",
+            base, collection, self.query, separator, page, self.per_page
+        )
+    }
+
+    /// `self`, `first` and `last` links, and `prev` and `next` where
+    /// those pages exist
+    fn links(&self, base: &str, collection: &str) -> Vec<(&'static str, String)> {
+        let last = self.total_pages();
+        let mut links = vec![
+            ("self", self.href(base, collection, self.page)),
+            ("first", self.href(base, collection, 1)),
+            ("last", self.href(base, collection, last)),
+        ];
+        if self.page > 1 {
+            links.push((
+                "prev",
+                self.href(base, collection, (self.page - 1).min(last)),
+            ));
+        }
+        if self.page < last {
+            links.push(("next", self.href(base, collection, self.page + 1)));
+        }
+        links
+    }
+}
+
+/// Render a single document
+///
+/// `base` is the path the API is mounted under, such as `/torm`, or empty
+/// at the server root; links start with it.
+pub fn document(
+    format: ResponseFormat,
+    base: &str,
+    collection: &str,
+    doc: Value,
+    included: &Included,
+) -> Value {
+    match format {
+        ResponseFormat::Plain => doc,
+        ResponseFormat::JsonApi => {
+            let mut side = Vec::new();
+            let resource = json_api_resource(base, collection, doc, included, &mut side);
+            let mut body = json!({ "data": resource });
+            if !side.is_empty() {
+                body["included"] = Value::Array(side);
+            }
+            body
+        }
+        ResponseFormat::Hal => hal_resource(base, collection, doc, included),
+    }
+}
+
+/// Render one page of a list of documents, see [`document`] for `base`
+pub fn collection(
+    format: ResponseFormat,
+    base: &str,
+    collection: &str,
+    docs: Vec<Value>,
+    included: &Included,
+    pagination: &Pagination,
+) -> Value {
+    let count = docs.len();
+    let meta = pagination.meta(count);
+    let links = pagination.links(base, collection);
+
+    match format {
+        ResponseFormat::Plain => {
+            let mut body = Map::new();
+            body.insert("collection".into(), collection.into());
+            body.extend(meta);
+            body.insert("documents".into(), Value::Array(docs));
+            Value::Object(body)
+        }
+        ResponseFormat::JsonApi => {
+            let mut side = Vec::new();
+            let data: Vec<Value> = docs
+                .into_iter()
+                .map(|doc| json_api_resource(base, collection, doc, included, &mut side))
+                .collect();
+            let links: Map<String, Value> = links
+                .into_iter()
+                .map(|(rel, href)| (rel.to_string(), Value::String(href)))
+                .collect();
+            let mut body = json!({
+                "data": data,
+                "meta": meta,
+                "links": links
+            });
+            if !side.is_empty() {
+                body["included"] = Value::Array(side);
+            }
+            body
+        }
+        ResponseFormat::Hal => {
+            let items: Vec<Value> = docs
+                .into_iter()
+                .map(|doc| hal_resource(base, collection, doc, included))
+                .collect();
+            let links: Map<String, Value> = links
+                .into_iter()
+                .map(|(rel, href)| (rel.to_string(), json!({ "href": href })))
+                .collect();
+            let mut body = Map::new();
+            body.insert("_links".into(), Value::Object(links));
+            body.extend(meta);
+            body.insert("_embedded".into(), json!({ collection: items }));
+            Value::Object(body)
+        }
+    }
+}
+
+fn doc_id(doc: &Value) -> String {
+    match doc.get("id") {
+        Some(Value::String(id)) => id.clone(),
+        Some(other) => other.to_string(),
+        None => String::new(),
+    }
+}
+
+fn json_api_identifier(collection: &str, doc: &Value) -> Value {
+    json!({ "type": collection, "id": doc_id(doc) })
+}
+
+/// Build a JSON:API resource object, moving embedded relations to `side`
+fn json_api_resource(
+    base: &str,
+    collection: &str,
+    doc: Value,
+    included: &Included,
+    side: &mut Vec<Value>,
+) -> Value {
+    let id = doc_id(&doc);
+    let mut attributes = match doc {
+        Value::Object(map) => map,
+        other => {
+            let mut map = Map::new();
+            map.insert("value".into(), other);
+            map
+        }
+    };
+    attributes.remove("id");
+
+    let mut relationships = Map::new();
+    for (name, target) in included {
+        let Some(related) = attributes.remove(*name) else {
+            continue;
+        };
+        let data = match related {
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| {
+                        let identifier = json_api_identifier(target, &item);
+                        push_included(side, base, target, item);
+                        identifier
+                    })
+                    .collect(),
+            ),
+            Value::Null => Value::Null,
+            item => {
+                let identifier = json_api_identifier(target, &item);
+                push_included(side, base, target, item);
+                identifier
+            }
+        };
+        relationships.insert((*name).to_string(), json!({ "data": data }));
+    }
+
+    let mut resource = json!({
+        "type": collection,
+        "id": id,
+        "attributes": attributes,
+        "links": { "self": format!("{}/api/{}/{}", base, collection, id) }
+    });
+    if !relationships.is_empty() {
+        resource["relationships"] = Value::Object(relationships);
+    }
+    resource
+}
+
+/// Add a related document to the `included` list once
+fn push_included(side: &mut Vec<Value>, base: &str, target: &str, item: Value) {
+    let id = doc_id(&item);
+    let exists = side
+        .iter()
+        .any(|r| r["type"] == target && r["id"] == id.as_str());
+    if !exists {
+        let mut nested = Vec::new();
+        side.push(json_api_resource(
+            base,
+            target,
+            item,
+            &Included::new(),
+            &mut nested,
+        ));
+    }
+}
+
+/// Build a HAL resource, moving embedded relations under `_embedded`
+fn hal_resource(base: &str, collection: &str, doc: Value, included: &Included) -> Value {
+    let id = doc_id(&doc);
+    let mut map = match doc {
+        Value::Object(map) => map,
+        other => return other,
+    };
+
+    let mut embedded = Map::new();
+    for (name, target) in included {
+        if let Some(related) = map.remove(*name) {
+            let related = match related {
+                Value::Array(items) => Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| hal_resource(base, target, item, &Included::new()))
+                        .collect(),
+                ),
+                Value::Null => Value::Null,
+                item => hal_resource(base, target, item, &Included::new()),
+            };
+            embedded.insert((*name).to_string(), related);
+        }
+    }
+
+    map.insert(
+        "_links".into(),
+        json!({ "self": { "href": format!("{}/api/{}/{}", base, collection, id) } }),
+    );
+    if !embedded.is_empty() {
+        map.insert("_embedded".into(), Value::Object(embedded));
+    }
+    Value::Object(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            ResponseFormat::negotiate(&headers, ResponseFormat::Plain),
+            ResponseFormat::Plain
+        );
+
+        headers.insert(header::ACCEPT, HAL.parse().unwrap());
+        assert_eq!(
+            ResponseFormat::negotiate(&headers, ResponseFormat::Plain),
+            ResponseFormat::Hal
+        );
+
+        let negotiate = |accept: &str, default| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, accept.parse().unwrap());
+            ResponseFormat::negotiate(&headers, default)
+        };
+        assert_eq!(
+            negotiate("application/json;q=0", ResponseFormat::Hal),
+            ResponseFormat::Hal
+        );
+        assert_eq!(
+            negotiate(
+                "application/json;q=0, application/vnd.api+json",
+                ResponseFormat::Plain
+            ),
+            ResponseFormat::JsonApi
+        );
+        assert_eq!(
+            negotiate(
+                "application/hal+json;q=0.5, application/json",
+                ResponseFormat::JsonApi
+            ),
+            ResponseFormat::Plain
+        );
+        assert_eq!(
+            negotiate("*/*, application/hal+json", ResponseFormat::Plain),
+            ResponseFormat::Hal
+        );
+        assert_eq!(
+            negotiate("text/html, */*;q=0.8", ResponseFormat::JsonApi),
+            ResponseFormat::JsonApi
+        );
+    }
+
+    #[test]
+    fn test_pagination_links() {
+        let pagination = Pagination {
+            page: 2,
+            per_page: 10,
+            total: 35,
+            ..Default::default()
+        }
+        .with_query(Some("filter=age%20ge%2018&page=2&per_page=10"));
+        let docs = vec![json!({ "id": "11" })];
+
+        let body = collection(
+            ResponseFormat::JsonApi,
+            "",
+            "user",
+            docs.clone(),
+            &Included::new(),
+            &pagination,
+        );
+        assert_eq!(
+            body["meta"],
+            json!({ "count": 1, "total": 35, "page": 2, "per_page": 10, "total_pages": 4 })
+        );
+        assert_eq!(
+            body["links"]["next"],
+            "/api/user?filter=age%20ge%2018&page=3&per_page=10"
+        );
+        assert_eq!(
+            body["links"]["prev"],
+            "/api/user?filter=age%20ge%2018&page=1&per_page=10"
+        );
+        assert_eq!(
+            body["links"]["last"],
+            "/api/user?filter=age%20ge%2018&page=4&per_page=10"
+        );
+
+        let last = Pagination {
+            page: 4,
+            ..pagination
+        };
+        let body = collection(
+            ResponseFormat::Hal,
+            "",
+            "user",
+            docs,
+            &Included::new(),
+            &last,
+        );
+        assert!(body["_links"].get("next").is_none());
+        assert_eq!(
+            body["_links"]["prev"]["href"],
+            "/api/user?filter=age%20ge%2018&page=3&per_page=10"
+        );
+        assert_eq!(body["total"], 35);
+    }
+
+    #[test]
+    fn test_json_api_document_with_included() {
+        let doc = json!({
+            "id": "1",
+            "title": "Hello",
+            "author": { "id": "u1", "name": "Ann" }
+        });
+        let included = Included::from([("author", "user")]);
+
+        let body = document(ResponseFormat::JsonApi, "", "post", doc, &included);
+        assert_eq!(body["data"]["id"], "1");
+        assert_eq!(body["data"]["attributes"]["title"], "Hello");
+        assert!(body["data"]["attributes"].get("author").is_none());
+        assert_eq!(body["data"]["relationships"]["author"]["data"]["id"], "u1");
+        assert_eq!(body["included"][0]["type"], "user");
+    }
+
+    #[test]
+    fn test_hal_collection() {
+        let docs = vec![json!({ "id": "1", "name": "Ann" })];
+        let body = collection(
+            ResponseFormat::Hal,
+            "/torm",
+            "user",
+            docs,
+            &Included::new(),
+            &Pagination::single(1),
+        );
+        assert_eq!(body["count"], 1);
+        assert_eq!(
+            body["_links"]["self"]["href"],
+            "/torm/api/user?page=1&per_page=1"
+        );
+        assert_eq!(
+            body["_embedded"]["user"][0]["_links"]["self"]["href"],
+            "/torm/api/user/1"
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! TORM REST API Server
+//!
+//! Provides HTTP API for multi-language TORM support. The API can run as the
+//! standalone `torm-server` binary or be mounted inside another axum
+//! application with [`ServerBuilder`].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use torm::TormDb;
+//! use torm_server::ServerBuilder;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! let db = TormDb::connect("redis://localhost:6379").await?;
+//!
+//! let torm_api = ServerBuilder::new(db)
+//!     .studio(false)
+//!     .api_key("secret")
+//!     .router();
+//!
+//! let app = axum::Router::new().nest("/torm", torm_api);
+//! # let _ = app;
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+mod api;
+mod config;
+mod diagnostics;
+mod filter;
+mod format;
+mod studio;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+
+pub use config::{ServerConfig, CONFIG_AUDIT_COLLECTION};
+pub use diagnostics::{Check, CheckStatus, Report, SelfCheck};
+pub use format::ResponseFormat;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use config::{LiveConfig, RateLimiter};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use torm::TormDb;
+use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use tracing::{info, Level};
+
+/// HTTP API version advertised to clients via `GET /capabilities`
+pub const API_VERSION: u32 = 1;
+
+/// Shared state available to every route
+#[derive(Clone)]
+pub struct AppState {
+    pub(crate) db: TormDb,
+    pub(crate) format: ResponseFormat,
+    pub(crate) config: LiveConfig,
+    pub(crate) limiter: Arc<RateLimiter>,
+    pub(crate) admin_keys: Vec<String>,
+}
+
+impl AppState {
+    /// Get the database handle used by the API
+    pub fn db(&self) -> &TormDb {
+        &self.db
+    }
+
+    /// Get the configuration currently in effect
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.config.get()
+    }
+}
+
+/// Custom routes added with [`ServerBuilder::routes`]
+type RouteExtension = Box<dyn FnOnce(Router<Arc<AppState>>) -> Router<Arc<AppState>> + Send>;
+
+/// Builder for embedding the TORM API in an application
+pub struct ServerBuilder {
+    db: TormDb,
+    studio: bool,
+    format: ResponseFormat,
+    config: ServerConfig,
+    config_path: Option<PathBuf>,
+    api_keys: Vec<String>,
+    admin_keys: Vec<String>,
+    extensions: Vec<RouteExtension>,
+}
+
+impl ServerBuilder {
+    /// Create a builder serving `db` with the default settings
+    ///
+    /// Studio and permissive CORS are enabled, responses are plain JSON and
+    /// no authentication is required.
+    pub fn new(db: TormDb) -> Self {
+        Self {
+            db,
+            studio: true,
+            format: ResponseFormat::default(),
+            config: ServerConfig::default(),
+            config_path: None,
+            api_keys: Vec::new(),
+            admin_keys: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Enable or disable TORM Studio under `/studio`
+    pub fn studio(mut self, enabled: bool) -> Self {
+        self.studio = enabled;
+        self
+    }
+
+    /// Enable or disable the permissive CORS layer
+    pub fn cors(mut self, enabled: bool) -> Self {
+        self.config.cors = enabled;
+        self
+    }
+
+    /// Set the default envelope for read responses
+    pub fn response_format(mut self, format: ResponseFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Require an API key on `/api` and `/studio` routes
+    ///
+    /// Clients send it as `Authorization: Bearer <key>` or `X-API-Key`.
+    /// Can be called several times to accept more than one key. These keys
+    /// are added to every runtime configuration, including reloaded ones.
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_keys.push(key.into());
+        self
+    }
+
+    /// Enable `/admin/config`, accessible only with this key
+    ///
+    /// Sent like an API key. API keys don't grant access to the admin
+    /// routes, which are not mounted at all until an admin key is set. Can
+    /// be called several times to accept more than one key.
+    pub fn admin_key(mut self, key: impl Into<String>) -> Self {
+        self.admin_keys.push(key.into());
+        self
+    }
+
+    /// Replace the initial runtime configuration
+    ///
+    /// Overrides anything set with [`ServerBuilder::cors`] before this call.
+    /// Keys set with [`ServerBuilder::api_key`] are kept.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Reload the runtime configuration whenever `path` changes
+    ///
+    /// The file holds a JSON [`ServerConfig`]. Load it up front with
+    /// [`ServerConfig::load`] and pass it to [`ServerBuilder::config`] to
+    /// fail fast on a broken file. The watcher is started by
+    /// [`ServerBuilder::router`], which must then run inside a Tokio runtime.
+    pub fn watch_config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Add custom routes that share the API state
+    ///
+    /// Handlers can extract `State<Arc<AppState>>` to reach the database.
+    pub fn routes<F>(mut self, extend: F) -> Self
+    where
+        F: FnOnce(Router<Arc<AppState>>) -> Router<Arc<AppState>> + Send + 'static,
+    {
+        self.extensions.push(Box::new(extend));
+        self
+    }
+
+    /// Build the router, ready to serve or nest into another app
+    pub fn router(self) -> Router {
+        let state = Arc::new(AppState {
+            db: self.db.clone(),
+            format: self.format,
+            config: LiveConfig::new(self.config, self.api_keys),
+            limiter: Arc::new(RateLimiter::default()),
+            admin_keys: self.admin_keys,
+        });
+
+        if let Some(path) = self.config_path {
+            config::spawn_watcher(state.clone(), path);
+        }
+
+        let mut protected = Router::new()
+            .route("/api/:collection", post(api::create_document))
+            .route("/api/:collection", get(api::find_all_documents))
+            .route("/api/:collection/:id", get(api::find_by_id))
+            .route(
+                "/api/:collection/:id",
+                axum::routing::put(api::update_document),
+            )
+            .route(
+                "/api/:collection/:id",
+                axum::routing::delete(api::delete_document),
+            )
+            .route("/api/:collection/query", post(api::query_documents))
+            .route("/api/:collection/batch-get", post(api::batch_get_documents))
+            .route("/api/:collection/relations", get(api::list_relations))
+            .route("/api/:collection/relations", post(api::register_relation))
+            .route("/api/:collection/count", get(api::count_documents));
+
+        if self.studio {
+            let studio_state = studio::StudioState {
+                redis_client: Arc::new(self.db.connection().clone()),
+            };
+            protected = protected.nest("/studio", studio::studio_router(studio_state));
+        }
+
+        for extend in self.extensions {
+            protected = extend(protected);
+        }
+
+        // Rate limiting runs after authentication, so that only accepted
+        // keys count as separate clients
+        let mut protected = protected
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                reject_writes_when_read_only,
+            ))
+            .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                require_api_key,
+            ));
+
+        if !state.admin_keys.is_empty() {
+            let admin = Router::new()
+                .route("/admin/config", get(api::get_config))
+                .route("/admin/config", axum::routing::put(api::update_config))
+                .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    require_admin_key,
+                ));
+            protected = protected.merge(admin);
+        }
+
+        Router::new()
+            .route("/", get(api::root))
+            .route("/health", get(api::health))
+            .route("/capabilities", get(api::capabilities))
+            .merge(protected)
+            .layer(middleware::from_fn_with_state(state.clone(), cors))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(DefaultMakeSpan::new().level(Level::INFO)),
+            )
+            .with_state(state)
+    }
+
+    /// Bind to `addr` and serve the API until the process exits
+    pub async fn serve(self, addr: SocketAddr) -> anyhow::Result<()> {
+        let studio = self.studio;
+        let app = self.router();
+
+        info!("🚀 TORM Server listening on http://{}", addr);
+        info!("📚 API Documentation: http://{}/", addr);
+        if studio {
+            info!("🎨 TORM Studio: http://{}/studio", addr);
+        }
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// API key sent as `Authorization: Bearer <key>` or `X-API-Key`
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+}
+
+/// Reject requests without a configured API key (no-op when none are set)
+async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = state.config.get();
+    if config.api_keys.is_empty() {
+        return next.run(request).await;
+    }
+
+    match presented_key(request.headers()) {
+        Some(key) if config.api_keys.iter().any(|k| k == key) => next.run(request).await,
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": "Missing or invalid API key"
+            })),
+        )
+            .into_response(),
+    }
+}
+
+/// Reject requests without an admin key
+async fn require_admin_key(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match presented_key(request.headers()) {
+        Some(key) if state.admin_keys.iter().any(|k| k == key) => next.run(request).await,
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": "Missing or invalid admin key"
+            })),
+        )
+            .into_response(),
+    }
+}
+
+/// Identify the client a request counts against for rate limiting
+///
+/// Clients are identified by a configured API key, then by the last
+/// `X-Forwarded-For` hop when [`ServerConfig::trust_proxy`] is set, then by
+/// the peer address. Keys that aren't configured are ignored, so made-up
+/// keys can't open new buckets.
+fn client_id(config: &ServerConfig, headers: &HeaderMap, peer: Option<IpAddr>) -> String {
+    presented_key(headers)
+        .filter(|key| config.api_keys.iter().any(|k| k == key))
+        .map(|key| format!("key:{}", key))
+        .or_else(|| {
+            headers
+                .get("x-forwarded-for")
+                .filter(|_| config.trust_proxy)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit(',').next())
+                .map(|addr| format!("addr:{}", addr.trim()))
+        })
+        .or_else(|| peer.map(|ip| format!("addr:{}", ip)))
+        .unwrap_or_else(|| "anonymous".to_string())
+}
+
+/// Limit requests per client when a rate limit is configured
+async fn rate_limit(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let config = state.config.get();
+    let Some(limit) = config.rate_limit else {
+        return next.run(request).await;
+    };
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let client = client_id(&config, request.headers(), peer);
+
+    if state.limiter.check(&client, limit) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(serde_json::json!({
+                "error": "Rate limit exceeded"
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Reject writes while the server is in read-only mode
+///
+/// POST endpoints that only read (`/query`, `/batch-get`) stay available.
+async fn reject_writes_when_read_only(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method();
+    let is_read = method == Method::GET
+        || method == Method::HEAD
+        || method == Method::OPTIONS
+        || (method == Method::POST
+            && (request.uri().path().ends_with("/query")
+                || request.uri().path().ends_with("/batch-get")));
+
+    if is_read || !state.config.get().read_only {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "error": "Server is in read-only mode"
+        })),
+    )
+        .into_response()
+}
+
+/// Permissive CORS, toggled by the runtime config
+async fn cors(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if !state.config.get().cors {
+        return next.run(request).await;
+    }
+
+    let preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = if preflight {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    let any = HeaderValue::from_static("*");
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, any.clone());
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, any.clone());
+    headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, any.clone());
+    headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, any);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_id() {
+        let config = ServerConfig {
+            api_keys: vec!["real".into()],
+            ..ServerConfig::default()
+        };
+        let peer = Some(IpAddr::from([10, 0, 0, 1]));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("real"));
+        assert_eq!(client_id(&config, &headers, peer), "key:real");
+
+        // Unknown keys and untrusted forwarding headers fall back to the peer
+        headers.insert("x-api-key", HeaderValue::from_static("fake"));
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("1.2.3.4, 5.6.7.8"),
+        );
+        assert_eq!(client_id(&config, &headers, peer), "addr:10.0.0.1");
+
+        let config = ServerConfig {
+            trust_proxy: true,
+            ..config
+        };
+        assert_eq!(client_id(&config, &headers, peer), "addr:5.6.7.8");
+    }
+}
@@ -5,15 +5,15 @@ use axum::{
     routing::{delete, get, post, put},
     Router,
 };
-use redis::aio::ConnectionManager;
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
+use torm::{Connection, KeyScanner};
 
 /// Studio server state
 #[derive(Clone)]
 pub struct StudioState {
-    pub redis_client: Arc<ConnectionManager>,
+    pub redis_client: Arc<Connection>,
 }
 
 /// Create studio router
@@ -48,6 +48,10 @@ fn default_limit() -> usize {
     100
 }
 
+fn internal(e: torm::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
 /// List all keys
 async fn list_keys(
     State(state): State<StudioState>,
@@ -60,13 +64,15 @@ async fn list_keys(
         query.pattern
     };
 
-    let keys: Vec<String> = redis::cmd("KEYS")
-        .arg(&pattern)
-        .query_async(&mut conn)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let limited_keys: Vec<String> = keys.into_iter().take(query.limit).collect();
+    let mut scanner = KeyScanner::new(pattern);
+    let mut limited_keys = Vec::new();
+    while limited_keys.len() < query.limit {
+        let Some(keys) = scanner.next_batch(&mut conn).await.map_err(internal)? else {
+            break;
+        };
+        limited_keys.extend(keys);
+    }
+    limited_keys.truncate(query.limit);
 
     Ok(Json(json!({
         "keys": limited_keys,
@@ -200,16 +206,13 @@ async fn list_collections(
 ) -> Result<Json<Value>, (StatusCode, String)> {
     let mut conn = state.redis_client.as_ref().clone();
 
-    let keys: Vec<String> = redis::cmd("KEYS")
-        .arg("*")
-        .query_async(&mut conn)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
+    let mut scanner = KeyScanner::new("*");
     let mut collections = std::collections::HashSet::new();
-    for key in keys {
-        if let Some(prefix) = key.split(':').next() {
-            collections.insert(prefix.to_string());
+    while let Some(keys) = scanner.next_batch(&mut conn).await.map_err(internal)? {
+        for key in keys {
+            if let Some(prefix) = key.split(':').next() {
+                collections.insert(prefix.to_string());
+            }
         }
     }
 
@@ -229,11 +232,11 @@ async fn get_collection_data(
     let mut conn = state.redis_client.as_ref().clone();
 
     let pattern = format!("{}:*", collection);
-    let keys: Vec<String> = redis::cmd("KEYS")
-        .arg(&pattern)
-        .query_async(&mut conn)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut scanner = KeyScanner::new(pattern);
+    let mut keys = Vec::new();
+    while let Some(batch) = scanner.next_batch(&mut conn).await.map_err(internal)? {
+        keys.extend(batch);
+    }
 
     let mut data = Vec::new();
     for key in &keys {
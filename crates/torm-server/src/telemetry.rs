@@ -0,0 +1,58 @@
+//! OpenTelemetry export (feature `otel`)
+//!
+//! Spans from the HTTP layer and from `torm` operations (save, find,
+//! queries, relation population) are exported over OTLP. The exporter reads
+//! the standard `OTEL_EXPORTER_OTLP_*` variables, so pointing it at Jaeger
+//! or Tempo needs no code changes.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use tracing_subscriber::prelude::*;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! let provider = torm_server::telemetry::provider("my-app")?;
+//! tracing_subscriber::registry()
+//!     .with(tracing_subscriber::fmt::layer())
+//!     .with(torm_server::telemetry::layer(&provider))
+//!     .init();
+//!
+//! // ... run the app, then flush pending spans
+//! provider.shutdown()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Create a tracer provider exporting batches over OTLP/gRPC
+///
+/// Must be called inside a Tokio runtime.
+pub fn provider(service_name: &str) -> anyhow::Result<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .build()?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build())
+}
+
+/// Tracing layer that forwards spans to `provider`
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("torm"))
+}
@@ -0,0 +1,101 @@
+//! Test harness for TORM
+//!
+//! Spins up a throwaway store container with [testcontainers] and hands back
+//! a connected [`TormDb`]. The container is removed when the [`TestDb`] is
+//! dropped, so every test starts from an empty store.
+//!
+//! Tests that need a container are marked `#[ignore]` so a plain
+//! `cargo test` passes without Docker; run them with `--ignored`. Starting
+//! fails with an error rather than skipping, so a missing Docker daemon
+//! can't pass them silently.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! #[tokio::test]
+//! #[ignore = "needs Docker"]
+//! async fn saves_user() {
+//!     let test_db = torm_test::TestDb::start().await.unwrap();
+//!     let db = test_db.db();
+//!     // ...
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+use std::ops::Deref;
+use testcontainers::core::{IntoContainerPort, WaitFor};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage};
+use torm::{Error, Result, TormDb};
+
+/// Environment variable overriding the container image (`name:tag`)
+pub const IMAGE_ENV: &str = "TORM_TEST_IMAGE";
+
+/// Image used when no override is set
+pub const DEFAULT_IMAGE: (&str, &str) = ("redis", "7-alpine");
+
+const STORE_PORT: u16 = 6379;
+
+/// A connected database backed by a throwaway container
+pub struct TestDb {
+    db: TormDb,
+    url: String,
+    _container: ContainerAsync<GenericImage>,
+}
+
+impl TestDb {
+    /// Start a fresh container and connect to it
+    pub async fn start() -> Result<Self> {
+        let (name, tag) = match std::env::var(IMAGE_ENV) {
+            Ok(image) => match image.split_once(':') {
+                Some((name, tag)) => (name.to_string(), tag.to_string()),
+                None => (image, "latest".to_string()),
+            },
+            Err(_) => (DEFAULT_IMAGE.0.to_string(), DEFAULT_IMAGE.1.to_string()),
+        };
+
+        let container = GenericImage::new(name, tag)
+            .with_exposed_port(STORE_PORT.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"))
+            .start()
+            .await
+            .map_err(|e| Error::Connection(format!("failed to start test container: {}", e)))?;
+
+        let host = container
+            .get_host()
+            .await
+            .map_err(|e| Error::Connection(e.to_string()))?;
+        let port = container
+            .get_host_port_ipv4(STORE_PORT)
+            .await
+            .map_err(|e| Error::Connection(e.to_string()))?;
+
+        let url = format!("redis://{}:{}", host, port);
+        let db = TormDb::connect(&url).await?;
+
+        Ok(Self {
+            db,
+            url,
+            _container: container,
+        })
+    }
+
+    /// Get the connected database handle
+    pub fn db(&self) -> &TormDb {
+        &self.db
+    }
+
+    /// Get the connection URL of the container
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Deref for TestDb {
+    type Target = TormDb;
+
+    fn deref(&self) -> &TormDb {
+        &self.db
+    }
+}
@@ -0,0 +1,1478 @@
+use serde::{Deserialize, Serialize};
+use torm::{
+    BulkWriter, Filter, HashStore, JsonStore, MergeStrategy, Model, PageRequest, Query,
+    RetryPolicy, TormDb,
+};
+use torm_test::TestDb;
+
+#[derive(Model, Serialize, Deserialize, Debug, PartialEq)]
+struct User {
+    #[id]
+    id: String,
+    name: String,
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_connect() {
+    let test_db = TestDb::start().await.expect("start test container");
+
+    let result = TormDb::connect(test_db.url()).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_connect_options() {
+    let test_db = TestDb::start().await.expect("start test container");
+
+    let db = TormDb::builder()
+        .url(test_db.url())
+        .connect_timeout(std::time::Duration::from_secs(5))
+        .command_timeout(std::time::Duration::from_secs(5))
+        .database(2)
+        .client_name("torm-test")
+        .build()
+        .await
+        .unwrap();
+    User {
+        id: "1".into(),
+        name: "John".into(),
+    }
+    .save(&db)
+    .await
+    .unwrap();
+    assert!(!User::exists(&test_db, "1").await.unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_connection_events() {
+    let test_db = TestDb::start().await.expect("start test container");
+
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let db = TormDb::builder()
+        .url(test_db.url())
+        .on_connection_event(move |event| seen.lock().unwrap().push(event.clone()))
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(*events.lock().unwrap(), [torm::ConnectionEvent::Connected]);
+    assert_eq!(db.connection_state(), torm::ConnectionState::Connected);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_health() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let health = db.health().await.unwrap();
+    assert!(health.server_version.is_some());
+    assert!(health.used_memory.is_some_and(|bytes| bytes > 0));
+    assert!(health.connected_clients.is_some_and(|clients| clients >= 1));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_close() {
+    let test_db = TestDb::start().await.expect("start test container");
+
+    let db = TormDb::connect(test_db.url()).await.unwrap();
+    db.close().await;
+    assert_eq!(db.connection_state(), torm::ConnectionState::Closed);
+    assert!(db.health().await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_connection_pool() {
+    let test_db = TestDb::start().await.expect("start test container");
+
+    let db = TormDb::builder()
+        .url(test_db.url())
+        .pool_size(2)
+        .checkout_timeout(std::time::Duration::from_secs(5))
+        .build()
+        .await
+        .unwrap();
+    let saves = (0..10).map(|i| {
+        let db = db.clone();
+        async move {
+            User {
+                id: i.to_string(),
+                name: format!("User {}", i),
+            }
+            .save(&db)
+            .await
+        }
+    });
+    for result in futures::future::join_all(saves).await {
+        result.unwrap();
+    }
+    assert_eq!(User::query().count(&db).await.unwrap(), 10);
+    assert_eq!(User::find_by_id(&db, "7").await.unwrap().name, "User 7");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_retry_policy() {
+    let test_db = TestDb::start().await.expect("start test container");
+
+    let db = test_db.with_retry(
+        RetryPolicy::new()
+            .max_attempts(4)
+            .initial_backoff(std::time::Duration::from_millis(1))
+            .idempotent_only(true),
+    );
+    User {
+        id: "1".into(),
+        name: "John".into(),
+    }
+    .save(&db)
+    .await
+    .unwrap();
+    assert_eq!(User::find_by_id(&db, "1").await.unwrap().name, "John");
+    assert_eq!(User::query().count(&db).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_key_prefix() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let staging = db.with_prefix("staging");
+    assert_eq!(staging.key("user:1"), "staging:user:1");
+    assert_eq!(staging.with_prefix("eu").prefix(), Some("staging:eu"));
+
+    User {
+        id: "1".into(),
+        name: "Staging".into(),
+    }
+    .save(&staging)
+    .await
+    .unwrap();
+    User {
+        id: "1".into(),
+        name: "Production".into(),
+    }
+    .save(&db)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        User::find_by_id(&staging, "1").await.unwrap().name,
+        "Staging"
+    );
+    assert_eq!(User::find_by_id(&db, "1").await.unwrap().name, "Production");
+    assert_eq!(User::count(&staging).await.unwrap(), 1);
+    assert_eq!(User::query().count(&staging).await.unwrap(), 1);
+
+    assert_eq!(User::truncate(&staging).await.unwrap(), 1);
+    assert!(User::exists(&db, "1").await.unwrap());
+
+    let () = staging
+        .raw("SET")
+        .key("flag")
+        .arg("on")
+        .query()
+        .await
+        .unwrap();
+    let flag: Option<String> = db.raw("GET").key("staging:flag").query().await.unwrap();
+    assert_eq!(flag.as_deref(), Some("on"));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_tenant_handles() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let tenants = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = tenants.clone();
+    db.on_event(move |event| seen.lock().unwrap().push(event.tenant.clone()));
+
+    for tenant in ["acme", "globex"] {
+        User {
+            id: "1".into(),
+            name: tenant.into(),
+        }
+        .save(&db.tenant(tenant))
+        .await
+        .unwrap();
+    }
+
+    let acme = db.tenant("acme");
+    assert_eq!(acme.tenant_id(), Some("acme"));
+    assert_eq!(User::find_by_id(&acme, "1").await.unwrap().name, "acme");
+    assert_eq!(User::find_all(&acme).await.unwrap().len(), 1);
+    assert!(!User::exists(&db, "1").await.unwrap());
+    assert_eq!(
+        *tenants.lock().unwrap(),
+        vec![Some("acme".to_string()), Some("globex".to_string())]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_hash_store() {
+    let db = TestDb::start().await.expect("start test container");
+
+    #[derive(Model, Serialize, Deserialize, Debug, PartialEq)]
+    struct Post {
+        #[id]
+        id: String,
+        title: String,
+        views: i64,
+    }
+
+    let posts = HashStore::<Post>::new(&db);
+    let post = Post {
+        id: "1".into(),
+        title: "Hello".into(),
+        views: 0,
+    };
+    posts.save(&post).await.unwrap();
+    assert_eq!(posts.find("1").await.unwrap(), post);
+
+    assert_eq!(posts.incr("1", "views", 5).await.unwrap(), 5);
+    assert_eq!(posts.get::<i64>("1", "views").await.unwrap(), Some(5));
+    let projected = posts.project("1", &["title", "missing"]).await.unwrap();
+    assert_eq!(
+        serde_json::Value::Object(projected),
+        serde_json::json!({ "title": "Hello" })
+    );
+    assert!(posts.incr("2", "views", 1).await.is_err());
+    assert_eq!(Post::count(&db).await.unwrap(), 1);
+
+    assert!(posts.delete("1").await.unwrap());
+    assert!(!posts.delete("1").await.unwrap());
+    assert!(posts.find("1").await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_json_store() {
+    let db = TestDb::start().await.expect("start test container");
+
+    #[derive(Model, Serialize, Deserialize, Debug, PartialEq)]
+    struct Post {
+        #[id]
+        id: String,
+        title: String,
+        views: i64,
+    }
+
+    // Runs on RedisJSON or on the fallback, whichever the server offers
+    let posts = JsonStore::<Post>::new(&db);
+    let post = Post {
+        id: "1".into(),
+        title: "Hello".into(),
+        views: 0,
+    };
+    posts.save(&post).await.unwrap();
+    assert_eq!(posts.find("1").await.unwrap(), post);
+
+    assert_eq!(posts.incr("1", "views", 5).await.unwrap(), 5);
+    assert!(posts.incr("1", "title", 1).await.is_err());
+    assert!(posts.incr("2", "views", 1).await.is_err());
+    let projected = posts.project("1", &["title", "missing"]).await.unwrap();
+    assert_eq!(
+        serde_json::Value::Object(projected),
+        serde_json::json!({ "title": "Hello" })
+    );
+
+    assert!(posts.delete("1").await.unwrap());
+    assert!(!posts.delete("1").await.unwrap());
+    assert!(posts.find("1").await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_transaction() {
+    let db = TestDb::start().await.expect("start test container");
+
+    #[derive(Model, Serialize, Deserialize, Debug, PartialEq)]
+    struct Product {
+        #[id]
+        id: String,
+        stock: u32,
+    }
+
+    Product {
+        id: "widget".into(),
+        stock: 1,
+    }
+    .save(&db)
+    .await
+    .unwrap();
+
+    let buy = |order: &'static str| {
+        db.transaction(move |tx| async move {
+            let mut product: Product = tx.get("widget").await?.unwrap();
+            product.stock = product
+                .stock
+                .checked_sub(1)
+                .ok_or_else(|| torm::Error::Other("out of stock".into()))?;
+            tx.save(&product)?;
+            tx.save(&User {
+                id: order.into(),
+                name: "order".into(),
+            })?;
+            Ok(product.stock)
+        })
+    };
+
+    assert_eq!(buy("o-1").await.unwrap(), 0);
+    assert!(buy("o-2").await.is_err());
+    assert_eq!(Product::find_by_id(&db, "widget").await.unwrap().stock, 0);
+    assert!(User::exists(&db, "o-1").await.unwrap());
+    assert!(!User::exists(&db, "o-2").await.unwrap());
+    assert_eq!(User::count(&db).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_pipeline() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let old = User {
+        id: "old".into(),
+        name: "Old".into(),
+    };
+    old.save(&db).await.unwrap();
+
+    let mut pipe = db.pipeline();
+    for i in 0..3 {
+        User {
+            id: i.to_string(),
+            name: format!("User {}", i),
+        }
+        .save_in(&mut pipe)
+        .unwrap();
+    }
+    old.delete_in(&mut pipe);
+    assert_eq!(pipe.len(), 4);
+    assert_eq!(pipe.exec().await.unwrap(), 4);
+
+    assert_eq!(User::find_by_id(&db, "2").await.unwrap().name, "User 2");
+    assert!(!User::exists(&db, "old").await.unwrap());
+    assert_eq!(User::count(&db).await.unwrap(), 3);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_lock() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let ttl = std::time::Duration::from_secs(5);
+    let guard = db.lock("report", ttl).await.unwrap();
+    assert!(db.try_lock("report", ttl).await.unwrap().is_none());
+    assert!(guard.extend(ttl).await.unwrap());
+    assert!(guard.release().await.unwrap());
+
+    let guard = db.try_lock("report", ttl).await.unwrap().unwrap();
+    let token: String = db
+        .raw("GET")
+        .key(&torm::lock_key("report"))
+        .query()
+        .await
+        .unwrap();
+    assert_eq!(token, guard.token());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_watch() {
+    use futures::StreamExt;
+    use torm::ChangeEvent;
+
+    let db = TestDb::start().await.expect("start test container");
+
+    async fn next(changes: &mut torm::ChangeStream<User>) -> ChangeEvent<User> {
+        tokio::time::timeout(std::time::Duration::from_secs(5), changes.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+    }
+
+    let mut changes = User::watch(&db).await.unwrap();
+    let users = HashStore::<User>::new(&db);
+    let user = User {
+        id: "1".into(),
+        name: "John".into(),
+    };
+
+    users.save(&user).await.unwrap();
+    assert!(
+        matches!(next(&mut changes).await, ChangeEvent::Saved { id, document: Some(_) } if id == "1")
+    );
+    // Saving again replaces the hash, which isn't reported as a delete
+    users.save(&user).await.unwrap();
+    assert!(
+        matches!(next(&mut changes).await, ChangeEvent::Saved { id, document: Some(_) } if id == "1")
+    );
+    users.delete("1").await.unwrap();
+    assert!(matches!(next(&mut changes).await, ChangeEvent::Deleted { id } if id == "1"));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_cdc() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let changes = db.cdc::<User>("indexer").await.unwrap();
+    let user = User {
+        id: "1".into(),
+        name: "John".into(),
+    };
+    // Only handles with change capture record anything
+    user.save(&db).await.unwrap();
+    user.save(&db.with_cdc()).await.unwrap();
+    user.delete(&db.with_cdc()).await.unwrap();
+
+    let records = changes
+        .read(10, std::time::Duration::from_millis(100))
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].op, torm::ModelOp::Save);
+    assert_eq!(records[0].document.as_ref(), Some(&user));
+    assert_eq!(records[1].op, torm::ModelOp::Delete);
+    assert_eq!(records[1].id, "1");
+    assert_eq!(changes.pending().await.unwrap(), 2);
+
+    // Unacknowledged records can be claimed by another consumer
+    assert!(changes.ack(&records[0].entry_id).await.unwrap());
+    let other = db.cdc::<User>("indexer").await.unwrap().consumer("other");
+    let claimed = other.claim(std::time::Duration::ZERO, 10).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].entry_id, records[1].entry_id);
+    assert!(other.ack(&claimed[0].entry_id).await.unwrap());
+    assert_eq!(changes.pending().await.unwrap(), 0);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_save_find_delete() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let user = User {
+        id: "1".into(),
+        name: "John".into(),
+    };
+    user.save(&db).await.unwrap();
+
+    let found = User::find_by_id(&db, "1").await.unwrap();
+    assert_eq!(found, user);
+    assert_eq!(User::count(&db).await.unwrap(), 1);
+
+    user.delete(&db).await.unwrap();
+    assert!(!User::exists(&db, "1").await.unwrap());
+    assert_eq!(User::count(&db).await.unwrap(), 0);
+    assert_eq!(User::recount(&db).await.unwrap(), 0);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_counter_rebuild() {
+    let db = TestDb::start().await.expect("start test container");
+
+    // A document written before the collection had a counter
+    let () = db
+        .raw("SET")
+        .key("user:0")
+        .arg(
+            serde_json::to_string(&User {
+                id: "0".into(),
+                name: "Legacy".into(),
+            })
+            .unwrap(),
+        )
+        .query()
+        .await
+        .unwrap();
+    User {
+        id: "1".into(),
+        name: "John".into(),
+    }
+    .save(&db)
+    .await
+    .unwrap();
+    assert_eq!(User::count(&db).await.unwrap(), 2);
+
+    let () = db
+        .raw("DEL")
+        .key(&torm::counter_key("user"))
+        .query()
+        .await
+        .unwrap();
+    User::find_by_id(&db, "1")
+        .await
+        .unwrap()
+        .delete(&db)
+        .await
+        .unwrap();
+    assert_eq!(User::count(&db).await.unwrap(), 1);
+}
+
+#[derive(Serialize, Deserialize)]
+struct Post {
+    id: String,
+    deleted: bool,
+}
+
+impl Model for Post {
+    fn collection() -> &'static str {
+        "post"
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+
+    fn default_scope(query: torm::QueryBuilder<Self>) -> torm::QueryBuilder<Self> {
+        query.filter("deleted", Query::ne(true))
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_count_scoped() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, deleted) in [("1", false), ("2", true), ("3", false)] {
+        Post {
+            id: id.into(),
+            deleted,
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(Post::count(&db).await.unwrap(), 2);
+    assert_eq!(Post::unscoped().count(&db).await.unwrap(), 3);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_page_tokens() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for id in ["1", "2", "3"] {
+        User {
+            id: id.into(),
+            name: format!("User {}", id),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let first = User::query()
+        .page(&db, PageRequest::first(2))
+        .await
+        .unwrap();
+    assert_eq!(first.total, 3);
+    assert_eq!(first.items.len(), 2);
+
+    let token = first.next_token.unwrap();
+    let second = User::query()
+        .page(&db, PageRequest::after(2, token))
+        .await
+        .unwrap();
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.items[0].id, "3");
+    assert!(second.next_token.is_none());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_exec_one() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Ann"), ("2", "Bob")] {
+        User {
+            id: id.into(),
+            name: name.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let bob = User::query()
+        .filter("name", Query::eq("Bob"))
+        .exec_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(bob.unwrap().id, "2");
+
+    let last = User::query()
+        .sort_by("name", torm::SortOrder::Desc)
+        .exec_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(last.unwrap().name, "Bob");
+
+    let none = User::query()
+        .filter("name", Query::eq("Eve"))
+        .exec_one(&db)
+        .await
+        .unwrap();
+    assert!(none.is_none());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_fetch_batch() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for i in 0..10 {
+        User {
+            id: i.to_string(),
+            name: if i % 2 == 0 { "Ann" } else { "Bob" }.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let anns = User::query()
+        .filter("name", Query::eq("Ann"))
+        .fetch_batch(3)
+        .exec(&db)
+        .await
+        .unwrap();
+    assert_eq!(anns.len(), 5);
+
+    let ids = |users: Vec<User>| users.into_iter().map(|u| u.id).collect::<Vec<_>>();
+    let serial = User::query()
+        .scan_count(10)
+        .fetch_batch(2)
+        .fetch_concurrency(1)
+        .exec(&db)
+        .await
+        .unwrap();
+    let parallel = User::query()
+        .scan_count(10)
+        .fetch_batch(2)
+        .fetch_concurrency(8)
+        .exec(&db)
+        .await
+        .unwrap();
+    assert_eq!(ids(parallel), ids(serial));
+
+    let limited = User::query()
+        .limit(2)
+        .fetch_batch(1)
+        .fetch_concurrency(1)
+        .explain(&db)
+        .await
+        .unwrap();
+    assert_eq!(limited.matched, 2);
+    assert!(limited.fetched < 10);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_delete_where() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Ann"), ("2", "Bob"), ("3", "Anton")] {
+        User {
+            id: id.into(),
+            name: name.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let removed = User::query()
+        .filter("name", Query::starts_with("An"))
+        .delete(&db)
+        .await
+        .unwrap();
+    assert_eq!(removed, 2);
+    assert_eq!(User::count(&db).await.unwrap(), 1);
+    assert_eq!(User::find_all(&db).await.unwrap()[0].id, "2");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_update_where() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Ann"), ("2", "Bob"), ("3", "Anton")] {
+        User {
+            id: id.into(),
+            name: name.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let changed = User::query()
+        .filter("name", Query::starts_with("An"))
+        .update_with(&db, |user| user.name = user.name.to_uppercase())
+        .await
+        .unwrap();
+    assert_eq!(changed, 2);
+    assert_eq!(User::find_by_id(&db, "3").await.unwrap().name, "ANTON");
+
+    let changed = User::query()
+        .filter("name", Query::eq("Bob"))
+        .update(&db, serde_json::json!({ "name": "Robert" }))
+        .await
+        .unwrap();
+    assert_eq!(changed, 1);
+    assert_eq!(User::find_by_id(&db, "2").await.unwrap().name, "Robert");
+
+    let unchanged = User::query().update_with(&db, |_| {}).await.unwrap();
+    assert_eq!(unchanged, 0);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_select() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Ann"), ("2", "Bob")] {
+        User {
+            id: id.into(),
+            name: name.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    #[derive(serde::Deserialize)]
+    struct IdOnly {
+        id: String,
+    }
+
+    for query in [User::query(), User::query().pushdown()] {
+        let rows: Vec<IdOnly> = query
+            .filter("name", Query::eq("Bob"))
+            .select(&["id"])
+            .exec(&db)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, "2");
+    }
+
+    let rows: Vec<serde_json::Value> = User::query()
+        .sort_by("name", torm::SortOrder::Desc)
+        .select(&["name"])
+        .exec(&db)
+        .await
+        .unwrap();
+    assert_eq!(rows[0], serde_json::json!({ "name": "Bob" }));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_distinct() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Ann"), ("2", "Bob"), ("3", "Ann")] {
+        User {
+            id: id.into(),
+            name: name.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let names = User::query().distinct("name", &db).await.unwrap();
+    assert_eq!(names, [serde_json::json!("Ann"), serde_json::json!("Bob")]);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_exec_stream() {
+    use futures::TryStreamExt;
+
+    let db = TestDb::start().await.expect("start test container");
+
+    for i in 0..250 {
+        User {
+            id: i.to_string(),
+            name: format!("User {}", i),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let all: Vec<User> = User::query().exec_stream(&db).try_collect().await.unwrap();
+    assert_eq!(all.len(), 250);
+
+    let window: Vec<User> = User::query()
+        .skip(100)
+        .limit(120)
+        .exec_stream(&db)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(window.len(), 120);
+
+    let sorted: Vec<User> = User::query()
+        .sort_by("name", torm::SortOrder::Asc)
+        .limit(2)
+        .exec_stream(&db)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(sorted[0].name, "User 0");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_keyset_pages() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for id in ["a", "b", "c", "d", "e"] {
+        User {
+            id: id.into(),
+            name: id.to_uppercase(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let first = User::query().keyset_page(&db, 2).await.unwrap();
+    assert_eq!(first.items[0].id, "a");
+    let token = first.next.unwrap().token();
+
+    let second = User::query()
+        .after(torm::Cursor::from_token(&token).unwrap())
+        .keyset_page(&db, 2)
+        .await
+        .unwrap();
+    assert_eq!(second.items[0].id, "c");
+
+    let rest = User::query().after("c").exec(&db).await.unwrap();
+    assert_eq!(rest.len(), 2);
+    assert_eq!(rest[1].id, "e");
+
+    let sorted = User::query()
+        .after("c")
+        .sort_by("name", torm::SortOrder::Asc)
+        .exec(&db)
+        .await;
+    assert!(sorted.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_pushdown() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Ann"), ("2", "Bob"), ("3", "Anton")] {
+        User {
+            id: id.into(),
+            name: name.into(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+
+    let query = User::query()
+        .filter("name", Query::contains("An"))
+        .filter("id", Query::not_in(vec!["3".into()]));
+    let local = query.exec(&db).await.unwrap();
+    let remote = query.pushdown().exec(&db).await.unwrap();
+    assert_eq!(remote, local);
+    assert_eq!(remote.len(), 1);
+    assert_eq!(remote[0].id, "1");
+
+    let grouped = User::query()
+        .or(|g| g.filter("id", Query::eq("2")).filter("id", Query::eq("3")))
+        .not(|g| g.filter("name", Query::eq("Bob")));
+    let local = grouped.exec(&db).await.unwrap();
+    assert_eq!(grouped.pushdown().exec(&db).await.unwrap(), local);
+    assert_eq!(local.len(), 1);
+    assert_eq!(local[0].id, "3");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_bulk_writer() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let users = ["1", "2", "2", "3"].map(|id| User {
+        id: id.into(),
+        name: format!("User {}", id),
+    });
+    let report = BulkWriter::<User>::new(&db)
+        .chunk_size(2)
+        .write_iter(users)
+        .await
+        .unwrap();
+
+    assert_eq!(report.succeeded, 3);
+    assert_eq!(report.duplicates, 1);
+    assert_eq!(report.failed, 0);
+    assert_eq!(User::count(&db).await.unwrap(), 3);
+}
+
+#[derive(Model, Serialize, Deserialize, Debug, Clone)]
+struct Template {
+    #[id]
+    id: String,
+    name: String,
+    #[created_at]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[updated_at]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_duplicate() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let old = chrono::DateTime::UNIX_EPOCH;
+    let template = Template {
+        id: "welcome".into(),
+        name: "Welcome".into(),
+        created_at: old,
+        updated_at: old,
+    };
+    template.save(&db).await.unwrap();
+
+    let copy = template.duplicate(&db, None).await.unwrap();
+    assert_ne!(copy.id, template.id);
+    assert_eq!(copy.name, template.name);
+    assert!(copy.created_at > old);
+
+    let named = template.duplicate(&db, Some("welcome-2")).await.unwrap();
+    assert_eq!(named.id, "welcome-2");
+    assert_eq!(Template::count(&db).await.unwrap(), 3);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_touch() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let old = chrono::DateTime::UNIX_EPOCH;
+    let mut template = Template {
+        id: "touched".into(),
+        name: "Touched".into(),
+        created_at: old,
+        updated_at: old,
+    };
+    template.save(&db).await.unwrap();
+    template.touch(&db).await.unwrap();
+    assert!(template.updated_at > old);
+
+    let stored = Template::find_by_id(&db, "touched").await.unwrap();
+    assert_eq!(stored.updated_at, template.updated_at);
+    assert_eq!(stored.created_at, old);
+
+    template.delete(&db).await.unwrap();
+    assert!(template.touch(&db).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_dump_and_load() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for i in 0..3 {
+        let user = User {
+            id: format!("dump-{}", i),
+            name: format!("user {}", i),
+        };
+        user.save(&db).await.unwrap();
+    }
+
+    let mut buffer = Vec::new();
+    assert_eq!(User::dump(&db, &mut buffer).await.unwrap(), 3);
+    assert_eq!(buffer.iter().filter(|b| **b == b'\n').count(), 3);
+
+    User::truncate(&db).await.unwrap();
+    assert_eq!(User::load(&db, buffer.as_slice()).await.unwrap(), 3);
+    assert_eq!(User::count(&db).await.unwrap(), 3);
+    assert!(User::load(&db, &b"not json\n"[..]).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_rename_id() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for id in ["old", "taken"] {
+        let user = User {
+            id: id.into(),
+            name: id.into(),
+        };
+        user.save(&db).await.unwrap();
+    }
+
+    let renamed = User::rename_id(&db, "old", "new").await.unwrap();
+    assert_eq!(renamed.id, "new");
+    assert!(!User::exists(&db, "old").await.unwrap());
+    assert_eq!(User::find_by_id(&db, "new").await.unwrap().id, "new");
+    assert_eq!(User::count(&db).await.unwrap(), 2);
+
+    assert!(matches!(
+        User::rename_id(&db, "new", "taken").await,
+        Err(torm::Error::Conflict(_))
+    ));
+    assert!(matches!(
+        User::rename_id(&db, "missing", "other").await,
+        Err(torm::Error::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_truncate() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for i in 0..250 {
+        let user = User {
+            id: i.to_string(),
+            name: format!("user {}", i),
+        };
+        user.save(&db).await.unwrap();
+    }
+
+    let sample = User::sample(&db, 10).await.unwrap();
+    assert_eq!(sample.len(), 10);
+    assert_eq!(User::sample(&db, 1000).await.unwrap().len(), 250);
+
+    assert_eq!(User::truncate(&db).await.unwrap(), 250);
+    assert_eq!(User::count(&db).await.unwrap(), 0);
+}
+
+#[derive(Model, Serialize, Deserialize, Debug)]
+struct Person {
+    #[id]
+    id: String,
+    first: String,
+    last: String,
+    #[serde(skip)]
+    #[skip(compute = "Self::compute_full_name")]
+    full_name: String,
+}
+
+impl Person {
+    fn compute_full_name(&self) -> String {
+        format!("{} {}", self.first, self.last)
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_computed_fields() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let person = Person {
+        id: "ada".into(),
+        first: "Ada".into(),
+        last: "Lovelace".into(),
+        full_name: String::new(),
+    };
+    person.save(&db).await.unwrap();
+
+    let found = Person::find_by_id(&db, "ada").await.unwrap();
+    assert_eq!(found.full_name, "Ada Lovelace");
+
+    let all = Person::find_all(&db).await.unwrap();
+    assert_eq!(all[0].full_name, "Ada Lovelace");
+}
+
+#[derive(Model, Serialize, Deserialize, Debug)]
+struct Employee {
+    #[id]
+    id: String,
+    #[index]
+    team: String,
+    #[index]
+    salary: u64,
+}
+
+#[test]
+fn test_index_fields() {
+    assert_eq!(Employee::indexes(), ["team", "salary"]);
+    assert!(User::indexes().is_empty());
+}
+
+#[test]
+fn test_query_macro() {
+    let min = 100;
+    let query = torm::query!(Employee, team == "web" && (salary >= min || !(salary < 50)));
+
+    assert_eq!(
+        query.to_spec().filters,
+        [
+            Filter::field("team", Query::eq("web")),
+            Filter::Or(vec![
+                Filter::field("salary", Query::gte(100)),
+                Filter::Not(Box::new(Filter::field("salary", Query::lt(50)))),
+            ]),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_indexed_queries() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, team, salary) in [("1", "web", 100), ("2", "web", 150), ("3", "ops", 120)] {
+        Employee {
+            id: id.into(),
+            team: team.into(),
+            salary,
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+    assert_eq!(Employee::build_indexes(&db).await.unwrap(), 3);
+
+    let web = Employee::query()
+        .filter("team", Query::eq("web"))
+        .filter("salary", Query::gt(120))
+        .exec(&db)
+        .await
+        .unwrap();
+    assert_eq!(web.len(), 1);
+    assert_eq!(web[0].id, "2");
+
+    let explain = Employee::query()
+        .filter("team", Query::eq("web"))
+        .explain(&db)
+        .await
+        .unwrap();
+    assert_eq!(explain.source, "index");
+    assert_eq!(explain.indexes, ["team"]);
+    assert_eq!((explain.scanned, explain.matched), (2, 2));
+
+    let estimate = Employee::query()
+        .filter("team", Query::eq("web"))
+        .filter("salary", Query::gt(120))
+        .count_estimate(&db)
+        .await
+        .unwrap();
+    assert_eq!(estimate, 1);
+    let teams = Employee::query()
+        .distinct_count_estimate("team", &db)
+        .await
+        .unwrap();
+    assert_eq!(teams, 2);
+
+    let senior = Employee::query().filter("salary", Query::gte(120));
+    assert_eq!(senior.count(&db).await.unwrap(), 2);
+    assert_eq!(senior.sum("salary", &db).await.unwrap(), 270.0);
+    assert_eq!(
+        Employee::query().max("salary", &db).await.unwrap(),
+        Some(serde_json::json!(150))
+    );
+
+    let mut moved = Employee::find_by_id(&db, "2").await.unwrap();
+    moved.team = "ops".into();
+    moved.save(&db).await.unwrap();
+    Employee::find_by_id(&db, "3")
+        .await
+        .unwrap()
+        .delete(&db)
+        .await
+        .unwrap();
+
+    let ops = Employee::query()
+        .filter("team", Query::in_values(vec!["ops".into()]))
+        .exec(&db)
+        .await
+        .unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].id, "2");
+    assert_eq!(
+        Employee::query()
+            .filter("team", Query::eq("web"))
+            .count(&db)
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+#[derive(Model, Serialize, Deserialize, Debug)]
+struct Invoice {
+    #[id(auto = "sequence")]
+    id: String,
+    total: u64,
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_populate_has_many() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, team) in [("1", "web"), ("2", "web"), ("3", "ops")] {
+        Employee {
+            id: id.into(),
+            team: team.into(),
+            salary: 100,
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+    torm::Relation::has_many("members", "employee", "team")
+        .register(&db, "team")
+        .await
+        .unwrap();
+
+    // Found by a filtered scan, then through the built index
+    for build in [false, true] {
+        if build {
+            Employee::build_indexes(&db).await.unwrap();
+        }
+        let mut teams = [
+            serde_json::json!({ "id": "web" }),
+            serde_json::json!({ "id": "ops" }),
+            serde_json::json!({ "id": "qa" }),
+        ];
+        torm::populate(&db, "team", &mut teams, &["members"])
+            .await
+            .unwrap();
+        let members: Vec<usize> = teams
+            .iter()
+            .map(|team| team["members"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(members, [2, 1, 0]);
+    }
+
+    let unknown = torm::populate(&db, "team", &mut [serde_json::json!({})], &["owner"]).await;
+    assert!(matches!(unknown, Err(torm::Error::InvalidQuery(_))));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_sequence_ids() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let mut first = Invoice {
+        id: String::new(),
+        total: 10,
+    };
+    first.create(&db).await.unwrap();
+    let mut second = Invoice {
+        id: String::new(),
+        total: 20,
+    };
+    second.create(&db).await.unwrap();
+
+    assert_eq!(first.id, "1");
+    assert_eq!(second.id, "2");
+    assert_eq!(first.duplicate(&db, None).await.unwrap().id, "3");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_aggregates() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for total in [10, 20, 45] {
+        let mut invoice = Invoice {
+            id: String::new(),
+            total,
+        };
+        invoice.create(&db).await.unwrap();
+    }
+
+    let query = Invoice::query();
+    assert_eq!(query.sum("total", &db).await.unwrap(), 75.0);
+    assert_eq!(query.avg("total", &db).await.unwrap(), Some(25.0));
+    assert_eq!(
+        query.min("total", &db).await.unwrap(),
+        Some(serde_json::json!(10))
+    );
+    assert_eq!(
+        query.max("total", &db).await.unwrap(),
+        Some(serde_json::json!(45))
+    );
+
+    let groups = Invoice::query()
+        .group_by("total")
+        .aggregate(torm::Agg::Count, &db)
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 3);
+    assert_eq!(groups["45"], serde_json::json!(1));
+
+    let empty = Invoice::query().filter("total", Query::gt(100));
+    assert_eq!(empty.avg("total", &db).await.unwrap(), None);
+    assert_eq!(empty.max("total", &db).await.unwrap(), None);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_schemaless_query() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for (id, name) in [("1", "Zoe"), ("2", "Ann"), ("3", "Bob")] {
+        let user = User {
+            id: id.into(),
+            name: name.into(),
+        };
+        user.save(&db).await.unwrap();
+    }
+
+    let rows = db
+        .collection(User::collection())
+        .query()
+        .filter("name", Query::ne("Bob"))
+        .sort_by("name", torm::SortOrder::Asc)
+        .exec(&db)
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({ "id": "2", "name": "Ann" }),
+            serde_json::json!({ "id": "1", "name": "Zoe" }),
+        ]
+    );
+}
+
+#[derive(Model, Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Note {
+    #[id]
+    id: String,
+    title: String,
+    body: String,
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_merge_save() {
+    let db = TestDb::start().await.expect("start test container");
+
+    let base = Note {
+        id: "1".into(),
+        title: "Draft".into(),
+        body: "a".into(),
+    };
+    base.save(&db).await.unwrap();
+
+    let theirs = Note {
+        body: "b".into(),
+        ..base.clone()
+    };
+    theirs.save(&db).await.unwrap();
+
+    let mut mine = Note {
+        title: "Final".into(),
+        ..base.clone()
+    };
+    mine.merge_save(&db, &base, MergeStrategy::FieldLevel)
+        .await
+        .unwrap();
+    assert_eq!(mine.title, "Final");
+    assert_eq!(mine.body, "b");
+    assert_eq!(Note::find_by_id(&db, "1").await.unwrap(), mine);
+
+    let mut edit = Note {
+        body: "c".into(),
+        ..base.clone()
+    };
+    edit.merge_save(
+        &db,
+        &base,
+        MergeStrategy::custom(|mine: Note, theirs: Note| Note {
+            body: format!("{}{}", theirs.body, mine.body),
+            ..theirs
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(edit.title, "Final");
+    assert_eq!(edit.body, "bc");
+}
+
+#[derive(Model, Serialize, Deserialize, Debug)]
+#[model(collection = "ordered_task", ordered)]
+struct Task {
+    #[id]
+    id: String,
+    title: String,
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_insertion_order() {
+    let db = TestDb::start().await.expect("start test container");
+
+    for id in ["c", "a", "b"] {
+        Task {
+            id: id.into(),
+            title: id.to_uppercase(),
+        }
+        .save(&db)
+        .await
+        .unwrap();
+    }
+    Task::find_by_id(&db, "a")
+        .await
+        .unwrap()
+        .delete(&db)
+        .await
+        .unwrap();
+    Task::rename_id(&db, "c", "d").await.unwrap();
+
+    let ids: Vec<String> = Task::find_all(&db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|t| t.id)
+        .collect();
+    assert_eq!(ids, ["d", "b"]);
+}
+
+#[derive(Model, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[model(collection = "account", dto = "AccountDto")]
+struct Account {
+    #[id]
+    id: String,
+    #[dto(rename = "display_name")]
+    name: String,
+    #[dto(skip)]
+    password_hash: String,
+}
+
+#[test]
+fn test_dto_mapping() {
+    let account = Account {
+        id: "1".into(),
+        name: "Ann".into(),
+        password_hash: "secret".into(),
+    };
+
+    let dto = AccountDto::from(account.clone());
+    assert_eq!(dto.display_name, "Ann");
+    let json = serde_json::to_value(&dto).unwrap();
+    assert!(json.get("password_hash").is_none());
+
+    let back: Account = dto.into();
+    assert_eq!(back.id, account.id);
+    assert_eq!(back.password_hash, "");
+}
@@ -18,6 +18,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Connect to ToonStore
     println!("Connecting to ToonStore...");
+    #[cfg(feature = "embedded")]
+    let db = TormDb::embedded().await?;
+    #[cfg(not(feature = "embedded"))]
     let db = TormDb::connect("redis://localhost:6379").await?;
     println!("✅ Connected!\n");
 
@@ -0,0 +1,128 @@
+//! Aggregates computed while scanning a query
+//!
+//! Used by the terminal aggregate methods of [`crate::QueryBuilder`] and by
+//! [`crate::GroupBy`], so that reports fold documents as they are scanned
+//! instead of collecting them first.
+
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// An aggregate over the documents of a query or group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Agg {
+    /// Number of documents
+    Count,
+    /// Sum of the numeric values of a field
+    Sum(String),
+    /// Average of the numeric values of a field, `null` if there are none
+    Avg(String),
+    /// Smallest value of a field, `null` if no document has it
+    Min(String),
+    /// Largest value of a field, `null` if no document has it
+    Max(String),
+}
+
+impl Agg {
+    /// Sum `field`
+    pub fn sum(field: impl Into<String>) -> Self {
+        Agg::Sum(field.into())
+    }
+
+    /// Average `field`
+    pub fn avg(field: impl Into<String>) -> Self {
+        Agg::Avg(field.into())
+    }
+
+    /// Smallest value of `field`
+    pub fn min(field: impl Into<String>) -> Self {
+        Agg::Min(field.into())
+    }
+
+    /// Largest value of `field`
+    pub fn max(field: impl Into<String>) -> Self {
+        Agg::Max(field.into())
+    }
+}
+
+/// Running state of one aggregate
+#[derive(Debug, Default)]
+pub(crate) struct Accumulator {
+    count: usize,
+    numbers: usize,
+    sum: f64,
+    best: Option<Value>,
+}
+
+impl Accumulator {
+    /// Fold one matching document into the aggregate
+    ///
+    /// Fields may be dot paths; every value they lead to counts.
+    pub(crate) fn add(&mut self, agg: &Agg, doc: &Value) {
+        self.count += 1;
+        let (field, wanted) = match agg {
+            Agg::Count => return,
+            Agg::Sum(field) | Agg::Avg(field) => {
+                for n in crate::path::resolve(doc, field)
+                    .into_iter()
+                    .filter_map(Value::as_f64)
+                {
+                    self.numbers += 1;
+                    self.sum += n;
+                }
+                return;
+            }
+            Agg::Min(field) => (field, Ordering::Less),
+            Agg::Max(field) => (field, Ordering::Greater),
+        };
+
+        for value in crate::path::resolve(doc, field) {
+            if value.is_null() {
+                continue;
+            }
+            let better = self.best.as_ref().is_none_or(|best| {
+                crate::query::compare_json_values(Some(value), Some(best)) == wanted
+            });
+            if better {
+                self.best = Some(value.clone());
+            }
+        }
+    }
+
+    /// The aggregate's value
+    pub(crate) fn finish(self, agg: &Agg) -> Value {
+        match agg {
+            Agg::Count => Value::from(self.count),
+            Agg::Sum(_) => Value::from(self.sum),
+            Agg::Avg(_) if self.numbers > 0 => Value::from(self.sum / self.numbers as f64),
+            Agg::Avg(_) => Value::Null,
+            Agg::Min(_) | Agg::Max(_) => self.best.unwrap_or(Value::Null),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_accumulator() {
+        let docs = [
+            json!({ "n": 4 }),
+            json!({ "n": "x" }),
+            json!({ "n": 1 }),
+            json!({}),
+        ];
+        let fold = |agg: Agg| {
+            let mut acc = Accumulator::default();
+            docs.iter().for_each(|doc| acc.add(&agg, doc));
+            acc.finish(&agg)
+        };
+
+        assert_eq!(fold(Agg::Count), json!(4));
+        assert_eq!(fold(Agg::sum("n")), json!(5.0));
+        assert_eq!(fold(Agg::avg("n")), json!(2.5));
+        assert_eq!(fold(Agg::min("n")), json!(1));
+        assert_eq!(fold(Agg::avg("missing")), Value::Null);
+    }
+}
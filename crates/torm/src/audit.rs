@@ -0,0 +1,87 @@
+//! Audit trail for model changes
+//!
+//! When an actor is attached to a [`TormDb`] handle via [`TormDb::with_actor`],
+//! every save and delete made through that handle appends an [`AuditEntry`]
+//! to an append-only list in ToonStore.
+
+use crate::{ModelEvent, ModelOp, Result, TormDb};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Key of the append-only audit list, under the handle's
+/// [prefix](TormDb::with_prefix) if it has one
+pub const AUDIT_KEY: &str = "torm:audit";
+
+/// A single audit trail record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Who made the change (e.g. "admin:42")
+    pub actor: String,
+    /// What kind of change was made
+    pub action: ModelOp,
+    /// Collection of the changed document
+    pub collection: String,
+    /// ID of the changed document
+    pub id: String,
+    /// Document contents after a save
+    pub data: Option<serde_json::Value>,
+    /// When the change was made
+    pub at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// Append this entry to the audit trail
+    ///
+    /// Model changes are recorded automatically; use this for other
+    /// auditable actions such as configuration changes.
+    pub async fn append(&self, db: &TormDb) -> Result<()> {
+        let mut conn = db.connection().clone();
+        redis::cmd("RPUSH")
+            .arg(db.key(AUDIT_KEY))
+            .arg(serde_json::to_string(self)?)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Read the most recent audit entries, newest first
+    pub async fn recent(db: &TormDb, limit: usize) -> Result<Vec<AuditEntry>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = db.connection().clone();
+        let raw: Vec<String> = redis::cmd("LRANGE")
+            .arg(db.key(AUDIT_KEY))
+            .arg(-(limit as i64))
+            .arg(-1)
+            .query_async(&mut conn)
+            .await?;
+
+        let mut entries = raw
+            .iter()
+            .map(|v| serde_json::from_str(v))
+            .collect::<std::result::Result<Vec<AuditEntry>, _>>()?;
+        entries.reverse();
+
+        Ok(entries)
+    }
+}
+
+/// Append an audit entry if the event carries an actor
+pub(crate) async fn record(db: &TormDb, event: &ModelEvent) -> Result<()> {
+    let Some(actor) = &event.actor else {
+        return Ok(());
+    };
+
+    AuditEntry {
+        actor: actor.clone(),
+        action: event.op,
+        collection: event.collection.clone(),
+        id: event.id.clone(),
+        data: event.payload.clone(),
+        at: Utc::now(),
+    }
+    .append(db)
+    .await
+}
@@ -0,0 +1,179 @@
+//! Pluggable storage behind [`TormDb`]
+//!
+//! TORM talks to its store in Redis commands. A [`Backend`] receives those
+//! commands in place of a ToonStore connection, so an embedded or durable
+//! store, or an in-memory mock for tests, can serve models and queries
+//! without changes to them. Create the handle with
+//! [`TormDb::from_backend`].
+//!
+//! Plain reads and writes only need `GET`, `SET` (with its `GET` and `XX`
+//! options), `MGET`, `GETDEL`, `EXISTS`, `DEL`, `UNLINK`, `SCAN` and the
+//! counter commands `INCR`, `INCRBY`, `DECR` and `DECRBY`. Indexes,
+//! ordered collections and the audit trail add set, sorted set and list
+//! commands. Server-side filtering, bulk writes, [`Model::merge_save`],
+//! [`Model::touch`] and [`Model::rename_id`] run Lua scripts, which a
+//! backend may reject with an error; turn filtering off with
+//! [`QueryBuilder::pushdown`](crate::QueryBuilder::pushdown).
+//!
+//! [`Model::merge_save`]: crate::Model::merge_save
+//! [`Model::touch`]: crate::Model::touch
+//! [`Model::rename_id`]: crate::Model::rename_id
+
+use crate::pool::Connection;
+use crate::TormDb;
+use async_trait::async_trait;
+use redis::{Cmd, Pipeline, RedisResult, Value};
+
+/// A store that executes TORM's Redis commands
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{Backend, TormDb};
+/// # use async_trait::async_trait;
+/// # use redis::{Cmd, RedisResult, Value};
+/// struct ReadOnly;
+///
+/// #[async_trait]
+/// impl Backend for ReadOnly {
+///     async fn command(&self, _cmd: &Cmd) -> RedisResult<Value> {
+///         Ok(Value::Nil)
+///     }
+/// }
+///
+/// let db = TormDb::from_backend(ReadOnly);
+/// ```
+#[async_trait]
+pub trait Backend: Send + Sync + 'static {
+    /// Execute one command and return its reply
+    ///
+    /// Replies use the shapes Redis would: `Nil` for a missing key, an
+    /// `Array` for `MGET` and `SCAN`, `Int` for counters and so on.
+    async fn command(&self, cmd: &Cmd) -> RedisResult<Value>;
+
+    /// Execute the commands of `pipeline` in order, one reply each
+    ///
+    /// `atomic` is set for `MULTI`/`EXEC` transactions, which should not
+    /// interleave with other writers. The default runs the commands one
+    /// by one with [`Backend::command`], stopping at the first error.
+    async fn pipeline(&self, pipeline: &Pipeline, atomic: bool) -> RedisResult<Vec<Value>> {
+        let _ = atomic;
+        let mut replies = Vec::new();
+        for cmd in pipeline.cmd_iter() {
+            replies.push(self.command(cmd).await?);
+        }
+        Ok(replies)
+    }
+
+    /// Database index reported to commands that ask for it
+    fn database(&self) -> i64 {
+        0
+    }
+}
+
+impl TormDb {
+    /// Create a handle on `backend` instead of a ToonStore connection
+    ///
+    /// Everything but change streams works through it; those need Redis
+    /// pub/sub, so [`TormDb::client`] is `None`.
+    pub fn from_backend(backend: impl Backend) -> Self {
+        TormDb::from_parts(Connection::backend(backend), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Memory(Mutex<HashMap<Vec<u8>, Vec<u8>>>);
+
+    #[async_trait]
+    impl Backend for Memory {
+        async fn command(&self, cmd: &Cmd) -> RedisResult<Value> {
+            let args: Vec<Vec<u8>> = cmd
+                .args_iter()
+                .map(|arg| match arg {
+                    redis::Arg::Simple(bytes) => bytes.to_vec(),
+                    redis::Arg::Cursor => Vec::new(),
+                })
+                .collect();
+            let mut data = self.0.lock().unwrap();
+            Ok(match args[0].as_slice() {
+                b"SET" => {
+                    data.insert(args[1].clone(), args[2].clone());
+                    Value::Okay
+                }
+                b"GET" => data
+                    .get(&args[1])
+                    .map_or(Value::Nil, |v| Value::BulkString(v.clone())),
+                _ => {
+                    return Err((redis::ErrorKind::ResponseError, "unknown command").into());
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backend() {
+        let db = TormDb::from_backend(Memory::default());
+        assert!(db.client().is_none());
+        let mut conn = db.connection().clone();
+
+        redis::cmd("SET")
+            .arg("user:1")
+            .arg("Ann")
+            .query_async::<()>(&mut conn)
+            .await
+            .unwrap();
+        let (a, b): (Option<String>, Option<String>) = redis::pipe()
+            .atomic()
+            .cmd("GET")
+            .arg("user:1")
+            .cmd("GET")
+            .arg("user:2")
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!((a.as_deref(), b), (Some("Ann"), None));
+
+        let unknown = redis::cmd("ZADD").query_async::<()>(&mut conn).await;
+        assert!(unknown.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replica_reads() {
+        let node = |name: &str| {
+            let memory = Memory::default();
+            memory
+                .0
+                .lock()
+                .unwrap()
+                .insert(b"node".to_vec(), name.as_bytes().to_vec());
+            memory
+        };
+        let db = TormDb::from_backend(node("primary")).with_replicas(vec![
+            Connection::backend(node("replica-1")),
+            Connection::backend(node("replica-2")),
+        ]);
+        assert_eq!(db.replica_count(), 2);
+
+        let mut read_from = Vec::new();
+        for _ in 0..3 {
+            let node: String = db.reader().raw("GET").key("node").query().await.unwrap();
+            read_from.push(node);
+        }
+        assert_eq!(read_from, ["replica-1", "replica-2", "replica-1"]);
+
+        let node: String = db
+            .primary()
+            .reader()
+            .raw("GET")
+            .key("node")
+            .query()
+            .await
+            .unwrap();
+        assert_eq!(node, "primary");
+    }
+}
@@ -0,0 +1,303 @@
+//! Chunked bulk writes with retries
+//!
+//! [`BulkWriter`] is the shared primitive for importers, seeders and data
+//! migrations: it writes documents in chunks of one server-side script
+//! each, retries failed chunks with exponential backoff and reports what
+//! happened to every document.
+
+use crate::{Model, Result, TormDb};
+use futures::{Stream, StreamExt};
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Write a chunk of documents and bump the counter by the number created
+///
+/// KEYS are the document keys followed by the counter key; ARGV holds the
+/// encoded documents in the same order. Returns the replaced documents,
+/// `nil` for new keys.
+const BULK_SET_SCRIPT: &str = concat!(
+    crate::counter::bump_lua!(),
+    r#"
+local created = 0
+local previous = {}
+for i = 1, #KEYS - 1 do
+    local old = redis.call('SET', KEYS[i], ARGV[i], 'GET')
+    if not old then
+        created = created + 1
+    end
+    previous[i] = old
+end
+if created > 0 then
+    bump(KEYS[#KEYS], ARGV[#ARGV], created)
+end
+return previous
+"#
+);
+
+/// A document that could not be written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkError {
+    /// ID of the document
+    pub id: String,
+    /// Why it failed
+    pub error: String,
+}
+
+/// Outcome of a [`BulkWriter`] run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BulkReport {
+    /// Documents written
+    pub succeeded: usize,
+    /// Documents that failed validation or exhausted their retries
+    pub failed: usize,
+    /// Documents whose chunk had to be retried at least once
+    pub retried: usize,
+    /// Documents skipped because their ID was already seen in this run
+    pub duplicates: usize,
+    /// Details for every failed document
+    pub errors: Vec<BulkError>,
+}
+
+/// Writes many models in retried chunks
+///
+/// Documents without an ID get one from [`Model::next_id`]. Within a run,
+/// only the first document with a given ID is written; later ones are
+/// counted as duplicates. Lifecycle events are published for every written
+/// document.
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{BulkWriter, Model, TormDb};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Model, Serialize, Deserialize)]
+/// # struct User { #[id] id: String, name: String }
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let users = (0..10_000).map(|i| User { id: i.to_string(), name: format!("user {}", i) });
+/// let report = BulkWriter::<User>::new(&db)
+///     .chunk_size(500)
+///     .max_retries(5)
+///     .write_iter(users)
+///     .await?;
+/// println!("{} written, {} failed", report.succeeded, report.failed);
+/// # Ok(())
+/// # }
+/// ```
+pub struct BulkWriter<M> {
+    db: TormDb,
+    chunk_size: usize,
+    max_retries: u32,
+    backoff: Duration,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M: Model> BulkWriter<M> {
+    /// Create a writer with chunks of 100, 3 retries and 100 ms initial backoff
+    pub fn new(db: &TormDb) -> Self {
+        Self {
+            db: db.clone(),
+            chunk_size: 100,
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+            _model: PhantomData,
+        }
+    }
+
+    /// Set how many documents are written per chunk
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Set how often a failed chunk is retried
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Set the delay before the first retry; it doubles on every attempt
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Write every model from `models`
+    ///
+    /// Only fails if IDs cannot be generated; write failures are reported
+    /// in the [`BulkReport`].
+    pub async fn write<S>(&self, models: S) -> Result<BulkReport>
+    where
+        S: Stream<Item = M> + Send,
+    {
+        let mut report = BulkReport::default();
+        let mut seen = HashSet::new();
+        let mut chunk: Vec<(M, Vec<u8>)> = Vec::with_capacity(self.chunk_size);
+        let mut models = std::pin::pin!(models);
+
+        while let Some(mut model) = models.next().await {
+            if model.id().is_empty() {
+                model.set_id(M::next_id(&self.db).await?);
+            }
+            if !seen.insert(model.id().to_string()) {
+                report.duplicates += 1;
+                continue;
+            }
+
+            let encoded = model
+                .validate()
+                .and_then(|_| M::codec().encode(&model))
+                .and_then(|value| crate::codec::compress(M::compression(), value));
+            match encoded {
+                Ok(value) => chunk.push((model, value)),
+                Err(e) => report.fail(model.id(), &e),
+            }
+
+            if chunk.len() >= self.chunk_size {
+                self.flush(&mut chunk, &mut report).await;
+            }
+        }
+        self.flush(&mut chunk, &mut report).await;
+
+        Ok(report)
+    }
+
+    /// Write every model from an iterator
+    pub async fn write_iter<I>(&self, models: I) -> Result<BulkReport>
+    where
+        I: IntoIterator<Item = M>,
+        I::IntoIter: Send,
+    {
+        self.write(futures::stream::iter(models)).await
+    }
+
+    /// Write one chunk, retrying with backoff, and record the outcome
+    async fn flush(&self, chunk: &mut Vec<(M, Vec<u8>)>, report: &mut BulkReport) {
+        if chunk.is_empty() {
+            return;
+        }
+
+        let mut attempt = 0;
+        let result = loop {
+            match self.write_chunk(chunk).await {
+                Ok(previous) => break Ok(previous),
+                Err(e) if attempt >= self.max_retries => break Err(e),
+                Err(e) => {
+                    tracing::warn!(
+                        collection = M::collection(),
+                        attempt,
+                        error = %e,
+                        "bulk chunk failed, retrying"
+                    );
+                    tokio::time::sleep(self.backoff * 2u32.saturating_pow(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        };
+
+        if attempt > 0 {
+            report.retried += chunk.len();
+        }
+        match result {
+            Ok(previous) => {
+                report.succeeded += chunk.len();
+                for ((model, value), previous) in chunk.iter().zip(previous) {
+                    if let Err(e) = self.after_write(model, value, previous.as_deref()).await {
+                        tracing::warn!(collection = M::collection(), id = model.id(), error = %e, "bulk post-write step failed");
+                    }
+                }
+            }
+            Err(e) => {
+                for (model, _) in chunk.iter() {
+                    report.fail(model.id(), &e);
+                }
+            }
+        }
+        chunk.clear();
+    }
+
+    /// Write one chunk, returning the documents it replaced
+    async fn write_chunk(&self, chunk: &[(M, Vec<u8>)]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut conn = self.db.connection().clone();
+        if conn.is_cluster() {
+            return self.write_chunk_split(&mut conn, chunk).await;
+        }
+
+        let script = redis::Script::new(BULK_SET_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for (model, value) in chunk {
+            invocation
+                .key(self.db.key(&model.key()))
+                .arg(value.as_slice());
+        }
+        invocation
+            .key(self.db.key(&crate::counter_key(M::collection())))
+            .arg(crate::counter::key_pattern(&self.db, M::collection()));
+
+        let mut previous: Vec<Option<Vec<u8>>> = invocation.invoke_async(&mut conn).await?;
+        previous.resize(chunk.len(), None);
+        Ok(previous)
+    }
+
+    /// Write one chunk with a pipeline of SETs, as keys in different hash
+    /// slots can't share a script on a cluster
+    ///
+    /// The chunk is no longer written atomically: a failed chunk may be
+    /// partly written, and retrying it rewrites the same documents.
+    async fn write_chunk_split(
+        &self,
+        conn: &mut crate::Connection,
+        chunk: &[(M, Vec<u8>)],
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut pipe = redis::pipe();
+        for (model, value) in chunk {
+            pipe.cmd("SET")
+                .arg(self.db.key(&model.key()))
+                .arg(value.as_slice())
+                .arg("GET");
+        }
+        let previous: Vec<Option<Vec<u8>>> = pipe.query_async(conn).await?;
+
+        let created = previous.iter().filter(|old| old.is_none()).count();
+        if created > 0 {
+            crate::counter::adjust(&self.db, M::collection(), created as i64).await?;
+        }
+        Ok(previous)
+    }
+
+    /// Index and publish a written document
+    async fn after_write(&self, model: &M, value: &[u8], previous: Option<&[u8]>) -> Result<()> {
+        crate::index::update_encoded(
+            &self.db,
+            M::collection(),
+            M::indexes(),
+            M::codec(),
+            model.id(),
+            previous,
+            Some(value),
+        )
+        .await?;
+        if M::ordered() {
+            crate::order::track(&self.db, M::collection(), model.id(), true).await?;
+        }
+        crate::events::publish(
+            &self.db,
+            crate::ModelOp::Save,
+            M::collection(),
+            model.id(),
+            Some(serde_json::to_value(model)?),
+        )
+        .await
+    }
+}
+
+impl BulkReport {
+    fn fail(&mut self, id: &str, error: &crate::Error) {
+        self.failed += 1;
+        self.errors.push(BulkError {
+            id: id.to_string(),
+            error: error.to_string(),
+        });
+    }
+}
@@ -0,0 +1,402 @@
+//! Change data capture into Redis Streams
+//!
+//! Saves and deletes made through a handle from [`TormDb::with_cdc`] are
+//! appended to the collection's stream, `torm:cdc:{collection}` under the
+//! handle's prefix. Downstream systems tail it through a consumer group
+//! with [`TormDb::cdc`]: each record is delivered to one consumer of the
+//! group and stays pending until acknowledged, so records of a consumer
+//! that crashed can be claimed by another.
+//!
+//! Streams are not trimmed; trim them with `XTRIM` once every group has
+//! caught up.
+
+use crate::{Error, Model, ModelEvent, ModelOp, Result, TormDb};
+use chrono::{DateTime, Utc};
+use redis::Value;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Get the change stream of a collection
+pub fn cdc_key(collection: &str) -> String {
+    format!("torm:cdc:{}", collection)
+}
+
+/// Append a change record if the handle captures changes
+pub(crate) async fn record(db: &TormDb, event: &ModelEvent) -> Result<()> {
+    if !db.cdc_enabled() {
+        return Ok(());
+    }
+
+    let mut cmd = redis::cmd("XADD");
+    cmd.arg(db.key(&cdc_key(&event.collection)))
+        .arg("*")
+        .arg("op")
+        .arg(match event.op {
+            ModelOp::Save => "save",
+            ModelOp::Delete => "delete",
+        })
+        .arg("id")
+        .arg(&event.id)
+        .arg("at")
+        .arg(Utc::now().to_rfc3339());
+    if let Some(payload) = &event.payload {
+        cmd.arg("data").arg(serde_json::to_string(payload)?);
+    }
+    if let Some(actor) = &event.actor {
+        cmd.arg("actor").arg(actor);
+    }
+    if let Some(tenant) = &event.tenant {
+        cmd.arg("tenant").arg(tenant);
+    }
+
+    let mut conn = db.connection().clone();
+    cmd.query_async::<()>(&mut conn).await?;
+    Ok(())
+}
+
+/// A change read from a collection's stream
+#[derive(Debug, Clone)]
+pub struct ChangeRecord<M> {
+    /// ID of the stream entry, to acknowledge it with
+    pub entry_id: String,
+    /// What happened to the document
+    pub op: ModelOp,
+    /// ID of the document
+    pub id: String,
+    /// Document contents after a save
+    pub document: Option<M>,
+    /// Actor attached to the handle that made the change
+    pub actor: Option<String>,
+    /// Tenant of the handle that made the change
+    pub tenant: Option<String>,
+    /// When the change was made
+    pub at: DateTime<Utc>,
+}
+
+/// A consumer in a consumer group of a collection's change stream
+///
+/// Returned by [`TormDb::cdc`].
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{Model, ModelOp, TormDb};
+/// # use serde::{Deserialize, Serialize};
+/// # use std::time::Duration;
+/// # #[derive(Model, Serialize, Deserialize)]
+/// # struct User { #[id] id: String, name: String }
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let changes = db.cdc::<User>("indexer").await?.consumer("indexer-1");
+/// loop {
+///     // Take over what a crashed consumer left pending, then read new changes
+///     let mut records = changes.claim(Duration::from_secs(60), 100).await?;
+///     records.extend(changes.read(100, Duration::from_secs(5)).await?);
+///     for record in &records {
+///         match record.op {
+///             ModelOp::Save => println!("reindex {}", record.id),
+///             ModelOp::Delete => println!("unindex {}", record.id),
+///         }
+///         changes.ack(&record.entry_id).await?;
+///     }
+/// }
+/// # }
+/// ```
+pub struct CdcConsumer<M> {
+    db: TormDb,
+    /// Connection of its own for blocking reads, if the handle has a client
+    blocking: Option<redis::aio::MultiplexedConnection>,
+    key: String,
+    group: String,
+    consumer: String,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M: Model> CdcConsumer<M> {
+    /// Read as the consumer named `name`
+    ///
+    /// Consumers of a group share its records; give each worker a stable
+    /// name so what it leaves pending can be found again. Defaults to
+    /// `torm`.
+    pub fn consumer(mut self, name: impl Into<String>) -> Self {
+        self.consumer = name.into();
+        self
+    }
+
+    /// Read up to `count` records not yet delivered to the group
+    ///
+    /// Waits up to `block` for new records, returning none if there are
+    /// none by then; [`Duration::ZERO`] waits for as long as it takes.
+    /// The records stay pending until acknowledged.
+    pub async fn read(&self, count: usize, block: Duration) -> Result<Vec<ChangeRecord<M>>> {
+        let mut cmd = redis::cmd("XREADGROUP");
+        cmd.arg("GROUP")
+            .arg(&self.group)
+            .arg(&self.consumer)
+            .arg("COUNT")
+            .arg(count.max(1))
+            .arg("BLOCK")
+            .arg(block.as_millis() as u64)
+            .arg("STREAMS")
+            .arg(&self.key)
+            .arg(">");
+        let Some(conn) = &self.blocking else {
+            let reply: Value = cmd.query_async(&mut self.db.connection().clone()).await?;
+            return parse_entries(stream_entries(reply));
+        };
+
+        // Wait on a connection of its own, as waiting on the shared one
+        // would hold up everyone else's commands, until the handle is closed
+        let link = self.db.connection().link();
+        if link.is_closed() {
+            return Err(crate::lifecycle::closed_error().into());
+        }
+        let mut conn = conn.clone();
+        let reply: Value = tokio::select! {
+            reply = cmd.query_async(&mut conn) => reply?,
+            () = link.closed() => return Ok(Vec::new()),
+        };
+        parse_entries(stream_entries(reply))
+    }
+
+    /// Take over up to `count` records pending for longer than `min_idle`
+    ///
+    /// Records delivered to a consumer that never acknowledged them, e.g.
+    /// because it crashed, are handed to this one. Needs Redis 6.2 or
+    /// later.
+    pub async fn claim(&self, min_idle: Duration, count: usize) -> Result<Vec<ChangeRecord<M>>> {
+        let mut conn = self.db.connection().clone();
+        let reply: Value = redis::cmd("XAUTOCLAIM")
+            .arg(&self.key)
+            .arg(&self.group)
+            .arg(&self.consumer)
+            .arg(min_idle.as_millis() as u64)
+            .arg("0-0")
+            .arg("COUNT")
+            .arg(count.max(1))
+            .query_async(&mut conn)
+            .await?;
+
+        // [next cursor, entries, deleted ids]
+        let entries = match reply {
+            Value::Array(mut items) if items.len() >= 2 => items.remove(1),
+            _ => Value::Nil,
+        };
+        parse_entries(entries)
+    }
+
+    /// Acknowledge a record once processed, so it isn't delivered again
+    ///
+    /// Returns `false` if it wasn't pending.
+    pub async fn ack(&self, entry_id: &str) -> Result<bool> {
+        let mut conn = self.db.connection().clone();
+        let acked: i64 = redis::cmd("XACK")
+            .arg(&self.key)
+            .arg(&self.group)
+            .arg(entry_id)
+            .query_async(&mut conn)
+            .await?;
+        Ok(acked > 0)
+    }
+
+    /// Get the number of records delivered to the group but not yet
+    /// acknowledged
+    pub async fn pending(&self) -> Result<usize> {
+        let mut conn = self.db.connection().clone();
+        let reply: Value = redis::cmd("XPENDING")
+            .arg(&self.key)
+            .arg(&self.group)
+            .query_async(&mut conn)
+            .await?;
+        match reply {
+            Value::Array(items) => match items.first() {
+                Some(Value::Int(pending)) => Ok((*pending).max(0) as usize),
+                _ => Ok(0),
+            },
+            _ => Ok(0),
+        }
+    }
+}
+
+/// Take the entries out of an `XREADGROUP` reply, `[[stream, entries]]`,
+/// or nil once the wait ran out
+fn stream_entries(reply: Value) -> Value {
+    match reply {
+        Value::Array(streams) => match streams.into_iter().next() {
+            Some(Value::Array(mut stream)) if stream.len() == 2 => stream.remove(1),
+            _ => Value::Nil,
+        },
+        _ => Value::Nil,
+    }
+}
+
+/// Decode stream entries, `[[entry id, [field, value, ...]], ...]`
+///
+/// Entries deleted while pending come back without fields and are
+/// skipped.
+fn parse_entries<M: Model>(entries: Value) -> Result<Vec<ChangeRecord<M>>> {
+    let Value::Array(entries) = entries else {
+        return Ok(Vec::new());
+    };
+    let mut records = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let (entry_id, fields): (String, Value) = redis::from_redis_value(&entry)?;
+        if !matches!(fields, Value::Array(_)) {
+            continue;
+        }
+        let fields: HashMap<String, String> = redis::from_redis_value(&fields)?;
+        records.push(parse_record(entry_id, fields)?);
+    }
+    Ok(records)
+}
+
+fn parse_record<M: Model>(
+    entry_id: String,
+    mut fields: HashMap<String, String>,
+) -> Result<ChangeRecord<M>> {
+    let invalid = |what: &str| Error::Codec(format!("change record {} {}", entry_id, what));
+    let op = fields.remove("op").ok_or_else(|| invalid("has no op"))?;
+    let op = match op.as_str() {
+        "save" => ModelOp::Save,
+        "delete" => ModelOp::Delete,
+        _ => return Err(invalid("has an unknown op")),
+    };
+    let id = fields.remove("id").ok_or_else(|| invalid("has no id"))?;
+    let at = fields
+        .remove("at")
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+        .ok_or_else(|| invalid("has no valid time"))?
+        .with_timezone(&Utc);
+    let document = match fields.remove("data") {
+        Some(data) => {
+            let mut document: M = serde_json::from_str(&data)?;
+            document.after_load();
+            Some(document)
+        }
+        None => None,
+    };
+
+    Ok(ChangeRecord {
+        entry_id,
+        op,
+        id,
+        document,
+        actor: fields.remove("actor"),
+        tenant: fields.remove("tenant"),
+        at,
+    })
+}
+
+impl TormDb {
+    /// Join consumer group `group` of `M`'s change stream
+    ///
+    /// Creates the stream and the group if needed; a new group starts
+    /// from the oldest record still in the stream. Changes are recorded
+    /// only for writes made through a handle from [`TormDb::with_cdc`].
+    ///
+    /// The consumer opens a connection of its own for blocking reads.
+    ///
+    /// See [`CdcConsumer`] for an example.
+    pub async fn cdc<M: Model>(&self, group: &str) -> Result<CdcConsumer<M>> {
+        let key = self.key(&cdc_key(M::collection()));
+        let mut conn = self.connection().clone();
+        let created: redis::RedisResult<()> = redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg(&key)
+            .arg(group)
+            .arg("0")
+            .arg("MKSTREAM")
+            .query_async(&mut conn)
+            .await;
+        match created {
+            Err(e) if e.code() != Some("BUSYGROUP") => return Err(e.into()),
+            _ => {}
+        }
+
+        let blocking = match self.client() {
+            Some(client) => Some(client.get_multiplexed_async_connection().await?),
+            None => None,
+        };
+        Ok(CdcConsumer {
+            db: self.clone(),
+            blocking,
+            key,
+            group: group.to_string(),
+            consumer: "torm".to_string(),
+            _model: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        id: String,
+    }
+
+    impl Model for Item {
+        fn collection() -> &'static str {
+            "item"
+        }
+
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn set_id(&mut self, id: String) {
+            self.id = id;
+        }
+    }
+
+    fn bulk(s: &str) -> Value {
+        Value::BulkString(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_parse_entries() {
+        let entries = Value::Array(vec![
+            Value::Array(vec![
+                bulk("1-0"),
+                Value::Array(vec![
+                    bulk("op"),
+                    bulk("save"),
+                    bulk("id"),
+                    bulk("a"),
+                    bulk("at"),
+                    bulk("2026-01-01T00:00:00+00:00"),
+                    bulk("data"),
+                    bulk(r#"{"id":"a"}"#),
+                ]),
+            ]),
+            // Deleted while pending
+            Value::Array(vec![bulk("2-0"), Value::Nil]),
+            Value::Array(vec![
+                bulk("3-0"),
+                Value::Array(vec![
+                    bulk("op"),
+                    bulk("delete"),
+                    bulk("id"),
+                    bulk("a"),
+                    bulk("at"),
+                    bulk("2026-01-01T00:00:01+00:00"),
+                    bulk("actor"),
+                    bulk("admin"),
+                ]),
+            ]),
+        ]);
+
+        let records = parse_entries::<Item>(entries).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].entry_id, "1-0");
+        assert_eq!(records[0].op, ModelOp::Save);
+        assert_eq!(records[0].document, Some(Item { id: "a".into() }));
+        assert_eq!(records[1].op, ModelOp::Delete);
+        assert_eq!(records[1].document, None);
+        assert_eq!(records[1].actor.as_deref(), Some("admin"));
+    }
+}
@@ -0,0 +1,270 @@
+//! Storage codecs
+//!
+//! Documents are stored as JSON by default. A model can pick a different
+//! encoding through [`crate::Model::codec`], e.g. MessagePack (feature
+//! `msgpack`) or CBOR (feature `cbor`) for a high-volume telemetry
+//! collection; both are markedly smaller than JSON and faster to decode.
+//! Documents stored with a non-JSON codec are not readable by JSON-only
+//! tools such as TORM Server.
+//!
+//! Codecs must be self-describing, as indexes and filters decode
+//! documents without their model type, which rules out formats such as
+//! bincode.
+//!
+//! Large documents can also be compressed with zstd (feature `zstd`) or
+//! gzip (feature `gzip`) through [`crate::Model::compression`]. Compressed
+//! values keep their format's magic bytes, which no encoded document
+//! starts with, so decoding recognizes them and documents written before
+//! compression was turned on still load.
+
+use crate::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Cow;
+
+/// zstd frame magic number
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+/// gzip member header
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+/// Encoding used for stored documents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// UTF-8 JSON
+    #[default]
+    Json,
+    /// MessagePack with named fields
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR (RFC 8949)
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Codec {
+    /// Encode a value for storage
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|e| crate::Error::Codec(e.to_string()))
+            }
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| crate::Error::Codec(e.to_string()))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Decode a stored value, decompressing it first if needed
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        let bytes = &*decompress(bytes)?;
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|e| crate::Error::Codec(e.to_string()))
+            }
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => {
+                ciborium::from_reader(bytes).map_err(|e| crate::Error::Codec(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Compression algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// Compression applied to stored documents above a size threshold
+///
+/// Documents whose encoding is shorter than the threshold (1 KiB by
+/// default) are stored as they are, as compressing them rarely pays off.
+/// Compressed documents can't be read by Lua scripts: queries filter
+/// them client-side even with [`QueryBuilder::pushdown`], and
+/// [`Model::touch`] is not available.
+///
+/// [`QueryBuilder::pushdown`]: crate::QueryBuilder::pushdown
+/// [`Model::touch`]: crate::Model::touch
+///
+/// # Example
+/// ```rust,ignore
+/// # use torm::Model;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Model, Serialize, Deserialize)]
+/// #[model(compression = "zstd", compress_above = 4096)]
+/// struct Report {
+///     #[id]
+///     id: String,
+///     body: String,
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    algorithm: Algorithm,
+    level: Option<u32>,
+    threshold: usize,
+}
+
+impl Compression {
+    /// zstd, by default at level 3
+    #[cfg(feature = "zstd")]
+    pub fn zstd() -> Self {
+        Self::with(Algorithm::Zstd)
+    }
+
+    /// gzip, by default at level 6
+    #[cfg(feature = "gzip")]
+    pub fn gzip() -> Self {
+        Self::with(Algorithm::Gzip)
+    }
+
+    #[cfg(any(feature = "zstd", feature = "gzip"))]
+    fn with(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            level: None,
+            threshold: 1024,
+        }
+    }
+
+    /// Set the compression level, from 1 (fastest) upwards
+    ///
+    /// zstd accepts up to 22 and gzip up to 9; higher values are clamped.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Some(level.max(1));
+        self
+    }
+
+    /// Only compress documents of at least `bytes` bytes
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// Compress an encoded document if it reaches the threshold
+    pub fn compress(self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        if bytes.len() < self.threshold {
+            return Ok(bytes);
+        }
+        match self.algorithm {
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => {
+                let level = self.level.map_or(3, |level| level.min(22) as i32);
+                Ok(zstd::encode_all(bytes.as_slice(), level)?)
+            }
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => {
+                use std::io::Write;
+                let level = flate2::Compression::new(self.level.map_or(6, |level| level.min(9)));
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(&bytes)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+/// Compress `bytes` with `compression`, if any
+pub(crate) fn compress(compression: Option<Compression>, bytes: Vec<u8>) -> Result<Vec<u8>> {
+    match compression {
+        Some(compression) => compression.compress(bytes),
+        None => Ok(bytes),
+    }
+}
+
+/// Undo the compression of a stored value, if it is compressed
+pub(crate) fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if bytes.starts_with(ZSTD_MAGIC) {
+        #[cfg(feature = "zstd")]
+        return Ok(Cow::Owned(zstd::decode_all(bytes)?));
+        #[cfg(not(feature = "zstd"))]
+        return Err(crate::Error::Codec(
+            "document is zstd-compressed; enable the `zstd` feature".into(),
+        ));
+    }
+    if bytes.starts_with(GZIP_MAGIC) {
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Read;
+            let mut plain = Vec::new();
+            flate2::read::GzDecoder::new(bytes).read_to_end(&mut plain)?;
+            return Ok(Cow::Owned(plain));
+        }
+        #[cfg(not(feature = "gzip"))]
+        return Err(crate::Error::Codec(
+            "document is gzip-compressed; enable the `gzip` feature".into(),
+        ));
+    }
+    Ok(Cow::Borrowed(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let doc = serde_json::json!({ "id": "1", "tags": ["a", "b"], "n": 3 });
+        let bytes = Codec::Json.encode(&doc).unwrap();
+        assert_eq!(
+            Codec::Json.decode::<serde_json::Value>(&bytes).unwrap(),
+            doc
+        );
+
+        #[cfg(feature = "msgpack")]
+        {
+            let bytes = Codec::MessagePack.encode(&doc).unwrap();
+            let back: serde_json::Value = Codec::MessagePack.decode(&bytes).unwrap();
+            assert_eq!(back, doc);
+        }
+
+        #[cfg(feature = "cbor")]
+        {
+            let bytes = Codec::Cbor.encode(&doc).unwrap();
+            let back: serde_json::Value = Codec::Cbor.decode(&bytes).unwrap();
+            assert_eq!(back, doc);
+        }
+    }
+
+    #[test]
+    fn test_uncompressed_passthrough() {
+        let bytes = br#"{"id":"1"}"#;
+        assert!(matches!(decompress(bytes).unwrap(), Cow::Borrowed(_)));
+        assert_eq!(compress(None, bytes.to_vec()).unwrap(), bytes);
+    }
+
+    #[cfg(any(feature = "zstd", feature = "gzip"))]
+    #[test]
+    fn test_compression() {
+        let doc = serde_json::json!({ "id": "1", "body": "lorem ipsum ".repeat(200) });
+        let plain = Codec::Json.encode(&doc).unwrap();
+
+        #[cfg(feature = "zstd")]
+        let compression = Compression::zstd();
+        #[cfg(not(feature = "zstd"))]
+        let compression = Compression::gzip();
+
+        let small = compression
+            .threshold(usize::MAX)
+            .compress(plain.clone())
+            .unwrap();
+        assert_eq!(small, plain);
+
+        let packed = compression.level(3).compress(plain.clone()).unwrap();
+        assert!(packed.len() < plain.len());
+        assert_eq!(
+            Codec::Json.decode::<serde_json::Value>(&packed).unwrap(),
+            doc
+        );
+    }
+}
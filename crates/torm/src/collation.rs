@@ -0,0 +1,60 @@
+//! Locale-aware string ordering for sorts
+//!
+//! Available with the `collation` feature. Without a collation, sorts
+//! compare strings byte-wise, which puts `"Émile"` after `"Zoe"`.
+
+use crate::{Error, Result};
+use icu_collator::options::CollatorOptions;
+use icu_collator::{Collator, CollatorBorrowed, CollatorPreferences};
+use icu_locale_core::Locale;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// Collation rules of a locale, used to order strings in sorts
+#[derive(Clone)]
+pub struct Collation {
+    locale: String,
+    collator: Arc<CollatorBorrowed<'static>>,
+}
+
+impl Collation {
+    /// Load the collation rules of a BCP 47 locale such as `"fr"` or `"de-AT"`
+    ///
+    /// Fails with [`Error::InvalidQuery`] if the locale cannot be parsed.
+    pub fn new(locale: &str) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            Error::InvalidQuery(format!("invalid collation locale '{}': {}", locale, e))
+        };
+        let parsed: Locale = locale.parse().map_err(|e| invalid(&e))?;
+        let collator = Collator::try_new(
+            CollatorPreferences::from(&parsed),
+            CollatorOptions::default(),
+        )
+        .map_err(|e| invalid(&e))?;
+        Ok(Self {
+            locale: locale.to_string(),
+            collator: Arc::new(collator),
+        })
+    }
+
+    /// The locale the rules were loaded for
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Compare two sort values, strings by the collation rules and
+    /// anything else like an uncollated sort
+    pub(crate) fn compare(&self, a: Option<&Value>, b: Option<&Value>) -> Ordering {
+        match (a.and_then(Value::as_str), b.and_then(Value::as_str)) {
+            (Some(a), Some(b)) => self.collator.compare(a, b),
+            _ => crate::query::compare_json_values(a, b),
+        }
+    }
+}
+
+impl std::fmt::Debug for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Collation").field(&self.locale).finish()
+    }
+}
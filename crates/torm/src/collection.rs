@@ -0,0 +1,62 @@
+//! Schemaless access to a collection
+//!
+//! Tooling that has no Rust struct for a collection, such as admin
+//! consoles or scripts, gets a [`Collection`] from
+//! [`TormDb::collection`](crate::TormDb::collection) and queries its
+//! documents as raw JSON with the same builder models use.
+
+use crate::query::QueryBuilder;
+use crate::Codec;
+
+/// A collection addressed by name, with documents as [`serde_json::Value`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    name: String,
+    codec: Codec,
+}
+
+impl Collection {
+    pub(crate) fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            codec: Codec::Json,
+        }
+    }
+
+    /// Read documents stored with `codec` instead of JSON
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Get the collection name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Create a query returning the collection's documents as raw JSON
+    ///
+    /// Filters, sorting, pagination and aggregates work as on a model
+    /// query. Fields are not known to be indexed, so every query scans.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Query, SortOrder, TormDb};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let adults: Vec<serde_json::Value> = db
+    ///     .collection("users")
+    ///     .query()
+    ///     .filter("age", Query::gte(18))
+    ///     .sort_by("name", SortOrder::Asc)
+    ///     .limit(20)
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query(&self) -> QueryBuilder<serde_json::Value> {
+        QueryBuilder::new(self.name.as_str()).with_codec(self.codec)
+    }
+}
@@ -0,0 +1,208 @@
+//! Per-collection document counters
+//!
+//! Each collection keeps its document count in `torm:count:{collection}`
+//! so that counting doesn't need to walk the keyspace. The counter is
+//! bumped only when a save creates a new key and when a delete actually
+//! removes one.
+//!
+//! A missing counter, on a collection written before counters existed or
+//! after the key was lost, is rebuilt by counting the collection's keys
+//! rather than started from zero. On a standalone server the rebuild and
+//! the write run in one Lua script, so no concurrent write is missed or
+//! counted twice. Cluster connections and custom backends send plain
+//! commands instead, and rebuild with a scan that isn't atomic.
+
+use crate::scan::KeyScanner;
+use crate::{Connection, Result, TormDb};
+
+/// Lua defining `bump(counter, pattern, delta)`, which adds `delta` to
+/// the counter, or sets it to the number of keys matching `pattern` if it
+/// is missing, and returns the new count
+///
+/// Called after the write, so a rebuilt count already includes it.
+macro_rules! bump_lua {
+    () => {
+        r#"
+local function bump(counter, pattern, delta)
+    if redis.call('EXISTS', counter) == 1 then
+        return redis.call('INCRBY', counter, delta)
+    end
+    local cursor, count = '0', 0
+    repeat
+        local reply = redis.call('SCAN', cursor, 'MATCH', pattern, 'COUNT', 1000)
+        cursor = reply[1]
+        count = count + #reply[2]
+    until cursor == '0'
+    redis.call('SET', counter, count)
+    return count
+end
+"#
+    };
+}
+pub(crate) use bump_lua;
+
+const SET_SCRIPT: &str = concat!(
+    bump_lua!(),
+    r#"
+local previous = redis.call('SET', KEYS[1], ARGV[1], 'GET')
+if not previous then
+    bump(KEYS[2], ARGV[2], 1)
+end
+return previous
+"#
+);
+
+const DEL_SCRIPT: &str = concat!(
+    bump_lua!(),
+    r#"
+local removed = redis.call('GETDEL', KEYS[1])
+if removed then
+    bump(KEYS[2], ARGV[1], -1)
+end
+return removed
+"#
+);
+
+const ADJUST_SCRIPT: &str = concat!(bump_lua!(), "return bump(KEYS[1], ARGV[1], ARGV[2])");
+
+/// Get the counter key for a collection
+pub fn counter_key(collection: &str) -> String {
+    format!("torm:count:{}", collection)
+}
+
+/// Get the pattern matching a collection's document keys
+pub(crate) fn key_pattern(db: &TormDb, collection: &str) -> String {
+    db.key_pattern(&format!("{}:*", collection))
+}
+
+/// Whether the counter can be kept by a script next to the keys it counts
+fn scripted(conn: &Connection) -> bool {
+    !conn.is_cluster() && !conn.is_backend()
+}
+
+/// Write a document and bump the counter if the key is new
+///
+/// Returns the document it replaced. For writers that bypass
+/// [`crate::Model::save`], such as TORM Server.
+pub async fn set_counted(
+    db: &TormDb,
+    collection: &str,
+    key: &str,
+    value: &[u8],
+) -> Result<Option<Vec<u8>>> {
+    let mut conn = db.connection().clone();
+
+    if scripted(&conn) {
+        return Ok(redis::Script::new(SET_SCRIPT)
+            .key(key)
+            .key(db.key(&counter_key(collection)))
+            .arg(value)
+            .arg(key_pattern(db, collection))
+            .invoke_async(&mut conn)
+            .await?);
+    }
+
+    let previous: Option<Vec<u8>> = redis::cmd("SET")
+        .arg(key)
+        .arg(value)
+        .arg("GET")
+        .query_async(&mut conn)
+        .await?;
+
+    if previous.is_none() {
+        adjust(db, collection, 1).await?;
+    }
+
+    Ok(previous)
+}
+
+/// Delete a document and decrement the counter if it existed
+///
+/// Returns the removed document. For writers that bypass
+/// [`crate::Model::delete`], such as TORM Server.
+pub async fn del_counted(db: &TormDb, collection: &str, key: &str) -> Result<Option<Vec<u8>>> {
+    let mut conn = db.connection().clone();
+
+    if scripted(&conn) {
+        return Ok(redis::Script::new(DEL_SCRIPT)
+            .key(key)
+            .key(db.key(&counter_key(collection)))
+            .arg(key_pattern(db, collection))
+            .invoke_async(&mut conn)
+            .await?);
+    }
+
+    let removed: Option<Vec<u8>> = redis::cmd("GETDEL").arg(key).query_async(&mut conn).await?;
+
+    if removed.is_some() {
+        adjust(db, collection, -1).await?;
+    }
+
+    Ok(removed)
+}
+
+/// Add `delta` to the counter after a write, rebuilding it if it is
+/// missing
+///
+/// Returns the new count.
+pub(crate) async fn adjust(db: &TormDb, collection: &str, delta: i64) -> Result<usize> {
+    let mut conn = db.connection().clone();
+    let counter = db.key(&counter_key(collection));
+
+    let count: i64 = if scripted(&conn) {
+        redis::Script::new(ADJUST_SCRIPT)
+            .key(counter)
+            .arg(key_pattern(db, collection))
+            .arg(delta)
+            .invoke_async(&mut conn)
+            .await?
+    } else {
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(&counter)
+            .query_async(&mut conn)
+            .await?;
+        if !exists {
+            return recount(db, collection).await;
+        }
+        redis::cmd("INCRBY")
+            .arg(counter)
+            .arg(delta)
+            .query_async(&mut conn)
+            .await?
+    };
+    Ok(count.max(0) as usize)
+}
+
+/// Read the counter, rebuilding it from the keyspace if it is missing
+pub(crate) async fn read(db: &TormDb, collection: &str) -> Result<usize> {
+    let mut conn = db.connection().clone();
+
+    let count: Option<i64> = redis::cmd("GET")
+        .arg(db.key(&counter_key(collection)))
+        .query_async(&mut conn)
+        .await?;
+
+    match count {
+        Some(n) => Ok(n.max(0) as usize),
+        None => recount(db, collection).await,
+    }
+}
+
+/// Count the collection's keys and overwrite the counter with the result
+pub(crate) async fn recount(db: &TormDb, collection: &str) -> Result<usize> {
+    let mut conn = db.connection().clone();
+
+    let mut scanner = KeyScanner::new(key_pattern(db, collection));
+    let mut count = 0;
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        count += keys.len();
+    }
+
+    redis::cmd("SET")
+        .arg(db.key(&counter_key(collection)))
+        .arg(count)
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(count)
+}
@@ -1,18 +1,56 @@
 //! Database connection and client
 
-use crate::{Error, Result};
-use redis::aio::ConnectionManager;
+use crate::events::Observers;
+use crate::pool::Connection;
+use crate::watchdog::OpStats;
+use crate::{Collection, Result, RetryPolicy};
 use redis::Client;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Connections to read replicas, taken in turn
+struct Replicas {
+    connections: Vec<Connection>,
+    next: AtomicUsize,
+}
+
+impl Replicas {
+    fn new(connections: Vec<Connection>) -> Option<Arc<Self>> {
+        (!connections.is_empty()).then(|| {
+            Arc::new(Self {
+                connections,
+                next: AtomicUsize::new(0),
+            })
+        })
+    }
+}
 
 /// TORM database connection
 #[derive(Clone)]
 pub struct TormDb {
-    client: ConnectionManager,
+    client: Connection,
+    redis_client: Option<Client>,
+    replicas: Option<Arc<Replicas>>,
+    actor: Option<Arc<str>>,
+    prefix: Option<Arc<str>>,
+    tenant: Option<Arc<str>>,
+    json_module: bool,
+    search_module: bool,
+    cdc: bool,
+    observers: Observers,
+    op_stats: Arc<OpStats>,
+    #[cfg(feature = "embedded")]
+    pub(crate) embedded: Option<Arc<crate::embedded::EmbeddedServer>>,
 }
 
 impl TormDb {
     /// Connect to ToonStore via Redis protocol
     ///
+    /// Use [`TormDb::builder`] for timeouts, a client name or credentials
+    /// kept out of the URL.
+    ///
     /// # Arguments
     /// * `url` - Redis connection URL (e.g., "redis://localhost:6379")
     ///
@@ -26,26 +64,324 @@ impl TormDb {
     /// # }
     /// ```
     pub async fn connect(url: &str) -> Result<Self> {
-        let client = Client::open(url).map_err(|e| Error::Connection(e.to_string()))?;
-        let manager = ConnectionManager::new(client).await?;
+        Self::builder().url(url).build().await
+    }
 
-        Ok(Self { client: manager })
+    /// Wrap an open connection
+    pub(crate) fn from_parts(conn: Connection, client: Option<Client>) -> Self {
+        Self {
+            client: conn,
+            redis_client: client,
+            replicas: None,
+            actor: None,
+            prefix: None,
+            tenant: None,
+            json_module: false,
+            search_module: false,
+            cdc: false,
+            observers: Observers::default(),
+            op_stats: Arc::default(),
+            #[cfg(feature = "embedded")]
+            embedded: None,
+        }
     }
 
     /// Get a reference to the Redis connection
-    pub fn connection(&self) -> &ConnectionManager {
+    ///
+    /// Clone it to run commands; with a pool, see
+    /// [`ConnectOptions::pool_size`](crate::ConnectOptions::pool_size),
+    /// each command checks out a connection of its own.
+    pub fn connection(&self) -> &Connection {
         &self.client
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Get the underlying Redis client
+    ///
+    /// Used to open dedicated connections such as pub/sub subscriptions.
+    /// `None` for a handle created with [`TormDb::from_backend`].
+    pub fn client(&self) -> Option<&Client> {
+        self.redis_client.as_ref()
+    }
+
+    /// Return a handle that records `actor` in the audit trail
+    ///
+    /// Saves and deletes made through the returned handle are appended to
+    /// the audit log together with the actor and a timestamp. The handle
+    /// shares the underlying connection, so this is cheap to call per request.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let admin_db = db.with_actor("admin:42");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_actor(&self, actor: impl Into<String>) -> Self {
+        Self {
+            actor: Some(Arc::from(actor.into())),
+            ..self.clone()
+        }
+    }
+
+    /// Return a handle that records its writes in change streams
+    ///
+    /// Saves and deletes made through the returned handle also append a
+    /// change record to the collection's stream, `torm:cdc:{collection}`, for
+    /// consumers opened with [`TormDb::cdc`]. Like
+    /// [`TormDb::with_actor`], the handle shares the underlying
+    /// connection.
+    pub fn with_cdc(&self) -> Self {
+        Self {
+            cdc: true,
+            ..self.clone()
+        }
+    }
+
+    /// Return a handle that retries commands after transient errors
+    ///
+    /// Every command the handle sends, from model reads and writes to
+    /// scans, is retried as `policy` says when the connection drops,
+    /// times out or the server is failing over. Like
+    /// [`TormDb::with_actor`], the handle shares the underlying
+    /// connection.
+    ///
+    /// See [`RetryPolicy`] for an example.
+    pub fn with_retry(&self, policy: RetryPolicy) -> Self {
+        Self {
+            replicas: self.map_replicas(|conn| conn.with_retry(policy.clone())),
+            client: self.client.with_retry(policy),
+            ..self.clone()
+        }
+    }
+
+    /// Return a handle whose commands fail with
+    /// [`Error::Timeout`](crate::Error::Timeout) after `timeout`
+    ///
+    /// Overrides [`ConnectOptions::command_timeout`](crate::ConnectOptions::command_timeout)
+    /// for operations made through the handle, e.g. to give one slow
+    /// report more time. With a retry policy, each attempt gets `timeout`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let patient_db = db.with_timeout(Duration::from_secs(30));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            client: self.client.with_timeout(timeout),
+            replicas: self.map_replicas(|conn| conn.with_timeout(timeout)),
+            ..self.clone()
+        }
+    }
+
+    /// Return a handle that reads from the primary
+    ///
+    /// With read replicas, see
+    /// [`ConnectOptions::replica`](crate::ConnectOptions::replica), reads go
+    /// to a replica, which may not have applied the latest writes yet. Read
+    /// through this handle where that matters, e.g. right after a save.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::builder()
+    ///     .url("redis://primary:6379")
+    ///     .replica("redis://replica-1:6379")
+    ///     .build()
+    ///     .await?;
+    /// User { id: "1".into(), name: "John".into() }.save(&db).await?;
+    /// let user = User::find_by_id(&db.primary(), "1").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn primary(&self) -> Self {
+        Self {
+            replicas: None,
+            ..self.clone()
+        }
+    }
+
+    /// Get the number of read replicas this handle reads from
+    pub fn replica_count(&self) -> usize {
+        self.replicas
+            .as_ref()
+            .map_or(0, |replicas| replicas.connections.len())
+    }
+
+    pub(crate) fn replica_connections(&self) -> &[Connection] {
+        self.replicas
+            .as_ref()
+            .map_or(&[], |replicas| &replicas.connections)
+    }
+
+    pub(crate) fn with_replicas(mut self, connections: Vec<Connection>) -> Self {
+        self.replicas = Replicas::new(connections);
+        self
+    }
+
+    fn map_replicas(&self, f: impl Fn(&Connection) -> Connection) -> Option<Arc<Replicas>> {
+        let replicas = self.replicas.as_ref()?;
+        Replicas::new(replicas.connections.iter().map(f).collect())
+    }
+
+    /// Get the handle to read with: this one, or one on the next replica
+    pub(crate) fn reader(&self) -> Cow<'_, TormDb> {
+        let Some(replicas) = &self.replicas else {
+            return Cow::Borrowed(self);
+        };
+        let next = replicas.next.fetch_add(1, Ordering::Relaxed) % replicas.connections.len();
+        Cow::Owned(Self {
+            client: replicas.connections[next].clone(),
+            replicas: None,
+            ..self.clone()
+        })
+    }
+
+    /// Return a handle that keeps every key under `prefix`
+    ///
+    /// Documents are stored as `{prefix}:{collection}:{id}` and TORM's own
+    /// keys, such as indexes, counters and migration records, get the same
+    /// prefix, so several environments can share one ToonStore. Prefixes
+    /// nest: `db.with_prefix("a").with_prefix("b")` uses `a:b`. Observers
+    /// registered with [`TormDb::on_event`] are shared by all prefixes.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?.with_prefix("staging");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_prefix(&self, prefix: impl AsRef<str>) -> Self {
+        Self {
+            prefix: Some(Arc::from(self.key(prefix.as_ref()))),
+            ..self.clone()
+        }
+    }
+
+    /// Get the key prefix of this handle, if any
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Get the full key for `key` under this handle's prefix
+    ///
+    /// Returns `key` unchanged without a prefix. Use it for keys read or
+    /// written outside of models, so they follow [`TormDb::with_prefix`].
+    pub fn key(&self, key: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, key),
+            None => key.to_string(),
+        }
+    }
+
+    /// Get the SCAN pattern matching `pattern` under this handle's prefix
+    ///
+    /// Glob characters in the prefix are escaped, so a prefix such as
+    /// `a*` doesn't match other prefixes' keys.
+    pub(crate) fn key_pattern(&self, pattern: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", crate::scan::escape_pattern(prefix), pattern),
+            None => pattern.to_string(),
+        }
+    }
+
+    /// Return a handle scoped to tenant `id`
+    ///
+    /// Every operation made through the handle reads and writes only the
+    /// tenant's keyspace, `tenant:{id}:`, under this handle's own prefix
+    /// if it has one. Events it publishes carry the tenant in
+    /// [`ModelEvent::tenant`](crate::ModelEvent::tenant). Like
+    /// [`TormDb::with_actor`], it shares the connection, so create one
+    /// per request.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let acme = db.tenant("acme");
+    /// assert_eq!(acme.tenant_id(), Some("acme"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tenant(&self, id: impl Into<String>) -> Self {
+        let id = id.into();
+        Self {
+            tenant: Some(Arc::from(id.as_str())),
+            ..self.with_prefix(format!("tenant:{}", id))
+        }
+    }
+
+    /// Get the tenant this handle is scoped to, if any
+    pub fn tenant_id(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Whether the server has the RedisJSON module
+    ///
+    /// Checked once when connecting; [`JsonStore`](crate::JsonStore) uses
+    /// RedisJSON commands only if it is set.
+    pub fn has_json_module(&self) -> bool {
+        self.json_module
+    }
+
+    /// Whether the server has the RediSearch module
+    ///
+    /// Checked once when connecting; queries run on RediSearch with
+    /// [`QueryBuilder::search`](crate::QueryBuilder::search) only if it is
+    /// set.
+    pub fn has_search_module(&self) -> bool {
+        self.search_module
+    }
+
+    /// Look up the server's modules
+    pub(crate) async fn detect_modules(mut self) -> Self {
+        let modules = crate::json::list_modules(&self).await;
+        self.json_module = crate::json::lists_module(&modules, crate::json::MODULE_NAME);
+        self.search_module = crate::json::lists_module(&modules, "search");
+        self
+    }
+
+    /// Get a handle on a collection for schemaless queries
+    ///
+    /// See [`Collection::query`] for querying documents as raw JSON when
+    /// there is no model type for them.
+    pub fn collection(&self, name: impl Into<String>) -> Collection {
+        Collection::new(name)
+    }
+
+    /// Get the actor attached to this handle, if any
+    pub fn actor(&self) -> Option<&str> {
+        self.actor.as_deref()
+    }
+
+    pub(crate) fn cdc_enabled(&self) -> bool {
+        self.cdc
+    }
+
+    pub(crate) fn observers(&self) -> &Observers {
+        &self.observers
+    }
 
-    #[tokio::test]
-    #[ignore] // Requires running ToonStore server
-    async fn test_connect() {
-        let result = TormDb::connect("redis://localhost:6379").await;
-        assert!(result.is_ok());
+    pub(crate) fn op_stats(&self) -> &OpStats {
+        &self.op_stats
     }
 }
@@ -0,0 +1,107 @@
+//! Embedded store for local development
+//!
+//! Launches a throwaway ToonStore (or any Redis-compatible) server as a child
+//! process on a free local port. The process lives as long as the last
+//! [`TormDb`] handle that uses it and is killed when that handle is dropped.
+
+use crate::{Error, Result, TormDb};
+use std::net::TcpListener;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// Environment variable overriding the server binary to launch
+pub const EMBEDDED_BIN_ENV: &str = "TORM_EMBEDDED_BIN";
+
+/// Binaries tried in order when no override is set
+const DEFAULT_BINARIES: &[&str] = &["toonstore", "redis-server"];
+
+/// How long to wait for the spawned server to answer PING
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handle to the spawned server process
+pub(crate) struct EmbeddedServer {
+    _child: Child,
+}
+
+impl TormDb {
+    /// Launch an in-process store for development and connect to it
+    ///
+    /// The server binary is taken from `TORM_EMBEDDED_BIN`, falling back to
+    /// `toonstore` and then `redis-server` on `PATH`. Persistence is disabled,
+    /// so all data is lost when the last handle is dropped.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::embedded().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn embedded() -> Result<Self> {
+        let port = free_port()?;
+        let child = spawn_server(port)?;
+
+        let url = format!("redis://127.0.0.1:{}", port);
+        let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            match TormDb::connect(&url).await {
+                Ok(mut db) => {
+                    db.embedded = Some(std::sync::Arc::new(EmbeddedServer { _child: child }));
+                    return Ok(db);
+                }
+                Err(e) if tokio::time::Instant::now() >= deadline => {
+                    return Err(Error::Connection(format!(
+                        "embedded server did not start on port {}: {}",
+                        port, e
+                    )));
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }
+}
+
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| Error::Connection(format!("no free port available: {}", e)))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| Error::Connection(e.to_string()))?
+        .port();
+    Ok(port)
+}
+
+fn spawn_server(port: u16) -> Result<Child> {
+    let candidates: Vec<String> = match std::env::var(EMBEDDED_BIN_ENV) {
+        Ok(bin) => vec![bin],
+        Err(_) => DEFAULT_BINARIES.iter().map(|b| b.to_string()).collect(),
+    };
+
+    for bin in &candidates {
+        let spawned = Command::new(bin)
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--bind")
+            .arg("127.0.0.1")
+            .arg("--save")
+            .arg("")
+            .arg("--appendonly")
+            .arg("no")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn();
+
+        if let Ok(child) = spawned {
+            return Ok(child);
+        }
+    }
+
+    Err(Error::Connection(format!(
+        "could not launch an embedded server (tried: {}); set {} to a ToonStore or Redis binary",
+        candidates.join(", "),
+        EMBEDDED_BIN_ENV
+    )))
+}
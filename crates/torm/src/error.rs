@@ -10,16 +10,32 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     /// Redis error
     #[error("Redis error: {0}")]
-    Redis(#[from] redis::RedisError),
+    Redis(redis::RedisError),
+
+    /// A command got no response within its timeout
+    #[error("Timed out: {0}")]
+    Timeout(String),
 
     /// Serialization error
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
+    /// Storage codec error
+    #[error("Codec error: {0}")]
+    Codec(String),
+
+    /// I/O error
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
     /// Model not found
     #[error("Model not found: {0}")]
     NotFound(String),
 
+    /// Write conflicted with the current state of the store
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     /// Validation error
     #[error("Validation error: {0}")]
     Validation(String),
@@ -32,7 +48,33 @@ pub enum Error {
     #[error("Invalid query: {0}")]
     InvalidQuery(String),
 
+    /// Query was aborted after exceeding its timeout or scan limit
+    #[error(
+        "Query on '{collection}' exceeded {limit}: scanned {} keys and matched {} in {:?}",
+        stats.scanned,
+        stats.matched,
+        stats.elapsed
+    )]
+    QueryLimitExceeded {
+        /// Collection being queried
+        collection: String,
+        /// Which limit was exceeded
+        limit: crate::query::QueryLimit,
+        /// Statistics gathered before the query was aborted
+        stats: crate::query::QueryStats,
+    },
+
     /// Generic error
     #[error("{0}")]
     Other(String),
 }
+
+impl From<redis::RedisError> for Error {
+    fn from(e: redis::RedisError) -> Self {
+        if e.is_timeout() {
+            Error::Timeout(e.to_string())
+        } else {
+            Error::Redis(e)
+        }
+    }
+}
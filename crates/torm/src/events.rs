@@ -0,0 +1,115 @@
+//! Model lifecycle events
+//!
+//! Every save and delete builds a [`ModelEvent`] that is recorded in the
+//! audit trail (when an actor is attached) and handed to all observers
+//! registered with [`TormDb::on_event`].
+
+use crate::{Result, TormDb};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+/// Kind of lifecycle operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelOp {
+    /// Document was created or updated
+    Save,
+    /// Document was deleted
+    Delete,
+}
+
+/// A model lifecycle event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelEvent {
+    /// Collection of the affected document
+    pub collection: String,
+    /// ID of the affected document
+    pub id: String,
+    /// What happened to the document
+    pub op: ModelOp,
+    /// Document contents after a save
+    pub payload: Option<serde_json::Value>,
+    /// Actor attached to the handle that made the change
+    pub actor: Option<String>,
+    /// Tenant of the handle that made the change, see [`TormDb::tenant`]
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+type Observer = Arc<dyn Fn(&ModelEvent) + Send + Sync>;
+
+/// Observers shared by all clones of a [`TormDb`]
+#[derive(Clone, Default)]
+pub(crate) struct Observers {
+    inner: Arc<RwLock<Vec<Observer>>>,
+}
+
+impl Observers {
+    pub(crate) fn add(&self, observer: Observer) {
+        self.inner
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(observer);
+    }
+
+    fn notify(&self, event: &ModelEvent) {
+        // Clone the list so observers can register more observers
+        let observers = self.inner.read().unwrap_or_else(|e| e.into_inner()).clone();
+        for observer in observers {
+            observer(event);
+        }
+    }
+}
+
+impl TormDb {
+    /// Register an observer for model lifecycle events
+    ///
+    /// The observer is called synchronously after every save and delete
+    /// made through this handle or any of its clones, across all
+    /// collections. Keep it cheap; hand heavy work off to a task.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{ModelOp, TormDb};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// db.on_event(|event| {
+    ///     if event.op == ModelOp::Delete {
+    ///         println!("invalidate {}:{}", event.collection, event.id);
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_event<F>(&self, observer: F)
+    where
+        F: Fn(&ModelEvent) + Send + Sync + 'static,
+    {
+        self.observers().add(Arc::new(observer));
+    }
+}
+
+/// Publish an event to the audit trail and all observers
+pub(crate) async fn publish(
+    db: &TormDb,
+    op: ModelOp,
+    collection: &str,
+    id: &str,
+    payload: Option<serde_json::Value>,
+) -> Result<()> {
+    let event = ModelEvent {
+        collection: collection.to_string(),
+        id: id.to_string(),
+        op,
+        payload,
+        actor: db.actor().map(str::to_string),
+        tenant: db.tenant_id().map(str::to_string),
+    };
+
+    crate::audit::record(db, &event).await?;
+    crate::cdc::record(db, &event).await?;
+    db.observers().notify(&event);
+
+    Ok(())
+}
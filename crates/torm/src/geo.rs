@@ -0,0 +1,149 @@
+//! Geospatial fields and queries
+//!
+//! A [`GeoPoint`] field marked `#[geo]` is indexed like any `#[index]`
+//! field, except that its coordinates go into a GEO key,
+//! `torm:index:{collection}:{field}:geo`, which [`Query::Near`] and
+//! [`Query::WithinBox`] filters search with GEOSEARCH once the indexes are
+//! built. Without it, or for other fields, the same filters are evaluated
+//! on each scanned document.
+
+use crate::Query;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Earth radius in meters, as used by ToonStore's GEO commands
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+
+/// Latitudes GEOADD accepts
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+/// A latitude/longitude pair in degrees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    /// Latitude in degrees
+    pub lat: f64,
+    /// Longitude in degrees
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    /// Create a point
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// Great-circle distance to `other` in meters
+    pub fn distance(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    /// Read a point stored as `{"lat": .., "lon": ..}`
+    pub(crate) fn from_value(value: &Value) -> Option<Self> {
+        let point = Self::new(value.get("lat")?.as_f64()?, value.get("lon")?.as_f64()?);
+        (point.lat.abs() <= MAX_LATITUDE && point.lon.abs() <= 180.0).then_some(point)
+    }
+}
+
+/// Get the GEO key indexing a field's points
+pub fn geo_key(collection: &str, field: &str) -> String {
+    format!("{}:geo", crate::index::index_key(collection, field))
+}
+
+/// Whether a stored value satisfies a geospatial filter
+pub(crate) fn matches(value: Option<&Value>, query: &Query) -> bool {
+    let Some(point) = value.and_then(GeoPoint::from_value) else {
+        return false;
+    };
+    match *query {
+        Query::Near { lat, lon, radius } => point.distance(&GeoPoint::new(lat, lon)) <= radius,
+        Query::WithinBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        } => (min_lat..=max_lat).contains(&point.lat) && (min_lon..=max_lon).contains(&point.lon),
+        _ => false,
+    }
+}
+
+/// The GEOSEARCH of `key` listing the IDs that may match a geospatial filter
+///
+/// The search area is slightly larger than the filter's, so candidates
+/// are a superset of the matches.
+pub(crate) fn search(key: String, query: &Query) -> Option<redis::Cmd> {
+    let mut cmd = redis::cmd("GEOSEARCH");
+    cmd.arg(key);
+    match *query {
+        Query::Near { lat, lon, radius } => {
+            cmd.arg("FROMLONLAT")
+                .arg(lon)
+                .arg(lat)
+                .arg("BYRADIUS")
+                .arg(radius * 1.001 + 1.0)
+                .arg("m");
+        }
+        Query::WithinBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        } => {
+            if min_lat > max_lat || min_lon > max_lon {
+                return None;
+            }
+            // The box is widest at the latitude closest to the equator
+            let widest = if min_lat <= 0.0 && max_lat >= 0.0 {
+                0.0
+            } else {
+                min_lat.abs().min(max_lat.abs())
+            };
+            let width = EARTH_RADIUS * widest.to_radians().cos() * (max_lon - min_lon).to_radians();
+            let height = EARTH_RADIUS * (max_lat - min_lat).to_radians();
+            cmd.arg("FROMLONLAT")
+                .arg((min_lon + max_lon) / 2.0)
+                .arg((min_lat + max_lat) / 2.0)
+                .arg("BYBOX")
+                .arg(width * 1.01 + 1.0)
+                .arg(height * 1.01 + 1.0)
+                .arg("m");
+        }
+        _ => return None,
+    }
+    Some(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches() {
+        let paris = json!({ "lat": 48.8566, "lon": 2.3522 });
+        let london = GeoPoint::new(51.5074, -0.1278);
+
+        let distance = GeoPoint::from_value(&paris).unwrap().distance(&london);
+        assert!((distance - 343_500.0).abs() < 1_000.0);
+
+        assert!(matches(
+            Some(&paris),
+            &Query::near(51.5074, -0.1278, 350_000.0)
+        ));
+        assert!(!matches(
+            Some(&paris),
+            &Query::near(51.5074, -0.1278, 300_000.0)
+        ));
+        assert!(matches(
+            Some(&paris),
+            &Query::within_box(48.0, 2.0, 49.0, 3.0)
+        ));
+        assert!(!matches(
+            Some(&json!("Paris")),
+            &Query::within_box(48.0, 2.0, 49.0, 3.0)
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+//! Models stored as Redis hashes
+//!
+//! [`HashStore`] writes each model as a hash with one field per top-level
+//! attribute instead of one encoded document, so single fields can be read
+//! with HGET, projected with HMGET and incremented with HINCRBY without
+//! fetching the document or running a script. Field values are stored as
+//! JSON, which keeps numbers as plain digits for HINCRBY.
+//!
+//! Hashes live under the same `{collection}:{id}` keys as documents, so a
+//! collection uses one layout or the other. Queries, index builds, bulk
+//! writes and the other [`Model`] methods read encoded documents and skip
+//! hash-stored ones; secondary indexes, the document counter, the
+//! insertion order and lifecycle events are kept up to date by the store.
+
+use crate::{Error, Model, Result, TormDb};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Reads and writes models as Redis hashes
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{HashStore, Model, TormDb};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Model, Serialize, Deserialize)]
+/// # struct Post { #[id] id: String, title: String, views: i64 }
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let posts = HashStore::<Post>::new(&db);
+/// posts
+///     .save(&Post { id: "1".into(), title: "Hello".into(), views: 0 })
+///     .await?;
+/// let views = posts.incr("1", "views", 1).await?;
+/// let title: Option<String> = posts.get("1", "title").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct HashStore<M> {
+    db: TormDb,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M: Model> HashStore<M> {
+    /// Create a store for `M` on `db`
+    pub fn new(db: &TormDb) -> Self {
+        Self {
+            db: db.clone(),
+            _model: PhantomData,
+        }
+    }
+
+    fn key(&self, id: &str) -> String {
+        self.db.key(&format!("{}:{}", M::collection(), id))
+    }
+
+    /// Validate and write a model, replacing all of its fields
+    #[tracing::instrument(name = "torm.hash.save", level = "info", skip_all, fields(collection = M::collection(), id = model.id()))]
+    pub async fn save(&self, model: &M) -> Result<()> {
+        model.validate()?;
+        let doc = serde_json::to_value(model)?;
+        let Value::Object(fields) = &doc else {
+            return Err(Error::Other(format!(
+                "model '{}' does not serialize to an object",
+                M::collection()
+            )));
+        };
+
+        let key = self.key(model.id());
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("HGETALL")
+            .arg(&key)
+            .cmd("DEL")
+            .arg(&key)
+            .ignore();
+        if !fields.is_empty() {
+            pipe.cmd("HSET").arg(&key);
+            for (field, value) in fields {
+                pipe.arg(field).arg(serde_json::to_string(value)?);
+            }
+            pipe.ignore();
+        }
+        let mut conn = self.db.connection().clone();
+        let (previous,): (HashMap<String, String>,) = pipe.query_async(&mut conn).await?;
+
+        if previous.is_empty() {
+            crate::counter::adjust(&self.db, M::collection(), 1).await?;
+        }
+        let previous = (!previous.is_empty())
+            .then(|| decode(previous))
+            .transpose()?;
+        crate::index::update(
+            &self.db,
+            M::collection(),
+            M::indexes(),
+            model.id(),
+            previous.as_ref(),
+            Some(&doc),
+        )
+        .await?;
+        if M::ordered() {
+            crate::order::track(&self.db, M::collection(), model.id(), true).await?;
+        }
+
+        crate::events::publish(
+            &self.db,
+            crate::ModelOp::Save,
+            M::collection(),
+            model.id(),
+            Some(doc),
+        )
+        .await
+    }
+
+    /// Read a model from its fields
+    pub async fn find(&self, id: &str) -> Result<M> {
+        let mut conn = self.db.connection().clone();
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(self.key(id))
+            .query_async(&mut conn)
+            .await?;
+        if fields.is_empty() {
+            return Err(Error::NotFound(format!("{}:{}", M::collection(), id)));
+        }
+        let mut model: M = serde_json::from_value(decode(fields)?)?;
+        model.after_load();
+        Ok(model)
+    }
+
+    /// Read one field of a model with HGET
+    ///
+    /// Returns `None` if the model or the field doesn't exist.
+    pub async fn get<V: DeserializeOwned>(&self, id: &str, field: &str) -> Result<Option<V>> {
+        let mut conn = self.db.connection().clone();
+        let value: Option<String> = redis::cmd("HGET")
+            .arg(self.key(id))
+            .arg(field)
+            .query_async(&mut conn)
+            .await?;
+        Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+    }
+
+    /// Read some fields of a model with HMGET
+    ///
+    /// Fields that don't exist are left out, so a missing model gives an
+    /// empty object.
+    pub async fn project(&self, id: &str, fields: &[&str]) -> Result<Map<String, Value>> {
+        if fields.is_empty() {
+            return Ok(Map::new());
+        }
+        let mut conn = self.db.connection().clone();
+        let values: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(self.key(id))
+            .arg(fields)
+            .query_async(&mut conn)
+            .await?;
+        fields
+            .iter()
+            .zip(values)
+            .filter_map(|(field, value)| Some((field.to_string(), value?)))
+            .map(|(field, value)| Ok((field, serde_json::from_str(&value)?)))
+            .collect()
+    }
+
+    /// Add `by` to an integer field with HINCRBY and return the new value
+    ///
+    /// A missing field starts at 0. Fails with [`Error::NotFound`] if the
+    /// model doesn't exist, and with a response error if the field holds
+    /// something other than an integer.
+    pub async fn incr(&self, id: &str, field: &str, by: i64) -> Result<i64> {
+        let key = self.key(id);
+        let mut conn = self.db.connection().clone();
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await?;
+        if !exists {
+            return Err(Error::NotFound(format!("{}:{}", M::collection(), id)));
+        }
+        let value: i64 = redis::cmd("HINCRBY")
+            .arg(&key)
+            .arg(field)
+            .arg(by)
+            .query_async(&mut conn)
+            .await?;
+
+        if M::indexes().contains(&field) {
+            let old = serde_json::json!({ field: value - by });
+            let new = serde_json::json!({ field: value });
+            crate::index::update(
+                &self.db,
+                M::collection(),
+                M::indexes(),
+                id,
+                Some(&old),
+                Some(&new),
+            )
+            .await?;
+        }
+        crate::events::publish(&self.db, crate::ModelOp::Save, M::collection(), id, None).await?;
+        Ok(value)
+    }
+
+    /// Delete a model, returning whether it existed
+    pub async fn delete(&self, id: &str) -> Result<bool> {
+        let key = self.key(id);
+        let mut conn = self.db.connection().clone();
+        let (removed,): (HashMap<String, String>,) = redis::pipe()
+            .atomic()
+            .cmd("HGETALL")
+            .arg(&key)
+            .cmd("DEL")
+            .arg(&key)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        if removed.is_empty() {
+            return Ok(false);
+        }
+
+        crate::counter::adjust(&self.db, M::collection(), -1).await?;
+        let removed = decode(removed)?;
+        crate::index::update(
+            &self.db,
+            M::collection(),
+            M::indexes(),
+            id,
+            Some(&removed),
+            None,
+        )
+        .await?;
+        crate::order::untrack_insertion(&self.db, M::collection(), id).await?;
+        crate::events::publish(&self.db, crate::ModelOp::Delete, M::collection(), id, None).await?;
+        Ok(true)
+    }
+}
+
+/// Rebuild a document from its hash fields
+fn decode(fields: HashMap<String, String>) -> Result<Value> {
+    fields
+        .into_iter()
+        .map(|(field, value)| Ok((field, serde_json::from_str(&value)?)))
+        .collect::<Result<Map<_, _>>>()
+        .map(Value::Object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let fields = HashMap::from([
+            ("id".to_string(), "\"1\"".to_string()),
+            ("views".to_string(), "42".to_string()),
+            ("tags".to_string(), "[\"a\"]".to_string()),
+        ]);
+        assert_eq!(
+            decode(fields).unwrap(),
+            serde_json::json!({ "id": "1", "views": 42, "tags": ["a"] })
+        );
+        assert!(decode(HashMap::from([("id".to_string(), "1x".to_string())])).is_err());
+    }
+}
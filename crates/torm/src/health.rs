@@ -0,0 +1,114 @@
+//! Health of the connection and the server behind it
+
+use crate::{Result, TormDb};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// What [`TormDb::health`] found out
+///
+/// Serializes to JSON for readiness endpoints, with the latency in
+/// milliseconds as `latency_ms`. Server details are `None` when the
+/// server doesn't report them in `INFO`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Health {
+    /// Round trip of a `PING`
+    #[serde(rename = "latency_ms", serialize_with = "as_millis")]
+    pub latency: Duration,
+    /// Server version, `redis_version` in `INFO`
+    pub server_version: Option<String>,
+    /// Bytes of memory used by the server's data, `used_memory` in `INFO`
+    pub used_memory: Option<u64>,
+    /// Number of client connections, `connected_clients` in `INFO`
+    pub connected_clients: Option<u64>,
+}
+
+fn as_millis<S: Serializer>(
+    latency: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(latency.as_micros() as f64 / 1000.0)
+}
+
+/// Read the `field:value` lines of an `INFO` reply, skipping section
+/// headers
+fn parse_info(info: &str) -> HashMap<&str, &str> {
+    info.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.trim_end().split_once(':'))
+        .collect()
+}
+
+impl TormDb {
+    /// Check the connection and report on the server
+    ///
+    /// Fails if the server doesn't answer a `PING`. Details come from
+    /// `INFO`; a server that refuses it is still healthy, with the
+    /// details left out.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let health = db.health().await?;
+    /// println!("ping {:?}, {:?} clients", health.latency, health.connected_clients);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn health(&self) -> Result<Health> {
+        let mut conn = self.connection().clone();
+        let started = Instant::now();
+        redis::cmd("PING").query_async::<()>(&mut conn).await?;
+        let latency = started.elapsed();
+
+        let info: String = redis::cmd("INFO")
+            .query_async(&mut conn)
+            .await
+            .unwrap_or_default();
+        let fields = parse_info(&info);
+        let number = |name: &str| fields.get(name).and_then(|v| v.parse().ok());
+
+        Ok(Health {
+            latency,
+            server_version: fields.get("redis_version").map(|v| v.to_string()),
+            used_memory: number("used_memory"),
+            connected_clients: number("connected_clients"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let info = "# Server\r\nredis_version:7.2.4\r\nredis_mode:standalone\r\n\r\n\
+                    # Clients\r\nconnected_clients:3\r\n";
+        let fields = parse_info(info);
+        assert_eq!(fields.get("redis_version"), Some(&"7.2.4"));
+        assert_eq!(fields.get("connected_clients"), Some(&"3"));
+        assert!(!fields.contains_key("# Server"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let health = Health {
+            latency: Duration::from_micros(1500),
+            server_version: Some("7.2.4".into()),
+            used_memory: None,
+            connected_clients: Some(3),
+        };
+        assert_eq!(
+            serde_json::to_value(&health).unwrap(),
+            serde_json::json!({
+                "latency_ms": 1.5,
+                "server_version": "7.2.4",
+                "used_memory": null,
+                "connected_clients": 3
+            })
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! ID generation strategies
+
+use crate::{Result, TormDb};
+
+/// How new model IDs are generated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdStrategy {
+    /// Random UUID v4 strings (via [`crate::Model::generate_id`])
+    #[default]
+    Uuid,
+    /// Monotonically increasing integers from `INCR torm:seq:{collection}`
+    Sequence,
+}
+
+/// Get the key holding the ID sequence for a collection
+pub fn sequence_key(collection: &str) -> String {
+    format!("torm:seq:{}", collection)
+}
+
+/// Take the next value from a collection's ID sequence
+pub(crate) async fn next_sequence(db: &TormDb, collection: &str) -> Result<u64> {
+    let mut conn = db.connection().clone();
+    let next: u64 = redis::cmd("INCR")
+        .arg(db.key(&sequence_key(collection)))
+        .query_async(&mut conn)
+        .await?;
+    Ok(next)
+}
@@ -0,0 +1,613 @@
+//! Secondary indexes on model fields
+//!
+//! Every value of a field marked `#[index]` gets a set of document IDs,
+//! `torm:index:{collection}:{field}:{value}`, and numeric values are also
+//! kept in a sorted set, `torm:index:{collection}:{field}`, scored by the
+//! value, and points in a GEO key (see [`crate::geo`]). A HyperLogLog,
+//! `torm:index:{collection}:{field}:hll`, estimates how many distinct
+//! values the field has held. Writes through
+//! TORM keep them up to date; writers that bypass it, such as TORM
+//! Server, do not.
+//!
+//! An index is only used by queries once it has been built over the
+//! existing documents with [`crate::Model::build_indexes`], which records
+//! the field in `torm:indexed:{collection}`. Until then queries scan.
+
+use crate::query::{Filter, Query};
+use crate::scan::KeyScanner;
+use crate::{Agg, Codec, GeoPoint, Result, TormDb};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Get the key of the sorted set indexing a field's numeric values
+pub fn index_key(collection: &str, field: &str) -> String {
+    format!("torm:index:{}:{}", collection, field)
+}
+
+/// Key of the set of IDs whose `field` equals `value`
+///
+/// Numbers are normalized so `30` and `30.0` share a set. Arrays, objects
+/// and `null` are not indexed.
+fn value_key(collection: &str, field: &str, value: &Value) -> Option<String> {
+    let token = match value {
+        Value::String(s) => serde_json::to_string(s).ok()?,
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => {
+            let n = n.as_f64()?;
+            if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 {
+                (n as i64).to_string()
+            } else {
+                n.to_string()
+            }
+        }
+        _ => return None,
+    };
+    Some(format!("{}:{}", index_key(collection, field), token))
+}
+
+/// Key of the HyperLogLog counting a field's distinct values
+fn hll_key(collection: &str, field: &str) -> String {
+    format!("{}:hll", index_key(collection, field))
+}
+
+/// Get the key listing a collection's fully built indexes
+pub fn built_key(collection: &str) -> String {
+    format!("torm:indexed:{}", collection)
+}
+
+/// Move document `id` from the index entries of `old` to those of `new`
+///
+/// Pass `None` for `old` when the document is created and for `new` when
+/// it is deleted.
+pub(crate) async fn update(
+    db: &TormDb,
+    collection: &str,
+    fields: &[&str],
+    id: &str,
+    old: Option<&Value>,
+    new: Option<&Value>,
+) -> Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+
+    let mut pipe = redis::pipe();
+    for field in fields {
+        let old = old.and_then(|doc| doc.get(*field));
+        let new = new.and_then(|doc| doc.get(*field));
+
+        let old_set = old.and_then(|v| value_key(collection, field, v).map(|k| db.key(&k)));
+        let new_set = new.and_then(|v| value_key(collection, field, v).map(|k| db.key(&k)));
+        if old_set != new_set {
+            if let Some(key) = old_set {
+                pipe.cmd("SREM").arg(key).arg(id).ignore();
+            }
+            if let Some(key) = new_set {
+                pipe.cmd("SADD").arg(&key).arg(id).ignore();
+                pipe.cmd("PFADD")
+                    .arg(db.key(&hll_key(collection, field)))
+                    .arg(key)
+                    .ignore();
+            }
+        }
+
+        let geo = db.key(&crate::geo::geo_key(collection, field));
+        match new.and_then(GeoPoint::from_value) {
+            Some(point) => {
+                pipe.cmd("GEOADD")
+                    .arg(&geo)
+                    .arg(point.lon)
+                    .arg(point.lat)
+                    .arg(id)
+                    .ignore();
+            }
+            None if old.and_then(GeoPoint::from_value).is_some() => {
+                pipe.cmd("ZREM").arg(&geo).arg(id).ignore();
+            }
+            None => {}
+        }
+
+        match new.and_then(Value::as_f64) {
+            Some(score) => {
+                pipe.cmd("ZADD")
+                    .arg(db.key(&index_key(collection, field)))
+                    .arg(score)
+                    .arg(id)
+                    .ignore();
+            }
+            None if old.is_some_and(Value::is_number) => {
+                pipe.cmd("ZREM")
+                    .arg(db.key(&index_key(collection, field)))
+                    .arg(id)
+                    .ignore();
+            }
+            None => {}
+        }
+    }
+
+    let mut conn = db.connection().clone();
+    pipe.query_async::<()>(&mut conn).await?;
+    Ok(())
+}
+
+/// Like [`update`], decoding stored documents with `codec`
+pub(crate) async fn update_encoded(
+    db: &TormDb,
+    collection: &str,
+    fields: &[&str],
+    codec: Codec,
+    id: &str,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+) -> Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let old: Option<Value> = old.map(|v| codec.decode(v)).transpose()?;
+    let new: Option<Value> = new.map(|v| codec.decode(v)).transpose()?;
+    update(db, collection, fields, id, old.as_ref(), new.as_ref()).await
+}
+
+/// Rebuild the indexes of `fields` from the stored documents
+///
+/// Returns how many documents were indexed.
+pub(crate) async fn build(
+    db: &TormDb,
+    collection: &str,
+    fields: &[&str],
+    codec: Codec,
+) -> Result<usize> {
+    let mut conn = db.connection().clone();
+    redis::cmd("DEL")
+        .arg(db.key(&built_key(collection)))
+        .query_async::<()>(&mut conn)
+        .await?;
+    for field in fields {
+        let key = index_key(collection, field);
+        redis::cmd("DEL")
+            .arg(db.key(&key))
+            .query_async::<()>(&mut conn)
+            .await?;
+        clear_keys(db, &db.key_pattern(&format!("{}:*", key))).await?;
+    }
+
+    let prefix = db.key(&format!("{}:", collection));
+    let mut scanner = KeyScanner::new(db.key_pattern(&format!("{}:*", collection)));
+    let mut indexed = 0;
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        if keys.is_empty() {
+            continue;
+        }
+        let values: Vec<Option<Vec<u8>>> =
+            redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+        for (key, value) in keys.iter().zip(values) {
+            let (Some(id), Some(value)) = (key.strip_prefix(&prefix), value) else {
+                continue;
+            };
+            let Ok(doc) = codec.decode::<Value>(&value) else {
+                continue;
+            };
+            update(db, collection, fields, id, None, Some(&doc)).await?;
+            indexed += 1;
+        }
+    }
+
+    if !fields.is_empty() {
+        redis::cmd("SADD")
+            .arg(db.key(&built_key(collection)))
+            .arg(fields)
+            .query_async::<()>(&mut conn)
+            .await?;
+    }
+    Ok(indexed)
+}
+
+/// Estimate how many distinct values an indexed field has
+///
+/// Values are never removed from the HyperLogLog, so ones no document
+/// holds anymore still count until the index is rebuilt. Returns `None`
+/// if the field's index is not built.
+pub(crate) async fn distinct_estimate(
+    db: &TormDb,
+    collection: &str,
+    field: &str,
+) -> Result<Option<usize>> {
+    let mut conn = db.connection().clone();
+    let built: bool = redis::cmd("SISMEMBER")
+        .arg(db.key(&built_key(collection)))
+        .arg(field)
+        .query_async(&mut conn)
+        .await?;
+    if !built {
+        return Ok(None);
+    }
+    let count: usize = redis::cmd("PFCOUNT")
+        .arg(db.key(&hll_key(collection, field)))
+        .query_async(&mut conn)
+        .await?;
+    Ok(Some(count))
+}
+
+/// Remove every index of a collection, keeping the built markers
+pub(crate) async fn clear(db: &TormDb, collection: &str) -> Result<()> {
+    clear_keys(db, &db.key_pattern(&format!("torm:index:{}:*", collection))).await
+}
+
+async fn clear_keys(db: &TormDb, pattern: &str) -> Result<()> {
+    let mut conn = db.connection().clone();
+    let mut scanner = KeyScanner::new(pattern);
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        if !keys.is_empty() {
+            redis::cmd("UNLINK")
+                .arg(&keys)
+                .query_async::<()>(&mut conn)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Candidate documents for a query, read from indexes
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Plan {
+    /// Indexed fields the candidates were resolved with
+    pub(crate) fields: Vec<String>,
+    /// IDs of the candidate documents, sorted
+    pub(crate) ids: Vec<String>,
+}
+
+/// Resolve the top-level conditions of `filters` that indexes can answer
+///
+/// `eq` and `in` on scalar values use the value sets, numeric ranges use
+/// the sorted set and geospatial filters the GEO key. Returns `None` if
+/// no condition can use a built index; otherwise the candidates are a
+/// superset of the matches and still need to be filtered.
+pub(crate) async fn plan(
+    db: &TormDb,
+    collection: &str,
+    fields: &[&str],
+    filters: &[Filter],
+) -> Result<Option<Plan>> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    let mut conn = db.connection().clone();
+    let built: HashSet<String> = redis::cmd("SMEMBERS")
+        .arg(db.key(&built_key(collection)))
+        .query_async(&mut conn)
+        .await?;
+
+    let mut plan = Plan::default();
+    let mut candidates: Option<HashSet<String>> = None;
+    for filter in filters {
+        let Filter::Field(field, query) = filter else {
+            continue;
+        };
+        if !fields.contains(&field.as_str()) || !built.contains(field) {
+            continue;
+        }
+        let Some(pipe) = lookup(&|key| db.key(key), collection, field, query) else {
+            continue;
+        };
+
+        let sets: Vec<HashSet<String>> = pipe.query_async(&mut conn).await?;
+        let ids: HashSet<String> = sets.into_iter().flatten().collect();
+        candidates = Some(match candidates {
+            Some(current) => current.intersection(&ids).cloned().collect(),
+            None => ids,
+        });
+        plan.fields.push(field.clone());
+    }
+
+    Ok(candidates.map(|ids| {
+        plan.ids = ids.into_iter().collect();
+        plan.ids.sort();
+        plan
+    }))
+}
+
+/// IDs of the documents whose `field` equals one of `values`, sorted
+///
+/// Returns `None` if the field's index isn't built or can't hold these
+/// values, so the documents must be scanned instead.
+pub(crate) async fn find_ids(
+    db: &TormDb,
+    collection: &str,
+    field: &str,
+    values: &[Value],
+) -> Result<Option<Vec<String>>> {
+    let mut conn = db.connection().clone();
+    let built: bool = redis::cmd("SISMEMBER")
+        .arg(db.key(&built_key(collection)))
+        .arg(field)
+        .query_async(&mut conn)
+        .await?;
+    if !built {
+        return Ok(None);
+    }
+    let Some(pipe) = lookup(
+        &|key| db.key(key),
+        collection,
+        field,
+        &Query::In(values.to_vec()),
+    ) else {
+        return Ok(None);
+    };
+
+    let sets: Vec<HashSet<String>> = pipe.query_async(&mut conn).await?;
+    let mut ids: Vec<String> = sets
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    ids.sort();
+    Ok(Some(ids))
+}
+
+/// Compute an aggregate from a field's sorted set, without reading documents
+///
+/// Only applies when the field's index is built and every filter is a
+/// numeric range on that field, so the matches are exactly the sorted
+/// set members within the range. `min` and `max` without filters also
+/// need every document in the sorted set, as others may hold strings.
+/// Returns `None` when the documents must be scanned instead.
+pub(crate) async fn aggregate(
+    db: &TormDb,
+    collection: &str,
+    fields: &[&str],
+    filters: &[Filter],
+    agg: &Agg,
+) -> Result<Option<Value>> {
+    let field = match agg {
+        Agg::Count => match filters.first() {
+            Some(Filter::Field(field, _)) => field.as_str(),
+            _ => return Ok(None),
+        },
+        Agg::Sum(field) | Agg::Avg(field) | Agg::Min(field) | Agg::Max(field) => field.as_str(),
+    };
+    if !fields.contains(&field) {
+        return Ok(None);
+    }
+    let Some((min, max)) = score_range(field, filters) else {
+        return Ok(None);
+    };
+
+    let mut conn = db.connection().clone();
+    let built: bool = redis::cmd("SISMEMBER")
+        .arg(db.key(&built_key(collection)))
+        .arg(field)
+        .query_async(&mut conn)
+        .await?;
+    if !built {
+        return Ok(None);
+    }
+
+    let key = db.key(&index_key(collection, field));
+    let value = match agg {
+        Agg::Count => {
+            let count: usize = redis::cmd("ZCOUNT")
+                .arg(&key)
+                .arg(min)
+                .arg(max)
+                .query_async(&mut conn)
+                .await?;
+            Value::from(count)
+        }
+        Agg::Sum(_) | Agg::Avg(_) => {
+            let scored: Vec<(String, f64)> = redis::cmd("ZRANGEBYSCORE")
+                .arg(&key)
+                .arg(min)
+                .arg(max)
+                .arg("WITHSCORES")
+                .query_async(&mut conn)
+                .await?;
+            let sum: f64 = scored.iter().map(|(_, score)| score).sum();
+            match agg {
+                Agg::Avg(_) if scored.is_empty() => Value::Null,
+                Agg::Avg(_) => Value::from(sum / scored.len() as f64),
+                _ => Value::from(sum),
+            }
+        }
+        Agg::Min(_) | Agg::Max(_) => {
+            if filters.is_empty() {
+                let indexed: usize = redis::cmd("ZCARD").arg(&key).query_async(&mut conn).await?;
+                if indexed != crate::counter::read(db, collection).await? {
+                    return Ok(None);
+                }
+            }
+            let mut cmd = if matches!(agg, Agg::Min(_)) {
+                let mut cmd = redis::cmd("ZRANGEBYSCORE");
+                cmd.arg(&key).arg(min).arg(max);
+                cmd
+            } else {
+                let mut cmd = redis::cmd("ZREVRANGEBYSCORE");
+                cmd.arg(&key).arg(max).arg(min);
+                cmd
+            };
+            let best: Vec<(String, f64)> = cmd
+                .arg("WITHSCORES")
+                .arg("LIMIT")
+                .arg(0)
+                .arg(1)
+                .query_async(&mut conn)
+                .await?;
+            best.first()
+                .map_or(Value::Null, |(_, score)| score_value(*score))
+        }
+    };
+    Ok(Some(value))
+}
+
+/// The sorted set score range matching all `filters`, if every one is a
+/// numeric range on `field`
+fn score_range(field: &str, filters: &[Filter]) -> Option<(String, String)> {
+    // (bound, inclusive), tightened by each filter
+    let mut low = (f64::NEG_INFINITY, true);
+    let mut high = (f64::INFINITY, true);
+    for filter in filters {
+        let Filter::Field(name, query) = filter else {
+            return None;
+        };
+        if name != field {
+            return None;
+        }
+        let (min, max) = match query {
+            Query::Gt(v) => (Some((v.as_f64()?, false)), None),
+            Query::Gte(v) => (Some((v.as_f64()?, true)), None),
+            Query::Lt(v) => (None, Some((v.as_f64()?, false))),
+            Query::Lte(v) => (None, Some((v.as_f64()?, true))),
+            Query::Between {
+                low,
+                high,
+                inclusive,
+            } => (
+                Some((low.as_f64()?, *inclusive)),
+                Some((high.as_f64()?, *inclusive)),
+            ),
+            _ => return None,
+        };
+        if let Some((n, inclusive)) = min {
+            if n > low.0 || (n == low.0 && !inclusive) {
+                low = (n, inclusive);
+            }
+        }
+        if let Some((n, inclusive)) = max {
+            if n < high.0 || (n == high.0 && !inclusive) {
+                high = (n, inclusive);
+            }
+        }
+    }
+
+    let format = |(n, inclusive): (f64, bool)| {
+        if n.is_infinite() {
+            let inf = if n > 0.0 { "+inf" } else { "-inf" };
+            inf.to_string()
+        } else if inclusive {
+            n.to_string()
+        } else {
+            format!("({}", n)
+        }
+    };
+    Some((format(low), format(high)))
+}
+
+/// A score as a JSON number, integral when it is a whole number
+fn score_value(score: f64) -> Value {
+    if score.fract() == 0.0 && score.abs() < 9_007_199_254_740_992.0 {
+        Value::from(score as i64)
+    } else {
+        Value::from(score)
+    }
+}
+
+/// The commands listing the IDs that may match `query` on an indexed field,
+/// to be unioned, with keys passed through `key`
+///
+/// `in` reads each value set separately rather than with `SUNION`, as the
+/// sets may live on different cluster nodes.
+fn lookup(
+    key: &dyn Fn(&str) -> String,
+    collection: &str,
+    field: &str,
+    query: &Query,
+) -> Option<redis::Pipeline> {
+    let bound = |v: &Value, inclusive: bool| {
+        let n = v.as_f64()?;
+        Some(if inclusive {
+            n.to_string()
+        } else {
+            format!("({}", n)
+        })
+    };
+    let range = |min: Option<String>, max: Option<String>| {
+        let mut cmd = redis::cmd("ZRANGEBYSCORE");
+        cmd.arg(key(&index_key(collection, field)))
+            .arg(min.unwrap_or_else(|| "-inf".to_string()))
+            .arg(max.unwrap_or_else(|| "+inf".to_string()));
+        cmd
+    };
+
+    let members = |values: &[Value]| {
+        let mut pipe = redis::pipe();
+        for value in values {
+            pipe.cmd("SMEMBERS")
+                .arg(key(&value_key(collection, field, value)?));
+        }
+        Some(pipe)
+    };
+    let single = |cmd: redis::Cmd| {
+        let mut pipe = redis::pipe();
+        pipe.add_command(cmd);
+        pipe
+    };
+
+    match query {
+        Query::Eq(value) => members(std::slice::from_ref(value)),
+        Query::In(values) if !values.is_empty() => members(values),
+        Query::Gt(v) => Some(single(range(Some(bound(v, false)?), None))),
+        Query::Gte(v) => Some(single(range(Some(bound(v, true)?), None))),
+        Query::Lt(v) => Some(single(range(None, Some(bound(v, false)?)))),
+        Query::Lte(v) => Some(single(range(None, Some(bound(v, true)?)))),
+        Query::Between {
+            low,
+            high,
+            inclusive,
+        } => Some(single(range(
+            Some(bound(low, *inclusive)?),
+            Some(bound(high, *inclusive)?),
+        ))),
+        Query::Near { .. } | Query::WithinBox { .. } => {
+            crate::geo::search(key(&crate::geo::geo_key(collection, field)), query).map(single)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_value_key() {
+        assert_eq!(
+            value_key("user", "age", &json!(30)),
+            value_key("user", "age", &json!(30.0))
+        );
+        assert_eq!(
+            value_key("user", "name", &json!("Ann")).unwrap(),
+            "torm:index:user:name:\"Ann\""
+        );
+        assert!(value_key("user", "tags", &json!(["a"])).is_none());
+        assert!(value_key("user", "nick", &Value::Null).is_none());
+    }
+
+    #[test]
+    fn test_score_range() {
+        let range = |filters: &[Filter]| score_range("age", filters);
+        assert_eq!(range(&[]), Some(("-inf".to_string(), "+inf".to_string())));
+        assert_eq!(
+            range(&[
+                Filter::field("age", Query::gte(18)),
+                Filter::field("age", Query::between(10, 65)),
+                Filter::field("age", Query::lt(65)),
+            ]),
+            Some(("18".to_string(), "(65".to_string()))
+        );
+        assert!(range(&[Filter::field("name", Query::gte(18))]).is_none());
+        assert!(range(&[Filter::field("age", Query::gte("18"))]).is_none());
+        assert!(range(&[Filter::field("age", Query::eq(18))]).is_none());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let cmd =
+            |q: Query| lookup(&str::to_string, "user", "age", &q).map(|p| p.get_packed_pipeline());
+        let packed = String::from_utf8(cmd(Query::gt(18)).unwrap()).unwrap();
+        assert!(packed.contains("ZRANGEBYSCORE") && packed.contains("(18"));
+        assert!(cmd(Query::gt("2024-01-01")).is_none());
+        assert!(cmd(Query::eq(Value::Null)).is_none());
+        assert!(cmd(Query::contains("x")).is_none());
+    }
+}
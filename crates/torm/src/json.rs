@@ -0,0 +1,364 @@
+//! Models stored with the RedisJSON module
+//!
+//! When the server has RedisJSON, [`JsonStore`] writes documents with
+//! `JSON.SET`, reads fields with `JSON.GET` paths and increments numbers in
+//! place with `JSON.NUMINCRBY`, so neither projections nor counters fetch
+//! the whole document. [`TormDb::connect`] and
+//! [`ConnectOptions::build`](crate::ConnectOptions::build) check for the
+//! module with `MODULE LIST`; see [`TormDb::has_json_module`].
+//!
+//! Without the module, or where `MODULE LIST` is not allowed, the store
+//! falls back to the model's own encoding: saves, finds and deletes go
+//! through [`Model`], projections decode the document client-side and
+//! increments run a Lua script. Either way a save or delete has the same
+//! side effects as [`Model::save`] and [`Model::delete`]: validation, the
+//! document counter, indexes, insertion order, the audit trail, CDC and
+//! observers.
+//!
+//! RedisJSON values are a key type of their own, so with the module a
+//! collection should be written only through the store; other [`Model`]
+//! methods and scanning queries don't read them, while
+//! [`QueryBuilder::search`](crate::QueryBuilder::search) queries do.
+
+use crate::{Error, Model, Result, TormDb};
+use serde_json::{Map, Value};
+use std::marker::PhantomData;
+
+/// Module name RedisJSON registers under
+pub(crate) const MODULE_NAME: &str = "ReJSON";
+
+/// Add ARGV[2] to the number in top-level field ARGV[1] of a stored JSON
+/// document and return the result, or `false` if the document is missing
+///
+/// Numbers in the document are re-encoded by the server's JSON library, as
+/// with [`Model::touch`].
+const INCR_FIELD_SCRIPT: &str = r#"
+local raw = redis.call('GET', KEYS[1])
+if not raw then
+    return false
+end
+local decode = cjson.decode_array_with_array_mt or cjson.decode
+local doc = decode(raw)
+local current = doc[ARGV[1]]
+if type(current) ~= 'number' then
+    return redis.error_reply('field ' .. ARGV[1] .. ' is not a number')
+end
+doc[ARGV[1]] = current + tonumber(ARGV[2])
+redis.call('SET', KEYS[1], cjson.encode(doc))
+return doc[ARGV[1]]
+"#;
+
+/// Run `JSON.NUMINCRBY` on KEYS[1] at path ARGV[1] by ARGV[2], or return
+/// `false` if the document is missing
+const NUMINCRBY_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return false
+end
+return redis.call('JSON.NUMINCRBY', KEYS[1], ARGV[1], ARGV[2])
+"#;
+
+/// List the modules of the server behind `db`, or `Nil` if it can't tell
+pub(crate) async fn list_modules(db: &TormDb) -> redis::Value {
+    let mut conn = db.connection().clone();
+    match redis::cmd("MODULE")
+        .arg("LIST")
+        .query_async::<redis::Value>(&mut conn)
+        .await
+    {
+        Ok(modules) => modules,
+        Err(e) => {
+            tracing::debug!(error = %e, "MODULE LIST failed, assuming no modules");
+            redis::Value::Nil
+        }
+    }
+}
+
+/// Whether a `MODULE LIST` reply names module `name`
+pub(crate) fn lists_module(reply: &redis::Value, name: &str) -> bool {
+    use redis::Value;
+    match reply {
+        Value::BulkString(bytes) => bytes.eq_ignore_ascii_case(name.as_bytes()),
+        Value::SimpleString(s) => s.eq_ignore_ascii_case(name),
+        Value::Array(items) | Value::Set(items) => {
+            items.iter().any(|item| lists_module(item, name))
+        }
+        Value::Map(entries) => entries
+            .iter()
+            .any(|(k, v)| lists_module(k, name) || lists_module(v, name)),
+        _ => false,
+    }
+}
+
+/// JSONPath selecting top-level field `field`
+fn field_path(field: &str) -> String {
+    format!("$[{}]", Value::from(field))
+}
+
+/// Reads and writes models with RedisJSON commands when available
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{JsonStore, Model, TormDb};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Model, Serialize, Deserialize)]
+/// # struct Post { #[id] id: String, title: String, views: i64 }
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let posts = JsonStore::<Post>::new(&db);
+/// let views = posts.incr("1", "views", 1).await?;
+/// let summary = posts.project("1", &["title", "views"]).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct JsonStore<M> {
+    db: TormDb,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M: Model> JsonStore<M> {
+    /// Create a store for `M` on `db`
+    pub fn new(db: &TormDb) -> Self {
+        Self {
+            db: db.clone(),
+            _model: PhantomData,
+        }
+    }
+
+    fn key(&self, id: &str) -> String {
+        self.db.key(&format!("{}:{}", M::collection(), id))
+    }
+
+    fn not_found(id: &str) -> Error {
+        Error::NotFound(format!("{}:{}", M::collection(), id))
+    }
+
+    /// Validate and write a model
+    #[tracing::instrument(name = "torm.json.save", level = "info", skip_all, fields(collection = M::collection(), id = model.id()))]
+    pub async fn save(&self, model: &M) -> Result<()> {
+        if !self.db.has_json_module() {
+            return model.save(&self.db).await;
+        }
+        let result: Result<()> = async {
+            model.validate()?;
+            let doc = serde_json::to_value(model)?;
+
+            let key = self.key(model.id());
+            let mut conn = self.db.connection().clone();
+            let (previous,): (Option<String>,) = redis::pipe()
+                .atomic()
+                .cmd("JSON.GET")
+                .arg(&key)
+                .cmd("JSON.SET")
+                .arg(&key)
+                .arg("$")
+                .arg(doc.to_string())
+                .ignore()
+                .query_async(&mut conn)
+                .await?;
+
+            if previous.is_none() {
+                crate::counter::adjust(&self.db, M::collection(), 1).await?;
+            }
+            let previous: Option<Value> = previous.map(|v| serde_json::from_str(&v)).transpose()?;
+            crate::model::after_save(&self.db, model, previous.as_ref(), doc).await
+        }
+        .await;
+        self.db.op_stats().record(&result);
+        result
+    }
+
+    /// Read a model
+    pub async fn find(&self, id: &str) -> Result<M> {
+        if !self.db.has_json_module() {
+            return M::find_by_id(&self.db, id).await;
+        }
+        let mut conn = self.db.connection().clone();
+        let raw: Option<String> = redis::cmd("JSON.GET")
+            .arg(self.key(id))
+            .query_async(&mut conn)
+            .await?;
+        let mut model: M = serde_json::from_str(&raw.ok_or_else(|| Self::not_found(id))?)?;
+        model.after_load();
+        Ok(model)
+    }
+
+    /// Read some top-level fields of a model
+    ///
+    /// With RedisJSON only those fields are sent back. Fields that don't
+    /// exist are left out; a missing model fails with [`Error::NotFound`].
+    pub async fn project(&self, id: &str, fields: &[&str]) -> Result<Map<String, Value>> {
+        let key = self.key(id);
+        let mut conn = self.db.connection().clone();
+
+        if !self.db.has_json_module() {
+            let raw: Option<Vec<u8>> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+            let doc: Value = M::codec().decode(&raw.ok_or_else(|| Self::not_found(id))?)?;
+            return Ok(fields
+                .iter()
+                .filter_map(|field| Some((field.to_string(), doc.get(*field)?.clone())))
+                .collect());
+        }
+
+        if fields.is_empty() {
+            let exists: bool = redis::cmd("EXISTS")
+                .arg(&key)
+                .query_async(&mut conn)
+                .await?;
+            return if exists {
+                Ok(Map::new())
+            } else {
+                Err(Self::not_found(id))
+            };
+        }
+        let paths: Vec<String> = fields.iter().map(|field| field_path(field)).collect();
+        let raw: Option<String> = redis::cmd("JSON.GET")
+            .arg(&key)
+            .arg(&paths)
+            .query_async(&mut conn)
+            .await?;
+        let Some(raw) = raw else {
+            return Err(Self::not_found(id));
+        };
+        // One path gives its matches, several an object of them by path
+        let matches: Vec<Value> = match serde_json::from_str(&raw)? {
+            Value::Object(mut by_path) => paths
+                .iter()
+                .map(|path| by_path.remove(path).unwrap_or(Value::Null))
+                .collect(),
+            single => vec![single],
+        };
+        Ok(fields
+            .iter()
+            .zip(matches)
+            .filter_map(|(field, found)| {
+                let value = found.as_array()?.first()?.clone();
+                Some((field.to_string(), value))
+            })
+            .collect())
+    }
+
+    /// Add `by` to an integer field and return the new value
+    ///
+    /// Runs `JSON.NUMINCRBY`, or without RedisJSON a Lua script on the
+    /// stored JSON document, which needs the JSON codec and no
+    /// compression. Fails with [`Error::NotFound`] if the model doesn't
+    /// exist, and with an error if the field doesn't hold an integer.
+    pub async fn incr(&self, id: &str, field: &str, by: i64) -> Result<i64> {
+        let key = self.key(id);
+        let mut conn = self.db.connection().clone();
+
+        let value = if self.db.has_json_module() {
+            let updated: Option<String> = redis::Script::new(NUMINCRBY_SCRIPT)
+                .key(&key)
+                .arg(field_path(field))
+                .arg(by)
+                .invoke_async(&mut conn)
+                .await?;
+            let updated = updated.ok_or_else(|| Self::not_found(id))?;
+            let updated: Vec<Option<i64>> = serde_json::from_str(&updated)?;
+            updated.into_iter().flatten().next().ok_or_else(|| {
+                Error::Other(format!("field '{}' of {} is not an integer", field, key))
+            })?
+        } else {
+            if M::codec() != crate::Codec::Json || M::compression().is_some() {
+                return Err(Error::Other(format!(
+                    "incr without RedisJSON requires the JSON codec without compression (model '{}')",
+                    M::collection()
+                )));
+            }
+            let value: Option<i64> = redis::Script::new(INCR_FIELD_SCRIPT)
+                .key(&key)
+                .arg(field)
+                .arg(by)
+                .invoke_async(&mut conn)
+                .await?;
+            value.ok_or_else(|| Self::not_found(id))?
+        };
+
+        if M::indexes().contains(&field) {
+            let old = serde_json::json!({ field: value - by });
+            let new = serde_json::json!({ field: value });
+            crate::index::update(
+                &self.db,
+                M::collection(),
+                M::indexes(),
+                id,
+                Some(&old),
+                Some(&new),
+            )
+            .await?;
+        }
+        crate::events::publish(&self.db, crate::ModelOp::Save, M::collection(), id, None).await?;
+        Ok(value)
+    }
+
+    /// Delete a model, returning whether it existed
+    pub async fn delete(&self, id: &str) -> Result<bool> {
+        if !self.db.has_json_module() {
+            let model = match M::find_by_id(&self.db.primary(), id).await {
+                Ok(model) => model,
+                Err(Error::NotFound(_)) => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            model.delete(&self.db).await?;
+            return Ok(true);
+        }
+
+        let result: Result<bool> = async {
+            let key = self.key(id);
+            let mut conn = self.db.connection().clone();
+            let (removed,): (Option<String>,) = redis::pipe()
+                .atomic()
+                .cmd("JSON.GET")
+                .arg(&key)
+                .cmd("DEL")
+                .arg(&key)
+                .ignore()
+                .query_async(&mut conn)
+                .await?;
+            let Some(removed) = removed else {
+                return Ok(false);
+            };
+
+            crate::counter::adjust(&self.db, M::collection(), -1).await?;
+            let removed: Value = serde_json::from_str(&removed)?;
+            crate::model::after_delete::<M>(&self.db, id, Some(&removed)).await?;
+            Ok(true)
+        }
+        .await;
+        self.db.op_stats().record(&result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Value as Reply;
+
+    #[test]
+    fn test_lists_module() {
+        let module = |name: &str| {
+            Reply::Array(vec![
+                Reply::BulkString(b"name".to_vec()),
+                Reply::BulkString(name.as_bytes().to_vec()),
+                Reply::BulkString(b"ver".to_vec()),
+                Reply::Int(20_800),
+            ])
+        };
+        let reply = Reply::Array(vec![module("search"), module("ReJSON")]);
+        assert!(lists_module(&reply, MODULE_NAME));
+        assert!(!lists_module(
+            &Reply::Array(vec![module("search")]),
+            MODULE_NAME
+        ));
+        assert!(!lists_module(&Reply::Array(vec![]), MODULE_NAME));
+    }
+
+    #[test]
+    fn test_field_path() {
+        assert_eq!(field_path("views"), r#"$["views"]"#);
+        assert_eq!(field_path(r#"a"b"#), r#"$["a\"b"]"#);
+    }
+}
@@ -0,0 +1,510 @@
+//! JSONPath expressions for [`Query::JsonPath`](crate::Query::JsonPath)
+//!
+//! Supports the common subset of RFC 9535: `$` for the value being
+//! tested, `.name` and `['name']` children, `[0]` and `[-1]` indexes, `*`
+//! and `[*]` wildcards, `..` descendants, and filter selectors such as
+//! `[?(@.qty > 2 && @.sku == 'a')]`. Filters compare single values found by
+//! `@` (current) or `$` (root) paths with each other or with literals, or
+//! test that a path finds anything, combined with `&&`, `||`, `!` and
+//! parentheses. Numbers compare numerically and strings by code point.
+
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A parsed JSONPath expression
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    /// Parse `expr`, which must start with `$`
+    pub(crate) fn parse(expr: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: expr.chars().collect(),
+            pos: 0,
+        };
+        parser.skip_whitespace();
+        if !parser.eat('$') {
+            return Err("expression must start with '$'".to_string());
+        }
+        let segments = parser.segments()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(Self { segments }),
+            Some(c) => Err(format!("unexpected '{}' at {}", c, parser.pos)),
+        }
+    }
+
+    /// Whether the expression selects anything in `value`
+    pub(crate) fn matches(&self, value: &Value) -> bool {
+        !select(&self.segments, value, value).is_empty()
+    }
+}
+
+/// One step of a path
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Applies to the current nodes
+    Child(Selector),
+    /// Applies to the current nodes and all their descendants
+    Descendant(Selector),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+    Filter(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Operand),
+    Compare(Operand, CompareOp, Operand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Literal(Value),
+    /// A path from the current node (`@`) or the root (`$`)
+    Path {
+        root: bool,
+        segments: Vec<Segment>,
+    },
+}
+
+/// The nodes `segments` lead to from `current`
+fn select<'a>(segments: &[Segment], root: &'a Value, current: &'a Value) -> Vec<&'a Value> {
+    let mut nodes = vec![current];
+    for segment in segments {
+        nodes = match segment {
+            Segment::Child(selector) => nodes
+                .into_iter()
+                .flat_map(|node| selector.apply(root, node))
+                .collect(),
+            Segment::Descendant(selector) => {
+                let mut all = Vec::new();
+                nodes
+                    .into_iter()
+                    .for_each(|node| descendants(node, &mut all));
+                all.into_iter()
+                    .flat_map(|node| selector.apply(root, node))
+                    .collect()
+            }
+        };
+    }
+    nodes
+}
+
+/// Collect `node` and everything nested in it, parents first
+fn descendants<'a>(node: &'a Value, out: &mut Vec<&'a Value>) {
+    out.push(node);
+    children(node).for_each(|child| descendants(child, out));
+}
+
+/// Elements of an array or values of an object
+fn children(node: &Value) -> Box<dyn Iterator<Item = &Value> + '_> {
+    match node {
+        Value::Array(items) => Box::new(items.iter()),
+        Value::Object(map) => Box::new(map.values()),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+impl Selector {
+    fn apply<'a>(&self, root: &'a Value, node: &'a Value) -> Vec<&'a Value> {
+        match self {
+            Selector::Name(name) => node
+                .as_object()
+                .and_then(|map| map.get(name))
+                .into_iter()
+                .collect(),
+            Selector::Index(index) => {
+                let Some(items) = node.as_array() else {
+                    return Vec::new();
+                };
+                let index = if *index < 0 {
+                    items.len() as i64 + index
+                } else {
+                    *index
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|i| items.get(i))
+                    .into_iter()
+                    .collect()
+            }
+            Selector::Wildcard => children(node).collect(),
+            Selector::Filter(expr) => children(node)
+                .filter(|child| expr.test(root, child))
+                .collect(),
+        }
+    }
+}
+
+impl Expr {
+    fn test(&self, root: &Value, current: &Value) -> bool {
+        match self {
+            Expr::Or(a, b) => a.test(root, current) || b.test(root, current),
+            Expr::And(a, b) => a.test(root, current) && b.test(root, current),
+            Expr::Not(expr) => !expr.test(root, current),
+            Expr::Exists(Operand::Path {
+                root: from_root,
+                segments,
+            }) => {
+                let start = if *from_root { root } else { current };
+                !select(segments, root, start).is_empty()
+            }
+            Expr::Exists(Operand::Literal(_)) => false,
+            Expr::Compare(a, op, b) => {
+                let a = a.value(root, current);
+                let b = b.value(root, current);
+                match op {
+                    CompareOp::Eq => equal(a, b),
+                    CompareOp::Ne => !equal(a, b),
+                    CompareOp::Lt => order(a, b) == Some(Ordering::Less),
+                    CompareOp::Gt => order(a, b) == Some(Ordering::Greater),
+                    CompareOp::Le => order(a, b) == Some(Ordering::Less) || equal(a, b),
+                    CompareOp::Ge => order(a, b) == Some(Ordering::Greater) || equal(a, b),
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    /// The single value this operand stands for, `None` if a path finds
+    /// nothing or several nodes
+    fn value<'a>(&'a self, root: &'a Value, current: &'a Value) -> Option<&'a Value> {
+        match self {
+            Operand::Literal(value) => Some(value),
+            Operand::Path {
+                root: from_root,
+                segments,
+            } => {
+                let start = if *from_root { root } else { current };
+                match select(segments, root, start).as_slice() {
+                    [value] => Some(*value),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Equality with `1 == 1.0`; two missing values are equal
+fn equal(a: Option<&Value>, b: Option<&Value>) -> bool {
+    match (a, b) {
+        (Some(Value::Number(x)), Some(Value::Number(y))) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+/// Order two numbers or two strings
+fn order(a: Option<&Value>, b: Option<&Value>) -> Option<Ordering> {
+    match (a?, b?) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let end = self.pos + s.chars().count();
+        if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(s.chars()) {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", c)))
+        }
+    }
+
+    fn unexpected(&self, wanted: &str) -> String {
+        match self.peek() {
+            Some(c) => format!("expected {} at {}, found '{}'", wanted, self.pos, c),
+            None => format!("expected {} at end of expression", wanted),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Segments following `$` or `@`
+    fn segments(&mut self) -> Result<Vec<Segment>, String> {
+        let mut segments = Vec::new();
+        loop {
+            if self.eat_str("..") {
+                let selector = if self.peek() == Some('[') {
+                    self.bracket()?
+                } else {
+                    self.dot_selector()?
+                };
+                segments.push(Segment::Descendant(selector));
+            } else if self.eat('.') {
+                segments.push(Segment::Child(self.dot_selector()?));
+            } else if self.peek() == Some('[') {
+                segments.push(Segment::Child(self.bracket()?));
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+
+    /// `*` or a member name after a dot
+    fn dot_selector(&mut self) -> Result<Selector, String> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.unexpected("a member name"));
+        }
+        Ok(Selector::Name(self.chars[start..self.pos].iter().collect()))
+    }
+
+    /// A selector in brackets
+    fn bracket(&mut self) -> Result<Selector, String> {
+        self.expect('[')?;
+        self.skip_whitespace();
+        let selector = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Selector::Wildcard
+            }
+            Some('?') => {
+                self.pos += 1;
+                Selector::Filter(self.or()?)
+            }
+            Some('\'' | '"') => Selector::Name(self.string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let n = self.number()?;
+                match n.as_i64() {
+                    Some(index) => Selector::Index(index),
+                    None => return Err(format!("index {} is not an integer", n)),
+                }
+            }
+            _ => return Err(self.unexpected("a selector")),
+        };
+        self.expect(']')?;
+        Ok(selector)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat_str("||") {
+                return Ok(expr);
+            }
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat_str("&&") {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        if self.eat('!') {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let expr = self.or()?;
+            self.expect(')')?;
+            return Ok(expr);
+        }
+
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let op = [
+            ("==", CompareOp::Eq),
+            ("!=", CompareOp::Ne),
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat_str(token));
+        match op {
+            Some((_, op)) => Ok(Expr::Compare(left, op, self.operand()?)),
+            None if matches!(left, Operand::Path { .. }) => Ok(Expr::Exists(left)),
+            None => Err(self.unexpected("a comparison")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c @ ('@' | '$')) => {
+                self.pos += 1;
+                Ok(Operand::Path {
+                    root: c == '$',
+                    segments: self.segments()?,
+                })
+            }
+            Some('\'' | '"') => Ok(Operand::Literal(Value::String(self.string()?))),
+            Some(c) if c == '-' || c.is_ascii_digit() => Ok(Operand::Literal(self.number()?)),
+            _ => {
+                for (word, value) in [
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                    ("null", Value::Null),
+                ] {
+                    if self.eat_str(word) {
+                        return Ok(Operand::Literal(value));
+                    }
+                }
+                Err(self.unexpected("a path or literal"))
+            }
+        }
+    }
+
+    /// A quoted string, with backslash escapes
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap_or('\'');
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_string()),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek().ok_or("unterminated string")?;
+                    s.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        c => c,
+                    });
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        serde_json::from_str::<serde_json::Number>(&text)
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number '{}' at {}", text, start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_select() {
+        let doc = json!({
+            "items": [
+                { "sku": "a", "qty": 1 },
+                { "sku": "b", "qty": 5, "tags": ["sale"] },
+            ],
+            "meta": { "owner": { "sku": "c" } },
+        });
+        let path = |expr: &str| JsonPath::parse(expr).unwrap();
+        let found = |expr: &str| select(&path(expr).segments, &doc, &doc).len();
+
+        assert_eq!(found("$"), 1);
+        assert_eq!(found("$.items[*].sku"), 2);
+        assert_eq!(found("$['items'][-1].qty"), 1);
+        assert_eq!(found("$..sku"), 3);
+        assert_eq!(found("$.meta.*"), 1);
+        assert_eq!(found("$.items[2]"), 0);
+
+        assert!(path("$.items[?(@.sku == 'b' && @.qty > 2)]").matches(&doc));
+        assert!(!path("$.items[?(@.sku == 'a' && @.qty > 2)]").matches(&doc));
+        assert!(path("$.items[?@.tags]").matches(&doc));
+        assert!(path("$.items[?(!@.tags && @.qty <= 1.0)]").matches(&doc));
+        assert!(path("$.items[?(@.qty == $.items[0].qty || @.sku >= \"z\")]").matches(&doc));
+        assert!(!path("$.items[?(@.qty < '5')]").matches(&doc));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(JsonPath::parse("items").is_err());
+        assert!(JsonPath::parse("$.items[").is_err());
+        assert!(JsonPath::parse("$.items[?(@.qty >)]").is_err());
+        assert!(JsonPath::parse("$.items[1.5]").is_err());
+        assert!(JsonPath::parse("$[?(@.a == 'x)]").is_err());
+        assert!(JsonPath::parse("$.a b").is_err());
+    }
+}
@@ -34,22 +34,100 @@
 
 #![warn(missing_docs)]
 
+mod aggregate;
+mod audit;
+mod backend;
+mod bulk;
+mod cdc;
+mod codec;
+#[cfg(feature = "collation")]
+mod collation;
+mod collection;
+mod connect;
+mod counter;
 mod db;
+#[cfg(feature = "embedded")]
+mod embedded;
 mod error;
+mod events;
+mod geo;
+mod hash;
+mod health;
+mod id;
+mod index;
+mod json;
+mod jsonpath;
+mod lifecycle;
+mod lock;
+mod merge;
 mod migration;
 mod model;
+mod order;
+mod path;
+mod pipeline;
+mod pool;
+mod pubsub;
 mod query;
+mod raw;
+mod relations;
+mod retry;
+mod scan;
+mod schema;
+mod search;
+mod transaction;
 mod validation;
+mod watch;
+mod watchdog;
 
+pub use aggregate::Agg;
+pub use audit::{AuditEntry, AUDIT_KEY};
+pub use backend::Backend;
+pub use bulk::{BulkError, BulkReport, BulkWriter};
+pub use cdc::{cdc_key, CdcConsumer, ChangeRecord};
+pub use codec::{Codec, Compression};
+#[cfg(feature = "collation")]
+pub use collation::Collation;
+pub use collection::Collection;
+pub use connect::ConnectOptions;
+pub use counter::{counter_key, del_counted, set_counted};
 pub use db::TormDb;
+#[cfg(feature = "embedded")]
+pub use embedded::EMBEDDED_BIN_ENV;
 pub use error::{Error, Result};
+pub use events::{ModelEvent, ModelOp};
+pub use geo::{geo_key, GeoPoint};
+pub use hash::HashStore;
+pub use health::Health;
+pub use id::{sequence_key, IdStrategy};
+pub use index::{built_key, index_key};
+pub use json::JsonStore;
+pub use lifecycle::{ConnectionEvent, ConnectionState};
+pub use lock::{lock_key, LockGuard, Redlock};
+pub use merge::MergeStrategy;
 pub use migration::{Migration, MigrationFile, MigrationManager, MigrationStatus};
 pub use model::Model;
-pub use query::{Query, QueryBuilder, SortOrder};
+pub use order::{order_key, track_insertion, untrack_insertion};
+pub use pipeline::Pipeline;
+pub use pool::Connection;
+pub use pubsub::{event_channel, EventStream, Subscription};
+pub use query::{
+    Cursor, CursorPage, Explain, Filter, FilterGroup, GroupBy, KeysetPage, Page, PageRequest,
+    Param, PreparedQuery, Query, QueryBuilder, QueryLimit, QuerySpec, QueryStats, Selection,
+    SortOrder, SortSpec,
+};
+pub use raw::RawCommand;
+pub use relations::{populate, Relation, RelationKind};
+pub use retry::RetryPolicy;
+pub use scan::KeyScanner;
+pub use schema::{schema_key, FieldType, Schema};
+pub use search::{search_index, SearchField, SearchType};
+pub use transaction::Transaction;
 pub use validation::{ValidationError, ValidationErrors, Validator, Validators};
+pub use watch::{ChangeEvent, ChangeStream};
+pub use watchdog::{Alert, Watchdog};
 
-// Re-export derive macro
-pub use torm_derive::Model;
+// Re-export derive and query macros
+pub use torm_derive::{query, Model};
 
 #[cfg(test)]
 mod tests {
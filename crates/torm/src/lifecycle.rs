@@ -0,0 +1,394 @@
+//! Connection lifecycle events
+//!
+//! Every command's outcome tells whether the link to the server is up: a
+//! dropped or refused connection marks it down, and the next command the
+//! server answers marks it up again. Listeners registered with
+//! [`TormDb::on_connection_event`] or
+//! [`ConnectOptions::on_connection_event`](crate::ConnectOptions::on_connection_event)
+//! hear about each change, and [`TormDb::connection_state`] tells the
+//! current one.
+//!
+//! The link is only checked by commands, so an idle handle notices a drop
+//! with its next command; send a `PING` periodically to find out sooner.
+//!
+//! [`TormDb::close`] shuts the connection down for good: it ends change
+//! streams, subscriptions and watchdogs, lets commands already sent
+//! finish, and refuses new ones.
+
+use crate::TormDb;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+
+/// Whether the link to the server is up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The last command reached the server
+    Connected,
+    /// The last command failed to reach the server
+    Disconnected,
+    /// The connection was shut down with [`TormDb::close`]
+    Closed,
+}
+
+/// A change in the link to the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// The connection was opened by
+    /// [`ConnectOptions::build`](crate::ConnectOptions::build)
+    Connected,
+    /// A command failed to reach the server
+    Disconnected {
+        /// Why the command failed
+        error: String,
+    },
+    /// A command reached the server again after a disconnection
+    Reconnected {
+        /// How long the link was down
+        downtime: Duration,
+    },
+}
+
+type Listener = Arc<dyn Fn(&ConnectionEvent) + Send + Sync>;
+
+/// Listeners registered before connecting, see
+/// [`ConnectOptions::on_connection_event`](crate::ConnectOptions::on_connection_event)
+#[derive(Clone, Default)]
+pub(crate) struct Listeners(Vec<Listener>);
+
+impl Listeners {
+    pub(crate) fn push(&mut self, listener: Listener) {
+        self.0.push(listener);
+    }
+
+    /// Register the listeners on a newly opened connection's link and
+    /// announce it
+    pub(crate) fn attach(self, link: &Link) {
+        for listener in self.0 {
+            link.add(listener);
+        }
+        link.connected();
+    }
+}
+
+impl std::fmt::Debug for Listeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Listeners({})", self.0.len())
+    }
+}
+
+/// State of the link behind a [`Connection`](crate::Connection) and its
+/// clones
+pub(crate) struct Link {
+    down: AtomicBool,
+    /// When the link went down
+    since: Mutex<Option<Instant>>,
+    listeners: RwLock<Vec<Listener>>,
+    shut_down: AtomicBool,
+    /// Commands sent and not yet answered
+    in_flight: AtomicUsize,
+    /// Notified when the last command in flight is answered
+    idle: Notify,
+    /// Set once closed, for tasks and streams to end on
+    shutdown: watch::Sender<bool>,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            down: AtomicBool::default(),
+            since: Mutex::default(),
+            listeners: RwLock::default(),
+            shut_down: AtomicBool::default(),
+            in_flight: AtomicUsize::default(),
+            idle: Notify::new(),
+            shutdown: watch::channel(false).0,
+        }
+    }
+}
+
+/// A command in flight, counted until dropped
+pub(crate) struct InFlight<'a>(&'a Link);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.end();
+    }
+}
+
+/// Error for commands sent after [`TormDb::close`]
+pub(crate) fn closed_error() -> RedisError {
+    (ErrorKind::ClientError, "connection closed").into()
+}
+
+impl Link {
+    pub(crate) fn add(&self, listener: Listener) {
+        self.listeners
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(listener);
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        if self.is_closed() {
+            ConnectionState::Closed
+        } else if self.down.load(Ordering::Relaxed) {
+            ConnectionState::Disconnected
+        } else {
+            ConnectionState::Connected
+        }
+    }
+
+    fn connected(&self) {
+        self.notify(&ConnectionEvent::Connected);
+    }
+
+    /// Update the state from the outcome of a command
+    pub(crate) fn observe<T>(&self, result: &RedisResult<T>) {
+        let failed = matches!(result, Err(e) if is_link_failure(e));
+        // Fast path for the usual case, without taking the lock
+        if failed == self.down.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let event = {
+            let mut since = self.since.lock().unwrap_or_else(|e| e.into_inner());
+            let event = match (failed, *since) {
+                (true, None) => {
+                    *since = Some(Instant::now());
+                    ConnectionEvent::Disconnected {
+                        error: result
+                            .as_ref()
+                            .err()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                    }
+                }
+                (false, Some(went_down)) => {
+                    *since = None;
+                    ConnectionEvent::Reconnected {
+                        downtime: went_down.elapsed(),
+                    }
+                }
+                // Another command got here first
+                _ => return,
+            };
+            self.down.store(failed, Ordering::Relaxed);
+            event
+        };
+        self.notify(&event);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shut_down.load(Ordering::SeqCst)
+    }
+
+    /// Count a command as in flight, or refuse it once closed
+    pub(crate) fn begin(&self) -> RedisResult<InFlight<'_>> {
+        // Counted before checking, so `close` can't miss it
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.is_closed() {
+            self.end();
+            return Err(closed_error());
+        }
+        Ok(InFlight(self))
+    }
+
+    fn end(&self) {
+        if self.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+
+    /// Resolve once the link is closed
+    pub(crate) fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut shutdown = self.shutdown.subscribe();
+        async move {
+            // An error means the link is gone along with every connection
+            let _ = shutdown.wait_for(|closed| *closed).await;
+        }
+    }
+
+    /// Refuse new commands, end what waits on [`Link::closed`] and wait
+    /// for the commands in flight
+    pub(crate) async fn close(&self) {
+        self.shut_down.store(true, Ordering::SeqCst);
+        self.shutdown.send_replace(true);
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    fn notify(&self, event: &ConnectionEvent) {
+        // Clone the list so listeners can register more listeners
+        let listeners = self
+            .listeners
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        for listener in listeners {
+            listener(event);
+        }
+    }
+}
+
+/// Whether `error` means the command never reached the server, rather
+/// than that the server refused it or was slow to answer
+fn is_link_failure(error: &redis::RedisError) -> bool {
+    error.is_connection_dropped()
+        || error.is_connection_refusal()
+        || (error.is_io_error() && !error.is_timeout())
+}
+
+impl TormDb {
+    /// Register a listener for changes in the link to the server
+    ///
+    /// The listener is called synchronously when a command through this
+    /// handle or any of its clones finds the link down, and when one gets
+    /// through again. Keep it cheap; hand heavy work off to a task. With
+    /// read replicas, only the primary's link is reported.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{ConnectionEvent, TormDb};
+    /// # use std::sync::atomic::{AtomicBool, Ordering};
+    /// # use std::sync::Arc;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let healthy = Arc::new(AtomicBool::new(true));
+    /// let flag = healthy.clone();
+    /// db.on_connection_event(move |event| match event {
+    ///     ConnectionEvent::Disconnected { error } => {
+    ///         flag.store(false, Ordering::Relaxed);
+    ///         eprintln!("lost the store: {}", error);
+    ///     }
+    ///     _ => flag.store(true, Ordering::Relaxed),
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_connection_event<F>(&self, listener: F)
+    where
+        F: Fn(&ConnectionEvent) + Send + Sync + 'static,
+    {
+        self.connection().link().add(Arc::new(listener));
+    }
+
+    /// Get the state of the link to the server, as of the last command
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection().link().state()
+    }
+
+    /// Shut the connection down
+    ///
+    /// Change streams from [`Model::watch`](crate::Model::watch),
+    /// subscriptions, [`CdcConsumer::read`](crate::CdcConsumer::read) and
+    /// watchdogs end, then `close` waits for the commands already sent,
+    /// including pipelines being executed, to be answered. Every clone of
+    /// the handle shares the connection and is closed with it; their
+    /// commands fail from then on. Pooled connections are dropped at once,
+    /// others once the last handle is dropped.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = TormDb::connect("redis://localhost:6379").await?;
+    /// // ... serve until asked to stop
+    /// tokio::signal::ctrl_c().await?;
+    /// db.close().await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn close(&self) {
+        self.connection().close().await;
+        for replica in self.replica_connections() {
+            replica.close().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+    use async_trait::async_trait;
+    use redis::{Cmd, Value};
+
+    #[test]
+    fn test_observe() {
+        let link = Link::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        link.add(Arc::new(move |event: &ConnectionEvent| {
+            seen.lock().unwrap().push(event.clone())
+        }));
+
+        let refused: RedisResult<()> =
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into());
+        let rejected: RedisResult<()> = Err((ErrorKind::ResponseError, "WRONGTYPE").into());
+        let timed_out: RedisResult<()> =
+            Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+
+        // Errors from a reachable server don't take the link down
+        link.observe(&rejected);
+        link.observe(&timed_out);
+        assert_eq!(link.state(), ConnectionState::Connected);
+
+        link.observe(&refused);
+        link.observe(&refused);
+        assert_eq!(link.state(), ConnectionState::Disconnected);
+
+        // Any answer from the server brings it back
+        link.observe(&rejected);
+        assert_eq!(link.state(), ConnectionState::Connected);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ConnectionEvent::Disconnected { .. }));
+        assert!(matches!(events[1], ConnectionEvent::Reconnected { .. }));
+    }
+
+    /// Answers every command after a pause
+    struct Slow;
+
+    #[async_trait]
+    impl Backend for Slow {
+        async fn command(&self, _cmd: &Cmd) -> RedisResult<Value> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(Value::Okay)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let db = TormDb::from_backend(Slow);
+        let closed = db.connection().link().closed();
+        let in_flight = tokio::spawn({
+            let mut conn = db.connection().clone();
+            async move { redis::cmd("SET").query_async::<()>(&mut conn).await }
+        });
+        tokio::task::yield_now().await;
+
+        db.close().await;
+        // The command sent before closing was answered, later ones fail
+        assert!(in_flight.await.unwrap().is_ok());
+        let refused = redis::cmd("GET")
+            .query_async::<()>(&mut db.connection().clone())
+            .await;
+        assert!(refused.is_err());
+        assert_eq!(db.connection_state(), ConnectionState::Closed);
+        closed.await;
+    }
+}
@@ -0,0 +1,269 @@
+//! Distributed locks
+//!
+//! [`TormDb::lock`] takes a lock with `SET NX PX` under a random token, so
+//! only its holder can release or extend it and a crashed holder's lock
+//! expires after its TTL. [`Redlock`] takes the same lock on a majority
+//! of independent servers, following the Redlock algorithm, for locks
+//! that must survive the loss of one server.
+
+use crate::{Error, Result, TormDb};
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// Delete the lock in KEYS[1] if it still holds token ARGV[1]
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Reset the TTL of the lock in KEYS[1] to ARGV[2] ms if it still holds
+/// token ARGV[1]
+const EXTEND_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Longest pause between two attempts of a waiting [`TormDb::lock`]
+const MAX_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Get the key holding the lock on `resource`
+pub fn lock_key(resource: &str) -> String {
+    format!("torm:lock:{}", resource)
+}
+
+/// A held lock, released when dropped
+///
+/// Dropping the guard releases the lock in the background; call
+/// [`LockGuard::release`] to wait for it and learn whether the lock was
+/// still held.
+pub struct LockGuard {
+    nodes: Vec<TormDb>,
+    resource: String,
+    token: String,
+    released: bool,
+}
+
+impl LockGuard {
+    /// Get the resource this lock is on
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Get the random token identifying this holder
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Reset the lock's TTL to `ttl`
+    ///
+    /// Returns `false` if it expired and was taken by someone else, or
+    /// for [`Redlock`], if it no longer holds on a majority of servers.
+    pub async fn extend(&self, ttl: Duration) -> Result<bool> {
+        let mut held = 0;
+        for db in &self.nodes {
+            let extended: i64 = redis::Script::new(EXTEND_SCRIPT)
+                .key(db.key(&lock_key(&self.resource)))
+                .arg(&self.token)
+                .arg(ttl.as_millis() as u64)
+                .invoke_async(&mut db.connection().clone())
+                .await
+                .unwrap_or(0);
+            held += extended;
+        }
+        Ok(held as usize >= quorum(self.nodes.len()))
+    }
+
+    /// Release the lock, returning whether it was still held
+    pub async fn release(mut self) -> Result<bool> {
+        self.released = true;
+        let released = release(&self.nodes, &self.resource, &self.token).await;
+        Ok(released >= quorum(self.nodes.len()))
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let nodes = std::mem::take(&mut self.nodes);
+            let resource = std::mem::take(&mut self.resource);
+            let token = std::mem::take(&mut self.token);
+            runtime.spawn(async move {
+                release(&nodes, &resource, &token).await;
+            });
+        }
+    }
+}
+
+/// Release the lock on every node, returning on how many it was held
+async fn release(nodes: &[TormDb], resource: &str, token: &str) -> usize {
+    let mut released = 0;
+    for db in nodes {
+        let deleted: redis::RedisResult<i64> = redis::Script::new(RELEASE_SCRIPT)
+            .key(db.key(&lock_key(resource)))
+            .arg(token)
+            .invoke_async(&mut db.connection().clone())
+            .await;
+        match deleted {
+            Ok(deleted) => released += deleted as usize,
+            Err(e) => tracing::warn!(resource, error = %e, "failed to release lock"),
+        }
+    }
+    released
+}
+
+/// Number of nodes that must agree out of `nodes`
+fn quorum(nodes: usize) -> usize {
+    nodes / 2 + 1
+}
+
+/// Try once to take the lock on a majority of `nodes`
+async fn acquire(nodes: &[TormDb], resource: &str, ttl: Duration) -> Result<Option<LockGuard>> {
+    if nodes.is_empty() {
+        return Err(Error::Other("a lock needs at least one server".to_string()));
+    }
+    let token = uuid::Uuid::new_v4().to_string();
+    let started = Instant::now();
+
+    let mut acquired = 0;
+    for db in nodes {
+        let set: redis::RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(db.key(&lock_key(resource)))
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut db.connection().clone())
+            .await;
+        match set {
+            Ok(Some(_)) => acquired += 1,
+            Ok(None) => {}
+            // A single server's error is the caller's; with several, the
+            // others may still make a majority
+            Err(e) if nodes.len() == 1 => return Err(e.into()),
+            Err(e) => tracing::warn!(resource, error = %e, "lock server unavailable"),
+        }
+    }
+
+    // Allow for clock drift between servers, as Redlock prescribes
+    let drift = ttl / 100 + Duration::from_millis(2);
+    let valid = started.elapsed() + drift < ttl;
+    if acquired >= quorum(nodes.len()) && valid {
+        return Ok(Some(LockGuard {
+            nodes: nodes.to_vec(),
+            resource: resource.to_string(),
+            token,
+            released: false,
+        }));
+    }
+    if acquired > 0 {
+        release(nodes, resource, &token).await;
+    }
+    Ok(None)
+}
+
+/// Take the lock, retrying for up to `ttl` while someone else holds it
+async fn acquire_waiting(nodes: &[TormDb], resource: &str, ttl: Duration) -> Result<LockGuard> {
+    let deadline = Instant::now() + ttl;
+    loop {
+        if let Some(guard) = acquire(nodes, resource, ttl).await? {
+            return Ok(guard);
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::Conflict(format!("lock on '{}' is held", resource)));
+        }
+        let delay = MAX_RETRY_DELAY.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        tokio::time::sleep(delay).await;
+    }
+}
+
+impl TormDb {
+    /// Take the lock on `resource`, waiting while someone else holds it
+    ///
+    /// The lock expires after `ttl` unless extended with
+    /// [`LockGuard::extend`], so a crashed holder can't keep it forever.
+    /// Gives up with [`Error::Conflict`] after waiting `ttl`. The lock is
+    /// kept in [`lock_key`] under this handle's prefix.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::TormDb;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let guard = db.lock("nightly-report", Duration::from_secs(30)).await?;
+    /// // ... only one worker gets here at a time
+    /// guard.release().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn lock(&self, resource: &str, ttl: Duration) -> Result<LockGuard> {
+        acquire_waiting(std::slice::from_ref(self), resource, ttl).await
+    }
+
+    /// Take the lock on `resource` if it is free
+    pub async fn try_lock(&self, resource: &str, ttl: Duration) -> Result<Option<LockGuard>> {
+        acquire(std::slice::from_ref(self), resource, ttl).await
+    }
+}
+
+/// Locks held on a majority of independent servers
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{Redlock, TormDb};
+/// # use std::time::Duration;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let redlock = Redlock::new(vec![
+///     TormDb::connect("redis://lock-1:6379").await?,
+///     TormDb::connect("redis://lock-2:6379").await?,
+///     TormDb::connect("redis://lock-3:6379").await?,
+/// ]);
+/// let guard = redlock.lock("billing", Duration::from_secs(10)).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Redlock {
+    nodes: Vec<TormDb>,
+}
+
+impl Redlock {
+    /// Lock across `nodes`, which should be independent servers
+    pub fn new(nodes: Vec<TormDb>) -> Self {
+        Self { nodes }
+    }
+
+    /// Take the lock on `resource`, waiting while someone else holds it
+    ///
+    /// See [`TormDb::lock`].
+    pub async fn lock(&self, resource: &str, ttl: Duration) -> Result<LockGuard> {
+        acquire_waiting(&self.nodes, resource, ttl).await
+    }
+
+    /// Take the lock on `resource` if a majority of servers grant it
+    pub async fn try_lock(&self, resource: &str, ttl: Duration) -> Result<Option<LockGuard>> {
+        acquire(&self.nodes, resource, ttl).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quorum() {
+        assert_eq!(quorum(1), 1);
+        assert_eq!(quorum(2), 2);
+        assert_eq!(quorum(3), 2);
+        assert_eq!(quorum(5), 3);
+    }
+}
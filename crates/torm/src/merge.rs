@@ -0,0 +1,124 @@
+//! Merge strategies for concurrent edits
+//!
+//! Used by [`crate::Model::merge_save`] when the stored document changed
+//! between loading a model and saving it, and by
+//! [`crate::QueryBuilder::update`] to apply patches.
+
+use serde_json::Value;
+
+/// Give up after this many compare-and-set attempts
+pub(crate) const MAX_MERGE_ATTEMPTS: usize = 5;
+
+/// How to combine local changes with a concurrently stored document
+pub enum MergeStrategy<T> {
+    /// Overwrite the stored document with the local one
+    LastWriteWins,
+    /// Keep every top-level field changed locally, take the rest from the
+    /// stored document
+    ///
+    /// Fields changed on both sides keep the local value.
+    FieldLevel,
+    /// Resolve with a closure receiving `(mine, theirs)`
+    Custom(Box<dyn Fn(T, T) -> T + Send + Sync>),
+}
+
+impl<T> MergeStrategy<T> {
+    /// Resolve with `resolve(mine, theirs)`
+    pub fn custom<F>(resolve: F) -> Self
+    where
+        F: Fn(T, T) -> T + Send + Sync + 'static,
+    {
+        MergeStrategy::Custom(Box::new(resolve))
+    }
+}
+
+impl<T> std::fmt::Debug for MergeStrategy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeStrategy::LastWriteWins => f.write_str("LastWriteWins"),
+            MergeStrategy::FieldLevel => f.write_str("FieldLevel"),
+            MergeStrategy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Three-way merge of top-level fields
+///
+/// Takes fields from `mine` where they differ from `base` and from `theirs`
+/// otherwise. Non-object documents resolve to `mine`.
+pub(crate) fn merge_fields(base: &Value, mine: &Value, theirs: &Value) -> Value {
+    let (Some(base), Some(mine_fields), Some(theirs_fields)) =
+        (base.as_object(), mine.as_object(), theirs.as_object())
+    else {
+        return mine.clone();
+    };
+
+    let mut merged = theirs_fields.clone();
+    for (field, value) in mine_fields {
+        if base.get(field) != Some(value) {
+            merged.insert(field.clone(), value.clone());
+        }
+    }
+    for field in base.keys() {
+        if !mine_fields.contains_key(field) {
+            merged.remove(field);
+        }
+    }
+
+    Value::Object(merged)
+}
+
+/// Apply a JSON merge patch (RFC 7396) to `target`
+///
+/// Object fields are merged recursively, `null` removes a field and any
+/// other value replaces it.
+pub(crate) fn merge_patch(target: &mut Value, patch: &Value) {
+    let Some(patch) = patch.as_object() else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Some(fields) = target.as_object_mut() {
+        for (field, value) in patch {
+            if value.is_null() {
+                fields.remove(field);
+            } else {
+                merge_patch(fields.entry(field.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_fields() {
+        let base = json!({ "id": "1", "title": "Draft", "body": "a", "tags": [] });
+        let mine = json!({ "id": "1", "title": "Final", "body": "a" });
+        let theirs = json!({ "id": "1", "title": "Draft 2", "body": "b", "tags": ["x"] });
+
+        assert_eq!(
+            merge_fields(&base, &mine, &theirs),
+            json!({ "id": "1", "title": "Final", "body": "b" })
+        );
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut doc = json!({ "name": "Ann", "address": { "city": "Paris", "zip": "75001" } });
+        merge_patch(
+            &mut doc,
+            &json!({ "active": true, "address": { "zip": null }, "name": "Anna" }),
+        );
+
+        assert_eq!(
+            doc,
+            json!({ "name": "Anna", "active": true, "address": { "city": "Paris" } })
+        );
+    }
+}
@@ -1,8 +1,19 @@
 use crate::error::{Error, Result};
-use crate::TormDb;
+use crate::{LockGuard, TormDb};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Key of the applied migrations record
+const MIGRATIONS_KEY: &str = "torm:migrations";
+
+/// Resource locked while migrations run, so runners don't apply them twice
+const MIGRATION_LOCK: &str = "migrations";
+
+/// How long the migration lock lasts without renewal, and how long runners
+/// wait for it
+const MIGRATION_LOCK_TTL: std::time::Duration = std::time::Duration::from_secs(60);
 
 /// Type alias for migration functions
 type MigrationFn = Box<dyn Fn(&TormDb) -> Result<()> + Send + Sync>;
@@ -32,6 +43,68 @@ pub struct MigrationFile {
     pub down: MigrationFn,
 }
 
+/// The migration lock, renewed in the background while migrations run
+///
+/// Migrations are synchronous, so one that blocks the runtime's only
+/// thread can keep the renewal from running. The lock is therefore
+/// checked again before each migration is recorded and when it is
+/// released, and losing it fails the run instead of going unnoticed.
+struct MigrationLock {
+    guard: Arc<LockGuard>,
+    renewal: tokio::task::JoinHandle<()>,
+}
+
+impl MigrationLock {
+    async fn acquire(db: &TormDb) -> Result<Self> {
+        let guard = Arc::new(db.lock(MIGRATION_LOCK, MIGRATION_LOCK_TTL).await?);
+        let renewal = tokio::spawn({
+            let guard = guard.clone();
+            async move {
+                let mut interval = tokio::time::interval(MIGRATION_LOCK_TTL / 3);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if !matches!(guard.extend(MIGRATION_LOCK_TTL).await, Ok(true)) {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { guard, renewal })
+    }
+
+    fn lost() -> Error {
+        Error::Conflict(
+            "migration lock expired while migrations ran; another runner may have applied them too"
+                .to_string(),
+        )
+    }
+
+    /// Renew the lock now, failing if it was lost
+    async fn check(&self) -> Result<()> {
+        if self.guard.extend(MIGRATION_LOCK_TTL).await? {
+            Ok(())
+        } else {
+            Err(Self::lost())
+        }
+    }
+
+    /// Stop renewing and release the lock, failing if it was lost
+    async fn release(self) -> Result<()> {
+        self.renewal.abort();
+        let _ = self.renewal.await;
+        let held = match Arc::try_unwrap(self.guard) {
+            Ok(guard) => guard.release().await?,
+            Err(_) => true,
+        };
+        if held {
+            Ok(())
+        } else {
+            Err(Self::lost())
+        }
+    }
+}
+
 /// Migration manager
 pub struct MigrationManager {
     migrations: Vec<MigrationFile>,
@@ -62,7 +135,21 @@ impl MigrationManager {
     }
 
     /// Run all pending migrations
+    ///
+    /// Holds the `migrations` lock of [`TormDb::lock`] meanwhile, so
+    /// runners started together apply each migration once. The lock is
+    /// renewed while migrations run; if it expires anyway, the run fails
+    /// with [`Error::Conflict`] before recording the next migration.
     pub async fn migrate(&self, db: &TormDb) -> Result<Vec<String>> {
+        let lock = MigrationLock::acquire(db).await?;
+        let result = self.apply_pending(db, &lock).await;
+        let released = lock.release().await;
+        let applied = result?;
+        released?;
+        Ok(applied)
+    }
+
+    async fn apply_pending(&self, db: &TormDb, lock: &MigrationLock) -> Result<Vec<String>> {
         let applied = self.get_applied_migrations(db).await?;
         let mut newly_applied = Vec::new();
 
@@ -70,6 +157,7 @@ impl MigrationManager {
             if !applied.contains_key(&migration.id) {
                 // Run migration
                 (migration.up)(db)?;
+                lock.check().await?;
 
                 // Record migration
                 let record = Migration {
@@ -88,19 +176,36 @@ impl MigrationManager {
     }
 
     /// Rollback last N migrations
+    ///
+    /// Holds the same lock as [`MigrationManager::migrate`].
     pub async fn rollback(&self, db: &TormDb, steps: usize) -> Result<Vec<String>> {
+        let lock = MigrationLock::acquire(db).await?;
+        let result = self.roll_back(db, steps, &lock).await;
+        let released = lock.release().await;
+        let rolled_back = result?;
+        released?;
+        Ok(rolled_back)
+    }
+
+    async fn roll_back(
+        &self,
+        db: &TormDb,
+        steps: usize,
+        lock: &MigrationLock,
+    ) -> Result<Vec<String>> {
         let applied = self.get_applied_migrations(db).await?;
         let mut rolled_back = Vec::new();
 
         // Sort by applied_at descending
         let mut migrations_vec: Vec<_> = applied.into_iter().collect();
-        migrations_vec.sort_by(|a, b| b.1.applied_at.cmp(&a.1.applied_at));
+        migrations_vec.sort_by_key(|m| std::cmp::Reverse(m.1.applied_at));
 
         for (migration_id, record) in migrations_vec.iter().take(steps) {
             // Find migration file
             if let Some(migration) = self.migrations.iter().find(|m| &m.id == migration_id) {
                 // Run down migration
                 (migration.down)(db)?;
+                lock.check().await?;
 
                 // Remove migration record
                 self.remove_migration(db, migration_id).await?;
@@ -138,9 +243,28 @@ impl MigrationManager {
         Ok(status)
     }
 
+    /// List migrations recorded as applied, oldest first
+    ///
+    /// Unlike [`MigrationManager::status`], this does not need the
+    /// migrations to be registered and fails if the record is unreadable.
+    pub async fn applied(db: &TormDb) -> Result<Vec<Migration>> {
+        let data: Option<String> = redis::cmd("GET")
+            .arg(db.key(MIGRATIONS_KEY))
+            .query_async(&mut db.connection().clone())
+            .await?;
+
+        let Some(data) = data else {
+            return Ok(Vec::new());
+        };
+        let migrations: HashMap<String, Migration> = serde_json::from_str(&data)?;
+        let mut migrations: Vec<Migration> = migrations.into_values().collect();
+        migrations.sort_by_key(|m| m.applied_at);
+        Ok(migrations)
+    }
+
     /// Get applied migrations from database
     async fn get_applied_migrations(&self, db: &TormDb) -> Result<HashMap<String, Migration>> {
-        let key = "torm:migrations";
+        let key = db.key(MIGRATIONS_KEY);
         match redis::cmd("GET")
             .arg(key)
             .query_async::<String>(&mut db.connection().clone())
@@ -157,7 +281,7 @@ impl MigrationManager {
 
     /// Save migration record
     async fn save_migration(&self, db: &TormDb, migration: &Migration) -> Result<()> {
-        let key = "torm:migrations";
+        let key = db.key(MIGRATIONS_KEY);
         let mut migrations = self.get_applied_migrations(db).await?;
         migrations.insert(migration.id.clone(), migration.clone());
 
@@ -175,7 +299,7 @@ impl MigrationManager {
 
     /// Remove migration record
     async fn remove_migration(&self, db: &TormDb, migration_id: &str) -> Result<()> {
-        let key = "torm:migrations";
+        let key = db.key(MIGRATIONS_KEY);
         let mut migrations = self.get_applied_migrations(db).await?;
         migrations.remove(migration_id);
 
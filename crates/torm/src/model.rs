@@ -4,6 +4,53 @@ use crate::{Error, Result, TormDb};
 use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Serialize};
 
+/// Set one top-level field of a stored JSON document, returning 0 if missing
+///
+/// Empty arrays are kept as arrays when the server's cjson supports it.
+const SET_FIELD_SCRIPT: &str = r#"
+local raw = redis.call('GET', KEYS[1])
+if not raw then
+    return 0
+end
+local decode = cjson.decode_array_with_array_mt or cjson.decode
+local doc = decode(raw)
+doc[ARGV[1]] = cjson.decode(ARGV[2])
+redis.call('SET', KEYS[1], cjson.encode(doc))
+return 1
+"#;
+
+/// Move a document to a new key if it is unchanged and the target is free
+///
+/// Returns 1 on success, 0 if the source is missing, -1 if the target
+/// exists and -2 if the source changed since it was read.
+const RENAME_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[2]) == 1 then
+    return -1
+end
+local current = redis.call('GET', KEYS[1])
+if not current then
+    return 0
+end
+if current ~= ARGV[1] then
+    return -2
+end
+redis.call('SET', KEYS[2], ARGV[2])
+redis.call('DEL', KEYS[1])
+return 1
+"#;
+
+/// Replace a document only if it still holds the expected bytes
+///
+/// Returns 1 on success and 0 if the document changed or is missing.
+const COMPARE_AND_SET_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+"#;
+
 /// Model trait for TORM entities
 ///
 /// This trait is typically derived using the `#[derive(Model)]` proc macro.
@@ -32,6 +79,123 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     /// Set the ID of this model instance
     fn set_id(&mut self, id: String);
 
+    /// Generate a new ID for this model
+    ///
+    /// Used when a copy needs a fresh key. Defaults to a random UUID.
+    fn generate_id() -> String
+    where
+        Self: Sized,
+    {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    /// How new IDs are generated for this model
+    ///
+    /// Defaults to [`IdStrategy::Uuid`](crate::IdStrategy::Uuid). The derive
+    /// switches to a sequence for `#[id(auto = "sequence")]`.
+    fn id_strategy() -> crate::IdStrategy
+    where
+        Self: Sized,
+    {
+        crate::IdStrategy::Uuid
+    }
+
+    /// Generate the next ID according to [`Model::id_strategy`]
+    async fn next_id(db: &TormDb) -> Result<String>
+    where
+        Self: Sized,
+    {
+        match Self::id_strategy() {
+            crate::IdStrategy::Uuid => Ok(Self::generate_id()),
+            crate::IdStrategy::Sequence => Ok(crate::id::next_sequence(db, Self::collection())
+                .await?
+                .to_string()),
+        }
+    }
+
+    /// Codec used to store documents of this model
+    ///
+    /// Defaults to [`Codec::Json`](crate::Codec::Json). Used by save, find,
+    /// queries, sampling and dumps.
+    fn codec() -> crate::Codec
+    where
+        Self: Sized,
+    {
+        crate::Codec::Json
+    }
+
+    /// Compression for large documents of this model
+    ///
+    /// Defaults to `None`. Documents already stored keep loading whatever
+    /// this returns, so compression can be turned on or off at any time.
+    fn compression() -> Option<crate::Compression>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Whether this collection keeps an insertion-order index
+    ///
+    /// Ordered collections track their IDs in `torm:order:{collection}` on
+    /// save and delete, and [`Model::find_all`] returns them in creation
+    /// order. Generated by the derive for `#[model(ordered)]`.
+    fn ordered() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Fields with a secondary index
+    ///
+    /// TORM keeps their indexes up to date on every write, and queries use
+    /// them for `eq`, `in` and numeric range filters once they have been
+    /// built with [`Model::build_indexes`]. Generated by the derive for
+    /// fields marked `#[index]`.
+    fn indexes() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    /// Fields of this collection's RediSearch schema
+    ///
+    /// Used by [`Model::create_search_index`] and by queries run with
+    /// [`QueryBuilder::search`](crate::QueryBuilder::search). Generated by
+    /// the derive for fields marked `#[search]`.
+    fn search_fields() -> &'static [crate::SearchField]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    /// Name of the managed `updated_at` field, if the model has one
+    ///
+    /// Generated by the derive for a field marked `#[updated_at]`.
+    fn updated_at_field() -> Option<&'static str>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Reset managed timestamps to `now`
+    ///
+    /// Generated by the derive for fields marked `#[created_at]` and
+    /// `#[updated_at]`. By default, does nothing.
+    fn reset_timestamps(&mut self, _now: chrono::DateTime<chrono::Utc>) {}
+
+    /// Called after a document is loaded from the store
+    ///
+    /// Runs on every model returned by `find_by_id`, `find_all`, queries and
+    /// `sample`. Override it to recompute fields that are not stored, such
+    /// as a `full_name` built from first and last names. The derive
+    /// generates it for fields marked `#[skip(compute = "...")]`.
+    fn after_load(&mut self) {}
+
     /// Validate this model instance
     ///
     /// Override this method to provide custom validation logic.
@@ -40,7 +204,7 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
         Ok(())
     }
 
-    /// Generate a Redis key for this model
+    /// Generate the Redis key for this model, before any [`TormDb::with_prefix`] prefix
     fn key(&self) -> String {
         format!("{}:{}", Self::collection(), self.id())
     }
@@ -63,23 +227,80 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     /// # Ok(())
     /// # }
     /// ```
+    #[tracing::instrument(name = "torm.save", level = "info", skip_all, fields(collection = Self::collection(), id = self.id()))]
     async fn save(&self, db: &TormDb) -> Result<()> {
-        // Validate before saving
-        self.validate()?;
+        let result: Result<()> = async {
+            // Validate before saving
+            self.validate()?;
 
-        let key = self.key();
-        let value = serde_json::to_string(self)?;
+            let key = db.key(&self.key());
+            let value = crate::codec::compress(Self::compression(), Self::codec().encode(self)?)?;
 
-        let mut conn = db.connection().clone();
-        redis::cmd("SET")
-            .arg(&key)
-            .arg(&value)
-            .query_async::<()>(&mut conn)
-            .await?;
+            let previous =
+                crate::counter::set_counted(db, Self::collection(), &key, &value).await?;
+            let previous = match previous {
+                Some(previous) if !Self::indexes().is_empty() => {
+                    Some(Self::codec().decode::<serde_json::Value>(&previous)?)
+                }
+                _ => None,
+            };
+            after_save(db, self, previous.as_ref(), serde_json::to_value(self)?).await
+        }
+        .await;
+        db.op_stats().record(&result);
+        result
+    }
 
+    /// Queue a save of this model on `pipeline`
+    ///
+    /// The model is validated and encoded now and written when the
+    /// pipeline is executed, see [`TormDb::pipeline`].
+    fn save_in(&self, pipeline: &mut crate::Pipeline) -> Result<()>
+    where
+        Self: Sized,
+    {
+        pipeline.push(crate::pipeline::Write::save(self)?);
         Ok(())
     }
 
+    /// Queue a delete of this model on `pipeline`
+    fn delete_in(&self, pipeline: &mut crate::Pipeline)
+    where
+        Self: Sized,
+    {
+        pipeline.push(crate::pipeline::Write::delete(self));
+    }
+
+    /// Assign a new ID if this model has none, then save it
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Model, Serialize, Deserialize)]
+    /// struct Invoice {
+    ///     #[id(auto = "sequence")]
+    ///     id: String,
+    ///     total: u64,
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let mut invoice = Invoice { id: String::new(), total: 100 };
+    /// invoice.create(&db).await?; // id: "1", "2", ...
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn create(&mut self, db: &TormDb) -> Result<()>
+    where
+        Self: Sized,
+    {
+        if self.id().is_empty() {
+            self.set_id(Self::next_id(db).await?);
+        }
+        self.save(db).await
+    }
+
     /// Find a model by ID
     ///
     /// # Example
@@ -95,22 +316,30 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     /// # Ok(())
     /// # }
     /// ```
+    #[tracing::instrument(name = "torm.find_by_id", level = "info", skip(db), fields(collection = Self::collection()))]
     async fn find_by_id(db: &TormDb, id: &str) -> Result<Self>
     where
         Self: Sized,
     {
-        let key = format!("{}:{}", Self::collection(), id);
-        let mut conn = db.connection().clone();
+        let db: &TormDb = &db.reader();
+        let result: Result<Self> = async {
+            let key = db.key(&format!("{}:{}", Self::collection(), id));
+            let mut conn = db.connection().clone();
 
-        let value: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+            let value: Option<Vec<u8>> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
 
-        match value {
-            Some(v) => {
-                let model = serde_json::from_str(&v)?;
-                Ok(model)
+            match value {
+                Some(v) => {
+                    let mut model: Self = Self::codec().decode(&v)?;
+                    model.after_load();
+                    Ok(model)
+                }
+                None => Err(Error::NotFound(format!("{}:{}", Self::collection(), id))),
             }
-            None => Err(Error::NotFound(format!("{}:{}", Self::collection(), id))),
         }
+        .await;
+        db.op_stats().record(&result);
+        result
     }
 
     /// Delete this model from the database
@@ -129,16 +358,349 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     /// # Ok(())
     /// # }
     /// ```
+    #[tracing::instrument(name = "torm.delete", level = "info", skip_all, fields(collection = Self::collection(), id = self.id()))]
     async fn delete(&self, db: &TormDb) -> Result<()> {
-        let key = self.key();
+        let result: Result<()> = async {
+            let key = db.key(&self.key());
+            let removed = crate::counter::del_counted(db, Self::collection(), &key).await?;
+            let removed = match removed {
+                Some(removed) if !Self::indexes().is_empty() => {
+                    Some(Self::codec().decode::<serde_json::Value>(&removed)?)
+                }
+                _ => None,
+            };
+            after_delete::<Self>(db, self.id(), removed.as_ref()).await
+        }
+        .await;
+        db.op_stats().record(&result);
+        result
+    }
+
+    /// Save a copy of this model under a new ID
+    ///
+    /// Uses `new_id` when given, otherwise [`Model::next_id`]. Managed
+    /// timestamps on the copy are reset to the current time.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Template { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let template = Template::find_by_id(&db, "welcome").await?;
+    /// let copy = template.duplicate(&db, None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn duplicate(&self, db: &TormDb, new_id: Option<&str>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut copy: Self = serde_json::from_value(serde_json::to_value(self)?)?;
+        copy.after_load();
+        let id = match new_id {
+            Some(id) => id.to_string(),
+            None => Self::next_id(db).await?,
+        };
+        copy.set_id(id);
+        copy.reset_timestamps(chrono::Utc::now());
+        copy.save(db).await?;
+        Ok(copy)
+    }
+
+    /// Bump the `updated_at` timestamp without a full save
+    ///
+    /// Only the `#[updated_at]` field is changed, inside ToonStore, so the
+    /// document is not sent over the wire. Validation is skipped. Numbers in
+    /// the stored document are re-encoded by the server's JSON library, so
+    /// integers beyond 2^53 may lose precision.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # use chrono::{DateTime, Utc};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Session { #[id] id: String, #[updated_at] last_seen: DateTime<Utc> }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let mut session = Session::find_by_id(&db, "abc").await?;
+    /// session.touch(&db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn touch(&mut self, db: &TormDb) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let field = Self::updated_at_field().ok_or_else(|| {
+            Error::Other(format!(
+                "model '{}' has no #[updated_at] field",
+                Self::collection()
+            ))
+        })?;
+        if Self::codec() != crate::Codec::Json || Self::compression().is_some() {
+            return Err(Error::Other(format!(
+                "touch requires the JSON codec without compression (model '{}')",
+                Self::collection()
+            )));
+        }
+        let now = serde_json::to_value(chrono::Utc::now())?;
+        let previous = serde_json::to_value(&*self)?;
+
+        let key = db.key(&self.key());
         let mut conn = db.connection().clone();
+        let touched: bool = redis::Script::new(SET_FIELD_SCRIPT)
+            .key(&key)
+            .arg(field)
+            .arg(now.to_string())
+            .invoke_async(&mut conn)
+            .await?;
+        if !touched {
+            return Err(Error::NotFound(key));
+        }
 
-        redis::cmd("DEL")
-            .arg(&key)
-            .query_async::<()>(&mut conn)
+        let mut value = serde_json::to_value(&*self)?;
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert(field.to_string(), now);
+        }
+        *self = serde_json::from_value(value.clone())?;
+        self.after_load();
+        crate::index::update(
+            db,
+            Self::collection(),
+            Self::indexes(),
+            self.id(),
+            Some(&previous),
+            Some(&value),
+        )
+        .await?;
+
+        crate::events::publish(
+            db,
+            crate::ModelOp::Save,
+            Self::collection(),
+            self.id(),
+            Some(value),
+        )
+        .await
+    }
+
+    /// Save local edits, merging with concurrent changes
+    ///
+    /// `base` is the model as it was loaded. If the stored document no
+    /// longer matches it, `strategy` decides how to combine the two. The
+    /// write is a compare-and-set that is retried if the document changes
+    /// again meanwhile; after 5 attempts it fails with [`Error::Conflict`].
+    /// On success `self` holds the saved document. A missing document is
+    /// simply saved.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{MergeStrategy, Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Note { #[id] id: String, title: String, body: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let base = Note::find_by_id(&db, "1").await?;
+    /// let mut note = Note::find_by_id(&db, "1").await?;
+    /// note.title = "Renamed".into();
+    /// note.merge_save(&db, &base, MergeStrategy::FieldLevel).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(name = "torm.merge_save", level = "info", skip_all, fields(collection = Self::collection(), id = self.id()))]
+    async fn merge_save(
+        &mut self,
+        db: &TormDb,
+        base: &Self,
+        strategy: crate::MergeStrategy<Self>,
+    ) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let key = db.key(&self.key());
+        let base_value = serde_json::to_value(base)?;
+        let mut conn = db.connection().clone();
+
+        for _ in 0..crate::merge::MAX_MERGE_ATTEMPTS {
+            let current: Option<Vec<u8>> =
+                redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+            let Some(current) = current else {
+                return self.save(db).await;
+            };
+
+            let theirs: Self = Self::codec().decode(&current)?;
+            let theirs_value = serde_json::to_value(&theirs)?;
+            let mine_value = serde_json::to_value(&*self)?;
+
+            let mut merged: Self = if theirs_value == base_value {
+                serde_json::from_value(mine_value)?
+            } else {
+                match &strategy {
+                    crate::MergeStrategy::LastWriteWins => serde_json::from_value(mine_value)?,
+                    crate::MergeStrategy::FieldLevel => serde_json::from_value(
+                        crate::merge::merge_fields(&base_value, &mine_value, &theirs_value),
+                    )?,
+                    crate::MergeStrategy::Custom(resolve) => {
+                        resolve(serde_json::from_value(mine_value)?, theirs)
+                    }
+                }
+            };
+            merged.set_id(self.id().to_string());
+            merged.validate()?;
+
+            let encoded =
+                crate::codec::compress(Self::compression(), Self::codec().encode(&merged)?)?;
+            let written: bool = redis::Script::new(COMPARE_AND_SET_SCRIPT)
+                .key(&key)
+                .arg(&current)
+                .arg(&encoded)
+                .invoke_async(&mut conn)
+                .await?;
+            if !written {
+                continue;
+            }
+            crate::index::update_encoded(
+                db,
+                Self::collection(),
+                Self::indexes(),
+                Self::codec(),
+                merged.id(),
+                Some(&current),
+                Some(&encoded),
+            )
             .await?;
 
-        Ok(())
+            merged.after_load();
+            crate::events::publish(
+                db,
+                crate::ModelOp::Save,
+                Self::collection(),
+                merged.id(),
+                Some(serde_json::to_value(&merged)?),
+            )
+            .await?;
+            *self = merged;
+            return Ok(());
+        }
+
+        Err(Error::Conflict(format!(
+            "{} kept changing during merge",
+            key
+        )))
+    }
+
+    /// Atomically move a document from `old_id` to `new_id`
+    ///
+    /// The stored document is rewritten with the new ID and the old key is
+    /// removed in one server-side step, so readers never see both or
+    /// neither. Fails with [`Error::Conflict`] if `new_id` is taken or the
+    /// document changed concurrently, and [`Error::NotFound`] if `old_id`
+    /// does not exist. Publishes a delete for the old ID and a save for the
+    /// new one.
+    ///
+    /// Not supported on Redis Cluster, where the two keys usually live in
+    /// different hash slots and can't share a script; fails with
+    /// [`Error::Other`] there. Save under the new ID and delete the old
+    /// document instead, giving up the atomicity.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Post { #[id] slug: String, title: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let post = Post::rename_id(&db, "hello-wrld", "hello-world").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn rename_id(db: &TormDb, old_id: &str, new_id: &str) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        if db.connection().is_cluster() {
+            return Err(Error::Other(
+                "rename_id is not supported on Redis Cluster".to_string(),
+            ));
+        }
+        let old_key = db.key(&format!("{}:{}", Self::collection(), old_id));
+        let new_key = db.key(&format!("{}:{}", Self::collection(), new_id));
+        let mut conn = db.connection().clone();
+
+        let current: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(&old_key)
+            .query_async(&mut conn)
+            .await?;
+        let current = current.ok_or_else(|| Error::NotFound(old_key.clone()))?;
+
+        let mut model: Self = Self::codec().decode(&current)?;
+        model.set_id(new_id.to_string());
+        let value = crate::codec::compress(Self::compression(), Self::codec().encode(&model)?)?;
+
+        let outcome: i64 = redis::Script::new(RENAME_SCRIPT)
+            .key(&old_key)
+            .key(&new_key)
+            .arg(&current)
+            .arg(&value)
+            .invoke_async(&mut conn)
+            .await?;
+        match outcome {
+            1 => {}
+            0 => return Err(Error::NotFound(old_key)),
+            -1 => return Err(Error::Conflict(format!("{} already exists", new_key))),
+            _ => {
+                return Err(Error::Conflict(format!(
+                    "{} changed during rename",
+                    old_key
+                )))
+            }
+        }
+
+        crate::order::rename(db, Self::collection(), old_id, new_id).await?;
+        let codec = Self::codec();
+        crate::index::update_encoded(
+            db,
+            Self::collection(),
+            Self::indexes(),
+            codec,
+            old_id,
+            Some(&current),
+            None,
+        )
+        .await?;
+        crate::index::update_encoded(
+            db,
+            Self::collection(),
+            Self::indexes(),
+            codec,
+            new_id,
+            None,
+            Some(&value),
+        )
+        .await?;
+        model.after_load();
+        crate::events::publish(db, crate::ModelOp::Delete, Self::collection(), old_id, None)
+            .await?;
+        crate::events::publish(
+            db,
+            crate::ModelOp::Save,
+            Self::collection(),
+            new_id,
+            Some(serde_json::to_value(&model)?),
+        )
+        .await?;
+
+        Ok(model)
     }
 
     /// Check if a model exists by ID
@@ -146,7 +708,8 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     where
         Self: Sized,
     {
-        let key = format!("{}:{}", Self::collection(), id);
+        let db: &TormDb = &db.reader();
+        let key = db.key(&format!("{}:{}", Self::collection(), id));
         let mut conn = db.connection().clone();
 
         let exists: bool = redis::cmd("EXISTS")
@@ -159,6 +722,8 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
 
     /// Find all models in this collection
     ///
+    /// Applies the model's [default scope](Model::default_scope).
+    ///
     /// # Example
     /// ```rust,no_run
     /// # use torm::{Model, TormDb};
@@ -176,30 +741,252 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     where
         Self: Sized,
     {
-        let pattern = format!("{}:*", Self::collection());
+        Self::query().exec(db).await
+    }
+
+    /// Count all models in this collection
+    ///
+    /// Applies the model's [default scope](Model::default_scope), like
+    /// [`Model::find_all`]. Without one this reads the maintained
+    /// `torm:count:{collection}` counter instead of walking the keyspace; use
+    /// `Self::unscoped().count(db)` to count every document regardless.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let count = User::count(&db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn count(db: &TormDb) -> Result<usize>
+    where
+        Self: Sized,
+    {
+        Self::query().count(db).await
+    }
+
+    /// Rebuild the collection counter from the keyspace
+    ///
+    /// Use this to repair the counter after keys were written or removed
+    /// outside of TORM. Returns the new count.
+    async fn recount(db: &TormDb) -> Result<usize>
+    where
+        Self: Sized,
+    {
+        crate::counter::recount(db, Self::collection()).await
+    }
+
+    /// Add documents missing from the insertion-order index
+    ///
+    /// Backfills an ordered collection that already held documents, in SCAN
+    /// order, and enables ordering for it. Returns how many IDs were added.
+    async fn reindex_order(db: &TormDb) -> Result<usize>
+    where
+        Self: Sized,
+    {
+        crate::order::reindex(db, Self::collection()).await
+    }
+
+    /// Build the indexes of [`Model::indexes`] from the stored documents
+    ///
+    /// Run once after adding `#[index]` to a field, or after documents were
+    /// written outside of TORM; queries only use an index once it has been
+    /// built. Returns how many documents were indexed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Model, Serialize, Deserialize)]
+    /// struct User {
+    ///     #[id]
+    ///     id: String,
+    ///     #[index]
+    ///     email: String,
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// User::build_indexes(&db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn build_indexes(db: &TormDb) -> Result<usize>
+    where
+        Self: Sized,
+    {
+        crate::index::build(db, Self::collection(), Self::indexes(), Self::codec()).await
+    }
+
+    /// Create this collection's RediSearch index from its `#[search]` fields
+    ///
+    /// The index covers documents written with [`JsonStore`](crate::JsonStore)
+    /// and stays current as they change. Returns `false` if it already
+    /// existed; drop it with `FT.DROPINDEX` after changing the schema.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Model, Serialize, Deserialize)]
+    /// struct User {
+    ///     #[id]
+    ///     id: String,
+    ///     #[search]
+    ///     city: String,
+    ///     #[search(sortable)]
+    ///     age: u32,
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// User::create_search_index(&db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn create_search_index(db: &TormDb) -> Result<bool>
+    where
+        Self: Sized,
+    {
+        crate::search::create_index(db, Self::collection(), Self::search_fields()).await
+    }
+
+    /// Fetch up to `n` random documents from this collection
+    ///
+    /// Uses reservoir sampling over a SCAN of the collection, so every
+    /// document is equally likely to be picked. Useful for spot-checking
+    /// data and building demo datasets; the default scope is not applied.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let users = User::sample(&db, 10).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(name = "torm.sample", level = "info", skip(db), fields(collection = Self::collection()))]
+    async fn sample(db: &TormDb, n: usize) -> Result<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        use rand::{Rng, SeedableRng};
+
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+
+        let db: &TormDb = &db.reader();
         let mut conn = db.connection().clone();
+        let mut scanner =
+            crate::scan::KeyScanner::new(db.key_pattern(&format!("{}:*", Self::collection())));
+        let mut rng = rand::rngs::StdRng::from_entropy();
+        let mut reservoir: Vec<String> = Vec::with_capacity(n);
+        let mut seen = 0usize;
+
+        while let Some(keys) = scanner.next_batch(&mut conn).await? {
+            for key in keys {
+                seen += 1;
+                if reservoir.len() < n {
+                    reservoir.push(key);
+                } else {
+                    let slot = rng.gen_range(0..seen);
+                    if slot < n {
+                        reservoir[slot] = key;
+                    }
+                }
+            }
+        }
 
-        // Use KEYS to find all matching keys
-        let keys: Vec<String> = redis::cmd("KEYS")
-            .arg(&pattern)
+        if reservoir.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+            .arg(&reservoir)
             .query_async(&mut conn)
             .await?;
 
-        let mut results = Vec::new();
-        for key in keys {
-            let value: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+        // Documents deleted since the scan are skipped
+        values
+            .into_iter()
+            .flatten()
+            .map(|v| {
+                let mut model: Self = Self::codec().decode(&v)?;
+                model.after_load();
+                Ok(model)
+            })
+            .collect()
+    }
 
-            if let Some(v) = value {
-                if let Ok(model) = serde_json::from_str(&v) {
-                    results.push(model);
-                }
+    /// Delete every document in this collection
+    ///
+    /// Walks the collection with SCAN and removes keys in batches with
+    /// UNLINK, then resets the collection counter. Returns how many
+    /// documents were removed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let removed = User::truncate(&db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(name = "torm.truncate", level = "info", skip_all, fields(collection = Self::collection(), keys = tracing::field::Empty))]
+    async fn truncate(db: &TormDb) -> Result<usize>
+    where
+        Self: Sized,
+    {
+        let mut conn = db.connection().clone();
+        let mut scanner =
+            crate::scan::KeyScanner::new(db.key_pattern(&format!("{}:*", Self::collection())));
+        let mut removed = 0;
+
+        while let Some(keys) = scanner.next_batch(&mut conn).await? {
+            if keys.is_empty() {
+                continue;
             }
+            let n: usize = redis::cmd("UNLINK")
+                .arg(&keys)
+                .query_async(&mut conn)
+                .await?;
+            removed += n;
         }
 
-        Ok(results)
+        redis::cmd("DEL")
+            .arg(db.key(&crate::counter_key(Self::collection())))
+            .arg(db.key(&crate::order::order_key(Self::collection())))
+            .arg(db.key(&crate::order::sequence_key(Self::collection())))
+            .query_async::<()>(&mut conn)
+            .await?;
+        crate::index::clear(db, Self::collection()).await?;
+
+        tracing::Span::current().record("keys", removed);
+        Ok(removed)
     }
 
-    /// Count all models in this collection
+    /// Write every document in this collection as NDJSON
+    ///
+    /// Documents are streamed in SCAN order, one stored JSON document per
+    /// line, without applying the default scope. Returns how many documents
+    /// were written.
     ///
     /// # Example
     /// ```rust,no_run
@@ -210,23 +997,129 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let db = TormDb::connect("redis://localhost:6379").await?;
-    /// let count = User::count(&db).await?;
+    /// let file = tokio::fs::File::create("users.ndjson").await?;
+    /// let written = User::dump(&db, file).await?;
     /// # Ok(())
     /// # }
     /// ```
-    async fn count(db: &TormDb) -> Result<usize>
+    async fn dump<W>(db: &TormDb, writer: W) -> Result<usize>
     where
         Self: Sized,
+        W: tokio::io::AsyncWrite + Unpin + Send,
     {
-        let pattern = format!("{}:*", Self::collection());
+        use tokio::io::AsyncWriteExt;
+
+        let mut writer = tokio::io::BufWriter::new(writer);
         let mut conn = db.connection().clone();
+        let mut scanner =
+            crate::scan::KeyScanner::new(db.key_pattern(&format!("{}:*", Self::collection())));
+        let mut written = 0;
 
-        let keys: Vec<String> = redis::cmd("KEYS")
-            .arg(&pattern)
-            .query_async(&mut conn)
-            .await?;
+        while let Some(keys) = scanner.next_batch(&mut conn).await? {
+            if keys.is_empty() {
+                continue;
+            }
+            let values: Vec<Option<Vec<u8>>> =
+                redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
 
-        Ok(keys.len())
+            for value in values.into_iter().flatten() {
+                let value = crate::codec::decompress(&value)?;
+                if Self::codec() == crate::Codec::Json {
+                    writer.write_all(&value).await?;
+                } else {
+                    let doc: serde_json::Value = Self::codec().decode(&value)?;
+                    writer.write_all(&serde_json::to_vec(&doc)?).await?;
+                }
+                writer.write_all(b"\n").await?;
+                written += 1;
+            }
+        }
+
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Save every document from an NDJSON stream produced by [`Model::dump`]
+    ///
+    /// Each line is deserialized, validated and saved, so counters, events
+    /// and the audit trail are kept up to date. Blank lines are skipped.
+    /// Stops at the first invalid line. Returns how many documents were saved.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let file = tokio::fs::File::open("users.ndjson").await?;
+    /// let loaded = User::load(&db, file).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn load<R>(db: &TormDb, reader: R) -> Result<usize>
+    where
+        Self: Sized,
+        R: tokio::io::AsyncRead + Unpin + Send,
+    {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let mut line_no = 0;
+        let mut loaded = 0;
+
+        while let Some(line) = lines.next_line().await? {
+            line_no += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let model: Self = serde_json::from_str(&line)
+                .map_err(|e| Error::Other(format!("line {}: {}", line_no, e)))?;
+            model.save(db).await?;
+            loaded += 1;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Watch this collection for changes
+    ///
+    /// Returns a stream of create/update/delete events built on keyspace
+    /// notifications, pre-filtered to this collection's keys. The stream
+    /// holds its own pub/sub connection.
+    ///
+    /// Writes through [`HashStore`](crate::HashStore) and
+    /// [`JsonStore`](crate::JsonStore) are reported too. The notification
+    /// classes this needs (`Kg$hxed`) are added to the server's
+    /// `notify-keyspace-events`, keeping those already set; on a server
+    /// that refuses `CONFIG`, enable them there.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{ChangeEvent, Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # use futures::StreamExt;
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let mut changes = User::watch(&db).await?;
+    /// while let Some(event) = changes.next().await {
+    ///     if let ChangeEvent::Deleted { id } = event? {
+    ///         println!("user {} deleted", id);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn watch(db: &TormDb) -> Result<crate::watch::ChangeStream<Self>>
+    where
+        Self: Sized + 'static,
+    {
+        crate::watch::watch::<Self>(db).await
     }
 
     /// Create a query builder for this model
@@ -250,9 +1143,109 @@ pub trait Model: Serialize + DeserializeOwned + Send + Sync {
     /// # }
     /// ```
     fn query() -> crate::query::QueryBuilder<Self>
+    where
+        Self: Sized,
+    {
+        Self::default_scope(Self::unscoped())
+    }
+
+    /// Create a query builder that ignores the default scope
+    fn unscoped() -> crate::query::QueryBuilder<Self>
     where
         Self: Sized,
     {
         crate::query::QueryBuilder::new(Self::collection())
+            .with_codec(Self::codec())
+            .with_compression(Self::compression())
+            .with_after_load(Self::after_load)
+            .with_indexes(Self::indexes())
+            .with_search_fields(Self::search_fields())
+            .insertion_order()
     }
+
+    /// Default scope applied by [`Model::query`], [`Model::find_all`] and
+    /// [`Model::count`]
+    ///
+    /// Override this to always filter the collection, e.g. to hide
+    /// soft-deleted documents. Use [`Model::unscoped`] to bypass it.
+    ///
+    /// Named scopes are plain functions taking and returning a query
+    /// builder, combined with [`QueryBuilder::scope`](crate::QueryBuilder::scope).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, Query, QueryBuilder};
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct User { id: String, active: bool, deleted: bool }
+    ///
+    /// impl User {
+    ///     /// Named scope: only active users
+    ///     fn active(query: QueryBuilder<Self>) -> QueryBuilder<Self> {
+    ///         query.filter("active", Query::eq(true))
+    ///     }
+    /// }
+    ///
+    /// impl Model for User {
+    ///     fn collection() -> &'static str { "user" }
+    ///     fn id(&self) -> &str { &self.id }
+    ///     fn set_id(&mut self, id: String) { self.id = id; }
+    ///
+    ///     fn default_scope(query: QueryBuilder<Self>) -> QueryBuilder<Self> {
+    ///         query.filter("deleted", Query::ne(true))
+    ///     }
+    /// }
+    ///
+    /// let active_users = User::query().scope(User::active);
+    /// ```
+    fn default_scope(query: crate::query::QueryBuilder<Self>) -> crate::query::QueryBuilder<Self>
+    where
+        Self: Sized,
+    {
+        query
+    }
+}
+
+/// Bring indexes, insertion order, the audit trail, CDC and observers up to
+/// date after `model` was written over `previous`
+///
+/// Shared by [`Model::save`] and [`crate::JsonStore::save`], so that both
+/// write paths have the same side effects.
+pub(crate) async fn after_save<M: Model>(
+    db: &TormDb,
+    model: &M,
+    previous: Option<&serde_json::Value>,
+    doc: serde_json::Value,
+) -> Result<()> {
+    crate::index::update(
+        db,
+        M::collection(),
+        M::indexes(),
+        model.id(),
+        previous,
+        Some(&doc),
+    )
+    .await?;
+    if M::ordered() {
+        crate::order::track(db, M::collection(), model.id(), true).await?;
+    }
+    crate::events::publish(
+        db,
+        crate::ModelOp::Save,
+        M::collection(),
+        model.id(),
+        Some(doc),
+    )
+    .await
+}
+
+/// Counterpart of [`after_save`] for a removed document
+pub(crate) async fn after_delete<M: Model>(
+    db: &TormDb,
+    id: &str,
+    removed: Option<&serde_json::Value>,
+) -> Result<()> {
+    crate::index::update(db, M::collection(), M::indexes(), id, removed, None).await?;
+    crate::order::untrack_insertion(db, M::collection(), id).await?;
+    crate::events::publish(db, crate::ModelOp::Delete, M::collection(), id, None).await
 }
@@ -0,0 +1,180 @@
+//! Insertion-order index for ordered collections
+//!
+//! Collections opt in through [`crate::Model::ordered`]. Their IDs are kept
+//! in a sorted set, `torm:order:{collection}`, scored by a per-collection
+//! sequence, so listings can return documents in creation order without a
+//! timestamp field. The sequence key doubles as the marker that a
+//! collection is ordered.
+
+use crate::scan::{KeyScanner, DEFAULT_SCAN_COUNT};
+use crate::{Connection, Result, TormDb};
+
+/// Add an ID to the index unless it is already there
+///
+/// With ARGV[2] = "0" the ID is only tracked if the collection is already
+/// ordered (its sequence key exists).
+const TRACK_SCRIPT: &str = r#"
+if ARGV[2] == '0' and redis.call('EXISTS', KEYS[2]) == 0 then
+    return 0
+end
+if redis.call('ZSCORE', KEYS[1], ARGV[1]) then
+    return 0
+end
+local score = redis.call('INCR', KEYS[2])
+redis.call('ZADD', KEYS[1], score, ARGV[1])
+return 1
+"#;
+
+/// Move an ID to a new one, keeping its position
+const RENAME_SCRIPT: &str = r#"
+local score = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if score then
+    redis.call('ZADD', KEYS[1], score, ARGV[2])
+    redis.call('ZREM', KEYS[1], ARGV[1])
+end
+return 1
+"#;
+
+/// Get the key of a collection's insertion-order index
+pub fn order_key(collection: &str) -> String {
+    format!("torm:order:{}", collection)
+}
+
+pub(crate) fn sequence_key(collection: &str) -> String {
+    format!("torm:order-seq:{}", collection)
+}
+
+/// Record a newly saved document in an ordered collection
+///
+/// Does nothing if the collection is not ordered or the ID is already
+/// tracked. For writers that bypass [`crate::Model::save`], such as TORM
+/// Server.
+pub async fn track_insertion(db: &TormDb, collection: &str, id: &str) -> Result<()> {
+    track(db, collection, id, false).await
+}
+
+/// Remove a deleted document from the insertion-order index
+pub async fn untrack_insertion(db: &TormDb, collection: &str, id: &str) -> Result<()> {
+    let mut conn = db.connection().clone();
+    redis::cmd("ZREM")
+        .arg(db.key(&order_key(collection)))
+        .arg(id)
+        .query_async::<()>(&mut conn)
+        .await?;
+    Ok(())
+}
+
+/// Track `id`, enabling ordering for the collection when `enable` is set
+pub(crate) async fn track(db: &TormDb, collection: &str, id: &str, enable: bool) -> Result<()> {
+    let mut conn = db.connection().clone();
+    redis::Script::new(TRACK_SCRIPT)
+        .key(db.key(&order_key(collection)))
+        .key(db.key(&sequence_key(collection)))
+        .arg(id)
+        .arg(if enable { "1" } else { "0" })
+        .invoke_async::<()>(&mut conn)
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn rename(
+    db: &TormDb,
+    collection: &str,
+    old_id: &str,
+    new_id: &str,
+) -> Result<()> {
+    let mut conn = db.connection().clone();
+    redis::Script::new(RENAME_SCRIPT)
+        .key(db.key(&order_key(collection)))
+        .arg(old_id)
+        .arg(new_id)
+        .invoke_async::<()>(&mut conn)
+        .await?;
+    Ok(())
+}
+
+/// Track every stored document of `collection` that is not indexed yet
+pub(crate) async fn reindex(db: &TormDb, collection: &str) -> Result<usize> {
+    let mut conn = db.connection().clone();
+    let prefix = db.key(&format!("{}:", collection));
+    let mut scanner = KeyScanner::new(db.key_pattern(&format!("{}:*", collection)));
+    let mut added = 0;
+
+    while let Some(keys) = scanner.next_batch(&mut conn).await? {
+        for key in keys {
+            let Some(id) = key.strip_prefix(&prefix) else {
+                continue;
+            };
+            let tracked: bool = redis::Script::new(TRACK_SCRIPT)
+                .key(db.key(&order_key(collection)))
+                .key(db.key(&sequence_key(collection)))
+                .arg(id)
+                .arg("1")
+                .invoke_async(&mut conn)
+                .await?;
+            if tracked {
+                added += 1;
+            }
+        }
+    }
+
+    Ok(added)
+}
+
+/// Whether the collection has an insertion-order index
+pub(crate) async fn is_ordered(db: &TormDb, collection: &str) -> Result<bool> {
+    let mut conn = db.connection().clone();
+    let exists: bool = redis::cmd("EXISTS")
+        .arg(db.key(&sequence_key(collection)))
+        .query_async(&mut conn)
+        .await?;
+    Ok(exists)
+}
+
+/// Iterates over a collection's document keys in insertion order
+pub(crate) struct OrderScanner {
+    order_key: String,
+    prefix: String,
+    offset: usize,
+    done: bool,
+}
+
+impl OrderScanner {
+    pub(crate) fn new(db: &TormDb, collection: &str) -> Self {
+        Self {
+            order_key: db.key(&order_key(collection)),
+            prefix: db.key(&format!("{}:", collection)),
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Fetch the next batch of document keys, or `None` at the end
+    pub(crate) async fn next_batch(
+        &mut self,
+        conn: &mut Connection,
+    ) -> Result<Option<Vec<String>>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let ids: Vec<String> = redis::cmd("ZRANGE")
+            .arg(&self.order_key)
+            .arg(self.offset)
+            .arg(self.offset + DEFAULT_SCAN_COUNT - 1)
+            .query_async(conn)
+            .await?;
+
+        self.offset += ids.len();
+        self.done = ids.len() < DEFAULT_SCAN_COUNT;
+        if ids.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            ids.into_iter()
+                .map(|id| format!("{}{}", self.prefix, id))
+                .collect(),
+        ))
+    }
+}
@@ -0,0 +1,79 @@
+//! Dot paths into nested JSON documents
+//!
+//! A path like `address.city` walks nested objects; `items[*].sku` visits
+//! every element of an array and `items[0].sku` a single one. A top-level
+//! key that literally contains dots still matches as a whole.
+
+use serde_json::Value;
+
+/// Whether `path` goes beyond a single top-level field
+pub(crate) fn is_nested(path: &str) -> bool {
+    path.contains(['.', '['])
+}
+
+/// The top-level field a path starts at
+pub(crate) fn root(path: &str) -> &str {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    &path[..end]
+}
+
+/// Every value `path` leads to in `doc`
+pub(crate) fn resolve<'a>(doc: &'a Value, path: &str) -> Vec<&'a Value> {
+    if let Some(value) = doc.get(path) {
+        return vec![value];
+    }
+    if !is_nested(path) {
+        return Vec::new();
+    }
+
+    let mut current = vec![doc];
+    for segment in path.split('.') {
+        let (name, indexes) = match segment.find('[') {
+            Some(i) => (&segment[..i], &segment[i..]),
+            None => (segment, ""),
+        };
+
+        if !name.is_empty() {
+            current = current.into_iter().filter_map(|v| v.get(name)).collect();
+        }
+
+        for index in indexes.split_terminator(']') {
+            let index = index.trim_start_matches('[');
+            current = match index {
+                "*" => current
+                    .into_iter()
+                    .filter_map(Value::as_array)
+                    .flatten()
+                    .collect(),
+                _ => match index.parse::<usize>() {
+                    Ok(i) => current.into_iter().filter_map(|v| v.get(i)).collect(),
+                    Err(_) => return Vec::new(),
+                },
+            };
+        }
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve() {
+        let doc = json!({
+            "address": { "city": "Paris" },
+            "items": [{ "sku": "a" }, { "sku": "b" }, { "qty": 1 }],
+            "a.b": 1,
+        });
+
+        assert_eq!(resolve(&doc, "address.city"), [&json!("Paris")]);
+        assert_eq!(resolve(&doc, "items[*].sku"), [&json!("a"), &json!("b")]);
+        assert_eq!(resolve(&doc, "items[1].sku"), [&json!("b")]);
+        assert_eq!(resolve(&doc, "a.b"), [&json!(1)]);
+        assert!(resolve(&doc, "address.zip").is_empty());
+        assert!(resolve(&doc, "items[x]").is_empty());
+        assert_eq!(root("items[*].sku"), "items");
+    }
+}
@@ -0,0 +1,200 @@
+//! Writing several models in one round trip
+//!
+//! A [`Pipeline`] from [`TormDb::pipeline`] collects saves and deletes
+//! queued with [`Model::save_in`] and [`Model::delete_in`] and sends them
+//! together when executed. Indexes, counters, the insertion order and
+//! lifecycle events are then updated as [`Model::save`] and
+//! [`Model::delete`] would.
+
+use crate::{Codec, Model, Result, TormDb};
+use redis::aio::ConnectionLike;
+
+/// A save or delete waiting to be sent, with what is needed to follow it up
+pub(crate) struct Write {
+    collection: &'static str,
+    indexes: &'static [&'static str],
+    codec: Codec,
+    id: String,
+    /// Encoded document and its JSON form for events, or `None` to delete
+    value: Option<(Vec<u8>, serde_json::Value)>,
+    ordered: bool,
+}
+
+impl Write {
+    /// Validate and encode `model` for saving
+    pub(crate) fn save<M: Model>(model: &M) -> Result<Self> {
+        model.validate()?;
+        let encoded = crate::codec::compress(M::compression(), M::codec().encode(model)?)?;
+        Ok(Self {
+            collection: M::collection(),
+            indexes: M::indexes(),
+            codec: M::codec(),
+            id: model.id().to_string(),
+            value: Some((encoded, serde_json::to_value(model)?)),
+            ordered: M::ordered(),
+        })
+    }
+
+    /// Delete `model`
+    pub(crate) fn delete<M: Model>(model: &M) -> Self {
+        Self {
+            collection: M::collection(),
+            indexes: M::indexes(),
+            codec: M::codec(),
+            id: model.id().to_string(),
+            value: None,
+            ordered: M::ordered(),
+        }
+    }
+
+    /// Add the command, which replies with the document it replaced
+    fn queue(&self, db: &TormDb, pipe: &mut redis::Pipeline) {
+        let key = db.key(&format!("{}:{}", self.collection, self.id));
+        match &self.value {
+            Some((encoded, _)) => pipe.cmd("SET").arg(key).arg(encoded).arg("GET"),
+            None => pipe.cmd("GETDEL").arg(key),
+        };
+    }
+
+    /// Update counters, indexes, insertion order and events once written
+    async fn finish(self, db: &TormDb, previous: Option<Vec<u8>>) -> Result<()> {
+        match (&self.value, &previous) {
+            (Some(_), None) => {
+                crate::counter::adjust(db, self.collection, 1).await?;
+            }
+            (None, Some(_)) => {
+                crate::counter::adjust(db, self.collection, -1).await?;
+            }
+            _ => {}
+        }
+        crate::index::update_encoded(
+            db,
+            self.collection,
+            self.indexes,
+            self.codec,
+            &self.id,
+            previous.as_deref(),
+            self.value.as_ref().map(|(encoded, _)| encoded.as_slice()),
+        )
+        .await?;
+
+        match self.value {
+            Some((_, payload)) => {
+                if self.ordered {
+                    crate::order::track(db, self.collection, &self.id, true).await?;
+                }
+                crate::events::publish(
+                    db,
+                    crate::ModelOp::Save,
+                    self.collection,
+                    &self.id,
+                    Some(payload),
+                )
+                .await
+            }
+            None if previous.is_some() => {
+                crate::order::untrack_insertion(db, self.collection, &self.id).await?;
+                crate::events::publish(db, crate::ModelOp::Delete, self.collection, &self.id, None)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Send `writes` on `conn` in one round trip, in `MULTI`/`EXEC` if
+/// `atomic`, and follow them up
+///
+/// Returns `false` if an atomic batch was aborted by a WATCHed key.
+pub(crate) async fn send<C: ConnectionLike>(
+    db: &TormDb,
+    conn: &mut C,
+    writes: Vec<Write>,
+    atomic: bool,
+) -> Result<bool> {
+    let mut pipe = redis::pipe();
+    if atomic {
+        pipe.atomic();
+    }
+    for write in &writes {
+        write.queue(db, &mut pipe);
+    }
+    let previous: Option<Vec<Option<Vec<u8>>>> = pipe.query_async(conn).await?;
+    let Some(previous) = previous else {
+        return Ok(false);
+    };
+
+    for (write, previous) in writes.into_iter().zip(previous) {
+        write.finish(db, previous).await?;
+    }
+    Ok(true)
+}
+
+/// Saves and deletes sent together by [`Pipeline::exec`]
+///
+/// Unlike [`TormDb::transaction`], the writes are not atomic: other
+/// clients may see some of them before the rest.
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{Model, TormDb};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Model, Serialize, Deserialize)]
+/// # struct User { #[id] id: String, name: String }
+/// # #[derive(Model, Serialize, Deserialize)]
+/// # struct Post { #[id] id: String, title: String }
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// # let user = User { id: "1".into(), name: "John".into() };
+/// # let post = Post { id: "1".into(), title: "Draft".into() };
+/// let mut pipe = db.pipeline();
+/// user.save_in(&mut pipe)?;
+/// post.delete_in(&mut pipe);
+/// pipe.exec().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Pipeline {
+    db: TormDb,
+    writes: Vec<Write>,
+}
+
+impl Pipeline {
+    pub(crate) fn push(&mut self, write: Write) {
+        self.writes.push(write);
+    }
+
+    /// Get the number of queued writes
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Whether no writes are queued
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Send the queued writes and return how many there were
+    #[tracing::instrument(name = "torm.pipeline", level = "info", skip_all, fields(writes = self.writes.len()))]
+    pub async fn exec(self) -> Result<usize> {
+        let written = self.writes.len();
+        if written > 0 {
+            let mut conn = self.db.connection().clone();
+            send(&self.db, &mut conn, self.writes, false).await?;
+        }
+        Ok(written)
+    }
+}
+
+impl TormDb {
+    /// Start a pipeline of model writes sent in one round trip
+    ///
+    /// See [`Pipeline`] for an example.
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline {
+            db: self.clone(),
+            writes: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! Connections handed out by [`TormDb::connection`](crate::TormDb::connection)
+//!
+//! By default every command goes through one multiplexed connection,
+//! which reconnects on failure. Commands are pipelined on it, so a
+//! blocking command or a large pipeline delays everything queued behind
+//! it. With [`ConnectOptions::pool_size`](crate::ConnectOptions::pool_size)
+//! each command or pipeline instead checks out one of up to `pool_size`
+//! connections for itself, waiting at most the checkout timeout when all
+//! are busy. Pooled connections are opened on demand and dropped after
+//! an I/O failure.
+//!
+//! Each command checks out separately, so commands that rely on
+//! connection state across calls, such as `WATCH` or `SELECT`, must be
+//! sent as one atomic pipeline.
+//!
+//! With the `cluster` feature and [`ConnectOptions::cluster`](crate::ConnectOptions::cluster),
+//! commands are routed to the node owning their keys' hash slot. MGET and
+//! other multi-key commands are split by slot, non-atomic pipelines are
+//! sent command by command, and key scans visit every primary. Lua
+//! scripts and atomic pipelines still need all their keys in one slot.
+//!
+//! With the `sentinel` feature and [`ConnectOptions::sentinel`](crate::ConnectOptions::sentinel),
+//! the master is looked up from the sentinels. When its connection fails,
+//! or it answers `READONLY` after being demoted, the next command asks the
+//! sentinels again and connects to the new master. A command refused with
+//! `READONLY` is retried once there.
+
+use crate::lifecycle::Link;
+use crate::{Backend, RetryPolicy};
+use redis::aio::{ConnectionLike, ConnectionManager, MultiplexedConnection};
+use redis::{AsyncConnectionConfig, Client, Cmd, Pipeline, RedisFuture, RedisResult, Value};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// A handle on the store's connections, cheap to clone
+///
+/// Implements [`ConnectionLike`], so it is used with `query_async` like
+/// any async Redis connection. Commands are retried as set with
+/// [`TormDb::with_retry`](crate::TormDb::with_retry), and each attempt
+/// fails after the timeout set with
+/// [`TormDb::with_timeout`](crate::TormDb::with_timeout).
+#[derive(Clone)]
+pub struct Connection {
+    inner: Inner,
+    link: Arc<Link>,
+    retry: Option<Arc<RetryPolicy>>,
+    timeout: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Single(Box<ConnectionManager>),
+    Pooled(Arc<Pool>),
+    #[cfg(feature = "cluster")]
+    Cluster(redis::cluster_async::ClusterConnection),
+    #[cfg(feature = "sentinel")]
+    Sentinel(Arc<Sentinel>),
+    Backend(Arc<dyn Backend>),
+}
+
+/// A cluster primary, `None` for the only node of a standalone server
+pub(crate) type Node = Option<(String, u16)>;
+
+impl Connection {
+    fn new(inner: Inner) -> Self {
+        Self {
+            inner,
+            link: Arc::default(),
+            retry: None,
+            timeout: None,
+        }
+    }
+
+    /// State of the link to the server, shared by clones
+    pub(crate) fn link(&self) -> &Link {
+        &self.link
+    }
+
+    /// Refuse new commands, wait for those in flight and drop idle
+    /// pooled connections
+    pub(crate) async fn close(&self) {
+        self.link.close().await;
+        if let Inner::Pooled(pool) = &self.inner {
+            pool.close();
+        }
+    }
+
+    pub(crate) fn single(manager: ConnectionManager) -> Self {
+        Self::new(Inner::Single(Box::new(manager)))
+    }
+
+    pub(crate) fn pooled(pool: Pool) -> Self {
+        Self::new(Inner::Pooled(Arc::new(pool)))
+    }
+
+    #[cfg(feature = "cluster")]
+    pub(crate) fn cluster(conn: redis::cluster_async::ClusterConnection) -> Self {
+        Self::new(Inner::Cluster(conn))
+    }
+
+    #[cfg(feature = "sentinel")]
+    pub(crate) fn sentinel(sentinel: Sentinel) -> Self {
+        Self::new(Inner::Sentinel(Arc::new(sentinel)))
+    }
+
+    pub(crate) fn backend(backend: impl Backend) -> Self {
+        Self::new(Inner::Backend(Arc::new(backend)))
+    }
+
+    /// The same connections, retrying commands with `policy`
+    pub(crate) fn with_retry(&self, policy: RetryPolicy) -> Self {
+        Self {
+            retry: Some(Arc::new(policy)),
+            ..self.clone()
+        }
+    }
+
+    /// The same connections, failing commands after `timeout`
+    pub(crate) fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Whether commands go to a Redis Cluster
+    pub fn is_cluster(&self) -> bool {
+        #[cfg(feature = "cluster")]
+        if matches!(self.inner, Inner::Cluster(_)) {
+            return true;
+        }
+        false
+    }
+
+    /// Whether commands go to a [`Backend`] rather than a server
+    pub(crate) fn is_backend(&self) -> bool {
+        matches!(self.inner, Inner::Backend(_))
+    }
+
+    /// The nodes holding keys, each to be scanned separately
+    pub(crate) async fn primaries(&mut self) -> RedisResult<Vec<Node>> {
+        #[cfg(feature = "cluster")]
+        if let Inner::Cluster(conn) = &mut self.inner {
+            let nodes: String = redis::cmd("CLUSTER").arg("NODES").query_async(conn).await?;
+            return Ok(parse_primaries(&nodes).into_iter().map(Some).collect());
+        }
+        Ok(vec![None])
+    }
+
+    /// Run `cmd` on `node`, or wherever it is routed when `None`
+    pub(crate) async fn query_node<T: redis::FromRedisValue>(
+        &mut self,
+        cmd: &Cmd,
+        node: &Node,
+    ) -> RedisResult<T> {
+        #[cfg(feature = "cluster")]
+        if let (Inner::Cluster(conn), Some((host, port))) = (&mut self.inner, node) {
+            use redis::cluster_routing::{RoutingInfo, SingleNodeRoutingInfo};
+            let routing = RoutingInfo::SingleNode(SingleNodeRoutingInfo::ByAddress {
+                host: host.clone(),
+                port: *port,
+            });
+            let value = conn.route_command(cmd, routing).await?;
+            return T::from_redis_value(&value);
+        }
+        let _ = node;
+        cmd.query_async(self).await
+    }
+}
+
+/// Addresses of the primaries serving slots in `CLUSTER NODES` output
+#[cfg(feature = "cluster")]
+fn parse_primaries(nodes: &str) -> Vec<(String, u16)> {
+    nodes
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = fields.get(2)?;
+            let serving = fields.len() > 8;
+            if !flags.split(',').any(|f| f == "master") || flags.contains("fail") || !serving {
+                return None;
+            }
+            // ip:port@cport[,hostname]
+            let address = fields[1].split(['@', ',']).next()?;
+            let (host, port) = address.rsplit_once(':')?;
+            Some((host.to_string(), port.parse().ok()?))
+        })
+        .collect()
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.inner {
+            Inner::Single(_) => f.write_str("Connection::Single"),
+            #[cfg(feature = "cluster")]
+            Inner::Cluster(_) => f.write_str("Connection::Cluster"),
+            #[cfg(feature = "sentinel")]
+            Inner::Sentinel(sentinel) => f
+                .debug_struct("Connection::Sentinel")
+                .field("master_name", &sentinel.master_name)
+                .finish(),
+            Inner::Backend(_) => f.write_str("Connection::Backend"),
+            Inner::Pooled(pool) => f
+                .debug_struct("Connection::Pooled")
+                .field("size", &pool.size)
+                .field("idle", &pool.idle_len())
+                .finish(),
+        }
+    }
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let retry = self.retry.clone();
+        Box::pin(async move {
+            let _in_flight = self.link.begin()?;
+            let mut attempt = 0;
+            loop {
+                let request = self.inner.req_packed_command(cmd);
+                let result = within(self.timeout, request).await;
+                self.link.observe(&result);
+                match (result, &retry) {
+                    (Err(e), Some(retry))
+                        if retry.should_retry(attempt, &e, std::iter::once(cmd)) =>
+                    {
+                        tracing::debug!(attempt, error = %e, "command failed, retrying");
+                        tokio::time::sleep(retry.backoff(attempt)).await;
+                        attempt += 1;
+                    }
+                    (result, _) => return result,
+                }
+            }
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        let retry = self.retry.clone();
+        Box::pin(async move {
+            let _in_flight = self.link.begin()?;
+            let mut attempt = 0;
+            loop {
+                let request = self.inner.req_packed_commands(cmd, offset, count);
+                let result = within(self.timeout, request).await;
+                self.link.observe(&result);
+                match (result, &retry) {
+                    (Err(e), Some(retry)) if retry.should_retry(attempt, &e, cmd.cmd_iter()) => {
+                        tracing::debug!(attempt, error = %e, "pipeline failed, retrying");
+                        tokio::time::sleep(retry.backoff(attempt)).await;
+                        attempt += 1;
+                    }
+                    (result, _) => return result,
+                }
+            }
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.inner.get_db()
+    }
+}
+
+/// Await `request`, failing with a timeout error after `timeout`
+async fn within<T>(timeout: Option<Duration>, request: RedisFuture<'_, T>) -> RedisResult<T> {
+    let Some(timeout) = timeout else {
+        return request.await;
+    };
+    tokio::time::timeout(timeout, request)
+        .await
+        .unwrap_or_else(|_| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("no response within {:?}", timeout),
+            )
+            .into())
+        })
+}
+
+impl ConnectionLike for Inner {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Inner::Single(conn) => conn.req_packed_command(cmd),
+            #[cfg(feature = "cluster")]
+            Inner::Cluster(conn) => conn.req_packed_command(cmd),
+            #[cfg(feature = "sentinel")]
+            Inner::Sentinel(sentinel) => Box::pin(async move {
+                let result = sentinel.master().await?.req_packed_command(cmd).await;
+                if !sentinel.failed_over(&result) {
+                    return result;
+                }
+                sentinel.master().await?.req_packed_command(cmd).await
+            }),
+            Inner::Backend(backend) => Box::pin(async move { backend.command(cmd).await }),
+            Inner::Pooled(pool) => Box::pin(async move {
+                let mut checkout = pool.checkout().await?;
+                let result = checkout.conn().req_packed_command(cmd).await;
+                checkout.keep_if_healthy(&result);
+                result
+            }),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Inner::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            #[cfg(feature = "cluster")]
+            Inner::Cluster(conn) => {
+                let commands: Vec<&Cmd> = cmd.cmd_iter().collect();
+                // A transaction is sent whole and needs its keys in one slot
+                if offset != 0 || count != commands.len() {
+                    return conn.req_packed_commands(cmd, offset, count);
+                }
+                let conn = conn.clone();
+                Box::pin(async move {
+                    futures::future::try_join_all(commands.into_iter().map(|command| {
+                        let mut conn = conn.clone();
+                        async move { conn.req_packed_command(command).await }
+                    }))
+                    .await
+                })
+            }
+            #[cfg(feature = "sentinel")]
+            Inner::Sentinel(sentinel) => Box::pin(async move {
+                let result = sentinel
+                    .master()
+                    .await?
+                    .req_packed_commands(cmd, offset, count)
+                    .await;
+                if !sentinel.failed_over(&result) {
+                    return result;
+                }
+                sentinel
+                    .master()
+                    .await?
+                    .req_packed_commands(cmd, offset, count)
+                    .await
+            }),
+            // A transaction's replies come back as the one reply of EXEC
+            Inner::Backend(backend) => Box::pin(async move {
+                if offset == 0 {
+                    return backend.pipeline(cmd, false).await;
+                }
+                let replies = backend.pipeline(cmd, true).await?;
+                Ok(vec![Value::Array(replies)])
+            }),
+            Inner::Pooled(pool) => Box::pin(async move {
+                let mut checkout = pool.checkout().await?;
+                let result = checkout
+                    .conn()
+                    .req_packed_commands(cmd, offset, count)
+                    .await;
+                checkout.keep_if_healthy(&result);
+                result
+            }),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Inner::Single(conn) => conn.get_db(),
+            #[cfg(feature = "cluster")]
+            Inner::Cluster(_) => 0,
+            #[cfg(feature = "sentinel")]
+            Inner::Sentinel(sentinel) => sentinel.db,
+            Inner::Backend(backend) => backend.database(),
+            Inner::Pooled(pool) => pool.client.get_connection_info().redis.db,
+        }
+    }
+}
+
+/// Up to `size` connections, each used by one command at a time
+pub(crate) struct Pool {
+    client: Client,
+    config: AsyncConnectionConfig,
+    client_name: Option<String>,
+    size: usize,
+    checkout_timeout: Option<Duration>,
+    permits: Semaphore,
+    idle: Mutex<Vec<MultiplexedConnection>>,
+}
+
+impl Pool {
+    /// Create a pool and open its first connection
+    pub(crate) async fn open(
+        client: Client,
+        config: AsyncConnectionConfig,
+        client_name: Option<String>,
+        size: usize,
+        checkout_timeout: Option<Duration>,
+    ) -> RedisResult<Self> {
+        let size = size.max(1);
+        let pool = Self {
+            client,
+            config,
+            client_name,
+            size,
+            checkout_timeout,
+            permits: Semaphore::new(size),
+            idle: Mutex::new(Vec::with_capacity(size)),
+        };
+        let first = pool.connect().await?;
+        pool.idle_list().push(first);
+        Ok(pool)
+    }
+
+    async fn connect(&self) -> RedisResult<MultiplexedConnection> {
+        let mut conn = self
+            .client
+            .get_multiplexed_async_connection_with_config(&self.config)
+            .await?;
+        if let Some(name) = &self.client_name {
+            redis::cmd("CLIENT")
+                .arg("SETNAME")
+                .arg(name)
+                .query_async::<()>(&mut conn)
+                .await?;
+        }
+        Ok(conn)
+    }
+
+    /// Wait for a free connection, opening one if none is idle
+    async fn checkout(&self) -> RedisResult<Checkout<'_>> {
+        let permit = match self.checkout_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.permits.acquire())
+                .await
+                .map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("no pooled connection free within {:?}", timeout),
+                    )
+                })?,
+            None => self.permits.acquire().await,
+        }
+        .map_err(|_| std::io::Error::other("connection pool closed"))?;
+
+        let idle = self.idle_list().pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => self.connect().await?,
+        };
+        Ok(Checkout {
+            pool: self,
+            conn: Some(conn),
+            _permit: permit,
+        })
+    }
+
+    fn idle_list(&self) -> std::sync::MutexGuard<'_, Vec<MultiplexedConnection>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn idle_len(&self) -> usize {
+        self.idle_list().len()
+    }
+
+    /// Fail later checkouts and drop the idle connections
+    fn close(&self) {
+        self.permits.close();
+        self.idle_list().clear();
+    }
+}
+
+/// A connection taken from the pool, returned to it when dropped
+struct Checkout<'a> {
+    pool: &'a Pool,
+    conn: Option<MultiplexedConnection>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Checkout<'_> {
+    fn conn(&mut self) -> &mut MultiplexedConnection {
+        self.conn
+            .as_mut()
+            .expect("connection is taken only on drop")
+    }
+
+    /// Drop the connection instead of returning it if `result` shows it
+    /// is broken
+    fn keep_if_healthy<T>(&mut self, result: &RedisResult<T>) {
+        if result
+            .as_ref()
+            .is_err_and(|e| e.is_unrecoverable_error() || e.is_timeout())
+        {
+            self.conn = None;
+        }
+    }
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.idle_list().push(conn);
+        }
+    }
+}
+
+/// The connection to the master a group of sentinels monitors
+#[cfg(feature = "sentinel")]
+pub(crate) struct Sentinel {
+    sentinels: tokio::sync::Mutex<redis::sentinel::Sentinel>,
+    master_name: String,
+    node: redis::sentinel::SentinelNodeConnectionInfo,
+    config: AsyncConnectionConfig,
+    client_name: Option<String>,
+    db: i64,
+    conn: Mutex<Option<MultiplexedConnection>>,
+}
+
+#[cfg(feature = "sentinel")]
+impl Sentinel {
+    /// Look up the master and connect to it, returning a client for it too
+    pub(crate) async fn open(
+        mut sentinels: redis::sentinel::Sentinel,
+        master_name: String,
+        node: redis::sentinel::SentinelNodeConnectionInfo,
+        config: AsyncConnectionConfig,
+        client_name: Option<String>,
+        db: i64,
+    ) -> RedisResult<(Self, Client)> {
+        let master = sentinels
+            .async_master_for(&master_name, Some(&node))
+            .await?;
+        let sentinel = Self {
+            sentinels: tokio::sync::Mutex::new(sentinels),
+            master_name,
+            node,
+            config,
+            client_name,
+            db,
+            conn: Mutex::new(None),
+        };
+        sentinel.master().await?;
+        Ok((sentinel, master))
+    }
+
+    /// The connection to the current master, looked up again after a
+    /// failure
+    async fn master(&self) -> RedisResult<MultiplexedConnection> {
+        if let Some(conn) = self.current().clone() {
+            return Ok(conn);
+        }
+        // One lookup at a time; whoever waited reuses its connection
+        let mut sentinels = self.sentinels.lock().await;
+        if let Some(conn) = self.current().clone() {
+            return Ok(conn);
+        }
+        let mut conn = sentinels
+            .async_master_for(&self.master_name, Some(&self.node))
+            .await?
+            .get_multiplexed_async_connection_with_config(&self.config)
+            .await?;
+        if let Some(name) = &self.client_name {
+            redis::cmd("CLIENT")
+                .arg("SETNAME")
+                .arg(name)
+                .query_async::<()>(&mut conn)
+                .await?;
+        }
+        *self.current() = Some(conn.clone());
+        Ok(conn)
+    }
+
+    /// Forget the master if `result` shows it is gone or demoted; `true`
+    /// when the command was refused and can be sent to the new master
+    fn failed_over<T>(&self, result: &RedisResult<T>) -> bool {
+        let Err(e) = result else {
+            return false;
+        };
+        let read_only = e.kind() == redis::ErrorKind::ReadOnly;
+        if read_only || e.is_unrecoverable_error() || e.is_timeout() {
+            *self.current() = None;
+        }
+        read_only
+    }
+
+    fn current(&self) -> std::sync::MutexGuard<'_, Option<MultiplexedConnection>> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_within_timeout() {
+        let hung: RedisFuture<'_, Value> = Box::pin(std::future::pending());
+        let err = within(Some(Duration::from_millis(10)), hung)
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+        assert!(matches!(crate::Error::from(err), crate::Error::Timeout(_)));
+
+        let ready: RedisFuture<'_, Value> = Box::pin(async { Ok(Value::Okay) });
+        assert_eq!(within(None, ready).await.unwrap(), Value::Okay);
+    }
+
+    #[cfg(feature = "cluster")]
+    #[test]
+    fn test_parse_primaries() {
+        let nodes = "\
+07c3 10.0.0.1:6379@16379 myself,master - 0 0 1 connected 0-5460
+67ed 10.0.0.2:6379@16379,node-2 master - 0 0 2 connected 5461-10922
+292f 10.0.0.3:6379@16379 slave 07c3 0 0 1 connected
+e7d1 10.0.0.4:6379@16379 master,fail - 0 0 3 connected 10923-16383
+6ec2 10.0.0.5:6379@16379 master - 0 0 0 connected
+";
+        assert_eq!(
+            parse_primaries(nodes),
+            vec![
+                ("10.0.0.1".to_string(), 6379),
+                ("10.0.0.2".to_string(), 6379)
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Typed publish/subscribe per collection
+//!
+//! [`TormDb::publish`] sends a serializable event to a collection's
+//! channel, `torm:channel:{collection}` under the handle's prefix, and
+//! [`TormDb::subscribe`] listens to it. Payloads travel as JSON, so
+//! services in other languages can take part.
+
+use crate::{Error, Model, Result, TormDb};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::pin::Pin;
+
+/// Get the pub/sub channel of a collection
+pub fn event_channel(collection: &str) -> String {
+    format!("torm:channel:{}", collection)
+}
+
+/// Stream of events returned by [`Subscription::into_stream`]
+pub type EventStream<E> = Pin<Box<dyn Stream<Item = Result<E>> + Send>>;
+
+/// Messages of a collection's channel, from [`TormDb::subscribe`]
+///
+/// Events are decoded when received, into the type given to
+/// [`Subscription::next`] or [`Subscription::into_stream`].
+pub struct Subscription {
+    messages: Pin<Box<dyn Stream<Item = redis::Msg> + Send>>,
+}
+
+impl Subscription {
+    /// Wait for the next event
+    ///
+    /// Returns `None` once the connection is lost or closed with
+    /// [`TormDb::close`], and an error for a payload that isn't an `E`.
+    pub async fn next<E: DeserializeOwned>(&mut self) -> Option<Result<E>> {
+        let msg = self.messages.next().await?;
+        Some(decode(msg.get_payload_bytes()))
+    }
+
+    /// Turn into a stream of events
+    pub fn into_stream<E: DeserializeOwned + 'static>(self) -> EventStream<E> {
+        Box::pin(self.messages.map(|msg| decode(msg.get_payload_bytes())))
+    }
+}
+
+fn decode<E: DeserializeOwned>(payload: &[u8]) -> Result<E> {
+    Ok(serde_json::from_slice(payload)?)
+}
+
+impl TormDb {
+    /// Publish `event` to the channel of `M`'s collection
+    ///
+    /// Returns the number of subscribers that received it.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// #[derive(Serialize, Deserialize)]
+    /// enum UserEvent {
+    ///     SignedUp { id: String },
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let mut events = db.subscribe::<User>().await?;
+    /// db.publish::<User>(&UserEvent::SignedUp { id: "1".into() }).await?;
+    /// while let Some(event) = events.next::<UserEvent>().await {
+    ///     let UserEvent::SignedUp { id } = event?;
+    ///     println!("welcome, user {}", id);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn publish<M: Model>(&self, event: &impl Serialize) -> Result<usize> {
+        let payload = serde_json::to_vec(event)?;
+        let mut conn = self.connection().clone();
+        Ok(redis::cmd("PUBLISH")
+            .arg(self.key(&event_channel(M::collection())))
+            .arg(payload)
+            .query_async(&mut conn)
+            .await?)
+    }
+
+    /// Subscribe to the channel of `M`'s collection
+    ///
+    /// Only events published after this returns are received. Needs a
+    /// Redis connection, as a subscription holds a connection of its own.
+    pub async fn subscribe<M: Model>(&self) -> Result<Subscription> {
+        let client = self.client().ok_or_else(|| {
+            Error::Connection("subscriptions need a Redis connection".to_string())
+        })?;
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub
+            .subscribe(self.key(&event_channel(M::collection())))
+            .await?;
+        let closed = self.connection().link().closed();
+        Ok(Subscription {
+            messages: Box::pin(pubsub.into_on_message().take_until(closed)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    enum Event {
+        SignedUp { id: String },
+    }
+
+    #[test]
+    fn test_decode() {
+        let event: Event = decode(br#"{"SignedUp":{"id":"1"}}"#).unwrap();
+        assert_eq!(event, Event::SignedUp { id: "1".into() });
+        assert!(matches!(
+            decode::<Event>(b"{}"),
+            Err(Error::Serialization(_))
+        ));
+    }
+}
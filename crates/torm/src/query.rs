@@ -1,11 +1,27 @@
 //! Query builder for filtering and sorting
 
-use crate::{Result, TormDb};
+use crate::aggregate::{Accumulator, Agg};
+use crate::jsonpath::JsonPath;
+use crate::order::OrderScanner;
+use crate::scan::KeyScanner;
+use crate::{Codec, Compression, Error, Result, TormDb};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use regex::Regex;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Documents read per MGET when a query fetches matches
+const DEFAULT_FETCH_BATCH: usize = 100;
+
+/// Fetch batches a query keeps in flight at once
+const DEFAULT_FETCH_CONCURRENCY: usize = 4;
 
 /// Query operators
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Query {
     /// Equal to
@@ -20,12 +36,68 @@ pub enum Query {
     Lt(serde_json::Value),
     /// Less than or equal to
     Lte(serde_json::Value),
+    /// Within a range (inclusive or exclusive of both bounds)
+    Between {
+        /// Lower bound
+        low: serde_json::Value,
+        /// Upper bound
+        high: serde_json::Value,
+        /// Whether the bounds themselves match
+        inclusive: bool,
+    },
     /// Contains (for strings)
     Contains(String),
+    /// Contains, ignoring case (for strings)
+    IContains(String),
+    /// Starts with (for strings)
+    StartsWith(String),
+    /// Ends with (for strings)
+    EndsWith(String),
+    /// Matches a regular expression (for strings)
+    Matches(String),
+    /// Within `max_distance` edits of a string, ignoring case
+    Fuzzy {
+        /// String to compare with
+        value: String,
+        /// Most single-character insertions, deletions or substitutions
+        max_distance: usize,
+    },
+    /// A JSONPath expression selects at least one node of the value
+    JsonPath(String),
     /// In array
     In(Vec<serde_json::Value>),
     /// Not in array
     NotIn(Vec<serde_json::Value>),
+    /// Array field contains the element
+    ArrayContains(serde_json::Value),
+    /// Array field contains at least one of the elements
+    ArrayAnyIn(Vec<serde_json::Value>),
+    /// Array field has exactly this many elements
+    ArraySize(usize),
+    /// [`GeoPoint`](crate::GeoPoint) field within `radius` meters of a point
+    Near {
+        /// Latitude of the center in degrees
+        lat: f64,
+        /// Longitude of the center in degrees
+        lon: f64,
+        /// Radius in meters
+        radius: f64,
+    },
+    /// [`GeoPoint`](crate::GeoPoint) field inside a latitude/longitude box
+    WithinBox {
+        /// Southern edge in degrees
+        min_lat: f64,
+        /// Western edge in degrees
+        min_lon: f64,
+        /// Northern edge in degrees
+        max_lat: f64,
+        /// Eastern edge in degrees
+        max_lon: f64,
+    },
+    /// Field is present (`true`) or missing (`false`); `null` counts as present
+    Exists(bool),
+    /// Field is present with an explicit `null`
+    IsNull,
 }
 
 impl Query {
@@ -59,11 +131,158 @@ impl Query {
         Query::Lte(value.into())
     }
 
+    /// Create an inclusive range query, `low <= value <= high`
+    ///
+    /// Compares like [`Query::gte`] and [`Query::lte`].
+    pub fn between<L, H>(low: L, high: H) -> Self
+    where
+        L: Into<serde_json::Value>,
+        H: Into<serde_json::Value>,
+    {
+        Query::Between {
+            low: low.into(),
+            high: high.into(),
+            inclusive: true,
+        }
+    }
+
+    /// Create an exclusive range query, `low < value < high`
+    pub fn between_exclusive<L, H>(low: L, high: H) -> Self
+    where
+        L: Into<serde_json::Value>,
+        H: Into<serde_json::Value>,
+    {
+        Query::Between {
+            low: low.into(),
+            high: high.into(),
+            inclusive: false,
+        }
+    }
+
+    /// Create a query for timestamps strictly after `time`
+    ///
+    /// Matches fields holding ISO-8601 dates or date-times, such as
+    /// serialized `DateTime<Utc>` values, compared chronologically.
+    pub fn after(time: chrono::DateTime<chrono::Utc>) -> Self {
+        Query::Gt(datetime_value(time))
+    }
+
+    /// Create a query for timestamps strictly before `time`
+    pub fn before(time: chrono::DateTime<chrono::Utc>) -> Self {
+        Query::Lt(datetime_value(time))
+    }
+
     /// Create a contains query
     pub fn contains(value: impl Into<String>) -> Self {
         Query::Contains(value.into())
     }
 
+    /// Create a case-insensitive contains query
+    pub fn icontains(value: impl Into<String>) -> Self {
+        Query::IContains(value.into())
+    }
+
+    /// Create a starts with query
+    pub fn starts_with(value: impl Into<String>) -> Self {
+        Query::StartsWith(value.into())
+    }
+
+    /// Create an ends with query
+    pub fn ends_with(value: impl Into<String>) -> Self {
+        Query::EndsWith(value.into())
+    }
+
+    /// Create a regular expression query
+    ///
+    /// The pattern uses the syntax of the `regex` crate and is compiled
+    /// once when the filter is added; an invalid pattern makes the query
+    /// fail with [`Error::InvalidQuery`].
+    pub fn matches(pattern: impl Into<String>) -> Self {
+        Query::Matches(pattern.into())
+    }
+
+    /// Create a typo-tolerant query for strings within `max_distance`
+    /// edits of `value`
+    ///
+    /// The distance is the Levenshtein distance between the lowercased
+    /// strings, counted in characters, so `"Jon"` matches `"john"` with a
+    /// `max_distance` of 1.
+    pub fn fuzzy(value: impl Into<String>, max_distance: usize) -> Self {
+        Query::Fuzzy {
+            value: value.into(),
+            max_distance,
+        }
+    }
+
+    /// Create a query evaluating a JSONPath expression against the field
+    ///
+    /// `$` is the field's value, so `"$[?(@.qty > 2 && @.sku == 'a')]"`
+    /// on an `items` field matches documents with at least one item that
+    /// satisfies both conditions, and `"$..sku"` any value with a `sku`
+    /// at any depth.
+    ///
+    /// Supported: `.name` and `['name']` children, `[0]` and `[-1]`
+    /// indexes, `*` and `[*]` wildcards, `..` descendants, and filters
+    /// comparing `@` (current) or `$` paths with literals using `==`,
+    /// `!=`, `<`, `<=`, `>`, `>=`, or testing that a path exists, combined
+    /// with `&&`, `||`, `!` and parentheses. The expression is parsed once
+    /// when the filter is added; an invalid one makes the query fail with
+    /// [`Error::InvalidQuery`].
+    pub fn json_path(expr: impl Into<String>) -> Self {
+        Query::JsonPath(expr.into())
+    }
+
+    /// Create a query for arrays containing `element`
+    pub fn array_contains<T: Into<serde_json::Value>>(element: T) -> Self {
+        Query::ArrayContains(element.into())
+    }
+
+    /// Create a query for arrays containing any of `elements`
+    pub fn array_any_in(elements: Vec<serde_json::Value>) -> Self {
+        Query::ArrayAnyIn(elements)
+    }
+
+    /// Create a query for arrays with exactly `size` elements
+    pub fn array_size(size: usize) -> Self {
+        Query::ArraySize(size)
+    }
+
+    /// Create a query for documents that have `field` (even if `null`)
+    pub fn exists() -> Self {
+        Query::Exists(true)
+    }
+
+    /// Create a query for documents without `field`
+    pub fn missing() -> Self {
+        Query::Exists(false)
+    }
+
+    /// Create a query for documents where `field` is explicitly `null`
+    ///
+    /// Combine with [`Query::missing`] in an
+    /// [`or`](QueryBuilder::or) group to also match documents without it.
+    pub fn is_null() -> Self {
+        Query::IsNull
+    }
+
+    /// Create a query for points within `radius` meters of `lat`, `lon`
+    pub fn near(lat: f64, lon: f64, radius: f64) -> Self {
+        Query::Near { lat, lon, radius }
+    }
+
+    /// Create a query for points inside a box, edges in degrees
+    ///
+    /// Boxes crossing the antimeridian are not supported; split them in
+    /// two [`or`](QueryBuilder::or)ed boxes.
+    pub fn within_box(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> Self {
+        Query::WithinBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        }
+    }
+
     /// Create an in query
     pub fn in_values(values: Vec<serde_json::Value>) -> Self {
         Query::In(values)
@@ -73,10 +292,190 @@ impl Query {
     pub fn not_in(values: Vec<serde_json::Value>) -> Self {
         Query::NotIn(values)
     }
+
+    /// The JSON values this operator compares with
+    fn values(&self) -> Vec<&serde_json::Value> {
+        match self {
+            Query::Eq(v)
+            | Query::Ne(v)
+            | Query::Gt(v)
+            | Query::Gte(v)
+            | Query::Lt(v)
+            | Query::Lte(v)
+            | Query::ArrayContains(v) => vec![v],
+            Query::Between { low, high, .. } => vec![low, high],
+            Query::In(values) | Query::NotIn(values) | Query::ArrayAnyIn(values) => {
+                values.iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn values_mut(&mut self) -> Vec<&mut serde_json::Value> {
+        match self {
+            Query::Eq(v)
+            | Query::Ne(v)
+            | Query::Gt(v)
+            | Query::Gte(v)
+            | Query::Lt(v)
+            | Query::Lte(v)
+            | Query::ArrayContains(v) => vec![v],
+            Query::Between { low, high, .. } => vec![low, high],
+            Query::In(values) | Query::NotIn(values) | Query::ArrayAnyIn(values) => {
+                values.iter_mut().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A named placeholder for a value in a [`PreparedQuery`]
+///
+/// Converts into the JSON object `{"$param": name}`, so it can stand in
+/// for any value an operator compares with, including `In` elements and
+/// `Between` bounds. A leading `:` in the name is ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param(String);
+
+impl Param {
+    /// Create a placeholder bound under `name`
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        match name.strip_prefix(':') {
+            Some(stripped) => Self(stripped.to_string()),
+            None => Self(name),
+        }
+    }
+
+    /// Get the placeholder name, without `:`
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Param> for serde_json::Value {
+    fn from(param: Param) -> Self {
+        serde_json::json!({ "$param": param.0 })
+    }
+}
+
+/// The placeholder name if `value` is a [`Param`]
+fn param_name(value: &serde_json::Value) -> Option<&str> {
+    let object = value.as_object()?;
+    match object.get("$param") {
+        Some(serde_json::Value::String(name)) if object.len() == 1 => Some(name),
+        _ => None,
+    }
+}
+
+/// Names of the placeholders in `filters`, in order of first appearance
+fn filter_params(filters: &[Filter]) -> Vec<String> {
+    let mut params: Vec<String> = Vec::new();
+    for filter in filters {
+        filter.for_each_condition(&mut |_, query| {
+            for name in query.values().into_iter().filter_map(param_name) {
+                if !params.iter().any(|p| p == name) {
+                    params.push(name.to_string());
+                }
+            }
+        });
+    }
+    params
+}
+
+/// A filter expression: a field condition or a logical group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Condition on one field
+    Field(String, Query),
+    /// Every filter must match
+    And(Vec<Filter>),
+    /// At least one filter must match
+    Or(Vec<Filter>),
+    /// The filter must not match
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Create a condition on `field`
+    pub fn field(field: impl Into<String>, query: Query) -> Self {
+        Filter::Field(field.into(), query)
+    }
+
+    /// Call `visit` for every field condition in this expression
+    fn for_each_condition<'a>(&'a self, visit: &mut impl FnMut(&'a str, &'a Query)) {
+        match self {
+            Filter::Field(field, query) => visit(field, query),
+            Filter::And(filters) | Filter::Or(filters) => {
+                filters.iter().for_each(|f| f.for_each_condition(visit))
+            }
+            Filter::Not(filter) => filter.for_each_condition(visit),
+        }
+    }
+
+    /// Call `visit` for every field condition, allowing changes
+    fn for_each_condition_mut(&mut self, visit: &mut impl FnMut(&mut Query)) {
+        match self {
+            Filter::Field(_, query) => visit(query),
+            Filter::And(filters) | Filter::Or(filters) => filters
+                .iter_mut()
+                .for_each(|f| f.for_each_condition_mut(visit)),
+            Filter::Not(filter) => filter.for_each_condition_mut(visit),
+        }
+    }
+}
+
+/// Builder for the members of a filter group
+///
+/// Passed to [`QueryBuilder::or`], [`QueryBuilder::and`] and
+/// [`QueryBuilder::not`], which decide how the members are combined.
+#[derive(Debug, Clone, Default)]
+pub struct FilterGroup {
+    filters: Vec<Filter>,
+}
+
+impl FilterGroup {
+    /// Add a field condition
+    pub fn filter(mut self, field: impl Into<String>, query: Query) -> Self {
+        self.filters.push(Filter::field(field, query));
+        self
+    }
+
+    /// Add a group whose members are ORed
+    pub fn or<F>(mut self, group: F) -> Self
+    where
+        F: FnOnce(FilterGroup) -> FilterGroup,
+    {
+        self.filters
+            .push(Filter::Or(group(FilterGroup::default()).filters));
+        self
+    }
+
+    /// Add a group whose members are ANDed
+    pub fn and<F>(mut self, group: F) -> Self
+    where
+        F: FnOnce(FilterGroup) -> FilterGroup,
+    {
+        self.filters
+            .push(Filter::And(group(FilterGroup::default()).filters));
+        self
+    }
+
+    /// Add a group that must not match as a whole
+    pub fn not<F>(mut self, group: F) -> Self
+    where
+        F: FnOnce(FilterGroup) -> FilterGroup,
+    {
+        self.filters.push(Filter::Not(Box::new(Filter::And(
+            group(FilterGroup::default()).filters,
+        ))));
+        self
+    }
 }
 
 /// Sort order
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SortOrder {
     /// Ascending
@@ -85,6 +484,549 @@ pub enum SortOrder {
     Desc,
 }
 
+/// A per-query limit, see [`QueryBuilder::timeout`] and
+/// [`QueryBuilder::max_scan`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryLimit {
+    /// Maximum running time
+    Timeout(Duration),
+    /// Maximum number of keys scanned
+    MaxScan(usize),
+}
+
+impl std::fmt::Display for QueryLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryLimit::Timeout(timeout) => write!(f, "its timeout of {:?}", timeout),
+            QueryLimit::MaxScan(max) => write!(f, "its scan limit of {} keys", max),
+        }
+    }
+}
+
+/// Serializable form of a query: filters, sort, skip, limit and selection
+///
+/// Produced by [`QueryBuilder::to_spec`] and [`Selection::to_spec`] and
+/// turned back into a builder with [`QueryBuilder::from_spec`], so a query
+/// built in Rust can be sent to TORM Server as JSON:
+///
+/// ```json
+/// {
+///   "filters": [{ "field": ["age", { "gte": 18 }] }],
+///   "sort": { "field": "name", "order": "asc" },
+///   "limit": 10,
+///   "select": ["id", "name"]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuerySpec {
+    /// Filter expressions, ANDed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<Filter>,
+    /// Sort field and order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortSpec>,
+    /// Number of matches to skip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip: Option<usize>,
+    /// Maximum number of matches to return
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// Fields to return, `None` for whole documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub select: Option<Vec<String>>,
+}
+
+/// Sort part of a [`QuerySpec`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortSpec {
+    /// Field to sort by, may be a dot path
+    pub field: String,
+    /// Sort order
+    pub order: SortOrder,
+    /// Locale whose collation rules order strings, see
+    /// [`QueryBuilder::collate`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collation: Option<String>,
+    /// Whether documents without a value sort last (`true`) or first
+    /// (`false`) whatever the order; `None` sorts missing values as the
+    /// lowest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nulls_last: Option<bool>,
+}
+
+/// A page of query results with pagination metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    /// Documents on this page
+    pub items: Vec<T>,
+    /// Total number of documents matching the query
+    pub total: usize,
+    /// 1-based page number
+    pub page: usize,
+    /// Maximum number of documents per page
+    pub per_page: usize,
+    /// Total number of pages
+    pub total_pages: usize,
+}
+
+/// Request for one page of a [`QueryBuilder::page`] listing
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRequest {
+    /// Maximum number of documents per page
+    pub size: usize,
+    /// Continuation token from the previous page, `None` for the first
+    pub token: Option<String>,
+}
+
+impl PageRequest {
+    /// Request the first page
+    pub fn first(size: usize) -> Self {
+        Self { size, token: None }
+    }
+
+    /// Request the page following `token`
+    pub fn after(size: usize, token: impl Into<String>) -> Self {
+        Self {
+            size,
+            token: Some(token.into()),
+        }
+    }
+}
+
+/// A page of results with a continuation token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorPage<T> {
+    /// Documents on this page
+    pub items: Vec<T>,
+    /// Total number of documents matching the query
+    pub total: usize,
+    /// Token for the next page, `None` on the last page
+    pub next_token: Option<String>,
+}
+
+/// Position of the last document seen, for [`QueryBuilder::after`]
+///
+/// Convert a document ID with `From`, or pass around the opaque
+/// [`Cursor::token`] returned in a [`KeysetPage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    id: String,
+    position: Option<f64>,
+}
+
+impl Cursor {
+    /// Continue after the document with this ID
+    pub fn after_id(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            position: None,
+        }
+    }
+
+    /// Parse a token produced by [`Cursor::token`]
+    pub fn from_token(token: &str) -> Result<Self> {
+        let (position, id) = decode_token(token)?;
+        Ok(Self {
+            id,
+            position: position.as_f64(),
+        })
+    }
+
+    /// Encode this cursor as an opaque, URL-safe token
+    pub fn token(&self) -> String {
+        let position = self
+            .position
+            .map(serde_json::Value::from)
+            .unwrap_or_default();
+        encode_token(&(position, self.id.clone()))
+    }
+
+    /// ID of the last document seen
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl From<&str> for Cursor {
+    fn from(id: &str) -> Self {
+        Self::after_id(id)
+    }
+}
+
+impl From<String> for Cursor {
+    fn from(id: String) -> Self {
+        Self::after_id(id)
+    }
+}
+
+/// A page of results from [`QueryBuilder::keyset_page`]
+#[derive(Debug, Clone)]
+pub struct KeysetPage<T> {
+    /// Documents on this page
+    pub items: Vec<T>,
+    /// Cursor for the next page, `None` on the last page
+    pub next: Option<Cursor>,
+}
+
+/// Statistics gathered while executing a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of keys examined
+    pub scanned: usize,
+    /// Number of documents fetched and checked against the filters
+    pub fetched: usize,
+    /// Number of documents that matched the filters
+    pub matched: usize,
+    /// Time spent executing the query
+    pub elapsed: Duration,
+}
+
+/// How a query was executed, from [`QueryBuilder::explain`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explain {
+    /// Where keys came from: `"index"`, `"insertion_order"` or `"scan"`
+    pub source: &'static str,
+    /// Indexed fields used to narrow down the candidates
+    pub indexes: Vec<String>,
+    /// Whether filters ran inside ToonStore
+    pub pushdown: bool,
+    /// Number of keys examined
+    pub scanned: usize,
+    /// Number of documents fetched and checked against the filters
+    pub fetched: usize,
+    /// Number of documents that matched the filters
+    pub matched: usize,
+    /// Time spent choosing where keys come from
+    pub planning: Duration,
+    /// Total time, including planning
+    pub elapsed: Duration,
+}
+
+impl std::fmt::Display for Explain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)?;
+        if !self.indexes.is_empty() {
+            write!(f, " on {}", self.indexes.join(", "))?;
+        }
+        if self.pushdown {
+            write!(f, " with pushdown")?;
+        }
+        write!(
+            f,
+            ": scanned {} keys, fetched {}, matched {} in {:?} (planning {:?})",
+            self.scanned, self.fetched, self.matched, self.elapsed, self.planning
+        )
+    }
+}
+
+/// Return `[key, document, ...]` for the documents under KEYS that may
+/// match the filters in ARGV[1]
+///
+/// If ARGV[3] is a JSON array of field names, documents are cut down to
+/// those top-level fields, unless re-encoding could change them (empty
+/// arrays or objects, numbers beyond cjson's precision).
+///
+/// Filters evaluate to true, false or nil (unknown) for operators that
+/// cannot be decided exactly like serde_json (nested values and dot paths,
+/// number equality, string and date ranges). Only documents that
+/// certainly don't match are dropped; values cjson can't decode, such as
+/// compressed documents, are returned as they are.
+const FILTER_SCRIPT: &str = r#"
+local filters = cjson.decode(ARGV[1])
+local string_ranges = ARGV[2] == '1'
+local fields = nil
+if ARGV[3] and ARGV[3] ~= '' then
+    fields = cjson.decode(ARGV[3])
+end
+
+local function member(value, expected)
+    local unknown = false
+    for _, e in ipairs(expected) do
+        if type(value) == 'table' or type(e) == 'table' then
+            unknown = true
+        elseif value == e then
+            if type(value) == 'number' then
+                unknown = true
+            else
+                return true
+            end
+        end
+    end
+    if unknown then
+        return nil
+    end
+    return false
+end
+
+local function test(value, op, expected)
+    if op == 'eq' or op == 'ne' then
+        if type(value) == 'table' or type(expected) == 'table' then
+            return nil
+        end
+        local equal = value == expected
+        if equal and type(value) == 'number' then
+            return nil
+        end
+        if op == 'ne' then
+            return not equal
+        end
+        return equal
+    elseif op == 'gt' or op == 'gte' or op == 'lt' or op == 'lte' then
+        if type(value) == 'string' and type(expected) == 'string' then
+            return nil
+        end
+        if type(value) ~= 'number' or type(expected) ~= 'number' then
+            if string_ranges and value ~= nil then
+                return nil
+            end
+            return false
+        end
+        if op == 'gt' then return value > expected end
+        if op == 'gte' then return value >= expected end
+        if op == 'lt' then return value < expected end
+        return value <= expected
+    elseif op == 'arraycontains' or op == 'arrayanyin' then
+        if type(value) ~= 'table' then
+            return false
+        end
+        local wanted = expected
+        if op == 'arraycontains' then
+            wanted = { expected }
+        end
+        local unknown = false
+        for _, item in ipairs(value) do
+            local found = member(item, wanted)
+            if found then
+                return true
+            elseif found == nil then
+                unknown = true
+            end
+        end
+        if unknown then
+            return nil
+        end
+        return false
+    elseif op == 'arraysize' then
+        if type(value) ~= 'table' then
+            return false
+        end
+        if #value == 0 then
+            -- cjson decodes [] and {} alike
+            if next(value) == nil and expected == 0 then
+                return nil
+            end
+            return false
+        end
+        return #value == expected
+    elseif op == 'exists' then
+        return (value ~= nil) == expected
+    elseif op == 'between' then
+        local low, high = expected.low, expected.high
+        if type(value) == 'string' and (type(low) == 'string' or type(high) == 'string') then
+            return nil
+        end
+        if type(value) ~= 'number' or type(low) ~= 'number' or type(high) ~= 'number' then
+            if string_ranges and value ~= nil then
+                return nil
+            end
+            return false
+        end
+        if expected.inclusive then
+            return value >= low and value <= high
+        end
+        return value > low and value < high
+    elseif op == 'contains' then
+        return type(value) == 'string' and string.find(value, expected, 1, true) ~= nil
+    elseif op == 'icontains' then
+        if type(value) ~= 'string' then
+            return false
+        end
+        -- string.lower only folds ASCII
+        if string.find(value, '[\128-\255]') or string.find(expected, '[\128-\255]') then
+            return nil
+        end
+        return string.find(string.lower(value), string.lower(expected), 1, true) ~= nil
+    elseif op == 'matches' or op == 'fuzzy' then
+        if type(value) ~= 'string' then
+            return false
+        end
+        return nil
+    elseif op == 'startswith' then
+        return type(value) == 'string' and string.sub(value, 1, #expected) == expected
+    elseif op == 'endswith' then
+        return type(value) == 'string' and (#expected == 0 or string.sub(value, -#expected) == expected)
+    elseif op == 'in' then
+        if value == nil then
+            return false
+        end
+        return member(value, expected)
+    elseif op == 'notin' then
+        if value == nil then
+            return true
+        end
+        local found = member(value, expected)
+        if found == nil then
+            return nil
+        end
+        return not found
+    end
+    return nil
+end
+
+local eval
+
+local function all(doc, list)
+    local result = true
+    for _, filter in ipairs(list) do
+        local r = eval(doc, filter)
+        if r == false then
+            return false
+        elseif r == nil then
+            result = nil
+        end
+    end
+    return result
+end
+
+eval = function(doc, filter)
+    local kind, body = next(filter)
+    if kind == 'field' then
+        local value = doc[body[1]]
+        if value == nil and string.find(body[1], '[%.%[]') then
+            return nil
+        end
+        if body[2] == 'isnull' then
+            return value == cjson.null
+        end
+        local op, expected = next(body[2])
+        return test(value, op, expected)
+    elseif kind == 'and' then
+        return all(doc, body)
+    elseif kind == 'or' then
+        local result = false
+        for _, sub in ipairs(body) do
+            local r = eval(doc, sub)
+            if r == true then
+                return true
+            elseif r == nil then
+                result = nil
+            end
+        end
+        return result
+    elseif kind == 'not' then
+        local r = eval(doc, body)
+        if r == nil then
+            return nil
+        end
+        return not r
+    end
+    return nil
+end
+
+local function lossy(value)
+    if type(value) == 'number' then
+        return tonumber(string.format('%.14g', value)) ~= value
+    elseif type(value) ~= 'table' then
+        return false
+    elseif next(value) == nil then
+        return true
+    end
+    for _, v in pairs(value) do
+        if lossy(v) then
+            return true
+        end
+    end
+    return false
+end
+
+local function project(doc, raw)
+    if not fields then
+        return raw
+    end
+    local kept = {}
+    for _, field in ipairs(fields) do
+        local value = doc[field]
+        if value ~= nil then
+            if lossy(value) then
+                return raw
+            end
+            kept[field] = value
+        end
+    end
+    return cjson.encode(kept)
+end
+
+local out = {}
+for _, key in ipairs(KEYS) do
+    local raw = redis.pcall('GET', key)
+    if type(raw) == 'string' then
+        local ok, doc = pcall(cjson.decode, raw)
+        if not ok then
+            out[#out + 1] = key
+            out[#out + 1] = raw
+        elseif type(doc) == 'table' and all(doc, filters) ~= false then
+            out[#out + 1] = key
+            out[#out + 1] = project(doc, raw)
+        end
+    end
+end
+return out
+"#;
+
+/// Where a query reads its document keys from
+enum KeySource {
+    Scan(KeyScanner),
+    Ordered(OrderScanner),
+    Index {
+        fields: Vec<String>,
+        batches: VecDeque<Vec<String>>,
+    },
+}
+
+impl KeySource {
+    async fn next_batch(&mut self, conn: &mut crate::Connection) -> Result<Option<Vec<String>>> {
+        match self {
+            KeySource::Scan(scanner) => scanner.next_batch(conn).await,
+            KeySource::Ordered(scanner) => scanner.next_batch(conn).await,
+            KeySource::Index { batches, .. } => Ok(batches.pop_front()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            KeySource::Scan(_) => "scan",
+            KeySource::Ordered(_) => "insertion_order",
+            KeySource::Index { .. } => "index",
+        }
+    }
+}
+
+/// What a query sorts by
+enum SortBy<T> {
+    /// A field or dot path
+    Field(String),
+    /// A key computed from the decoded document
+    Key(Arc<dyn Fn(&T) -> serde_json::Value + Send + Sync>),
+}
+
+impl<T> Clone for SortBy<T> {
+    fn clone(&self) -> Self {
+        match self {
+            SortBy::Field(field) => SortBy::Field(field.clone()),
+            SortBy::Key(key) => SortBy::Key(Arc::clone(key)),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for SortBy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortBy::Field(field) => f.debug_tuple("Field").field(field).finish(),
+            SortBy::Key(_) => f.write_str("Key(..)"),
+        }
+    }
+}
+
 /// Query builder for complex queries
 ///
 /// Performs in-memory filtering by scanning all keys in the collection.
@@ -92,11 +1034,33 @@ pub enum SortOrder {
 #[derive(Debug, Clone)]
 pub struct QueryBuilder<T> {
     collection: String,
-    filters: Vec<(String, Query)>,
-    sort: Option<(String, SortOrder)>,
+    filters: Vec<Filter>,
+    sort: Option<(SortBy<T>, SortOrder)>,
+    nulls_last: Option<bool>,
     limit: Option<usize>,
     skip: Option<usize>,
-    _phantom: std::marker::PhantomData<T>,
+    timeout: Option<Duration>,
+    max_scan: Option<usize>,
+    after_load: Option<fn(&mut T)>,
+    codec: Codec,
+    compression: Option<Compression>,
+    insertion_order: bool,
+    string_ranges: bool,
+    scan_count: usize,
+    fetch_batch: usize,
+    fetch_concurrency: usize,
+    pushdown: bool,
+    search: bool,
+    search_fields: &'static [crate::SearchField],
+    invalid: Option<String>,
+    regexes: HashMap<String, Regex>,
+    json_paths: HashMap<String, JsonPath>,
+    projection: Option<Vec<String>>,
+    after: Option<Cursor>,
+    indexes: &'static [&'static str],
+    #[cfg(feature = "collation")]
+    collation: Option<crate::Collation>,
+    _phantom: std::marker::PhantomData<fn() -> T>,
 }
 
 impl<T> QueryBuilder<T>
@@ -109,21 +1073,256 @@ where
             collection: collection.into(),
             filters: Vec::new(),
             sort: None,
+            nulls_last: None,
             limit: None,
             skip: None,
+            timeout: None,
+            max_scan: None,
+            after_load: None,
+            codec: Codec::Json,
+            compression: None,
+            insertion_order: false,
+            string_ranges: false,
+            scan_count: crate::scan::DEFAULT_SCAN_COUNT,
+            fetch_batch: DEFAULT_FETCH_BATCH,
+            fetch_concurrency: DEFAULT_FETCH_CONCURRENCY,
+            pushdown: false,
+            search: false,
+            search_fields: &[],
+            invalid: None,
+            regexes: HashMap::new(),
+            json_paths: HashMap::new(),
+            projection: None,
+            after: None,
+            indexes: &[],
+            #[cfg(feature = "collation")]
+            collation: None,
             _phantom: std::marker::PhantomData,
         }
     }
 
     /// Add a filter condition
-    pub fn filter(mut self, field: impl Into<String>, query: Query) -> Self {
-        self.filters.push((field.into(), query));
+    ///
+    /// If a [`Schema`](crate::Schema) is registered for the collection, the
+    /// filter is checked against it; a mismatch makes [`QueryBuilder::validate`]
+    /// and every execution method fail with [`Error::InvalidQuery`].
+    pub fn filter(self, field: impl Into<String>, query: Query) -> Self {
+        self.where_filter(Filter::field(field, query))
+    }
+
+    /// Add a filter expression, ANDed with the other filters
+    pub fn where_filter(mut self, filter: Filter) -> Self {
+        filter.for_each_condition(&mut |field, query| match query {
+            Query::Matches(pattern) => match Regex::new(pattern) {
+                Ok(regex) => {
+                    self.regexes.insert(pattern.clone(), regex);
+                }
+                Err(e) => {
+                    self.invalid
+                        .get_or_insert(format!("invalid pattern for field '{}': {}", field, e));
+                }
+            },
+            Query::JsonPath(expr) => match JsonPath::parse(expr) {
+                Ok(path) => {
+                    self.json_paths.insert(expr.clone(), path);
+                }
+                Err(e) => {
+                    self.invalid
+                        .get_or_insert(format!("invalid JSONPath for field '{}': {}", field, e));
+                }
+            },
+            _ => {}
+        });
+        if self.invalid.is_none() {
+            if let Some(schema) = crate::Schema::registered(&self.collection) {
+                filter.for_each_condition(&mut |field, query| {
+                    // Checked once the placeholders are bound
+                    if query.values().into_iter().any(|v| param_name(v).is_some()) {
+                        return;
+                    }
+                    if let Err(Error::InvalidQuery(reason)) = schema.check_filter(field, query) {
+                        self.invalid.get_or_insert(reason);
+                    }
+                });
+            }
+        }
+        self.filters.push(filter);
         self
     }
 
+    /// Add a group of conditions of which at least one must match
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, Query, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Ticket { #[id] id: String, status: String, age: u32 }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// // (status = "new" OR status = "pending") AND age >= 18
+    /// let tickets = Ticket::query()
+    ///     .or(|g| {
+    ///         g.filter("status", Query::eq("new"))
+    ///             .filter("status", Query::eq("pending"))
+    ///     })
+    ///     .filter("age", Query::gte(18))
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn or<F>(self, group: F) -> Self
+    where
+        F: FnOnce(FilterGroup) -> FilterGroup,
+    {
+        self.where_filter(Filter::Or(group(FilterGroup::default()).filters))
+    }
+
+    /// Add a group of conditions that must all match
+    ///
+    /// Mostly useful inside [`FilterGroup::or`] to AND a few conditions.
+    pub fn and<F>(self, group: F) -> Self
+    where
+        F: FnOnce(FilterGroup) -> FilterGroup,
+    {
+        self.where_filter(Filter::And(group(FilterGroup::default()).filters))
+    }
+
+    /// Add a group of conditions that must not all match
+    pub fn not<F>(self, group: F) -> Self
+    where
+        F: FnOnce(FilterGroup) -> FilterGroup,
+    {
+        self.where_filter(Filter::Not(Box::new(Filter::And(
+            group(FilterGroup::default()).filters,
+        ))))
+    }
+
+    /// Check the filters against the collection's registered schema
+    ///
+    /// Returns the first offending filter as [`Error::InvalidQuery`].
+    pub fn validate(&self) -> Result<()> {
+        if let Some(reason) = &self.invalid {
+            return Err(Error::InvalidQuery(reason.clone()));
+        }
+        if let Some(param) = filter_params(&self.filters).first() {
+            return Err(Error::InvalidQuery(format!(
+                "parameter ':{}' is not bound; prepare() the query and bind it",
+                param
+            )));
+        }
+        if self.after.is_some() && self.sort.is_some() {
+            return Err(Error::InvalidQuery(
+                "after() pages in key order and cannot be combined with sort_by(); use page()"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Apply a named scope
+    ///
+    /// A scope is any function that refines a query builder, which keeps
+    /// shared filter logic in one place.
+    pub fn scope<F>(self, scope: F) -> Self
+    where
+        F: FnOnce(Self) -> Self,
+    {
+        scope(self)
+    }
+
     /// Set sort order by field
     pub fn sort_by(mut self, field: impl Into<String>, order: SortOrder) -> Self {
-        self.sort = Some((field.into(), order));
+        self.sort = Some((SortBy::Field(field.into()), order));
+        self
+    }
+
+    /// Sort by a value computed from each document
+    ///
+    /// Keys compare like field values, so numbers, strings and ISO dates
+    /// all work. Replaces any [`QueryBuilder::sort_by`] field; paging and
+    /// skip/limit follow the computed order. Such a query has no
+    /// serializable form beyond its filters, see [`QueryBuilder::to_spec`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, SortOrder, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Line { #[id] id: String, price: f64, quantity: u32 }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let largest = Line::query()
+    ///     .sort_by_key(|line| line.price * line.quantity as f64, SortOrder::Desc)
+    ///     .limit(10)
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sort_by_key<K, F>(mut self, key: F, order: SortOrder) -> Self
+    where
+        K: Into<serde_json::Value>,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        let key = Arc::new(move |doc: &T| key(doc).into());
+        self.sort = Some((SortBy::Key(key), order));
+        self
+    }
+
+    /// Sort documents whose sort value is missing or `null` after all
+    /// others, in either order
+    ///
+    /// By default missing values sort as the lowest, first when ascending
+    /// and last when descending, and `null` compares equal to everything.
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls_last = Some(true);
+        self
+    }
+
+    /// Sort documents whose sort value is missing or `null` before all
+    /// others, in either order
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls_last = Some(false);
+        self
+    }
+
+    /// Order strings in the sort by the collation rules of `locale`
+    ///
+    /// Without it strings compare byte-wise, which misorders accented
+    /// and non-ASCII text. Applies to [`QueryBuilder::sort_by`] fields and
+    /// [`QueryBuilder::sort_by_key`] keys alike; an invalid locale makes
+    /// the query fail with [`Error::InvalidQuery`]. Requires the
+    /// `collation` feature.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, SortOrder, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let users = User::query()
+    ///     .sort_by("name", SortOrder::Asc)
+    ///     .collate("fr")
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "collation")]
+    pub fn collate(mut self, locale: &str) -> Self {
+        match crate::Collation::new(locale) {
+            Ok(collation) => self.collation = Some(collation),
+            Err(e) => {
+                self.invalid.get_or_insert(e.to_string());
+            }
+        }
         self
     }
 
@@ -139,49 +1338,404 @@ where
         self
     }
 
-    /// Execute the query
+    /// Match documents whose `field` point is within `radius` meters of
+    /// `lat`, `lon`
     ///
-    /// # Note
-    /// This performs in-memory filtering by fetching all documents
-    /// and filtering them locally. For large datasets, consider indexes.
-    pub async fn exec(&self, db: &TormDb) -> Result<Vec<T>> {
-        let pattern = format!("{}:*", self.collection);
-        let mut conn = db.connection().clone();
+    /// Uses the field's GEO index when it is marked `#[geo]` and built.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{GeoPoint, Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Store { #[id] id: String, #[geo] location: GeoPoint }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let nearby = Store::query()
+    ///     .near("location", 48.8566, 2.3522, 5_000.0)
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn near(self, field: impl Into<String>, lat: f64, lon: f64, radius: f64) -> Self {
+        self.filter(field, Query::near(lat, lon, radius))
+    }
 
-        // Get all keys in collection
-        let keys: Vec<String> = redis::cmd("KEYS")
-            .arg(&pattern)
-            .query_async(&mut conn)
-            .await?;
+    /// Match documents whose `field` point is inside a box, see
+    /// [`Query::within_box`]
+    pub fn within_box(
+        self,
+        field: impl Into<String>,
+        min_lat: f64,
+        min_lon: f64,
+        max_lat: f64,
+        max_lon: f64,
+    ) -> Self {
+        self.filter(field, Query::within_box(min_lat, min_lon, max_lat, max_lon))
+    }
 
-        // Fetch all documents
-        let mut documents = Vec::new();
-        for key in keys {
-            let value: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+    /// Abort the query once it has run longer than `timeout`
+    ///
+    /// Checked between SCAN batches; exceeding it returns
+    /// [`Error::QueryLimitExceeded`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use torm::{Model, Query, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, bio: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let users = User::query()
+    ///     .filter("bio", Query::contains("rust"))
+    ///     .timeout(Duration::from_secs(2))
+    ///     .max_scan(100_000)
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-            if let Some(v) = value {
-                if let Ok(doc) = serde_json::from_str::<T>(&v) {
-                    documents.push((doc, serde_json::from_str::<serde_json::Value>(&v)?));
-                }
+    /// Abort the query once more than `max` keys have been scanned
+    ///
+    /// Checked between SCAN batches; exceeding it returns
+    /// [`Error::QueryLimitExceeded`].
+    pub fn max_scan(mut self, max: usize) -> Self {
+        self.max_scan = Some(max);
+        self
+    }
+
+    /// Return documents in insertion order when no sort is set
+    ///
+    /// Only has an effect on ordered collections (see
+    /// [`crate::Model::ordered`]); others are returned in SCAN order.
+    pub fn insertion_order(mut self) -> Self {
+        self.insertion_order = true;
+        self
+    }
+
+    /// Set how many keys are requested per SCAN call
+    ///
+    /// Larger batches mean fewer round trips; smaller ones keep each
+    /// command short. Defaults to 100.
+    pub fn scan_count(mut self, count: usize) -> Self {
+        self.scan_count = count;
+        self
+    }
+
+    /// Set how many documents are read per MGET (or filter script) call
+    ///
+    /// Keys from each SCAN batch are fetched in chunks of this size, so it
+    /// only matters when it is smaller than [`QueryBuilder::scan_count`] or
+    /// an index returns many candidates. Defaults to 100.
+    pub fn fetch_batch(mut self, size: usize) -> Self {
+        self.fetch_batch = size.max(1);
+        self
+    }
+
+    /// Set how many fetch batches may be in flight at once
+    ///
+    /// The connection is multiplexed, so while one batch is being filtered
+    /// the next ones are already on their way. Documents are still visited
+    /// in key order. Defaults to 4; 1 fetches one batch at a time.
+    pub fn fetch_concurrency(mut self, limit: usize) -> Self {
+        self.fetch_concurrency = limit.max(1);
+        self
+    }
+
+    /// Evaluate filters inside ToonStore with a Lua script
+    ///
+    /// Each SCAN batch is filtered server-side and only candidate documents
+    /// are sent back, which saves bandwidth on selective queries. Requires
+    /// the JSON codec and a single server; other codecs and clusters,
+    /// where a batch spans hash slots, are filtered client-side as usual.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, Query, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, age: u32 }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let seniors = User::query()
+    ///     .filter("age", Query::gte(65))
+    ///     .pushdown()
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pushdown(mut self) -> Self {
+        self.pushdown = true;
+        self
+    }
+
+    /// Run [`QueryBuilder::exec`] as one `FT.SEARCH` on the collection's
+    /// RediSearch index
+    ///
+    /// Filters, sort, skip and limit are evaluated by the server, so the
+    /// query costs the same at millions of documents. Needs the model's
+    /// `#[search]` fields, an index made with
+    /// [`Model::create_search_index`](crate::Model::create_search_index)
+    /// and documents written with [`JsonStore`](crate::JsonStore). Filters
+    /// or sorts the index can't answer, such as ones on other fields,
+    /// fail rather than scan. Without the RediSearch module the query
+    /// scans as usual. How far results page is capped by the server's
+    /// `MAXSEARCHRESULTS`.
+    ///
+    /// [`QueryBuilder::paginate`], [`QueryBuilder::count`],
+    /// [`QueryBuilder::exists`], [`QueryBuilder::exec_one`] and
+    /// [`QueryBuilder::exec_stream`] also use the index. Operations that
+    /// have to scan, such as [`QueryBuilder::page`], keyset paging,
+    /// aggregates, updates and deletes, return an error instead of reading
+    /// the JSON documents with `GET`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, Query, SortOrder, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, #[search(sortable)] age: u32 }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let seniors = User::query()
+    ///     .filter("age", Query::gte(65))
+    ///     .sort_by("age", SortOrder::Desc)
+    ///     .limit(50)
+    ///     .search()
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn search(mut self) -> Self {
+        self.search = true;
+        self
+    }
+
+    /// Return only the given top-level fields of each document
+    ///
+    /// The [`Selection`] deserializes the reduced documents into a lighter
+    /// type. With [`QueryBuilder::pushdown`], documents are also cut down
+    /// inside ToonStore so unused fields are not transferred.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String, avatar: Vec<u8> }
+    /// #[derive(Deserialize)]
+    /// struct UserRow {
+    ///     id: String,
+    ///     name: String,
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let rows: Vec<UserRow> = User::query()
+    ///     .pushdown()
+    ///     .select(&["id", "name"])
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn select(self, fields: &[&str]) -> Selection<T> {
+        Selection {
+            query: self,
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    /// Get the serializable form of this query
+    ///
+    /// Covers filters, sort, skip and limit; options such as the codec or
+    /// time budget are not part of it, nor is a [`QueryBuilder::sort_by_key`]
+    /// sort.
+    pub fn to_spec(&self) -> QuerySpec {
+        QuerySpec {
+            filters: self.filters.clone(),
+            sort: self.sort.as_ref().and_then(|(by, order)| match by {
+                SortBy::Field(field) => Some(SortSpec {
+                    field: field.clone(),
+                    order: *order,
+                    collation: self.collation_locale(),
+                    nulls_last: self.nulls_last,
+                }),
+                SortBy::Key(_) => None,
+            }),
+            skip: self.skip,
+            limit: self.limit,
+            select: None,
+        }
+    }
+
+    /// Build a query on `collection` from its serializable form
+    ///
+    /// Filters are checked as with [`QueryBuilder::where_filter`]. The
+    /// selection of `spec` is not applied; pass it to
+    /// [`QueryBuilder::select`] to return only those fields.
+    pub fn from_spec(collection: impl Into<String>, spec: &QuerySpec) -> Self {
+        let mut query = spec
+            .filters
+            .iter()
+            .cloned()
+            .fold(Self::new(collection), Self::where_filter);
+        if let Some(sort) = &spec.sort {
+            query = query.sort_by(sort.field.clone(), sort.order);
+            query.nulls_last = sort.nulls_last;
+            if let Some(locale) = &sort.collation {
+                query = query.with_collation(locale);
             }
         }
+        query.skip = spec.skip;
+        query.limit = spec.limit;
+        query
+    }
+
+    /// Start after the given cursor or document ID (keyset pagination)
+    ///
+    /// Documents come in insertion order for ordered collections and in ID
+    /// order otherwise; `skip` is ignored. For ordered collections each
+    /// page reads only from the cursor on, so deep pages cost as much as
+    /// the first; otherwise the collection is still scanned, but never
+    /// sorted or held in memory as a whole.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let first = User::query().keyset_page(&db, 50).await?;
+    /// if let Some(next) = first.next {
+    ///     let second = User::query().after(next).keyset_page(&db, 50).await?;
+    /// }
+    /// let after_bob = User::query().after("user-bob").limit(10).exec(&db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn after(mut self, cursor: impl Into<Cursor>) -> Self {
+        self.after = Some(cursor.into());
+        self
+    }
 
-        // Apply filters
-        documents.retain(|(_, json_doc)| self.matches_filters(json_doc));
+    /// Keep documents whose `field` timestamp lies within the last `duration`
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # use chrono::{DateTime, Utc};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Session { #[id] id: String, last_seen: DateTime<Utc> }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let active = Session::query()
+    ///     .within_last("last_seen", Duration::from_secs(15 * 60))
+    ///     .exec(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn within_last(self, field: impl Into<String>, duration: Duration) -> Self {
+        let since = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|d| chrono::Utc::now().checked_sub_signed(d))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+        self.filter(field, Query::Gte(datetime_value(since)))
+    }
 
-        // Apply sorting
-        if let Some((field, order)) = &self.sort {
-            documents.sort_by(|(_, a), (_, b)| {
-                let a_val = a.get(field);
-                let b_val = b.get(field);
-                let cmp = compare_json_values(a_val, b_val);
-                match order {
-                    SortOrder::Asc => cmp,
-                    SortOrder::Desc => cmp.reverse(),
+    /// Let range operators compare plain strings and booleans
+    ///
+    /// By default `gt`/`gte`/`lt`/`lte` only match numbers and ISO-8601
+    /// dates. With this set, other strings compare lexicographically and
+    /// `false < true`.
+    pub fn string_ranges(mut self) -> Self {
+        self.string_ranges = true;
+        self
+    }
+
+    /// Decode documents with `codec` instead of JSON
+    pub(crate) fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Compress documents written by updates with `compression`
+    pub(crate) fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Use the secondary indexes of `indexes` where they are built
+    pub(crate) fn with_indexes(mut self, indexes: &'static [&'static str]) -> Self {
+        self.indexes = indexes;
+        self
+    }
+
+    /// Answer [`QueryBuilder::search`] queries on `fields`
+    pub(crate) fn with_search_fields(mut self, fields: &'static [crate::SearchField]) -> Self {
+        self.search_fields = fields;
+        self
+    }
+
+    /// Run `hook` on every document returned by [`QueryBuilder::exec`]
+    pub(crate) fn with_after_load(mut self, hook: fn(&mut T)) -> Self {
+        self.after_load = Some(hook);
+        self
+    }
+
+    /// Execute the query
+    ///
+    /// # Note
+    /// This performs in-memory filtering by fetching all documents
+    /// and filtering them locally. For large datasets, consider indexes.
+    pub async fn exec(&self, db: &TormDb) -> Result<Vec<T>> {
+        let db: &TormDb = &db.reader();
+        if self.after.is_some() {
+            let size = self.limit.unwrap_or(usize::MAX);
+            return Ok(self.keyset_page(db, size).await?.items);
+        }
+        if self.search && db.has_search_module() {
+            return self.exec_search(db).await;
+        }
+
+        // Fetch matching documents, stopping once an unsorted query has
+        // enough of them
+        let wanted = self.wanted();
+        let mut documents = Vec::new();
+        if wanted != Some(0) {
+            self.scan_matching(db, |_, v, json_doc| {
+                if let Ok(doc) = self.codec.decode::<T>(v) {
+                    documents.push((doc, json_doc));
                 }
-            });
+                enough(documents.len(), wanted)
+            })
+            .await?;
         }
 
+        // Apply sorting
+        self.sort_matches(&mut documents);
+
         // Extract just the documents (not JSON values)
         let mut results: Vec<T> = documents.into_iter().map(|(doc, _)| doc).collect();
 
@@ -195,115 +1749,1577 @@ where
             results.truncate(limit);
         }
 
+        if let Some(hook) = self.after_load {
+            results.iter_mut().for_each(hook);
+        }
+
         Ok(results)
     }
 
+    /// Whether the query runs on the collection's search index, see
+    /// [`QueryBuilder::search`]
+    fn uses_search(&self, db: &TormDb) -> bool {
+        self.search && db.has_search_module()
+    }
+
+    /// Error for a scan-based operation on a [`QueryBuilder::search`] query
+    fn search_unsupported(&self, operation: &str) -> Error {
+        Error::Other(format!(
+            "{} is not supported on search queries (collection '{}')",
+            operation, self.collection
+        ))
+    }
+
+    /// Translate filters and sort into an `FT.SEARCH` query
+    fn search_query(&self) -> Result<(String, Option<(&str, SortOrder)>)> {
+        let unsupported = || {
+            Error::Other(format!(
+                "query on '{}' can't be answered by its search index",
+                self.collection
+            ))
+        };
+        let query =
+            crate::search::translate(&self.filters, self.search_fields).ok_or_else(unsupported)?;
+        let sort = match &self.sort {
+            None => None,
+            Some((SortBy::Field(field), order))
+                if self.search_fields.iter().any(|f| f.name == field)
+                    && self.nulls_last.is_none()
+                    && self.collation_locale().is_none() =>
+            {
+                Some((field.as_str(), *order))
+            }
+            Some(_) => return Err(unsupported()),
+        };
+        Ok((query, sort))
+    }
+
+    /// Execute the query with `FT.SEARCH`, a page of results at a time
+    async fn exec_search(&self, db: &TormDb) -> Result<Vec<T>> {
+        let offset = self.skip.unwrap_or(0);
+        let limit = self.limit.unwrap_or(usize::MAX);
+        Ok(self.search_window(db, offset, limit).await?.1)
+    }
+
+    /// Count the matches of a search query without fetching them
+    async fn search_total(&self, db: &TormDb) -> Result<usize> {
+        let (query, _) = self.search_query()?;
+        let (total, _) = crate::search::search(db, &self.collection, &query, None, 0, 0).await?;
+        Ok(total)
+    }
+
+    /// Fetch up to `limit` search matches from `offset` on, along with the
+    /// total number of matches
+    #[tracing::instrument(name = "torm.search", level = "info", skip_all, fields(collection = %self.collection))]
+    async fn search_window(
+        &self,
+        db: &TormDb,
+        mut offset: usize,
+        limit: usize,
+    ) -> Result<(usize, Vec<T>)> {
+        let (query, sort) = self.search_query()?;
+
+        let mut total = 0;
+        let mut remaining = limit;
+        let mut results = Vec::new();
+        while remaining > 0 {
+            let count = remaining.min(self.fetch_batch);
+            let (matches, documents) =
+                crate::search::search(db, &self.collection, &query, sort, offset, count).await?;
+            total = matches;
+            let fetched = documents.len();
+            for doc in documents {
+                results.push(serde_json::from_value::<T>(doc)?);
+            }
+            offset += fetched;
+            remaining -= fetched;
+            if fetched < count || offset >= total {
+                break;
+            }
+        }
+        if limit == 0 {
+            total = self.search_total(db).await?;
+        }
+
+        if let Some(hook) = self.after_load {
+            results.iter_mut().for_each(hook);
+        }
+        Ok((total, results))
+    }
+
+    /// Execute the query and yield matching documents as they are scanned
+    ///
+    /// Only one SCAN batch is held in memory at a time, and skip, limit and
+    /// the query budgets are applied as the stream advances. A sorted query
+    /// has to see every match first, so it is executed like
+    /// [`QueryBuilder::exec`] and then streamed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use futures::TryStreamExt;
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let mut users = std::pin::pin!(User::query().exec_stream(&db));
+    /// while let Some(user) = users.try_next().await? {
+    ///     println!("{}", user.name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn exec_stream(&self, db: &TormDb) -> impl Stream<Item = Result<T>> + Send + 'static
+    where
+        T: Send + 'static,
+    {
+        let query = self.clone_query();
+        let db = db.reader().into_owned();
+
+        if query.sort.is_some() || query.uses_search(&db) {
+            return stream::once(async move { query.exec(&db).await })
+                .map_ok(|documents| stream::iter(documents.into_iter().map(Ok)))
+                .try_flatten()
+                .left_stream();
+        }
+
+        let state = ScanStream {
+            query,
+            db,
+            source: None,
+            buffer: VecDeque::new(),
+            stats: QueryStats::default(),
+            started: Instant::now(),
+            skipped: 0,
+            yielded: 0,
+            done: false,
+        };
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(doc) = state.buffer.pop_front() {
+                    return Some((Ok(doc), state));
+                }
+                if state.done {
+                    return None;
+                }
+                if let Err(e) = state.next_batch().await {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+            }
+        })
+        .right_stream()
+    }
+
+    /// Execute the query and return up to `size` documents after the
+    /// [`QueryBuilder::after`] cursor, if any
+    ///
+    /// See [`QueryBuilder::after`] for the order of documents.
+    pub async fn keyset_page(&self, db: &TormDb, size: usize) -> Result<KeysetPage<T>> {
+        let db: &TormDb = &db.reader();
+        if self.uses_search(db) {
+            return Err(self.search_unsupported("keyset paging"));
+        }
+        self.validate()?;
+        let mut entries = if crate::order::is_ordered(db, &self.collection).await? {
+            self.keyset_ordered(db, size).await?
+        } else {
+            self.keyset_by_id(db, size).await?
+        };
+
+        let next = if entries.len() > size {
+            entries.truncate(size);
+            entries.last().map(|(cursor, _)| cursor.clone())
+        } else {
+            None
+        };
+        let mut items: Vec<T> = entries.into_iter().map(|(_, doc)| doc).collect();
+        if let Some(hook) = self.after_load {
+            items.iter_mut().for_each(hook);
+        }
+
+        Ok(KeysetPage { items, next })
+    }
+
+    /// Read up to `size + 1` matches from the insertion-order index,
+    /// starting after the cursor's position
+    async fn keyset_ordered(&self, db: &TormDb, size: usize) -> Result<Vec<(Cursor, T)>> {
+        let mut conn = db.connection().clone();
+        let order_key = db.key(&crate::order::order_key(&self.collection));
+        let mut start = match &self.after {
+            Some(Cursor {
+                position: Some(position),
+                ..
+            }) => format!("({}", position),
+            Some(Cursor { id, .. }) => {
+                let score: Option<f64> = redis::cmd("ZSCORE")
+                    .arg(&order_key)
+                    .arg(id)
+                    .query_async(&mut conn)
+                    .await?;
+                let score = score.ok_or_else(|| {
+                    Error::InvalidQuery(format!("cursor document '{}' is not indexed", id))
+                })?;
+                format!("({}", score)
+            }
+            None => "-inf".to_string(),
+        };
+
+        let prefix = db.key(&format!("{}:", self.collection));
+        let mut stats = QueryStats::default();
+        let started = Instant::now();
+        let mut found = Vec::new();
+        while found.len() <= size {
+            let batch: Vec<(String, f64)> = redis::cmd("ZRANGEBYSCORE")
+                .arg(&order_key)
+                .arg(&start)
+                .arg("+inf")
+                .arg("WITHSCORES")
+                .arg("LIMIT")
+                .arg(0)
+                .arg(self.scan_count)
+                .query_async(&mut conn)
+                .await?;
+            let Some((_, last)) = batch.last() else {
+                break;
+            };
+            start = format!("({}", last);
+
+            let keys: Vec<String> = batch
+                .iter()
+                .map(|(id, _)| format!("{}{}", prefix, id))
+                .collect();
+            let values: Vec<Option<Vec<u8>>> =
+                redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+            stats.scanned += keys.len();
+
+            for ((id, score), value) in batch.into_iter().zip(values) {
+                let Some(v) = value else {
+                    continue;
+                };
+                let mut on_match = |_: &str, v: &[u8], _| {
+                    if let Ok(doc) = self.codec.decode::<T>(v) {
+                        let cursor = Cursor {
+                            id: id.clone(),
+                            position: Some(score),
+                        };
+                        found.push((cursor, doc));
+                    }
+                    ControlFlow::Continue(())
+                };
+                let _ = self.visit(&id, &v, &mut stats, &mut on_match);
+                if found.len() > size {
+                    break;
+                }
+            }
+
+            stats.elapsed = started.elapsed();
+            self.check_budget(&stats)?;
+        }
+
+        Ok(found)
+    }
+
+    /// Scan for the `size + 1` matches with the smallest IDs after the
+    /// cursor's ID
+    async fn keyset_by_id(&self, db: &TormDb, size: usize) -> Result<Vec<(Cursor, T)>> {
+        let prefix = db.key(&format!("{}:", self.collection));
+        let after = self.after.as_ref().map(Cursor::id);
+        let keep = size.saturating_add(1);
+        let mut smallest: std::collections::BinaryHeap<(String, Vec<u8>)> =
+            std::collections::BinaryHeap::new();
+
+        self.scan_matching(db, |key, v, _| {
+            let Some(id) = key.strip_prefix(&prefix) else {
+                return ControlFlow::Continue(());
+            };
+            if after.is_some_and(|after| id <= after) {
+                return ControlFlow::Continue(());
+            }
+            smallest.push((id.to_string(), v.to_vec()));
+            if smallest.len() > keep {
+                smallest.pop();
+            }
+            ControlFlow::Continue(())
+        })
+        .await?;
+
+        Ok(smallest
+            .into_sorted_vec()
+            .into_iter()
+            .filter_map(|(id, v)| {
+                let doc = self.codec.decode::<T>(&v).ok()?;
+                Some((Cursor::after_id(id), doc))
+            })
+            .collect())
+    }
+
+    /// Execute the query and return the first matching document
+    ///
+    /// Without a sort or skip, scanning stops at the first match; otherwise
+    /// this behaves like `.limit(1).exec()`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, email: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let user = User::query()
+    ///     .filter("email", Query::eq("john@example.com"))
+    ///     .exec_one(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn exec_one(&self, db: &TormDb) -> Result<Option<T>> {
+        let db: &TormDb = &db.reader();
+        if self.limit == Some(0) {
+            return Ok(None);
+        }
+        if self.sort.is_some() || self.skip.is_some_and(|skip| skip > 0) || self.uses_search(db) {
+            let mut results = Self {
+                limit: Some(1),
+                ..self.clone_query()
+            }
+            .exec(db)
+            .await?;
+            return Ok(results.pop());
+        }
+
+        let mut found = None;
+        self.scan_matching(db, |_, v, _| match self.codec.decode::<T>(v) {
+            Ok(doc) => {
+                found = Some(doc);
+                ControlFlow::Break(())
+            }
+            Err(_) => ControlFlow::Continue(()),
+        })
+        .await?;
+
+        if let (Some(doc), Some(hook)) = (found.as_mut(), self.after_load) {
+            hook(doc);
+        }
+        Ok(found)
+    }
+
+    /// Get the unique values of `field` across the matching documents
+    ///
+    /// `field` may be a dot path; each value it leads to counts. Documents
+    /// without the field are skipped. Values are returned in sort order.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, country: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let countries = User::query().distinct("country", &db).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn distinct(&self, field: &str, db: &TormDb) -> Result<Vec<serde_json::Value>> {
+        let db: &TormDb = &db.reader();
+        let mut seen = std::collections::HashSet::new();
+        let mut values = Vec::new();
+        self.scan_matching(db, |_, _, json_doc| {
+            for value in crate::path::resolve(&json_doc, field) {
+                if seen.insert(value.to_string()) {
+                    values.push(value.clone());
+                }
+            }
+            ControlFlow::Continue(())
+        })
+        .await?;
+
+        values.sort_by(|a, b| compare_json_values(Some(a), Some(b)));
+        Ok(values)
+    }
+
+    /// Sum the numeric values of `field` across the matching documents
+    ///
+    /// Computed during the scan; non-numeric and missing values are
+    /// skipped. `field` may be a dot path.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Order { #[id] id: String, status: String, amount: f64 }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let revenue = Order::query()
+    ///     .filter("status", Query::eq("paid"))
+    ///     .sum("amount", &db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn sum(&self, field: &str, db: &TormDb) -> Result<f64> {
+        let sum = self.aggregate(&Agg::sum(field), db).await?;
+        Ok(sum.as_f64().unwrap_or_default())
+    }
+
+    /// Average the numeric values of `field`, `None` if there are none
+    pub async fn avg(&self, field: &str, db: &TormDb) -> Result<Option<f64>> {
+        let avg = self.aggregate(&Agg::avg(field), db).await?;
+        Ok(avg.as_f64())
+    }
+
+    /// Get the smallest value of `field`, `None` if no document has it
+    ///
+    /// Values are ordered like [`QueryBuilder::sort_by`] orders them.
+    pub async fn min(&self, field: &str, db: &TormDb) -> Result<Option<serde_json::Value>> {
+        let min = self.aggregate(&Agg::min(field), db).await?;
+        Ok(Some(min).filter(|v| !v.is_null()))
+    }
+
+    /// Get the largest value of `field`, `None` if no document has it
+    pub async fn max(&self, field: &str, db: &TormDb) -> Result<Option<serde_json::Value>> {
+        let max = self.aggregate(&Agg::max(field), db).await?;
+        Ok(Some(max).filter(|v| !v.is_null()))
+    }
+
+    /// Compute an aggregate over every matching document
+    ///
+    /// A numeric field marked `#[index]` is aggregated from its sorted
+    /// set, without fetching documents, when the index is built and every
+    /// filter is a numeric range on that field. `min` and `max` then
+    /// return whole numbers as integers.
+    pub async fn aggregate(&self, agg: &Agg, db: &TormDb) -> Result<serde_json::Value> {
+        let db: &TormDb = &db.reader();
+        self.validate()?;
+        if let Some(value) =
+            crate::index::aggregate(db, &self.collection, self.indexes, &self.filters, agg).await?
+        {
+            return Ok(value);
+        }
+
+        let mut acc = Accumulator::default();
+        self.scan_matching(db, |_, _, json_doc| {
+            acc.add(agg, &json_doc);
+            ControlFlow::Continue(())
+        })
+        .await?;
+        Ok(acc.finish(agg))
+    }
+
+    /// Group the matching documents by the value of `field`
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Agg, Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Order { #[id] id: String, status: String, amount: f64 }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let per_status = Order::query()
+    ///     .group_by("status")
+    ///     .aggregate(Agg::Count, &db)
+    ///     .await?;
+    /// let revenue = Order::query()
+    ///     .group_by("status")
+    ///     .aggregate(Agg::sum("amount"), &db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn group_by(self, field: impl Into<String>) -> GroupBy<T> {
+        GroupBy {
+            query: self,
+            field: field.into(),
+        }
+    }
+
+    /// Turn this query into a template whose [`Param`] placeholders are
+    /// bound on each execution
+    ///
+    /// Everything but the placeholder values is set up once: filters,
+    /// compiled patterns, sort and limits. Executing the query without
+    /// binding them fails with [`Error::InvalidQuery`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, Param, Query, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, age: u32, status: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// // age >= :min_age AND status = :status
+    /// let adults = User::query()
+    ///     .filter("age", Query::gte(Param::new("min_age")))
+    ///     .filter("status", Query::eq(Param::new("status")))
+    ///     .prepare();
+    /// for min_age in [18, 21] {
+    ///     let users = adults
+    ///         .exec(&db, [("min_age", min_age.into()), ("status", "active".into())])
+    ///         .await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prepare(self) -> PreparedQuery<T> {
+        PreparedQuery {
+            params: filter_params(&self.filters),
+            query: self,
+        }
+    }
+
+    /// Delete every document matching the query
+    ///
+    /// Sort, skip and limit select which matches are removed. Keys are
+    /// deleted in pipelined batches; the collection counter and
+    /// insertion-order index are updated and a delete event is published
+    /// for each removed document. Returns how many were removed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, active: bool }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let removed = User::query()
+    ///     .filter("active", Query::eq(false))
+    ///     .delete(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(name = "torm.delete_where", level = "info", skip_all, fields(collection = %self.collection, removed = tracing::field::Empty))]
+    pub async fn delete(&self, db: &TormDb) -> Result<usize> {
+        let documents = self.matching_documents(db).await?;
+        let prefix = db.key(&format!("{}:", self.collection));
+        let mut conn = db.connection().clone();
+        let mut removed = 0;
+
+        for batch in documents.chunks(self.scan_count) {
+            let mut pipe = redis::pipe();
+            for (key, _) in batch {
+                pipe.cmd("GETDEL").arg(key);
+            }
+            let deleted: Vec<Option<Vec<u8>>> = pipe.query_async(&mut conn).await?;
+
+            let removed_docs: Vec<(&str, Vec<u8>)> = batch
+                .iter()
+                .zip(deleted)
+                .filter_map(|((key, _), old)| Some((key.strip_prefix(&prefix)?, old?)))
+                .collect();
+            if removed_docs.is_empty() {
+                continue;
+            }
+            let ids: Vec<&str> = removed_docs.iter().map(|(id, _)| *id).collect();
+
+            crate::counter::adjust(db, &self.collection, -(ids.len() as i64)).await?;
+            redis::cmd("ZREM")
+                .arg(db.key(&crate::order::order_key(&self.collection)))
+                .arg(&ids)
+                .query_async::<()>(&mut conn)
+                .await?;
+
+            for (id, old) in &removed_docs {
+                crate::index::update_encoded(
+                    db,
+                    &self.collection,
+                    self.indexes,
+                    self.codec,
+                    id,
+                    Some(old),
+                    None,
+                )
+                .await?;
+                crate::events::publish(db, crate::ModelOp::Delete, &self.collection, id, None)
+                    .await?;
+            }
+            removed += ids.len();
+        }
+
+        tracing::Span::current().record("removed", removed);
+        Ok(removed)
+    }
+
+    /// Update every document matching the query with a JSON merge patch
+    ///
+    /// The patch follows RFC 7396: object fields merge recursively and
+    /// `null` removes a field. Patched documents must still deserialize
+    /// into `T`. See [`QueryBuilder::update_with`] for how documents are
+    /// selected and written. Returns how many documents changed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, plan: String, active: bool }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let changed = User::query()
+    ///     .filter("plan", Query::eq("trial"))
+    ///     .update(&db, serde_json::json!({ "active": false }))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn update(&self, db: &TormDb, patch: serde_json::Value) -> Result<usize> {
+        self.rewrite(db, |codec, bytes| {
+            let mut doc: serde_json::Value = codec.decode(bytes)?;
+            crate::merge::merge_patch(&mut doc, &patch);
+            let doc: T = serde_json::from_value(doc)?;
+            codec.encode(&doc)
+        })
+        .await
+    }
+
+    /// Update every document matching the query with a closure
+    ///
+    /// Sort, skip and limit select which matches are updated. Documents
+    /// are written back in pipelined batches, skipping those the closure
+    /// left unchanged and those deleted in the meantime; a save event is
+    /// published for each. Returns how many documents changed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let changed = User::query()
+    ///     .update_with(&db, |user| user.name = user.name.trim().to_string())
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn update_with<F>(&self, db: &TormDb, mut edit: F) -> Result<usize>
+    where
+        F: FnMut(&mut T),
+    {
+        self.rewrite(db, |codec, bytes| {
+            let mut doc: T = codec.decode(bytes)?;
+            edit(&mut doc);
+            codec.encode(&doc)
+        })
+        .await
+    }
+
+    /// Re-encode every matching document with `edit` and store the changes
+    #[tracing::instrument(name = "torm.update_where", level = "info", skip_all, fields(collection = %self.collection, changed = tracing::field::Empty))]
+    async fn rewrite<F>(&self, db: &TormDb, mut edit: F) -> Result<usize>
+    where
+        F: FnMut(Codec, &[u8]) -> Result<Vec<u8>>,
+    {
+        let documents = self.matching_documents(db).await?;
+        let prefix = db.key(&format!("{}:", self.collection));
+        let mut conn = db.connection().clone();
+        let mut changed = 0;
+
+        for batch in documents.chunks(self.scan_count) {
+            let mut updates = Vec::new();
+            for (key, bytes) in batch {
+                let plain = crate::codec::decompress(bytes)?;
+                let updated = edit(self.codec, &plain)?;
+                if updated != *plain {
+                    updates.push((key, crate::codec::compress(self.compression, updated)?));
+                }
+            }
+            if updates.is_empty() {
+                continue;
+            }
+
+            let mut pipe = redis::pipe();
+            for (key, updated) in &updates {
+                pipe.cmd("SET").arg(key).arg(updated).arg("XX").arg("GET");
+            }
+            let replaced: Vec<Option<Vec<u8>>> = pipe.query_async(&mut conn).await?;
+
+            for ((key, updated), previous) in updates.iter().zip(replaced) {
+                let (Some(id), Some(previous)) = (key.strip_prefix(&prefix), previous) else {
+                    continue;
+                };
+                changed += 1;
+                crate::index::update_encoded(
+                    db,
+                    &self.collection,
+                    self.indexes,
+                    self.codec,
+                    id,
+                    Some(&previous),
+                    Some(updated),
+                )
+                .await?;
+                let payload = self.codec.decode::<serde_json::Value>(updated).ok();
+                crate::events::publish(db, crate::ModelOp::Save, &self.collection, id, payload)
+                    .await?;
+            }
+        }
+
+        tracing::Span::current().record("changed", changed);
+        Ok(changed)
+    }
+
+    /// Keys and stored bytes of the matching documents, sorted, skipped and
+    /// limited like [`QueryBuilder::exec`]
+    async fn matching_documents(&self, db: &TormDb) -> Result<Vec<(String, Vec<u8>)>> {
+        let wanted = self.wanted();
+        let mut matches = Vec::new();
+        if wanted != Some(0) {
+            self.scan_matching(db, |key, v, json_doc| {
+                matches.push(((key.to_string(), v.to_vec()), json_doc));
+                enough(matches.len(), wanted)
+            })
+            .await?;
+        }
+
+        self.sort_matches(&mut matches);
+
+        Ok(matches
+            .into_iter()
+            .map(|(document, _)| document)
+            .skip(self.skip.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// How many matches an unsorted query needs before it can stop
+    /// scanning: `skip + limit`, or `None` if it needs all of them
+    fn wanted(&self) -> Option<usize> {
+        match (&self.sort, self.limit) {
+            (None, Some(limit)) => Some(self.skip.unwrap_or(0).saturating_add(limit)),
+            _ => None,
+        }
+    }
+
+    /// The locale of the collation set with [`QueryBuilder::collate`]
+    fn collation_locale(&self) -> Option<String> {
+        #[cfg(feature = "collation")]
+        if let Some(collation) = &self.collation {
+            return Some(collation.locale().to_string());
+        }
+        None
+    }
+
+    /// Apply a collation read from a [`SortSpec`]
+    fn with_collation(self, locale: &str) -> Self {
+        #[cfg(feature = "collation")]
+        return self.collate(locale);
+        #[cfg(not(feature = "collation"))]
+        {
+            let mut query = self;
+            query.invalid.get_or_insert(format!(
+                "collation '{}' requires the `collation` feature",
+                locale
+            ));
+            query
+        }
+    }
+
+    /// Compare two sort values, using the collation if one is set
+    fn compare_sort_values(
+        &self,
+        a: Option<&serde_json::Value>,
+        b: Option<&serde_json::Value>,
+    ) -> Ordering {
+        #[cfg(feature = "collation")]
+        if let Some(collation) = &self.collation {
+            return collation.compare(a, b);
+        }
+        compare_json_values(a, b)
+    }
+
+    /// Compare two sort values in `order`, placing missing and `null`
+    /// values as set with [`QueryBuilder::nulls_last`]
+    fn compare_sorted(
+        &self,
+        a: Option<&serde_json::Value>,
+        b: Option<&serde_json::Value>,
+        order: SortOrder,
+    ) -> Ordering {
+        if let Some(nulls_last) = self.nulls_last {
+            let null = |v: Option<&serde_json::Value>| v.is_none_or(serde_json::Value::is_null);
+            let cmp = null(a).cmp(&null(b));
+            if cmp != Ordering::Equal || null(a) {
+                return if nulls_last { cmp } else { cmp.reverse() };
+            }
+        }
+        let cmp = self.compare_sort_values(a, b);
+        match order {
+            SortOrder::Asc => cmp,
+            SortOrder::Desc => cmp.reverse(),
+        }
+    }
+
+    /// Sort matched documents by the sort field or key, if one is set
+    fn sort_matches<D>(&self, matches: &mut Vec<(D, serde_json::Value)>) {
+        let Some((_, order)) = &self.sort else {
+            return;
+        };
+        let mut keyed: Vec<_> = matches
+            .drain(..)
+            .map(|(document, json_doc)| (self.sort_value(&json_doc), (document, json_doc)))
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| self.compare_sorted(a.as_ref(), b.as_ref(), *order));
+        matches.extend(keyed.into_iter().map(|(_, matched)| matched));
+    }
+
+    /// The value a document is sorted by, `None` if it has none
+    fn sort_value(&self, doc: &serde_json::Value) -> Option<serde_json::Value> {
+        match &self.sort {
+            Some((SortBy::Field(field), _)) => crate::path::resolve(doc, field)
+                .first()
+                .map(|v| (*v).clone()),
+            Some((SortBy::Key(key), _)) => serde_json::from_value::<T>(doc.clone())
+                .ok()
+                .map(|doc| key(&doc)),
+            None => None,
+        }
+    }
+
+    /// Whether the query sorts by a computed key, which may read any field
+    fn sorts_by_key(&self) -> bool {
+        matches!(self.sort, Some((SortBy::Key(_), _)))
+    }
+
+    /// Execute the query and return one page of results with totals
+    ///
+    /// `page` is 1-based; any skip/limit already set on the builder is
+    /// replaced by the page window.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let page = User::query().paginate(&db, 2, 20).await?;
+    /// println!("page {} of {}", page.page, page.total_pages);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn paginate(&self, db: &TormDb, page: usize, per_page: usize) -> Result<Page<T>> {
+        let db: &TormDb = &db.reader();
+        let page = page.max(1);
+        let per_page = per_page.max(1);
+
+        if self.uses_search(db) {
+            let offset = (page - 1).saturating_mul(per_page);
+            let (total, items) = self.search_window(db, offset, per_page).await?;
+            return Ok(Page {
+                items,
+                total,
+                page,
+                per_page,
+                total_pages: total.div_ceil(per_page),
+            });
+        }
+
+        let mut all = Self {
+            skip: None,
+            limit: None,
+            ..self.clone_query()
+        }
+        .exec(db)
+        .await?;
+
+        let total = all.len();
+        let items: Vec<T> = all.drain(page_window(page, per_page, total)).collect();
+
+        Ok(Page {
+            items,
+            total,
+            page,
+            per_page,
+            total_pages: total.div_ceil(per_page),
+        })
+    }
+
+    /// Execute the query and return one page with a continuation token
+    ///
+    /// Items and total come from the same scan, so they always agree.
+    /// Pages are ordered by the sort field, then by key, and the token
+    /// marks the last document returned: documents added or removed before
+    /// it do not shift later pages. Skip/limit and insertion order are
+    /// ignored.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, PageRequest, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, name: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let first = User::query().page(&db, PageRequest::first(20)).await?;
+    /// if let Some(token) = first.next_token {
+    ///     let second = User::query().page(&db, PageRequest::after(20, token)).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn page(&self, db: &TormDb, request: PageRequest) -> Result<CursorPage<T>> {
+        let db: &TormDb = &db.reader();
+        let size = request.size.max(1);
+        let after = request.token.as_deref().map(decode_token).transpose()?;
+
+        let mut entries = Vec::new();
+        self.scan_matching(db, |key, v, json_doc| {
+            if let Ok(doc) = self.codec.decode::<T>(v) {
+                let position = self.position(key, &json_doc);
+                entries.push((position, doc));
+            }
+            ControlFlow::Continue(())
+        })
+        .await?;
+
+        entries.sort_by(|(a, _), (b, _)| self.compare_positions(a, b));
+        let total = entries.len();
+
+        let start = match &after {
+            Some(after) => entries.partition_point(|(position, _)| {
+                self.compare_positions(position, after) != Ordering::Greater
+            }),
+            None => 0,
+        };
+        let end = (start + size).min(total);
+        let next_token = (end < total).then(|| encode_token(&entries[end - 1].0));
+
+        let mut items: Vec<T> = entries.drain(start..end).map(|(_, doc)| doc).collect();
+        if let Some(hook) = self.after_load {
+            items.iter_mut().for_each(hook);
+        }
+
+        Ok(CursorPage {
+            items,
+            total,
+            next_token,
+        })
+    }
+
+    /// Position of a document in [`QueryBuilder::page`] order
+    fn position(&self, key: &str, doc: &serde_json::Value) -> (serde_json::Value, String) {
+        (self.sort_value(doc).unwrap_or_default(), key.to_string())
+    }
+
+    fn compare_positions(
+        &self,
+        a: &(serde_json::Value, String),
+        b: &(serde_json::Value, String),
+    ) -> Ordering {
+        let order = self
+            .sort
+            .as_ref()
+            .map_or(SortOrder::Asc, |(_, order)| *order);
+        let cmp = self.compare_sorted(Some(&a.0), Some(&b.0), order);
+        cmp.then_with(|| a.1.cmp(&b.1))
+    }
+
+    /// Copy the query definition without requiring `T: Clone`
+    fn clone_query(&self) -> Self {
+        Self {
+            collection: self.collection.clone(),
+            filters: self.filters.clone(),
+            sort: self.sort.clone(),
+            nulls_last: self.nulls_last,
+            limit: self.limit,
+            skip: self.skip,
+            timeout: self.timeout,
+            max_scan: self.max_scan,
+            after_load: self.after_load,
+            codec: self.codec,
+            compression: self.compression,
+            insertion_order: self.insertion_order,
+            string_ranges: self.string_ranges,
+            scan_count: self.scan_count,
+            fetch_batch: self.fetch_batch,
+            fetch_concurrency: self.fetch_concurrency,
+            pushdown: self.pushdown,
+            search: self.search,
+            search_fields: self.search_fields,
+            invalid: self.invalid.clone(),
+            regexes: self.regexes.clone(),
+            json_paths: self.json_paths.clone(),
+            projection: self.projection.clone(),
+            after: self.after.clone(),
+            indexes: self.indexes,
+            #[cfg(feature = "collation")]
+            collation: self.collation.clone(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
     /// Count documents matching the query
+    ///
+    /// Without filters this reads the collection counter; numeric range
+    /// filters on one built `#[index]` field are counted from its sorted
+    /// set. Other queries scan.
     pub async fn count(&self, db: &TormDb) -> Result<usize> {
-        let pattern = format!("{}:*", self.collection);
-        let mut conn = db.connection().clone();
+        let db: &TormDb = &db.reader();
+        if self.filters.is_empty() {
+            return crate::counter::read(db, &self.collection).await;
+        }
+        if self.uses_search(db) {
+            return self.search_total(db).await;
+        }
+        self.validate()?;
+        if let Some(count) = crate::index::aggregate(
+            db,
+            &self.collection,
+            self.indexes,
+            &self.filters,
+            &Agg::Count,
+        )
+        .await?
+        {
+            return Ok(count.as_u64().unwrap_or_default() as usize);
+        }
+
+        // Need to filter, so fetch and count
+        let mut count = 0;
+        self.scan_matching(db, |_, _, _| {
+            count += 1;
+            ControlFlow::Continue(())
+        })
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Estimate how many documents match the query without fetching any
+    ///
+    /// Without filters this reads the collection counter. Otherwise, if
+    /// built indexes can answer some of the filters, it is the number of
+    /// index candidates, an upper bound that ignores the other filters.
+    /// Anything else falls back to [`QueryBuilder::count`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Order { #[id] id: String, #[index] status: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let pending = Order::query()
+    ///     .filter("status", Query::eq("pending"))
+    ///     .count_estimate(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn count_estimate(&self, db: &TormDb) -> Result<usize> {
+        let db: &TormDb = &db.reader();
+        self.validate()?;
+        if self.filters.is_empty() {
+            return crate::counter::read(db, &self.collection).await;
+        }
+        match crate::index::plan(db, &self.collection, self.indexes, &self.filters).await? {
+            Some(plan) => Ok(plan.ids.len()),
+            None => self.count(db).await,
+        }
+    }
+
+    /// Estimate how many distinct values `field` has across the collection
+    ///
+    /// Answered from a HyperLogLog kept with the field's index, within
+    /// about 1% and without a scan, when `field` is marked `#[index]`, the
+    /// index is built and the query has no filters. Values that no
+    /// document holds anymore still count until the index is rebuilt.
+    /// Anything else counts [`QueryBuilder::distinct`] values exactly.
+    pub async fn distinct_count_estimate(&self, field: &str, db: &TormDb) -> Result<usize> {
+        let db: &TormDb = &db.reader();
+        self.validate()?;
+        if self.filters.is_empty() && self.indexes.contains(&field) {
+            if let Some(count) =
+                crate::index::distinct_estimate(db, &self.collection, field).await?
+            {
+                return Ok(count);
+            }
+        }
+        Ok(self.distinct(field, db).await?.len())
+    }
+
+    /// Check whether any document matches the query
+    ///
+    /// Stops scanning at the first match instead of materializing the
+    /// result set.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, email: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let taken = User::query()
+    ///     .filter("email", Query::eq("john@example.com"))
+    ///     .exists(&db)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn exists(&self, db: &TormDb) -> Result<bool> {
+        let db: &TormDb = &db.reader();
+        if self.filters.is_empty() {
+            return Ok(crate::counter::read(db, &self.collection).await? > 0);
+        }
+        if self.uses_search(db) {
+            return Ok(self.search_total(db).await? > 0);
+        }
+
+        let mut found = false;
+        self.scan_matching(db, |_, _, _| {
+            found = true;
+            ControlFlow::Break(())
+        })
+        .await?;
+
+        Ok(found)
+    }
+
+    /// Run the query and report how it was executed
+    ///
+    /// Filters are evaluated as in [`QueryBuilder::exec`], including
+    /// stopping early once an unsorted query has `skip + limit` matches,
+    /// but documents are neither decoded into `T` nor kept.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Model, TormDb, Query};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct User { #[id] id: String, email: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// let plan = User::query()
+    ///     .filter("email", Query::eq("john@example.com"))
+    ///     .explain(&db)
+    ///     .await?;
+    /// println!("{}", plan);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn explain(&self, db: &TormDb) -> Result<Explain> {
+        let db: &TormDb = &db.reader();
+        self.validate()?;
+        let started = Instant::now();
+        let source = self.key_source(db).await?;
+        let planning = started.elapsed();
+
+        let name = source.name();
+        let indexes = match &source {
+            KeySource::Index { fields, .. } => fields.clone(),
+            _ => Vec::new(),
+        };
+        let wanted = self.wanted();
+        let mut found = 0;
+        let stats = self
+            .scan_source(source, db, |_, _, _| {
+                found += 1;
+                enough(found, wanted)
+            })
+            .await?;
+
+        Ok(Explain {
+            source: name,
+            indexes,
+            pushdown: self.uses_pushdown(),
+            scanned: stats.scanned,
+            fetched: stats.fetched,
+            matched: stats.matched,
+            planning,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Scan the collection and call `on_match` for every matching document
+    ///
+    /// Stops early when `on_match` returns `ControlFlow::Break`. Enforces the
+    /// timeout and max-scanned budgets between SCAN batches.
+    async fn scan_matching<F>(&self, db: &TormDb, on_match: F) -> Result<QueryStats>
+    where
+        F: FnMut(&str, &[u8], serde_json::Value) -> ControlFlow<()>,
+    {
+        self.validate()?;
+        let source = self.key_source(db).await?;
+        self.scan_source(source, db, on_match).await
+    }
+
+    /// Scan the keys from `source`, see [`QueryBuilder::scan_matching`]
+    #[tracing::instrument(
+        name = "torm.query",
+        level = "info",
+        skip_all,
+        fields(
+            collection = %self.collection,
+            filters = self.filters.len(),
+            strategy = if self.uses_pushdown() { "lua" } else { "scan" },
+            source = source.name(),
+            scanned = tracing::field::Empty,
+            matched = tracing::field::Empty,
+        )
+    )]
+    async fn scan_source<F>(
+        &self,
+        source: KeySource,
+        db: &TormDb,
+        mut on_match: F,
+    ) -> Result<QueryStats>
+    where
+        F: FnMut(&str, &[u8], serde_json::Value) -> ControlFlow<()>,
+    {
+        let started = Instant::now();
+        let mut stats = QueryStats::default();
+        let conn = db.connection().clone();
+        let span = tracing::Span::current();
+
+        let fetches = self
+            .key_chunks(source, conn.clone())
+            .map_ok(|chunk| {
+                let mut conn = conn.clone();
+                async move {
+                    let documents = self.fetch(&mut conn, &chunk).await?;
+                    Ok((chunk.len(), documents))
+                }
+            })
+            .try_buffered(self.fetch_concurrency);
+        let mut fetches = std::pin::pin!(fetches);
+
+        while let Some((scanned, documents)) = fetches.try_next().await? {
+            stats.scanned += scanned;
+            for (key, v) in documents {
+                if self.visit(&key, &v, &mut stats, &mut on_match).is_break() {
+                    stats.elapsed = started.elapsed();
+                    record_stats(&span, &stats);
+                    return Ok(stats);
+                }
+            }
+
+            stats.elapsed = started.elapsed();
+            record_stats(&span, &stats);
+            self.check_budget(&stats)?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Where the keys to scan come from: secondary indexes, the
+    /// insertion-order index or SCAN
+    async fn key_source(&self, db: &TormDb) -> Result<KeySource> {
+        if self.uses_search(db) {
+            return Err(self.search_unsupported("scanning"));
+        }
+        let ordered = self.insertion_order
+            && self.sort.is_none()
+            && crate::order::is_ordered(db, &self.collection).await?;
+
+        if let Some(plan) =
+            crate::index::plan(db, &self.collection, self.indexes, &self.filters).await?
+        {
+            let mut ids = plan.ids;
+            if ordered && !ids.is_empty() {
+                let mut conn = db.connection().clone();
+                let scores: Vec<Option<f64>> = redis::cmd("ZMSCORE")
+                    .arg(db.key(&crate::order::order_key(&self.collection)))
+                    .arg(&ids)
+                    .query_async(&mut conn)
+                    .await?;
+                let mut scored: Vec<_> = scores.into_iter().zip(ids).collect();
+                scored.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                ids = scored.into_iter().map(|(_, id)| id).collect();
+            }
+            let prefix = db.key(&format!("{}:", self.collection));
+            let batches = ids
+                .chunks(self.scan_count)
+                .map(|chunk| chunk.iter().map(|id| format!("{}{}", prefix, id)).collect())
+                .collect();
+            return Ok(KeySource::Index {
+                fields: plan.fields,
+                batches,
+            });
+        }
+
+        if ordered {
+            Ok(KeySource::Ordered(OrderScanner::new(db, &self.collection)))
+        } else {
+            Ok(KeySource::Scan(
+                KeyScanner::new(db.key_pattern(&format!("{}:*", self.collection)))
+                    .count(self.scan_count),
+            ))
+        }
+    }
+
+    /// Split the keys from `source` into chunks of at most `fetch_batch`
+    ///
+    /// Empty key batches come through as empty chunks so the time budget is
+    /// still checked while SCAN walks past other collections' keys.
+    fn key_chunks(
+        &self,
+        source: KeySource,
+        conn: crate::Connection,
+    ) -> impl Stream<Item = Result<Vec<String>>> + '_ {
+        stream::try_unfold(
+            (source, conn, VecDeque::new()),
+            move |(mut source, mut conn, mut pending)| async move {
+                loop {
+                    if let Some(chunk) = pending.pop_front() {
+                        return Ok(Some((chunk, (source, conn, pending))));
+                    }
+                    match source.next_batch(&mut conn).await? {
+                        Some(keys) if keys.is_empty() => pending.push_back(keys),
+                        Some(keys) => {
+                            pending.extend(keys.chunks(self.fetch_batch).map(<[String]>::to_vec))
+                        }
+                        None => return Ok(None),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Read the documents stored at `keys`, skipping missing ones
+    ///
+    /// With pushdown only the documents matching the filters are returned.
+    async fn fetch(
+        &self,
+        conn: &mut crate::Connection,
+        keys: &[String],
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.uses_pushdown() && !conn.is_cluster() {
+            return self.filter_remote(conn, keys).await;
+        }
+        let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET").arg(keys).query_async(conn).await?;
+        Ok(keys
+            .iter()
+            .zip(values)
+            .filter_map(|(key, v)| Some((key.clone(), v?)))
+            .collect())
+    }
+
+    /// Decode, filter and hand one stored document to `on_match`
+    fn visit<F>(
+        &self,
+        key: &str,
+        v: &[u8],
+        stats: &mut QueryStats,
+        on_match: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(&str, &[u8], serde_json::Value) -> ControlFlow<()>,
+    {
+        stats.fetched += 1;
+        if let Ok(json_doc) = self.codec.decode::<serde_json::Value>(v) {
+            if self.matches_filters(&json_doc) {
+                stats.matched += 1;
+                return on_match(key, v, json_doc);
+            }
+        }
+        ControlFlow::Continue(())
+    }
 
-        let keys: Vec<String> = redis::cmd("KEYS")
-            .arg(&pattern)
-            .query_async(&mut conn)
-            .await?;
+    /// Whether filters run inside ToonStore
+    fn uses_pushdown(&self) -> bool {
+        self.pushdown && self.codec == Codec::Json
+    }
 
-        if self.filters.is_empty() {
-            return Ok(keys.len());
+    /// Fetch the documents under `keys` that may match, filtering server-side
+    ///
+    /// The script only drops documents that certainly don't match, so
+    /// results are still checked with [`QueryBuilder::matches_filters`].
+    async fn filter_remote(
+        &self,
+        conn: &mut crate::Connection,
+        keys: &[String],
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
         }
 
-        // Need to filter, so fetch and count
-        let mut count = 0;
+        let script = redis::Script::new(FILTER_SCRIPT);
+        let mut invocation = script.prepare_invoke();
         for key in keys {
-            let value: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
-
-            if let Some(v) = value {
-                if let Ok(json_doc) = serde_json::from_str::<serde_json::Value>(&v) {
-                    if self.matches_filters(&json_doc) {
-                        count += 1;
-                    }
-                }
-            }
+            invocation.key(key);
         }
+        let found: Vec<(String, Vec<u8>)> = invocation
+            .arg(serde_json::to_string(&self.filters)?)
+            .arg(if self.string_ranges { "1" } else { "0" })
+            .arg(match &self.projection {
+                Some(fields) => serde_json::to_string(fields)?,
+                None => String::new(),
+            })
+            .invoke_async(conn)
+            .await?;
+        Ok(found)
+    }
 
-        Ok(count)
+    /// Fail if the query has exceeded its timeout or scan limit
+    fn check_budget(&self, stats: &QueryStats) -> Result<()> {
+        let exceeded = match (self.timeout, self.max_scan) {
+            (Some(timeout), _) if stats.elapsed > timeout => QueryLimit::Timeout(timeout),
+            (_, Some(max)) if stats.scanned > max => QueryLimit::MaxScan(max),
+            _ => return Ok(()),
+        };
+        Err(Error::QueryLimitExceeded {
+            collection: self.collection.clone(),
+            limit: exceeded,
+            stats: *stats,
+        })
     }
 
     /// Check if a document matches all filters
     fn matches_filters(&self, doc: &serde_json::Value) -> bool {
-        for (field, query) in &self.filters {
-            if !self.matches_filter(doc, field, query) {
-                return false;
-            }
+        self.filters.iter().all(|filter| self.matches(doc, filter))
+    }
+
+    /// Evaluate one filter expression against a document
+    fn matches(&self, doc: &serde_json::Value, filter: &Filter) -> bool {
+        match filter {
+            Filter::Field(field, query) => self.matches_filter(doc, field, query),
+            Filter::And(filters) => filters.iter().all(|f| self.matches(doc, f)),
+            Filter::Or(filters) => filters.iter().any(|f| self.matches(doc, f)),
+            Filter::Not(filter) => !self.matches(doc, filter),
         }
-        true
+    }
+
+    /// Order a field value against a range bound
+    ///
+    /// Numbers and ISO-8601 dates always compare; other strings and
+    /// booleans only with [`QueryBuilder::string_ranges`]. `None` means the
+    /// values are not comparable.
+    fn compare_range(
+        &self,
+        value: Option<&serde_json::Value>,
+        expected: &serde_json::Value,
+    ) -> Option<Ordering> {
+        let value = value?;
+        let as_number = |v: &serde_json::Value| v.as_f64().or_else(|| v.as_i64().map(|i| i as f64));
+        if let (Some(v), Some(e)) = (as_number(value), as_number(expected)) {
+            return v.partial_cmp(&e);
+        }
+        if let (Some(v), Some(e)) = (
+            value.as_str().and_then(parse_datetime),
+            expected.as_str().and_then(parse_datetime),
+        ) {
+            return Some(v.cmp(&e));
+        }
+        if !self.string_ranges {
+            return None;
+        }
+
+        if let (Some(v), Some(e)) = (value.as_str(), expected.as_str()) {
+            return Some(v.cmp(e));
+        }
+        if let (Some(v), Some(e)) = (value.as_bool(), expected.as_bool()) {
+            return Some(v.cmp(&e));
+        }
+        None
     }
 
     /// Check if a document matches a single filter
+    ///
+    /// `field` may be a dot path. If it leads to several values (through
+    /// `[*]`), `Ne` and `NotIn` must hold for all of them and every other
+    /// operator for at least one.
     fn matches_filter(&self, doc: &serde_json::Value, field: &str, query: &Query) -> bool {
-        let value = doc.get(field);
+        match crate::path::resolve(doc, field).as_slice() {
+            [] => self.matches_value(None, query),
+            [value] => self.matches_value(Some(value), query),
+            values => match query {
+                Query::Ne(_) | Query::NotIn(_) => {
+                    values.iter().all(|v| self.matches_value(Some(v), query))
+                }
+                _ => values.iter().any(|v| self.matches_value(Some(v), query)),
+            },
+        }
+    }
 
+    /// Check a single resolved field value against an operator
+    fn matches_value(&self, value: Option<&serde_json::Value>, query: &Query) -> bool {
         match query {
             Query::Eq(expected) => value == Some(expected),
             Query::Ne(expected) => value != Some(expected),
-            Query::Gt(expected) => {
-                if let (Some(v), Some(e)) = (
-                    value,
-                    expected
-                        .as_f64()
-                        .or_else(|| expected.as_i64().map(|i| i as f64)),
-                ) {
-                    if let Some(vf) = v.as_f64().or_else(|| v.as_i64().map(|i| i as f64)) {
-                        return vf > e;
-                    }
-                }
-                false
-            }
-            Query::Gte(expected) => {
-                if let (Some(v), Some(e)) = (
-                    value,
-                    expected
-                        .as_f64()
-                        .or_else(|| expected.as_i64().map(|i| i as f64)),
-                ) {
-                    if let Some(vf) = v.as_f64().or_else(|| v.as_i64().map(|i| i as f64)) {
-                        return vf >= e;
-                    }
-                }
-                false
-            }
-            Query::Lt(expected) => {
-                if let (Some(v), Some(e)) = (
-                    value,
-                    expected
-                        .as_f64()
-                        .or_else(|| expected.as_i64().map(|i| i as f64)),
-                ) {
-                    if let Some(vf) = v.as_f64().or_else(|| v.as_i64().map(|i| i as f64)) {
-                        return vf < e;
-                    }
-                }
-                false
-            }
-            Query::Lte(expected) => {
-                if let (Some(v), Some(e)) = (
-                    value,
-                    expected
-                        .as_f64()
-                        .or_else(|| expected.as_i64().map(|i| i as f64)),
-                ) {
-                    if let Some(vf) = v.as_f64().or_else(|| v.as_i64().map(|i| i as f64)) {
-                        return vf <= e;
-                    }
+            Query::Gt(expected) => self.compare_range(value, expected) == Some(Ordering::Greater),
+            Query::Gte(expected) => matches!(
+                self.compare_range(value, expected),
+                Some(Ordering::Greater | Ordering::Equal)
+            ),
+            Query::Lt(expected) => self.compare_range(value, expected) == Some(Ordering::Less),
+            Query::Lte(expected) => matches!(
+                self.compare_range(value, expected),
+                Some(Ordering::Less | Ordering::Equal)
+            ),
+            Query::Between {
+                low,
+                high,
+                inclusive,
+            } => {
+                let above = self.compare_range(value, low);
+                let below = self.compare_range(value, high);
+                if *inclusive {
+                    matches!(above, Some(Ordering::Greater | Ordering::Equal))
+                        && matches!(below, Some(Ordering::Less | Ordering::Equal))
+                } else {
+                    above == Some(Ordering::Greater) && below == Some(Ordering::Less)
                 }
-                false
             }
-            Query::Contains(substr) => {
-                if let Some(v) = value.and_then(|v| v.as_str()) {
-                    return v.contains(substr);
-                }
-                false
+            Query::Contains(substr) => value
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| v.contains(substr.as_str())),
+            Query::IContains(substr) => value
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| v.to_lowercase().contains(&substr.to_lowercase())),
+            Query::Matches(pattern) => {
+                value
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|v| match self.regexes.get(pattern) {
+                        Some(regex) => regex.is_match(v),
+                        None => Regex::new(pattern).is_ok_and(|regex| regex.is_match(v)),
+                    })
             }
+            Query::Fuzzy {
+                value: expected,
+                max_distance,
+            } => value
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| within_distance(v, expected, *max_distance)),
+            Query::JsonPath(expr) => value.is_some_and(|v| match self.json_paths.get(expr) {
+                Some(path) => path.matches(v),
+                None => JsonPath::parse(expr).is_ok_and(|path| path.matches(v)),
+            }),
+            Query::StartsWith(prefix) => value
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| v.starts_with(prefix.as_str())),
+            Query::EndsWith(suffix) => value
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| v.ends_with(suffix.as_str())),
             Query::In(values) => {
                 if let Some(v) = value {
                     return values.contains(v);
@@ -316,12 +3332,393 @@ where
                 }
                 true
             }
+            Query::ArrayContains(element) => value
+                .and_then(|v| v.as_array())
+                .is_some_and(|items| items.contains(element)),
+            Query::ArrayAnyIn(elements) => value
+                .and_then(|v| v.as_array())
+                .is_some_and(|items| items.iter().any(|item| elements.contains(item))),
+            Query::ArraySize(size) => value
+                .and_then(|v| v.as_array())
+                .is_some_and(|items| items.len() == *size),
+            Query::Exists(expected) => value.is_some() == *expected,
+            Query::IsNull => value.is_some_and(|v| v.is_null()),
+            Query::Near { .. } | Query::WithinBox { .. } => crate::geo::matches(value, query),
+        }
+    }
+}
+
+/// Whether `a` and `b` are at most `max` edits apart, ignoring case
+fn within_distance(a: &str, b: &str, max: usize) -> bool {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return false;
+    }
+
+    // One row of the Levenshtein matrix at a time
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+        if row.iter().min().is_some_and(|&least| least > max) {
+            return false;
+        }
+    }
+    row[b.len()] <= max
+}
+
+/// A query returning only some fields of each document
+///
+/// Created by [`QueryBuilder::select`].
+#[derive(Debug, Clone)]
+pub struct Selection<T> {
+    query: QueryBuilder<T>,
+    fields: Vec<String>,
+}
+
+impl<T> Selection<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Execute the query and deserialize the selected fields into `P`
+    ///
+    /// Filters, sort, skip and limit apply as in [`QueryBuilder::exec`].
+    /// Selected fields missing from a document are left out, so `P` may use
+    /// `Option` or `#[serde(default)]` for them.
+    pub async fn exec<P: DeserializeOwned>(&self, db: &TormDb) -> Result<Vec<P>> {
+        let db: &TormDb = &db.reader();
+        let query = QueryBuilder {
+            projection: (!self.query.sorts_by_key()).then(|| self.remote_fields()),
+            ..self.query.clone_query()
+        };
+
+        let wanted = query.wanted();
+        let mut documents = Vec::new();
+        if wanted != Some(0) {
+            query
+                .scan_matching(db, |_, _, json_doc| {
+                    documents.push((self.project(&json_doc), json_doc));
+                    enough(documents.len(), wanted)
+                })
+                .await?;
+        }
+        query.sort_matches(&mut documents);
+
+        documents
+            .into_iter()
+            .map(|(projected, _)| projected)
+            .skip(query.skip.unwrap_or(0))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(|projected| Ok(serde_json::from_value(projected)?))
+            .collect()
+    }
+
+    /// Get the serializable form of this query, including the selection
+    pub fn to_spec(&self) -> QuerySpec {
+        QuerySpec {
+            select: Some(self.fields.clone()),
+            ..self.query.to_spec()
+        }
+    }
+
+    /// Fields to keep in ToonStore: the selection plus the roots of every
+    /// filtered and sorted field
+    fn remote_fields(&self) -> Vec<String> {
+        let mut fields = self.fields.clone();
+        let mut keep = |field: &str| {
+            let root = crate::path::root(field);
+            if !fields.iter().any(|f| f == root) {
+                fields.push(root.to_string());
+            }
+        };
+        for filter in &self.query.filters {
+            filter.for_each_condition(&mut |field, _| keep(field));
+        }
+        if let Some((SortBy::Field(field), _)) = &self.query.sort {
+            keep(field);
+        }
+        fields
+    }
+
+    /// Cut a document down to the selected fields
+    fn project(&self, doc: &serde_json::Value) -> serde_json::Value {
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|field| Some((field.clone(), doc.get(field)?.clone())))
+            .collect();
+        serde_json::Value::Object(fields)
+    }
+}
+
+/// State of a [`QueryBuilder::exec_stream`] scan
+struct ScanStream<T> {
+    query: QueryBuilder<T>,
+    db: TormDb,
+    source: Option<KeySource>,
+    buffer: VecDeque<T>,
+    stats: QueryStats,
+    started: Instant,
+    skipped: usize,
+    yielded: usize,
+    done: bool,
+}
+
+impl<T> ScanStream<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Scan the next batch of keys into the buffer, marking the end
+    async fn next_batch(&mut self) -> Result<()> {
+        let query = &self.query;
+        let limit = query.limit.unwrap_or(usize::MAX);
+        if self.yielded >= limit {
+            self.done = true;
+            return Ok(());
+        }
+
+        let mut conn = self.db.connection().clone();
+        if self.source.is_none() {
+            query.validate()?;
+            self.source = Some(query.key_source(&self.db).await?);
+        }
+        let Some(source) = self.source.as_mut() else {
+            return Ok(());
+        };
+        let Some(keys) = source.next_batch(&mut conn).await? else {
+            self.done = true;
+            return Ok(());
+        };
+
+        self.stats.scanned += keys.len();
+        let chunks: Vec<Vec<String>> = keys
+            .chunks(query.fetch_batch)
+            .map(<[String]>::to_vec)
+            .collect();
+        let documents: Vec<Vec<(String, Vec<u8>)>> = stream::iter(chunks)
+            .map(|chunk| {
+                let mut conn = conn.clone();
+                async move { query.fetch(&mut conn, &chunk).await }
+            })
+            .buffered(query.fetch_concurrency)
+            .try_collect()
+            .await?;
+
+        for (key, v) in documents.into_iter().flatten() {
+            let mut on_match = |_: &str, v: &[u8], _| {
+                if let Ok(doc) = query.codec.decode::<T>(v) {
+                    if self.skipped < query.skip.unwrap_or(0) {
+                        self.skipped += 1;
+                    } else if self.yielded < limit {
+                        self.yielded += 1;
+                        self.buffer.push_back(doc);
+                    }
+                }
+                ControlFlow::Continue(())
+            };
+            let _ = query.visit(&key, &v, &mut self.stats, &mut on_match);
+        }
+        if let Some(hook) = query.after_load {
+            self.buffer.iter_mut().for_each(hook);
+        }
+
+        self.stats.elapsed = self.started.elapsed();
+        query.check_budget(&self.stats)
+    }
+}
+
+/// A query with [`Param`] placeholders, executed with different values
+///
+/// Created by [`QueryBuilder::prepare`].
+#[derive(Debug, Clone)]
+pub struct PreparedQuery<T> {
+    query: QueryBuilder<T>,
+    params: Vec<String>,
+}
+
+impl<T> PreparedQuery<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Get the placeholder names, in order of first appearance
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Build the query with every placeholder replaced by its value
+    ///
+    /// Bound filters are checked against the collection's schema like
+    /// [`QueryBuilder::filter`] does. A missing or unknown name fails
+    /// with [`Error::InvalidQuery`].
+    pub fn bind<K>(
+        &self,
+        bindings: impl IntoIterator<Item = (K, serde_json::Value)>,
+    ) -> Result<QueryBuilder<T>>
+    where
+        K: Into<String>,
+    {
+        let bindings: HashMap<String, serde_json::Value> = bindings
+            .into_iter()
+            .map(|(name, value)| (Param::new(name).0, value))
+            .collect();
+        if let Some(name) = bindings.keys().find(|name| !self.params.contains(name)) {
+            return Err(Error::InvalidQuery(format!(
+                "unknown parameter ':{}'",
+                name
+            )));
+        }
+        if let Some(name) = self
+            .params
+            .iter()
+            .find(|name| !bindings.contains_key(*name))
+        {
+            return Err(Error::InvalidQuery(format!(
+                "missing value for parameter ':{}'",
+                name
+            )));
+        }
+
+        let mut query = self.query.clone_query();
+        for mut filter in std::mem::take(&mut query.filters) {
+            filter.for_each_condition_mut(&mut |condition| {
+                for value in condition.values_mut() {
+                    if let Some(bound) = param_name(value).and_then(|name| bindings.get(name)) {
+                        *value = bound.clone();
+                    }
+                }
+            });
+            query = query.where_filter(filter);
         }
+        Ok(query)
+    }
+
+    /// Bind the placeholders and execute the query
+    pub async fn exec<K>(
+        &self,
+        db: &TormDb,
+        bindings: impl IntoIterator<Item = (K, serde_json::Value)>,
+    ) -> Result<Vec<T>>
+    where
+        K: Into<String>,
+    {
+        self.bind(bindings)?.exec(db).await
+    }
+}
+
+/// Matching documents grouped by the value of a field
+///
+/// Created by [`QueryBuilder::group_by`].
+#[derive(Debug, Clone)]
+pub struct GroupBy<T> {
+    query: QueryBuilder<T>,
+    field: String,
+}
+
+impl<T> GroupBy<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Compute `agg` for every group in a single scan
+    ///
+    /// Groups are keyed by the field's value: strings as they are, other
+    /// values as JSON. Documents without the field fall into `"null"`; for
+    /// dot paths leading to several values, the first one is used.
+    pub async fn aggregate(
+        &self,
+        agg: Agg,
+        db: &TormDb,
+    ) -> Result<BTreeMap<String, serde_json::Value>> {
+        let db: &TormDb = &db.reader();
+        let mut groups: BTreeMap<String, Accumulator> = BTreeMap::new();
+        self.query
+            .scan_matching(db, |_, _, json_doc| {
+                let key = match crate::path::resolve(&json_doc, &self.field).first() {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                    None => serde_json::Value::Null.to_string(),
+                };
+                groups.entry(key).or_default().add(&agg, &json_doc);
+                ControlFlow::Continue(())
+            })
+            .await?;
+
+        Ok(groups
+            .into_iter()
+            .map(|(key, acc)| (key, acc.finish(&agg)))
+            .collect())
+    }
+}
+
+/// Positions of 1-based `page` among `total` results, empty past the end
+fn page_window(page: usize, per_page: usize, total: usize) -> std::ops::Range<usize> {
+    let start = page.saturating_sub(1).saturating_mul(per_page).min(total);
+    let end = start.saturating_add(per_page).min(total);
+    start..end
+}
+
+/// Stop scanning once `found` matches reach the `wanted` number
+fn enough(found: usize, wanted: Option<usize>) -> ControlFlow<()> {
+    if wanted.is_some_and(|wanted| found >= wanted) {
+        ControlFlow::Break(())
+    } else {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Record scan progress on the current query span
+fn record_stats(span: &tracing::Span, stats: &QueryStats) {
+    span.record("scanned", stats.scanned);
+    span.record("matched", stats.matched);
+}
+
+fn datetime_value(time: chrono::DateTime<chrono::Utc>) -> serde_json::Value {
+    serde_json::Value::String(time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+}
+
+/// Parse an ISO-8601 date-time or date (as midnight UTC)
+fn parse_datetime(s: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&chrono::Utc));
+    }
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+/// Encode a page position as an opaque, URL-safe token
+fn encode_token(position: &(serde_json::Value, String)) -> String {
+    serde_json::to_vec(position)
+        .unwrap_or_default()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_token(token: &str) -> Result<(serde_json::Value, String)> {
+    let invalid = || Error::InvalidQuery(format!("invalid page token '{}'", token));
+    if !token.len().is_multiple_of(2) {
+        return Err(invalid());
     }
+    let bytes = (0..token.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(token.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    serde_json::from_slice(&bytes).map_err(|_| invalid())
 }
 
 /// Compare two JSON values for sorting
-fn compare_json_values(a: Option<&serde_json::Value>, b: Option<&serde_json::Value>) -> Ordering {
+pub(crate) fn compare_json_values(
+    a: Option<&serde_json::Value>,
+    b: Option<&serde_json::Value>,
+) -> Ordering {
     match (a, b) {
         (None, None) => Ordering::Equal,
         (None, Some(_)) => Ordering::Less,
@@ -367,6 +3764,59 @@ mod tests {
         assert_eq!(query.limit, Some(10));
     }
 
+    #[test]
+    fn test_explain_display() {
+        let explain = Explain {
+            source: "index",
+            indexes: vec!["team".to_string()],
+            pushdown: false,
+            scanned: 12,
+            fetched: 12,
+            matched: 3,
+            planning: Duration::from_millis(1),
+            elapsed: Duration::from_millis(4),
+        };
+        assert_eq!(
+            explain.to_string(),
+            "index on team: scanned 12 keys, fetched 12, matched 3 in 4ms (planning 1ms)"
+        );
+    }
+
+    #[test]
+    fn test_check_budget() {
+        let query = QueryBuilder::<serde_json::Value>::new("users")
+            .timeout(Duration::from_secs(1))
+            .max_scan(100);
+
+        let within = QueryStats {
+            scanned: 100,
+            fetched: 100,
+            matched: 3,
+            elapsed: Duration::from_millis(10),
+        };
+        assert!(query.check_budget(&within).is_ok());
+
+        let over_scan = QueryStats {
+            scanned: 101,
+            ..within
+        };
+        assert!(matches!(
+            query.check_budget(&over_scan),
+            Err(Error::QueryLimitExceeded { limit: QueryLimit::MaxScan(100), stats, .. })
+                if stats.scanned == 101
+        ));
+
+        let over_time = QueryStats {
+            elapsed: Duration::from_secs(2),
+            ..within
+        };
+        let err = query.check_budget(&over_time).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Query on 'users' exceeded its timeout of 1s: scanned 100 keys and matched 3 in 2s"
+        );
+    }
+
     #[test]
     fn test_query_operators() {
         let eq = Query::eq(42);
@@ -378,4 +3828,409 @@ mod tests {
         assert!(matches!(gte, Query::Gte(_)));
         assert!(matches!(contains, Query::Contains(_)));
     }
+
+    #[test]
+    fn test_page_token() {
+        let position = (serde_json::json!(42), "user:1".to_string());
+        let token = encode_token(&position);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(decode_token(&token).unwrap(), position);
+        assert!(decode_token("zz").is_err());
+        assert!(decode_token("abc").is_err());
+    }
+
+    #[test]
+    fn test_page_window() {
+        assert_eq!(page_window(1, 20, 45), 0..20);
+        assert_eq!(page_window(3, 20, 45), 40..45);
+        assert_eq!(page_window(4, 20, 45), 45..45);
+        assert_eq!(page_window(usize::MAX, 20, 45), 45..45);
+        assert_eq!(page_window(2, usize::MAX, 45), 45..45);
+    }
+
+    #[test]
+    fn test_cursor_token() {
+        let cursor = Cursor {
+            id: "42".to_string(),
+            position: Some(7.0),
+        };
+        assert_eq!(Cursor::from_token(&cursor.token()).unwrap(), cursor);
+        assert_eq!(
+            Cursor::from_token(&Cursor::from("a").token()).unwrap(),
+            Cursor::after_id("a")
+        );
+    }
+
+    #[test]
+    fn test_string_ranges() {
+        let doc = serde_json::json!({
+            "created_at": "2024-03-01T12:00:00+02:00",
+            "name": "bob",
+            "active": true,
+        });
+        let plain = QueryBuilder::<serde_json::Value>::new("user");
+        assert!(!plain.matches_filter(&doc, "name", &Query::gt("alice")));
+        assert!(plain.matches_filter(&doc, "created_at", &Query::gt("2024-01-01")));
+
+        let query = plain.string_ranges();
+        assert!(query.matches_filter(&doc, "created_at", &Query::gt("2024-01-01")));
+        assert!(query.matches_filter(&doc, "created_at", &Query::lt("2024-03-01T11:00:00Z")));
+        assert!(query.matches_filter(&doc, "name", &Query::gt("alice")));
+        assert!(query.matches_filter(&doc, "active", &Query::gte(false)));
+        assert!(!query.matches_filter(&doc, "name", &Query::lt(5)));
+    }
+
+    #[test]
+    fn test_date_filters() {
+        use chrono::{TimeZone, Utc};
+
+        let now = Utc::now();
+        let doc = serde_json::json!({
+            "created_at": "2024-03-01T12:00:00+02:00",
+            "seen_at": now - chrono::Duration::minutes(5),
+            "stale_at": now - chrono::Duration::hours(2),
+        });
+        let query = QueryBuilder::<serde_json::Value>::new("user");
+        let march = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+
+        assert!(query.matches_filter(&doc, "created_at", &Query::after(march)));
+        assert!(!query.matches_filter(&doc, "created_at", &Query::before(march)));
+        assert!(query.matches_filter(&doc, "created_at", &Query::lt("2024-03-01T10:30:00Z")));
+
+        let recent = query.within_last("seen_at", Duration::from_secs(3600));
+        assert!(recent.matches_filters(&doc));
+        let recent = QueryBuilder::<serde_json::Value>::new("user")
+            .within_last("stale_at", Duration::from_secs(3600));
+        assert!(!recent.matches_filters(&doc));
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection = QueryBuilder::<serde_json::Value>::new("user")
+            .filter("address.city", Query::eq("Paris"))
+            .sort_by("age", SortOrder::Asc)
+            .select(&["id", "name"]);
+
+        assert_eq!(selection.remote_fields(), ["id", "name", "address", "age"]);
+        let doc = serde_json::json!({ "id": "1", "name": "Ann", "age": 30, "bio": "..." });
+        assert_eq!(
+            selection.project(&doc),
+            serde_json::json!({ "id": "1", "name": "Ann" })
+        );
+    }
+
+    #[test]
+    fn test_sort_by_key() {
+        let query = QueryBuilder::<serde_json::Value>::new("line").sort_by_key(
+            |line| line["price"].as_f64().unwrap_or(0.0) * line["quantity"].as_f64().unwrap_or(0.0),
+            SortOrder::Desc,
+        );
+        let mut lines: Vec<_> = [(2.0, 5), (30.0, 1), (4.0, 2)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (price, quantity))| {
+                (
+                    i,
+                    serde_json::json!({ "price": price, "quantity": quantity }),
+                )
+            })
+            .collect();
+
+        query.sort_matches(&mut lines);
+        assert_eq!(lines.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [1, 0, 2]);
+        assert!(query.wanted().is_none());
+        assert!(query.limit(1).to_spec().sort.is_none());
+    }
+
+    #[test]
+    fn test_nulls_ordering() {
+        let mut matches: Vec<_> = [
+            serde_json::json!({ "age": 30 }),
+            serde_json::json!({}),
+            serde_json::json!({ "age": null }),
+            serde_json::json!({ "age": 20 }),
+        ]
+        .into_iter()
+        .map(|doc| ((), doc))
+        .collect();
+        let ages = |matches: &[((), serde_json::Value)]| -> Vec<_> {
+            matches
+                .iter()
+                .map(|(_, doc)| doc.get("age").cloned())
+                .collect()
+        };
+
+        let query = QueryBuilder::<serde_json::Value>::new("user")
+            .sort_by("age", SortOrder::Desc)
+            .nulls_first();
+        query.sort_matches(&mut matches);
+        assert_eq!(
+            ages(&matches)[2..],
+            [Some(serde_json::json!(30)), Some(serde_json::json!(20))]
+        );
+
+        let query = QueryBuilder::<serde_json::Value>::new("user")
+            .sort_by("age", SortOrder::Asc)
+            .nulls_last();
+        query.sort_matches(&mut matches);
+        assert_eq!(
+            ages(&matches)[..2],
+            [Some(serde_json::json!(20)), Some(serde_json::json!(30))]
+        );
+        assert_eq!(query.to_spec().sort.unwrap().nulls_last, Some(true));
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn test_collation() {
+        let mut matches: Vec<_> = ["Zoe", "Émile", "eric", "Ana"]
+            .into_iter()
+            .map(|name| ((), serde_json::json!({ "name": name })))
+            .collect();
+
+        let query = QueryBuilder::<serde_json::Value>::new("user").sort_by("name", SortOrder::Asc);
+        query.sort_matches(&mut matches);
+        let names: Vec<_> = matches.iter().map(|(_, doc)| doc["name"].clone()).collect();
+        assert_eq!(names, ["Ana", "Zoe", "eric", "Émile"]);
+
+        let query = query.collate("fr");
+        query.sort_matches(&mut matches);
+        let names: Vec<_> = matches.iter().map(|(_, doc)| doc["name"].clone()).collect();
+        assert_eq!(names, ["Ana", "Émile", "eric", "Zoe"]);
+        assert_eq!(
+            query.to_spec().sort.unwrap().collation.as_deref(),
+            Some("fr")
+        );
+
+        let invalid = query.collate("not a locale");
+        assert!(matches!(invalid.validate(), Err(Error::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn test_wanted() {
+        let query = QueryBuilder::<serde_json::Value>::new("user");
+        assert_eq!(query.wanted(), None);
+
+        let query = query.skip(5).limit(10);
+        assert_eq!(query.wanted(), Some(15));
+        assert!(enough(14, Some(15)).is_continue());
+        assert!(enough(15, Some(15)).is_break());
+
+        let sorted = query.sort_by("name", SortOrder::Asc);
+        assert_eq!(sorted.wanted(), None);
+    }
+
+    #[test]
+    fn test_spec_round_trip() {
+        let selection = QueryBuilder::<serde_json::Value>::new("user")
+            .filter("age", Query::gte(18))
+            .or(|g| {
+                g.filter("role", Query::eq("admin"))
+                    .filter("vip", Query::eq(true))
+            })
+            .sort_by("name", SortOrder::Desc)
+            .skip(5)
+            .limit(10)
+            .select(&["id", "name"]);
+        let spec = selection.to_spec();
+
+        let json = serde_json::to_value(&spec).unwrap();
+        assert_eq!(
+            json["sort"],
+            serde_json::json!({ "field": "name", "order": "desc" })
+        );
+        assert_eq!(
+            json["filters"][0],
+            serde_json::json!({ "field": ["age", { "gte": 18 }] })
+        );
+        let parsed: QuerySpec = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, spec);
+
+        let rebuilt =
+            QueryBuilder::<serde_json::Value>::from_spec("user", &parsed).select(&["id", "name"]);
+        assert_eq!(rebuilt.to_spec(), spec);
+
+        let empty: QuerySpec = serde_json::from_str("{}").unwrap();
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_filter_checked_against_schema() {
+        let schema = crate::Schema::new().field("age", crate::FieldType::Number);
+        crate::Schema::cache("schema_checked", Some(schema));
+
+        let ok =
+            QueryBuilder::<serde_json::Value>::new("schema_checked").filter("age", Query::gt(18));
+        assert!(ok.validate().is_ok());
+
+        let typo = ok.filter("agee", Query::gt(18));
+        assert!(matches!(typo.validate(), Err(Error::InvalidQuery(msg)) if msg.contains("agee")));
+    }
+
+    #[test]
+    fn test_grouped_filters() {
+        let query = QueryBuilder::<serde_json::Value>::new("ticket")
+            .or(|g| {
+                g.filter("status", Query::eq("new"))
+                    .filter("status", Query::eq("pending"))
+            })
+            .filter("age", Query::gte(18))
+            .not(|g| g.filter("spam", Query::eq(true)));
+
+        let doc = |status: &str, age: u32, spam: bool| serde_json::json!({ "status": status, "age": age, "spam": spam });
+        assert!(query.matches_filters(&doc("new", 20, false)));
+        assert!(query.matches_filters(&doc("pending", 18, false)));
+        assert!(!query.matches_filters(&doc("closed", 20, false)));
+        assert!(!query.matches_filters(&doc("new", 17, false)));
+        assert!(!query.matches_filters(&doc("new", 20, true)));
+    }
+
+    #[test]
+    fn test_dot_path_filters() {
+        let doc = serde_json::json!({
+            "address": { "city": "Paris" },
+            "items": [{ "sku": "a" }, { "sku": "b" }],
+        });
+        let query = QueryBuilder::<serde_json::Value>::new("order");
+
+        assert!(query.matches_filter(&doc, "address.city", &Query::eq("Paris")));
+        assert!(query.matches_filter(&doc, "items[*].sku", &Query::eq("b")));
+        assert!(!query.matches_filter(&doc, "items[*].sku", &Query::ne("b")));
+        assert!(query.matches_filter(&doc, "items[*].sku", &Query::ne("c")));
+        assert!(!query.matches_filter(&doc, "address.zip", &Query::eq("75001")));
+    }
+
+    #[test]
+    fn test_string_operators() {
+        let doc = serde_json::json!({ "name": "Élodie Martin", "age": 30 });
+        let query = QueryBuilder::<serde_json::Value>::new("user");
+
+        assert!(query.matches_filter(&doc, "name", &Query::starts_with("Élo")));
+        assert!(query.matches_filter(&doc, "name", &Query::ends_with("Martin")));
+        assert!(!query.matches_filter(&doc, "name", &Query::ends_with("Élodie")));
+        assert!(query.matches_filter(&doc, "name", &Query::icontains("éLODIE")));
+        assert!(!query.matches_filter(&doc, "age", &Query::starts_with("3")));
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let doc = serde_json::json!({ "name": "Élodie", "age": 30 });
+        let query = QueryBuilder::<serde_json::Value>::new("user");
+
+        assert!(query.matches_filter(&doc, "name", &Query::fuzzy("élodie", 0)));
+        assert!(query.matches_filter(&doc, "name", &Query::fuzzy("Elodie", 1)));
+        assert!(query.matches_filter(&doc, "name", &Query::fuzzy("Elodei", 3)));
+        assert!(!query.matches_filter(&doc, "name", &Query::fuzzy("Elodei", 2)));
+        assert!(!query.matches_filter(&doc, "name", &Query::fuzzy("Mélodie Martin", 2)));
+        assert!(!query.matches_filter(&doc, "age", &Query::fuzzy("30", 0)));
+    }
+
+    #[test]
+    fn test_regex_filter() {
+        let doc = serde_json::json!({ "line": "ERROR [db] timeout after 30s" });
+        let query = QueryBuilder::<serde_json::Value>::new("log")
+            .filter("line", Query::matches(r"^ERROR \[\w+\]"));
+        assert!(query.validate().is_ok());
+        assert!(query.matches_filters(&doc));
+
+        let invalid = query.filter("line", Query::matches("("));
+        assert!(matches!(invalid.validate(), Err(Error::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn test_prepared_query() {
+        let doc = serde_json::json!({ "age": 30, "role": "admin" });
+        let prepared = QueryBuilder::<serde_json::Value>::new("user")
+            .filter("age", Query::gte(Param::new(":min_age")))
+            .or(|g| {
+                g.filter("role", Query::in_values(vec![Param::new("role").into()]))
+                    .filter("age", Query::lt(Param::new("min_age")))
+            })
+            .prepare();
+        assert_eq!(prepared.params(), ["min_age", "role"]);
+        assert!(matches!(
+            prepared.query.validate(),
+            Err(Error::InvalidQuery(_))
+        ));
+
+        let bind = |min_age: i64| {
+            prepared
+                .bind([(":min_age", min_age.into()), ("role", "admin".into())])
+                .unwrap()
+        };
+        assert!(bind(18).validate().is_ok());
+        assert!(bind(18).matches_filters(&doc));
+        assert!(!bind(40).matches_filters(&doc));
+
+        assert!(prepared.bind([("min_age", 18.into())]).is_err());
+        assert!(prepared
+            .bind([
+                ("min_age", 18.into()),
+                ("role", "x".into()),
+                ("x", 1.into())
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn test_json_path_filter() {
+        let doc = serde_json::json!({
+            "items": [{ "sku": "a", "qty": 1 }, { "sku": "b", "qty": 5 }],
+        });
+        let query = QueryBuilder::<serde_json::Value>::new("order")
+            .filter("items", Query::json_path("$[?(@.sku == 'b' && @.qty > 2)]"));
+        assert!(query.validate().is_ok());
+        assert!(query.matches_filters(&doc));
+
+        let none = QueryBuilder::<serde_json::Value>::new("order")
+            .filter("items", Query::json_path("$[?(@.sku == 'a' && @.qty > 2)]"));
+        assert!(!none.matches_filters(&doc));
+        assert!(!query.matches_filter(&doc, "missing", &Query::json_path("$")));
+
+        let invalid = query.filter("items", Query::json_path("$[?(@.qty >)]"));
+        assert!(matches!(invalid.validate(), Err(Error::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn test_between() {
+        let doc = serde_json::json!({ "age": 18, "day": "2024-06-01" });
+        let query = QueryBuilder::<serde_json::Value>::new("user");
+
+        assert!(query.matches_filter(&doc, "age", &Query::between(18, 65)));
+        assert!(!query.matches_filter(&doc, "age", &Query::between_exclusive(18, 65)));
+        assert!(query.matches_filter(&doc, "day", &Query::between("2024-01-01", "2024-12-31")));
+        assert!(!query.matches_filter(&doc, "day", &Query::between("2024-07-01", "2024-12-31")));
+    }
+
+    #[test]
+    fn test_exists_and_null() {
+        let doc = serde_json::json!({ "nickname": null, "name": "Ann" });
+        let query = QueryBuilder::<serde_json::Value>::new("user");
+
+        assert!(query.matches_filter(&doc, "nickname", &Query::exists()));
+        assert!(query.matches_filter(&doc, "nickname", &Query::is_null()));
+        assert!(query.matches_filter(&doc, "email", &Query::missing()));
+        assert!(!query.matches_filter(&doc, "email", &Query::is_null()));
+        assert!(!query.matches_filter(&doc, "name", &Query::is_null()));
+        assert_eq!(
+            serde_json::to_value(Query::is_null()).unwrap(),
+            serde_json::json!("isnull")
+        );
+    }
+
+    #[test]
+    fn test_array_operators() {
+        let doc = serde_json::json!({ "tags": ["rust", "db"], "name": "torm" });
+        let query = QueryBuilder::<serde_json::Value>::new("repo");
+
+        assert!(query.matches_filter(&doc, "tags", &Query::array_contains("rust")));
+        assert!(!query.matches_filter(&doc, "tags", &Query::array_contains("go")));
+        assert!(query.matches_filter(
+            &doc,
+            "tags",
+            &Query::array_any_in(vec!["go".into(), "db".into()])
+        ));
+        assert!(query.matches_filter(&doc, "tags", &Query::array_size(2)));
+        assert!(!query.matches_filter(&doc, "name", &Query::array_size(4)));
+    }
 }
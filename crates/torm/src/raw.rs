@@ -0,0 +1,122 @@
+//! Custom commands that stay inside a handle's namespace
+//!
+//! [`TormDb::raw`] builds a command whose key arguments get the handle's
+//! prefix, so commands TORM has no API for can't read or overwrite keys of
+//! another environment or tenant.
+
+use crate::{Result, TormDb};
+use redis::{FromRedisValue, ToRedisArgs};
+
+/// A Redis command built with [`TormDb::raw`]
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::TormDb;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let acme = db.tenant("acme");
+/// // Increments field `home` of `tenant:acme:visits`
+/// let visits: i64 = acme.raw("HINCRBY").key("visits").arg("home").arg(1).query().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RawCommand {
+    db: TormDb,
+    cmd: redis::Cmd,
+}
+
+impl RawCommand {
+    /// Append a key, under the handle's prefix
+    pub fn key(mut self, key: &str) -> Self {
+        self.cmd.arg(self.db.key(key));
+        self
+    }
+
+    /// Append several keys, each under the handle's prefix
+    pub fn keys<I, K>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        for key in keys {
+            self.cmd.arg(self.db.key(key.as_ref()));
+        }
+        self
+    }
+
+    /// Append an argument that is not a key, as it is
+    pub fn arg<A: ToRedisArgs>(mut self, arg: A) -> Self {
+        self.cmd.arg(arg);
+        self
+    }
+
+    /// Send the command and convert its reply
+    pub async fn query<T: FromRedisValue>(self) -> Result<T> {
+        let mut conn = self.db.connection().clone();
+        Ok(self.cmd.query_async(&mut conn).await?)
+    }
+}
+
+impl TormDb {
+    /// Build a custom command that respects this handle's key prefix
+    ///
+    /// Pass keys with [`RawCommand::key`] and everything else with
+    /// [`RawCommand::arg`]; only keys get the prefix of
+    /// [`TormDb::with_prefix`] or [`TormDb::tenant`]. Replies containing
+    /// key names, such as those of `SCAN`, come back with the prefix.
+    pub fn raw(&self, command: &str) -> RawCommand {
+        RawCommand {
+            db: self.clone(),
+            cmd: redis::cmd(command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+    use async_trait::async_trait;
+    use redis::{Cmd, RedisResult, Value};
+
+    /// Replies with the arguments of each command
+    struct Echo;
+
+    #[async_trait]
+    impl Backend for Echo {
+        async fn command(&self, cmd: &Cmd) -> RedisResult<Value> {
+            Ok(Value::Array(
+                cmd.args_iter()
+                    .map(|arg| match arg {
+                        redis::Arg::Simple(bytes) => Value::BulkString(bytes.to_vec()),
+                        redis::Arg::Cursor => Value::Nil,
+                    })
+                    .collect(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_prefixes_keys() {
+        let db = TormDb::from_backend(Echo).tenant("acme");
+        let args: Vec<String> = db
+            .raw("SUNIONSTORE")
+            .key("out")
+            .keys(["a", "b"])
+            .arg("a")
+            .query()
+            .await
+            .unwrap();
+        assert_eq!(
+            args,
+            [
+                "SUNIONSTORE",
+                "tenant:acme:out",
+                "tenant:acme:a",
+                "tenant:acme:b",
+                "a"
+            ]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! Relation metadata and population
+//!
+//! Relations are registered per collection in ToonStore so that every client
+//! (including TORM Server) can resolve them. Population works on raw JSON
+//! documents and fetches related documents in batches.
+
+use crate::{Query, QueryBuilder, Result, TormDb};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// How two collections are related
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum RelationKind {
+    /// The document holds the related document's ID in `local_field`
+    BelongsTo {
+        /// Field holding the related ID
+        local_field: String,
+    },
+    /// Related documents hold this document's ID in `foreign_field`
+    HasMany {
+        /// Field on the related documents holding this document's ID
+        foreign_field: String,
+    },
+}
+
+/// A named relation from one collection to another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relation {
+    /// Name used in `include` lists and as the embedded field name
+    pub name: String,
+    /// Collection of the related documents
+    pub target: String,
+    /// Relation kind and key fields
+    #[serde(flatten)]
+    pub kind: RelationKind,
+}
+
+impl Relation {
+    /// Create a belongs-to relation (e.g. `post.author_id -> user`)
+    pub fn belongs_to(
+        name: impl Into<String>,
+        target: impl Into<String>,
+        local_field: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            target: target.into(),
+            kind: RelationKind::BelongsTo {
+                local_field: local_field.into(),
+            },
+        }
+    }
+
+    /// Create a has-many relation (e.g. `post <- comment.post_id`)
+    pub fn has_many(
+        name: impl Into<String>,
+        target: impl Into<String>,
+        foreign_field: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            target: target.into(),
+            kind: RelationKind::HasMany {
+                foreign_field: foreign_field.into(),
+            },
+        }
+    }
+
+    /// Store this relation for `collection`
+    pub async fn register(&self, db: &TormDb, collection: &str) -> Result<()> {
+        let mut conn = db.connection().clone();
+        redis::cmd("HSET")
+            .arg(db.key(&relations_key(collection)))
+            .arg(&self.name)
+            .arg(serde_json::to_string(self)?)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Load all relations registered for `collection`
+    pub async fn for_collection(db: &TormDb, collection: &str) -> Result<Vec<Relation>> {
+        let mut conn = db.connection().clone();
+        let raw: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(db.key(&relations_key(collection)))
+            .query_async(&mut conn)
+            .await?;
+
+        let mut relations = raw
+            .values()
+            .map(|v| serde_json::from_str(v))
+            .collect::<std::result::Result<Vec<Relation>, _>>()?;
+        relations.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(relations)
+    }
+}
+
+/// Number of owners whose has-many children are looked up at once
+const OWNER_BATCH: usize = 100;
+
+fn relations_key(collection: &str) -> String {
+    format!("torm:relations:{}", collection)
+}
+
+/// Embed related documents into `documents` for each relation in `include`
+///
+/// Belongs-to relations are resolved with one MGET per relation. Has-many
+/// relations look children up by foreign key, in batches of owners: from
+/// the foreign field's index when it is built, otherwise with a scan of
+/// the target collection filtered on the field. Unknown relation
+/// names return [`crate::Error::InvalidQuery`]. Returns the relations that
+/// were applied, in `include` order.
+#[tracing::instrument(name = "torm.populate", level = "info", skip(db, documents), fields(documents = documents.len()))]
+pub async fn populate(
+    db: &TormDb,
+    collection: &str,
+    documents: &mut [serde_json::Value],
+    include: &[&str],
+) -> Result<Vec<Relation>> {
+    if include.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let relations = Relation::for_collection(db, collection).await?;
+    let mut applied = Vec::with_capacity(include.len());
+
+    for name in include {
+        let relation = relations.iter().find(|r| r.name == *name).ok_or_else(|| {
+            crate::Error::InvalidQuery(format!(
+                "unknown relation '{}' on collection '{}'",
+                name, collection
+            ))
+        })?;
+
+        if !documents.is_empty() {
+            match &relation.kind {
+                RelationKind::BelongsTo { local_field } => {
+                    populate_belongs_to(db, relation, local_field, documents).await?
+                }
+                RelationKind::HasMany { foreign_field } => {
+                    populate_has_many(db, relation, foreign_field, documents).await?
+                }
+            }
+        }
+        applied.push(relation.clone());
+    }
+
+    Ok(applied)
+}
+
+async fn populate_belongs_to(
+    db: &TormDb,
+    relation: &Relation,
+    local_field: &str,
+    documents: &mut [serde_json::Value],
+) -> Result<()> {
+    let keys: Vec<String> = documents
+        .iter()
+        .map(|doc| match doc.get(local_field) {
+            Some(serde_json::Value::String(id)) => db.key(&format!("{}:{}", relation.target, id)),
+            Some(other) => db.key(&format!("{}:{}", relation.target, other)),
+            None => String::new(),
+        })
+        .collect();
+
+    let mut conn = db.connection().clone();
+    let values: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+
+    for (doc, value) in documents.iter_mut().zip(values) {
+        let related = value
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or(serde_json::Value::Null);
+        if let Some(obj) = doc.as_object_mut() {
+            obj.insert(relation.name.clone(), related);
+        }
+    }
+
+    Ok(())
+}
+
+async fn populate_has_many(
+    db: &TormDb,
+    relation: &Relation,
+    foreign_field: &str,
+    documents: &mut [serde_json::Value],
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    let owners: Vec<&serde_json::Value> = documents
+        .iter()
+        .filter_map(|doc| doc.get("id"))
+        .filter(|id| seen.insert(id_string(id)))
+        .collect();
+
+    let mut related = Vec::new();
+    for batch in owners.chunks(OWNER_BATCH) {
+        let values: Vec<serde_json::Value> = batch.iter().flat_map(|id| owner_values(id)).collect();
+        related.extend(fetch_children(db, relation, foreign_field, &values).await?);
+    }
+
+    let mut grouped: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    for doc in related {
+        if let Some(owner) = doc.get(foreign_field).map(id_string) {
+            grouped.entry(owner).or_default().push(doc);
+        }
+    }
+
+    for doc in documents.iter_mut() {
+        let children = doc
+            .get("id")
+            .map(id_string)
+            .and_then(|id| grouped.get(&id).cloned())
+            .unwrap_or_default();
+        if let Some(obj) = doc.as_object_mut() {
+            obj.insert(relation.name.clone(), serde_json::Value::Array(children));
+        }
+    }
+
+    Ok(())
+}
+
+/// Children whose foreign field is one of `owners`
+async fn fetch_children(
+    db: &TormDb,
+    relation: &Relation,
+    foreign_field: &str,
+    owners: &[serde_json::Value],
+) -> Result<Vec<serde_json::Value>> {
+    let Some(ids) = crate::index::find_ids(db, &relation.target, foreign_field, owners).await?
+    else {
+        return QueryBuilder::<serde_json::Value>::new(relation.target.as_str())
+            .filter(foreign_field, Query::in_values(owners.to_vec()))
+            .exec(db)
+            .await;
+    };
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let keys: Vec<String> = ids
+        .iter()
+        .map(|id| db.key(&format!("{}:{}", relation.target, id)))
+        .collect();
+    let mut conn = db.connection().clone();
+    let values: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+    Ok(values
+        .into_iter()
+        .flatten()
+        .filter_map(|v| serde_json::from_str(&v).ok())
+        .collect())
+}
+
+/// Values a child's foreign field may hold for an owner's ID: the ID
+/// itself and its string or integer form
+fn owner_values(id: &serde_json::Value) -> Vec<serde_json::Value> {
+    let mut values = vec![id.clone()];
+    match id {
+        serde_json::Value::String(s) => {
+            if let Ok(n) = s.parse::<i64>() {
+                values.push(n.into());
+            }
+        }
+        serde_json::Value::Number(n) => values.push(n.to_string().into()),
+        _ => {}
+    }
+    values
+}
+
+fn id_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owner_values() {
+        assert_eq!(
+            owner_values(&serde_json::json!("7")),
+            [serde_json::json!("7"), serde_json::json!(7)]
+        );
+        assert_eq!(
+            owner_values(&serde_json::json!(7)),
+            [serde_json::json!(7), serde_json::json!("7")]
+        );
+        assert_eq!(
+            owner_values(&serde_json::json!("ann")),
+            [serde_json::json!("ann")]
+        );
+    }
+
+    #[test]
+    fn test_relation_roundtrip() {
+        let relation = Relation::belongs_to("author", "user", "author_id");
+        let json = serde_json::to_value(&relation).unwrap();
+        assert_eq!(json["kind"], "belongs_to");
+        assert_eq!(json["local_field"], "author_id");
+
+        let back: Relation = serde_json::from_value(json).unwrap();
+        assert_eq!(back, relation);
+    }
+}
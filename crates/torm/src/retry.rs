@@ -0,0 +1,238 @@
+//! Retrying commands after transient failures
+//!
+//! A [`RetryPolicy`] attached with [`TormDb::with_retry`](crate::TormDb::with_retry)
+//! applies to every command sent through the handle's connection, so model
+//! reads, writes and scans all recover from the same dropped connections,
+//! timeouts and failovers.
+
+use rand::Rng;
+use redis::{Cmd, ErrorKind, RedisError};
+use std::time::Duration;
+
+/// Commands that have the same effect on the data when sent twice
+const IDEMPOTENT: &[&str] = &[
+    "DBSIZE",
+    "DEL",
+    "EXISTS",
+    "EXPIRE",
+    "GEOADD",
+    "GEOPOS",
+    "GEOSEARCH",
+    "GET",
+    "HDEL",
+    "HGET",
+    "HGETALL",
+    "HMGET",
+    "HSCAN",
+    "HSET",
+    "INFO",
+    "KEYS",
+    "LLEN",
+    "LRANGE",
+    "MGET",
+    "MSET",
+    "PERSIST",
+    "PEXPIRE",
+    "PFADD",
+    "PFCOUNT",
+    "PING",
+    "PTTL",
+    "SADD",
+    "SCAN",
+    "SCARD",
+    "SET",
+    "SISMEMBER",
+    "SMEMBERS",
+    "SREM",
+    "SSCAN",
+    "STRLEN",
+    "TTL",
+    "TYPE",
+    "UNLINK",
+    "XLEN",
+    "XRANGE",
+    "XREVRANGE",
+    "ZADD",
+    "ZCARD",
+    "ZCOUNT",
+    "ZRANGE",
+    "ZRANGEBYSCORE",
+    "ZREM",
+    "ZREVRANGE",
+    "ZSCAN",
+    "ZSCORE",
+];
+
+/// How commands are retried after transient network errors
+///
+/// Attempt `n` waits `initial_backoff * 2^n`, capped at `max_backoff`.
+/// With jitter, the wait is instead picked at random up to that delay, so
+/// clients that failed together don't retry together.
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{RetryPolicy, TormDb};
+/// # use std::time::Duration;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let db = TormDb::connect("redis://localhost:6379").await?.with_retry(
+///     RetryPolicy::new()
+///         .max_attempts(5)
+///         .initial_backoff(Duration::from_millis(20))
+///         .idempotent_only(true),
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    idempotent_only: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            jitter: true,
+            idempotent_only: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy making 3 attempts, backing off from 50ms, with jitter
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many times a command is sent in total, at least once
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry; it doubles on every attempt
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the longest delay between two attempts
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Randomize delays between zero and the backoff
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Only retry commands that are safe to send twice
+    ///
+    /// A command that timed out may still have run. With this set, scripts,
+    /// counters and other commands whose repeat changes the data fail on
+    /// the first error instead; pipelines are retried only if every
+    /// command in them is safe.
+    pub fn idempotent_only(mut self, idempotent_only: bool) -> Self {
+        self.idempotent_only = idempotent_only;
+        self
+    }
+
+    /// Whether a command that failed with `error` on attempt `attempt`
+    /// (from 0) is sent again
+    pub(crate) fn should_retry<'a>(
+        &self,
+        attempt: u32,
+        error: &RedisError,
+        mut commands: impl Iterator<Item = &'a Cmd>,
+    ) -> bool {
+        attempt + 1 < self.max_attempts
+            && is_transient(error)
+            && (!self.idempotent_only || commands.all(is_idempotent))
+    }
+
+    /// How long to wait before retrying after attempt `attempt` (from 0)
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        if self.jitter && !delay.is_zero() {
+            delay.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+/// Errors that may not happen again on another try
+fn is_transient(error: &RedisError) -> bool {
+    error.is_io_error()
+        || error.is_timeout()
+        || error.is_connection_dropped()
+        || error.is_connection_refusal()
+        || matches!(
+            error.kind(),
+            ErrorKind::TryAgain
+                | ErrorKind::BusyLoadingError
+                | ErrorKind::ClusterDown
+                | ErrorKind::MasterDown
+                | ErrorKind::ReadOnly
+        )
+}
+
+fn is_idempotent(cmd: &Cmd) -> bool {
+    match cmd.args_iter().next() {
+        Some(redis::Arg::Simple(name)) => IDEMPOTENT
+            .iter()
+            .any(|known| known.as_bytes().eq_ignore_ascii_case(name)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io_error() -> RedisError {
+        std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset").into()
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(10))
+            .max_backoff(Duration::from_millis(50))
+            .jitter(false);
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(40));
+        assert_eq!(policy.backoff(3), Duration::from_millis(50));
+
+        let jittered = policy.jitter(true);
+        assert!((0..10).all(|_| jittered.backoff(3) <= Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new().max_attempts(3).idempotent_only(true);
+        let get = redis::cmd("get");
+        let incr = redis::cmd("INCR");
+        let wrong_type = RedisError::from((ErrorKind::ResponseError, "WRONGTYPE"));
+
+        assert!(policy.should_retry(0, &io_error(), [&get].into_iter()));
+        assert!(!policy.should_retry(2, &io_error(), [&get].into_iter()));
+        assert!(!policy.should_retry(0, &wrong_type, [&get].into_iter()));
+        assert!(!policy.should_retry(0, &io_error(), [&get, &incr].into_iter()));
+        assert!(policy
+            .idempotent_only(false)
+            .should_retry(0, &io_error(), [&incr].into_iter()));
+    }
+}
@@ -0,0 +1,115 @@
+//! Cursor-based key iteration
+
+use crate::pool::Node;
+use crate::{Connection, Result};
+
+/// Default number of keys requested per SCAN call
+pub(crate) const DEFAULT_SCAN_COUNT: usize = 100;
+
+/// Iterates over keys matching a pattern in SCAN batches
+///
+/// Unlike `KEYS`, each batch is a short, non-blocking command, so large
+/// keyspaces can be walked without stalling the server. On a cluster,
+/// every primary is scanned in turn.
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{KeyScanner, TormDb};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// let mut conn = db.connection().clone();
+/// let mut scanner = KeyScanner::new("user:*").count(500);
+/// while let Some(keys) = scanner.next_batch(&mut conn).await? {
+///     println!("{} keys", keys.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct KeyScanner {
+    pattern: String,
+    count: usize,
+    cursor: u64,
+    done: bool,
+    /// Nodes left to scan, the current one last; found on the first batch
+    nodes: Option<Vec<Node>>,
+}
+
+/// Escape the glob characters of `s`, so it only matches itself in a
+/// SCAN pattern
+pub(crate) fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl KeyScanner {
+    /// Scan keys matching `pattern`
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            count: DEFAULT_SCAN_COUNT,
+            cursor: 0,
+            done: false,
+            nodes: None,
+        }
+    }
+
+    /// Set how many keys are requested per SCAN call (a hint to the server)
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count.max(1);
+        self
+    }
+
+    /// Fetch the next batch of keys, or `None` once the cursor is exhausted
+    ///
+    /// A batch may be empty even when more keys remain.
+    pub async fn next_batch(&mut self, conn: &mut Connection) -> Result<Option<Vec<String>>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        if self.nodes.is_none() {
+            let mut nodes = conn.primaries().await?;
+            nodes.reverse();
+            self.nodes = Some(nodes);
+        }
+        let nodes = self.nodes.get_or_insert_with(Vec::new);
+        let Some(node) = nodes.last() else {
+            self.done = true;
+            return Ok(None);
+        };
+
+        let mut scan = redis::cmd("SCAN");
+        scan.arg(self.cursor)
+            .arg("MATCH")
+            .arg(&self.pattern)
+            .arg("COUNT")
+            .arg(self.count);
+        let (cursor, keys): (u64, Vec<String>) = conn.query_node(&scan, node).await?;
+
+        self.cursor = cursor;
+        if cursor == 0 {
+            nodes.pop();
+            self.done = nodes.is_empty();
+        }
+
+        Ok(Some(keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_pattern() {
+        assert_eq!(escape_pattern("acme"), "acme");
+        assert_eq!(escape_pattern("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+    }
+}
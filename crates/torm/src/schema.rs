@@ -0,0 +1,244 @@
+//! Collection schemas for validating query filters
+//!
+//! A [`Schema`] lists the top-level fields of a collection and their JSON
+//! types. Registered schemas are stored in ToonStore under
+//! `torm:schema:{collection}` and cached in the process, so query builders
+//! can reject filters on unknown fields or with mismatched values up front
+//! instead of silently matching nothing.
+
+use crate::{Error, Query, Result, TormDb};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// JSON type of a schema field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// JSON string (also dates)
+    String,
+    /// JSON number
+    Number,
+    /// JSON boolean
+    Boolean,
+    /// JSON array
+    Array,
+    /// JSON object
+    Object,
+    /// Any value
+    Any,
+}
+
+impl FieldType {
+    /// Type of a JSON value; `null` gives `Any`
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::String(_) => FieldType::String,
+            Value::Number(_) => FieldType::Number,
+            Value::Bool(_) => FieldType::Boolean,
+            Value::Array(_) => FieldType::Array,
+            Value::Object(_) => FieldType::Object,
+            Value::Null => FieldType::Any,
+        }
+    }
+
+    /// Whether `value` can be compared with a field of this type
+    ///
+    /// `null` is accepted for every type.
+    fn accepts(self, value: &Value) -> bool {
+        self == FieldType::Any || value.is_null() || FieldType::of(value) == self
+    }
+}
+
+/// Field names and types of a collection
+///
+/// # Example
+/// ```rust,no_run
+/// # use torm::{FieldType, Schema, TormDb};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let db = TormDb::connect("redis://localhost:6379").await?;
+/// Schema::new()
+///     .field("id", FieldType::String)
+///     .field("age", FieldType::Number)
+///     .register(&db, "user")
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    fields: BTreeMap<String, FieldType>,
+}
+
+type Registry = RwLock<HashMap<String, Arc<Schema>>>;
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+/// Get the key a collection's schema is stored under
+pub fn schema_key(collection: &str) -> String {
+    format!("torm:schema:{}", collection)
+}
+
+impl Schema {
+    /// Create an empty schema
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a field
+    pub fn field(mut self, name: impl Into<String>, ty: FieldType) -> Self {
+        self.fields.insert(name.into(), ty);
+        self
+    }
+
+    /// Infer a schema from the serialized form of `sample`
+    ///
+    /// Fields that serialize to `null` become [`FieldType::Any`].
+    pub fn infer<T: Serialize>(sample: &T) -> Result<Self> {
+        match serde_json::to_value(sample)? {
+            Value::Object(fields) => Ok(Self {
+                fields: fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), FieldType::of(value)))
+                    .collect(),
+            }),
+            _ => Err(Error::Other(
+                "schema sample must serialize to an object".to_string(),
+            )),
+        }
+    }
+
+    /// Get the type of a field
+    pub fn field_type(&self, name: &str) -> Option<FieldType> {
+        self.fields.get(name).copied()
+    }
+
+    /// Check a filter against this schema
+    ///
+    /// Fails with [`Error::InvalidQuery`] naming the field if it is unknown
+    /// or the filter value does not fit its type.
+    ///
+    /// For dot paths only the top-level field is checked, which must be an
+    /// object or array.
+    pub fn check_filter(&self, field: &str, query: &Query) -> Result<()> {
+        let root = crate::path::root(field);
+        let ty = self
+            .field_type(field)
+            .or_else(|| self.field_type(root))
+            .ok_or_else(|| Error::InvalidQuery(format!("unknown field '{}'", root)))?;
+
+        if self.field_type(field).is_none() {
+            return match ty {
+                FieldType::Object | FieldType::Array | FieldType::Any => Ok(()),
+                _ => Err(Error::InvalidQuery(format!(
+                    "field '{}' is {:?} and has no nested fields",
+                    root, ty
+                ))),
+            };
+        }
+
+        let fits = match query {
+            Query::Eq(v)
+            | Query::Ne(v)
+            | Query::Gt(v)
+            | Query::Gte(v)
+            | Query::Lt(v)
+            | Query::Lte(v) => ty.accepts(v),
+            Query::Exists(_) | Query::IsNull | Query::JsonPath(_) => true,
+            Query::ArrayContains(_) | Query::ArrayAnyIn(_) | Query::ArraySize(_) => {
+                matches!(ty, FieldType::Array | FieldType::Any)
+            }
+            Query::Near { .. } | Query::WithinBox { .. } => {
+                matches!(ty, FieldType::Object | FieldType::Any)
+            }
+            Query::Between { low, high, .. } => ty.accepts(low) && ty.accepts(high),
+            Query::In(values) | Query::NotIn(values) => values.iter().all(|v| ty.accepts(v)),
+            Query::Contains(_)
+            | Query::IContains(_)
+            | Query::StartsWith(_)
+            | Query::EndsWith(_)
+            | Query::Matches(_)
+            | Query::Fuzzy { .. } => matches!(ty, FieldType::String | FieldType::Any),
+        };
+        if fits {
+            Ok(())
+        } else {
+            Err(Error::InvalidQuery(format!(
+                "field '{}' is {:?}, filter {:?} does not match",
+                field, ty, query
+            )))
+        }
+    }
+
+    /// Store this schema for `collection` and use it in this process
+    pub async fn register(&self, db: &TormDb, collection: &str) -> Result<()> {
+        let mut conn = db.connection().clone();
+        redis::cmd("SET")
+            .arg(db.key(&schema_key(collection)))
+            .arg(serde_json::to_string(self)?)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Self::cache(collection, Some(self.clone()));
+        Ok(())
+    }
+
+    /// Load the stored schema for `collection` and refresh the local cache
+    pub async fn load(db: &TormDb, collection: &str) -> Result<Option<Self>> {
+        let mut conn = db.connection().clone();
+        let raw: Option<String> = redis::cmd("GET")
+            .arg(db.key(&schema_key(collection)))
+            .query_async(&mut conn)
+            .await?;
+        let schema = raw.map(|raw| serde_json::from_str(&raw)).transpose()?;
+        Self::cache(collection, schema.clone());
+        Ok(schema)
+    }
+
+    /// Get the schema registered or loaded for `collection` in this process
+    pub fn registered(collection: &str) -> Option<Arc<Self>> {
+        registry()
+            .read()
+            .ok()
+            .and_then(|schemas| schemas.get(collection).cloned())
+    }
+
+    pub(crate) fn cache(collection: &str, schema: Option<Self>) {
+        if let Ok(mut schemas) = registry().write() {
+            match schema {
+                Some(schema) => schemas.insert(collection.to_string(), Arc::new(schema)),
+                None => schemas.remove(collection),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_filter() {
+        let schema =
+            Schema::infer(&json!({ "id": "1", "age": 30, "tags": [], "nick": null })).unwrap();
+
+        assert!(schema.check_filter("age", &Query::gte(18)).is_ok());
+        assert!(schema.check_filter("nick", &Query::contains("x")).is_ok());
+        assert!(schema.check_filter("id", &Query::eq(Value::Null)).is_ok());
+
+        let unknown = schema.check_filter("agee", &Query::gte(18)).unwrap_err();
+        assert!(unknown.to_string().contains("'agee'"));
+        assert!(schema.check_filter("tags[*].name", &Query::eq("x")).is_ok());
+        assert!(schema.check_filter("age.years", &Query::eq(1)).is_err());
+        assert!(schema.check_filter("age", &Query::eq("thirty")).is_err());
+        assert!(schema.check_filter("age", &Query::contains("3")).is_err());
+        assert!(schema
+            .check_filter("id", &Query::in_values(vec![json!("1"), json!(2)]))
+            .is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Queries answered by RediSearch
+//!
+//! Fields marked `#[search]` make up a RediSearch schema over the
+//! collection's RedisJSON documents, created with
+//! [`Model::create_search_index`](crate::Model::create_search_index) in
+//! `torm:search:{collection}`. Queries opting in with
+//! [`QueryBuilder::search`](crate::QueryBuilder::search) then run as one
+//! `FT.SEARCH` instead of a scan, with filters, sort, skip and limit
+//! evaluated by the server. RediSearch only indexes hashes and RedisJSON
+//! values, so the collection must be written through
+//! [`JsonStore`](crate::JsonStore).
+//!
+//! Numeric fields answer comparisons, ranges and `in` on numbers; tag
+//! fields, which are case-sensitive, answer equality and `in` on strings.
+//! Both can be combined with `and`, `or` and `not` groups.
+
+use crate::query::{Filter, Query, SortOrder};
+use crate::{Error, Result, TormDb};
+use serde_json::Value;
+
+/// How RediSearch indexes a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchType {
+    /// Exact string values
+    Tag,
+    /// Numbers, for comparisons and ranges
+    Numeric,
+}
+
+/// A field of a collection's RediSearch schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchField {
+    /// Top-level field name
+    pub name: &'static str,
+    /// How the field is indexed
+    pub kind: SearchType,
+    /// Whether the index keeps the field sortable
+    pub sortable: bool,
+}
+
+/// Get the name of a collection's RediSearch index
+pub fn search_index(collection: &str) -> String {
+    format!("torm:search:{}", collection)
+}
+
+/// Create the RediSearch index of `fields` unless it exists
+///
+/// Returns whether it was created.
+pub(crate) async fn create_index(
+    db: &TormDb,
+    collection: &str,
+    fields: &[SearchField],
+) -> Result<bool> {
+    if fields.is_empty() {
+        return Err(Error::Other(format!(
+            "model '{}' has no #[search] fields",
+            collection
+        )));
+    }
+    let mut cmd = redis::cmd("FT.CREATE");
+    cmd.arg(db.key(&search_index(collection)))
+        .arg("ON")
+        .arg("JSON")
+        .arg("PREFIX")
+        .arg(1)
+        .arg(db.key(&format!("{}:", collection)))
+        .arg("SCHEMA");
+    for field in fields {
+        cmd.arg(format!("$[{}]", Value::from(field.name)))
+            .arg("AS")
+            .arg(field.name);
+        match field.kind {
+            SearchType::Tag => cmd.arg("TAG").arg("CASESENSITIVE"),
+            SearchType::Numeric => cmd.arg("NUMERIC"),
+        };
+        if field.sortable {
+            cmd.arg("SORTABLE");
+        }
+    }
+
+    let mut conn = db.connection().clone();
+    match cmd.query_async::<()>(&mut conn).await {
+        Ok(()) => Ok(true),
+        Err(e) if e.to_string().contains("Index already exists") => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Translate filters into a RediSearch query, or `None` if one of them
+/// can't be answered exactly by the index
+pub(crate) fn translate(filters: &[Filter], fields: &[SearchField]) -> Option<String> {
+    if filters.is_empty() {
+        return Some("*".to_string());
+    }
+    let parts = filters
+        .iter()
+        .map(|filter| filter_query(filter, fields))
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join(" "))
+}
+
+fn filter_query(filter: &Filter, fields: &[SearchField]) -> Option<String> {
+    match filter {
+        Filter::Field(name, query) => {
+            let field = fields.iter().find(|field| field.name == name)?;
+            condition(field, query)
+        }
+        Filter::And(filters) if !filters.is_empty() => {
+            Some(format!("({})", translate(filters, fields)?))
+        }
+        Filter::Or(filters) if !filters.is_empty() => {
+            let parts = filters
+                .iter()
+                .map(|filter| filter_query(filter, fields))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("({})", parts.join(" | ")))
+        }
+        Filter::Not(filter) => Some(format!("-({})", filter_query(filter, fields)?)),
+        _ => None,
+    }
+}
+
+fn condition(field: &SearchField, query: &Query) -> Option<String> {
+    let name = field.name;
+    match (field.kind, query) {
+        (SearchType::Numeric, Query::Eq(v)) => {
+            let v = number(v)?;
+            Some(format!("@{}:[{} {}]", name, v, v))
+        }
+        (SearchType::Numeric, Query::Ne(v)) => {
+            let v = number(v)?;
+            Some(format!("-@{}:[{} {}]", name, v, v))
+        }
+        (SearchType::Numeric, Query::Gt(v)) => Some(format!("@{}:[({} +inf]", name, number(v)?)),
+        (SearchType::Numeric, Query::Gte(v)) => Some(format!("@{}:[{} +inf]", name, number(v)?)),
+        (SearchType::Numeric, Query::Lt(v)) => Some(format!("@{}:[-inf ({}]", name, number(v)?)),
+        (SearchType::Numeric, Query::Lte(v)) => Some(format!("@{}:[-inf {}]", name, number(v)?)),
+        (
+            SearchType::Numeric,
+            Query::Between {
+                low,
+                high,
+                inclusive,
+            },
+        ) => {
+            let open = if *inclusive { "" } else { "(" };
+            Some(format!(
+                "@{}:[{}{} {}{}]",
+                name,
+                open,
+                number(low)?,
+                open,
+                number(high)?
+            ))
+        }
+        (SearchType::Numeric, Query::In(values)) if !values.is_empty() => {
+            let ranges = values
+                .iter()
+                .map(|v| number(v).map(|v| format!("@{}:[{} {}]", name, v, v)))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("({})", ranges.join(" | ")))
+        }
+        (SearchType::Tag, Query::Eq(v)) => Some(format!("@{}:{{{}}}", name, tag(v)?)),
+        (SearchType::Tag, Query::Ne(v)) => Some(format!("-@{}:{{{}}}", name, tag(v)?)),
+        (SearchType::Tag, Query::In(values)) if !values.is_empty() => {
+            let tags = values.iter().map(tag).collect::<Option<Vec<_>>>()?;
+            Some(format!("@{}:{{{}}}", name, tags.join(" | ")))
+        }
+        (SearchType::Tag, Query::NotIn(values)) if !values.is_empty() => {
+            let tags = values.iter().map(tag).collect::<Option<Vec<_>>>()?;
+            Some(format!("-@{}:{{{}}}", name, tags.join(" | ")))
+        }
+        _ => None,
+    }
+}
+
+/// A number as a query bound
+fn number(value: &Value) -> Option<f64> {
+    value.as_f64().filter(|v| v.is_finite())
+}
+
+/// A string as a tag, with RediSearch's punctuation escaped
+fn tag(value: &Value) -> Option<String> {
+    let value = value.as_str().filter(|v| !v.is_empty())?;
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if !c.is_alphanumeric() && c != '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Some(escaped)
+}
+
+/// Run `query` on a collection's index and return its total number of
+/// matches and the documents from `offset` on, at most `count` of them
+pub(crate) async fn search(
+    db: &TormDb,
+    collection: &str,
+    query: &str,
+    sort: Option<(&str, SortOrder)>,
+    offset: usize,
+    count: usize,
+) -> Result<(usize, Vec<Value>)> {
+    let mut cmd = redis::cmd("FT.SEARCH");
+    cmd.arg(db.key(&search_index(collection))).arg(query);
+    if let Some((field, order)) = sort {
+        cmd.arg("SORTBY").arg(field).arg(match order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        });
+    }
+    cmd.arg("LIMIT")
+        .arg(offset)
+        .arg(count)
+        .arg("RETURN")
+        .arg(1)
+        .arg("$")
+        .arg("DIALECT")
+        .arg(2);
+
+    let mut conn = db.connection().clone();
+    let reply: redis::Value = cmd.query_async(&mut conn).await?;
+    parse_reply(reply)
+}
+
+/// Read an `FT.SEARCH ... RETURN 1 $` reply: the total, then each key
+/// followed by `["$", document]`
+fn parse_reply(reply: redis::Value) -> Result<(usize, Vec<Value>)> {
+    let unexpected = || Error::Other("unexpected FT.SEARCH reply".to_string());
+    let redis::Value::Array(items) = reply else {
+        return Err(unexpected());
+    };
+    let mut items = items.into_iter();
+    let total = match items.next() {
+        Some(redis::Value::Int(total)) => total.max(0) as usize,
+        _ => return Err(unexpected()),
+    };
+
+    let mut documents = Vec::new();
+    while let (Some(_key), Some(fields)) = (items.next(), items.next()) {
+        let fields: Vec<String> = redis::from_redis_value(&fields)?;
+        let raw = fields
+            .chunks(2)
+            .find(|pair| pair[0] == "$")
+            .and_then(|pair| pair.get(1))
+            .ok_or_else(unexpected)?;
+        documents.push(match serde_json::from_str(raw)? {
+            Value::Array(mut matches) if matches.len() == 1 => matches.remove(0),
+            doc => doc,
+        });
+    }
+    Ok((total, documents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[SearchField] = &[
+        SearchField {
+            name: "age",
+            kind: SearchType::Numeric,
+            sortable: true,
+        },
+        SearchField {
+            name: "city",
+            kind: SearchType::Tag,
+            sortable: false,
+        },
+    ];
+
+    #[test]
+    fn test_translate() {
+        let filters = vec![
+            Filter::field("age", Query::gte(18)),
+            Filter::Or(vec![
+                Filter::field("city", Query::eq("New York")),
+                Filter::field("city", Query::In(vec!["Oslo".into()])),
+            ]),
+        ];
+        assert_eq!(
+            translate(&filters, FIELDS).as_deref(),
+            Some(r"@age:[18 +inf] (@city:{New\ York} | @city:{Oslo})")
+        );
+        assert_eq!(translate(&[], FIELDS).as_deref(), Some("*"));
+
+        // Unindexed fields and unsupported operators fall back to scanning
+        assert!(translate(&[Filter::field("name", Query::eq("Ann"))], FIELDS).is_none());
+        assert!(translate(&[Filter::field("city", Query::contains("Y"))], FIELDS).is_none());
+        assert!(translate(&[Filter::field("age", Query::eq("18"))], FIELDS).is_none());
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        use redis::Value as Reply;
+        let reply = Reply::Array(vec![
+            Reply::Int(7),
+            Reply::BulkString(b"user:1".to_vec()),
+            Reply::Array(vec![
+                Reply::BulkString(b"$".to_vec()),
+                Reply::BulkString(br#"{"id":"1","age":30}"#.to_vec()),
+            ]),
+        ]);
+        let (total, documents) = parse_reply(reply).unwrap();
+        assert_eq!(total, 7);
+        assert_eq!(documents, vec![serde_json::json!({ "id": "1", "age": 30 })]);
+    }
+}
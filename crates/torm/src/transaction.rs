@@ -0,0 +1,160 @@
+//! Atomic multi-document writes
+//!
+//! [`TormDb::transaction`] runs a closure that reads models and queues
+//! saves and deletes on a [`Transaction`]. Every key read is WATCHed on a
+//! dedicated connection, and the queued writes are sent in one
+//! `MULTI`/`EXEC`, so they apply together and only if nothing they read
+//! has changed; otherwise the closure runs again on fresh data.
+//!
+//! Indexes, counters, the insertion order and lifecycle events are
+//! updated after `EXEC`, as they are for [`Model::save`].
+
+use crate::pipeline::Write;
+use crate::{Error, Model, Result, TormDb};
+use redis::aio::MultiplexedConnection;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Attempts before a transaction whose reads keep changing fails
+pub(crate) const MAX_TRANSACTION_ATTEMPTS: usize = 5;
+
+/// Reads and queued writes of one [`TormDb::transaction`] attempt
+///
+/// Handles are cheap to clone and share the same transaction.
+#[derive(Clone)]
+pub struct Transaction {
+    db: TormDb,
+    conn: MultiplexedConnection,
+    writes: Arc<Mutex<Vec<Write>>>,
+}
+
+impl Transaction {
+    /// Read a model, WATCHing its key
+    ///
+    /// Returns `None` if it doesn't exist. If the model changes before
+    /// the transaction commits, the transaction is retried.
+    pub async fn get<M: Model>(&self, id: &str) -> Result<Option<M>> {
+        let key = self.db.key(&format!("{}:{}", M::collection(), id));
+        let mut conn = self.conn.clone();
+        redis::cmd("WATCH")
+            .arg(&key)
+            .query_async::<()>(&mut conn)
+            .await?;
+        let value: Option<Vec<u8>> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+        value
+            .map(|v| {
+                let mut model: M = M::codec().decode(&v)?;
+                model.after_load();
+                Ok(model)
+            })
+            .transpose()
+    }
+
+    /// Queue a save of `model`, validating it now
+    pub fn save<M: Model>(&self, model: &M) -> Result<()> {
+        let write = Write::save(model)?;
+        self.writes.lock().unwrap().push(write);
+        Ok(())
+    }
+
+    /// Queue a delete of `model`
+    pub fn delete<M: Model>(&self, model: &M) {
+        self.writes.lock().unwrap().push(Write::delete(model));
+    }
+
+    /// Send the queued writes in one MULTI/EXEC
+    ///
+    /// Returns `false` if a WATCHed key changed and nothing was written.
+    async fn commit(&self) -> Result<bool> {
+        let writes = std::mem::take(&mut *self.writes.lock().unwrap());
+        let mut conn = self.conn.clone();
+        if writes.is_empty() {
+            redis::cmd("UNWATCH").query_async::<()>(&mut conn).await?;
+            return Ok(true);
+        }
+        crate::pipeline::send(&self.db, &mut conn, writes, true).await
+    }
+}
+
+impl TormDb {
+    /// Run `body` as a transaction and return its result
+    ///
+    /// Models read with [`Transaction::get`] are WATCHed; saves and
+    /// deletes queued with [`Transaction::save`] and
+    /// [`Transaction::delete`] are written together once `body` returns
+    /// `Ok`. If a watched model changed meanwhile, nothing is written and
+    /// `body` runs again, up to 5 times before failing with
+    /// [`Error::Conflict`]. If `body` fails, nothing is written.
+    ///
+    /// Needs a single server or Sentinel connection, as WATCH holds a
+    /// connection of its own; Redis Cluster and custom backends are not
+    /// supported.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use torm::{Error, Model, TormDb};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Product { #[id] id: String, stock: u32 }
+    /// # #[derive(Model, Serialize, Deserialize)]
+    /// # struct Order { #[id] id: String, product: String }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let db = TormDb::connect("redis://localhost:6379").await?;
+    /// db.transaction(|tx| async move {
+    ///     let mut product: Product = tx
+    ///         .get("widget")
+    ///         .await?
+    ///         .ok_or_else(|| Error::NotFound("product:widget".into()))?;
+    ///     product.stock = product
+    ///         .stock
+    ///         .checked_sub(1)
+    ///         .ok_or_else(|| Error::Other("out of stock".into()))?;
+    ///     tx.save(&product)?;
+    ///     tx.save(&Order { id: "o-1".into(), product: product.id.clone() })?;
+    ///     Ok(())
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(name = "torm.transaction", level = "info", skip_all)]
+    pub async fn transaction<F, Fut, R>(&self, mut body: F) -> Result<R>
+    where
+        F: FnMut(Transaction) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        if self.connection().is_cluster() {
+            return Err(Error::Other(
+                "transactions are not supported on Redis Cluster".to_string(),
+            ));
+        }
+        let client = self
+            .client()
+            .ok_or_else(|| Error::Connection("transactions need a Redis connection".to_string()))?;
+        let conn = client.get_multiplexed_async_connection().await?;
+
+        for _ in 0..MAX_TRANSACTION_ATTEMPTS {
+            let tx = Transaction {
+                db: self.clone(),
+                conn: conn.clone(),
+                writes: Arc::default(),
+            };
+            let result = match body(tx.clone()).await {
+                Ok(result) => result,
+                Err(e) => {
+                    let mut conn = conn.clone();
+                    redis::cmd("UNWATCH").query_async::<()>(&mut conn).await?;
+                    return Err(e);
+                }
+            };
+            if tx.commit().await? {
+                return Ok(result);
+            }
+        }
+        Err(Error::Conflict(format!(
+            "transaction retried {} times on concurrent changes",
+            MAX_TRANSACTION_ATTEMPTS
+        )))
+    }
+}
@@ -0,0 +1,175 @@
+//! Change streams built on keyspace notifications
+
+use crate::{HashStore, JsonStore, Model, Result, TormDb};
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::pin::Pin;
+
+/// A change to a document in a watched collection
+///
+/// Keyspace notifications don't tell a create apart from an update, so both
+/// are reported as [`ChangeEvent::Saved`].
+#[derive(Debug, Clone)]
+pub enum ChangeEvent<T> {
+    /// Document was created or updated
+    Saved {
+        /// Document ID
+        id: String,
+        /// Current document, or `None` if it was removed before it could be read
+        document: Option<T>,
+    },
+    /// Document was deleted, expired or evicted
+    Deleted {
+        /// Document ID
+        id: String,
+    },
+}
+
+/// Stream of change events returned by [`Model::watch`]
+pub type ChangeStream<T> = Pin<Box<dyn Stream<Item = Result<ChangeEvent<T>>> + Send>>;
+
+/// Keyspace notification classes change streams need: keyspace events
+/// (`K`), generic commands such as `DEL` (`g`), strings (`$`), hashes
+/// (`h`), expiry (`x`), eviction (`e`) and module types such as RedisJSON
+/// (`d`)
+const NOTIFY_FLAGS: &str = "Kg$hxed";
+
+/// [`NOTIFY_FLAGS`] without `d`, which servers before Redis 7 reject
+const LEGACY_NOTIFY_FLAGS: &str = "Kg$hxe";
+
+/// Add the flags of `needed` that `current` lacks, keeping the rest of
+/// the server's setting
+fn merge_flags(current: &str, needed: &str) -> String {
+    let mut merged = current.to_string();
+    for flag in needed.chars() {
+        if !merged.contains(flag) {
+            merged.push(flag);
+        }
+    }
+    merged
+}
+
+/// Turn on the notifications change streams need, on top of those
+/// already enabled
+///
+/// Best effort: managed servers may reject CONFIG, in which case
+/// notifications have to be enabled by the operator.
+async fn enable_notifications(db: &TormDb) {
+    let mut conn = db.connection().clone();
+    let Ok(config) = redis::cmd("CONFIG")
+        .arg("GET")
+        .arg("notify-keyspace-events")
+        .query_async::<HashMap<String, String>>(&mut conn)
+        .await
+    else {
+        return;
+    };
+    let current = config
+        .get("notify-keyspace-events")
+        .map_or("", String::as_str);
+
+    for needed in [NOTIFY_FLAGS, LEGACY_NOTIFY_FLAGS] {
+        let merged = merge_flags(current, needed);
+        if merged == current {
+            return;
+        }
+        let set = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg(&merged)
+            .query_async::<()>(&mut conn)
+            .await;
+        if set.is_ok() {
+            return;
+        }
+    }
+}
+
+/// Report a document written by `op` as saved, reading it the way the
+/// command stored it
+async fn saved<T: Model>(db: &TormDb, op: &str, id: String) -> Result<ChangeEvent<T>> {
+    let found = if op.starts_with("json.") {
+        JsonStore::<T>::new(db).find(&id).await
+    } else if op.starts_with('h') {
+        HashStore::<T>::new(db).find(&id).await
+    } else {
+        T::find_by_id(db, &id).await
+    };
+    match found {
+        Ok(document) => Ok(ChangeEvent::Saved {
+            id,
+            document: Some(document),
+        }),
+        Err(crate::Error::NotFound(_)) => Ok(ChangeEvent::Saved { id, document: None }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Subscribe to keyspace notifications for a model's collection
+pub(crate) async fn watch<T>(db: &TormDb) -> Result<ChangeStream<T>>
+where
+    T: Model + 'static,
+{
+    enable_notifications(db).await;
+
+    let client = db.client().ok_or_else(|| {
+        crate::Error::Connection("change streams need a Redis connection".to_string())
+    })?;
+    let db_index = client.get_connection_info().redis.db;
+    let prefix = format!(
+        "__keyspace@{}__:{}",
+        db_index,
+        db.key(&format!("{}:", T::collection()))
+    );
+    let pattern = format!(
+        "__keyspace@{}__:{}",
+        db_index,
+        db.key_pattern(&format!("{}:*", T::collection()))
+    );
+
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.psubscribe(pattern).await?;
+
+    let closed = db.connection().link().closed();
+    // Read from the primary that sent the notification, not a lagging
+    // replica
+    let db = db.primary();
+    let stream = pubsub.into_on_message().filter_map(move |msg| {
+        let db = db.clone();
+        let prefix = prefix.clone();
+        async move {
+            let id = msg.get_channel_name().strip_prefix(&prefix)?.to_string();
+            let op: String = msg.get_payload().ok()?;
+
+            match op.as_str() {
+                "set" | "hset" | "hincrby" | "hincrbyfloat" | "hdel" => {
+                    Some(saved(&db, &op, id).await)
+                }
+                op if op.starts_with("json.") => Some(saved(&db, op, id).await),
+                // A HashStore save replaces the hash with DEL and HSET;
+                // the write that follows is reported instead
+                "del" => match T::exists(&db, &id).await {
+                    Ok(true) => None,
+                    Ok(false) => Some(Ok(ChangeEvent::Deleted { id })),
+                    Err(e) => Some(Err(e)),
+                },
+                "expired" | "evicted" => Some(Ok(ChangeEvent::Deleted { id })),
+                _ => None,
+            }
+        }
+    });
+
+    Ok(Box::pin(stream.take_until(closed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_flags() {
+        assert_eq!(merge_flags("", NOTIFY_FLAGS), "Kg$hxed");
+        assert_eq!(merge_flags("Ex", NOTIFY_FLAGS), "ExKg$hed");
+        assert_eq!(merge_flags("Kg$hxed", NOTIFY_FLAGS), "Kg$hxed");
+    }
+}
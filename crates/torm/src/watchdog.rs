@@ -0,0 +1,222 @@
+//! Anomaly alerts on collection growth and error rates
+//!
+//! A [`Watchdog`] samples collection counters and the operation error rate
+//! of a [`TormDb`] handle at a fixed interval and calls the registered
+//! callbacks when a threshold is exceeded, giving early warning of runaway
+//! writers or broken consumers.
+
+use crate::{Error, Result, TormDb};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Ignore error rates until at least this many operations were seen
+const MIN_OPERATIONS: u64 = 20;
+
+/// Operation and error counts shared by all clones of a [`TormDb`]
+#[derive(Default)]
+pub(crate) struct OpStats {
+    operations: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl OpStats {
+    /// Count an operation; missing documents are not errors
+    pub(crate) fn record<T>(&self, result: &Result<T>) {
+        self.operations.fetch_add(1, Ordering::Relaxed);
+        if matches!(result, Err(e) if !matches!(e, Error::NotFound(_))) {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> (u64, u64) {
+        (
+            self.operations.load(Ordering::Relaxed),
+            self.errors.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// A threshold that was exceeded during one watchdog interval
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    /// A collection grew faster than allowed
+    CollectionGrowth {
+        /// Collection that grew
+        collection: String,
+        /// Documents added during the interval
+        added: u64,
+        /// Configured maximum per interval
+        threshold: u64,
+    },
+    /// Too many saves, deletes or finds failed
+    ErrorRate {
+        /// Failed operations during the interval
+        errors: u64,
+        /// All operations during the interval
+        operations: u64,
+        /// Configured maximum error rate (0.0 - 1.0)
+        threshold: f64,
+    },
+}
+
+type AlertCallback = Arc<dyn Fn(&Alert) + Send + Sync>;
+
+/// Background task that raises [`Alert`]s
+///
+/// # Example
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use torm::{TormDb, Watchdog};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let db = TormDb::connect("redis://localhost:6379").await?;
+/// Watchdog::new(&db)
+///     .interval(Duration::from_secs(60))
+///     .max_growth("user", 10_000)
+///     .max_error_rate(0.05)
+///     .on_alert(|alert| eprintln!("torm alert: {:?}", alert))
+///     .spawn();
+/// # Ok(())
+/// # }
+/// ```
+pub struct Watchdog {
+    db: TormDb,
+    interval: Duration,
+    max_growth: Vec<(String, u64)>,
+    max_error_rate: Option<f64>,
+    callbacks: Vec<AlertCallback>,
+    last_counts: HashMap<String, u64>,
+    last_ops: (u64, u64),
+}
+
+impl Watchdog {
+    /// Create a watchdog for `db` that checks once a minute
+    pub fn new(db: &TormDb) -> Self {
+        Self {
+            db: db.clone(),
+            interval: Duration::from_secs(60),
+            max_growth: Vec::new(),
+            max_error_rate: None,
+            callbacks: Vec::new(),
+            last_counts: HashMap::new(),
+            last_ops: db.op_stats().snapshot(),
+        }
+    }
+
+    /// Set how often thresholds are checked
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Alert when `collection` gains more than `per_interval` documents
+    pub fn max_growth(mut self, collection: impl Into<String>, per_interval: u64) -> Self {
+        self.max_growth.push((collection.into(), per_interval));
+        self
+    }
+
+    /// Alert when the share of failed operations exceeds `rate`
+    ///
+    /// Counts saves, deletes and finds made through the handle and its
+    /// clones. Missing documents are not errors. Intervals with fewer than
+    /// 20 operations are ignored.
+    pub fn max_error_rate(mut self, rate: f64) -> Self {
+        self.max_error_rate = Some(rate);
+        self
+    }
+
+    /// Register a callback for alerts
+    pub fn on_alert<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Alert) + Send + Sync + 'static,
+    {
+        self.callbacks.push(Arc::new(callback));
+        self
+    }
+
+    /// Run the watchdog on a background task
+    ///
+    /// Failed checks are skipped; the task runs until aborted or the
+    /// handle is closed with [`TormDb::close`].
+    pub fn spawn(mut self) -> tokio::task::JoinHandle<()> {
+        let closed = self.db.connection().link().closed();
+        tokio::spawn(async move {
+            tokio::pin!(closed);
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    () = &mut closed => return,
+                }
+                let _ = self.check().await;
+            }
+        })
+    }
+
+    /// Run one check, notify callbacks and return the raised alerts
+    ///
+    /// Growth is measured against the previous check, so the first check
+    /// only records a baseline for each collection.
+    pub async fn check(&mut self) -> Result<Vec<Alert>> {
+        let mut alerts = Vec::new();
+
+        for (collection, threshold) in &self.max_growth {
+            let count = crate::counter::read(&self.db, collection).await? as u64;
+            if let Some(previous) = self.last_counts.insert(collection.clone(), count) {
+                let added = count.saturating_sub(previous);
+                if added > *threshold {
+                    alerts.push(Alert::CollectionGrowth {
+                        collection: collection.clone(),
+                        added,
+                        threshold: *threshold,
+                    });
+                }
+            }
+        }
+
+        let current = self.db.op_stats().snapshot();
+        let (operations, errors) = (current.0 - self.last_ops.0, current.1 - self.last_ops.1);
+        self.last_ops = current;
+        if let Some(threshold) = self.max_error_rate {
+            if error_rate_exceeded(operations, errors, threshold) {
+                alerts.push(Alert::ErrorRate {
+                    errors,
+                    operations,
+                    threshold,
+                });
+            }
+        }
+
+        for alert in &alerts {
+            for callback in &self.callbacks {
+                callback(alert);
+            }
+        }
+
+        Ok(alerts)
+    }
+}
+
+fn error_rate_exceeded(operations: u64, errors: u64, threshold: f64) -> bool {
+    operations >= MIN_OPERATIONS && errors as f64 / operations as f64 > threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_rate() {
+        let stats = OpStats::default();
+        stats.record::<()>(&Ok(()));
+        stats.record::<()>(&Err(Error::NotFound("user:1".into())));
+        stats.record::<()>(&Err(Error::Validation("bad".into())));
+        assert_eq!(stats.snapshot(), (3, 1));
+
+        assert!(!error_rate_exceeded(3, 1, 0.1));
+        assert!(error_rate_exceeded(20, 3, 0.1));
+        assert!(!error_rate_exceeded(20, 2, 0.1));
+    }
+}
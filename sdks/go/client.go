@@ -7,14 +7,21 @@ import (
 	"fmt"
 	"io"
 	"net/http"
+	"sync"
 	"time"
 )
 
+// APIVersion is the version of the server API this client speaks
+const APIVersion = 1
+
 // Client is the main ToonStore ORM client
 type Client struct {
 	BaseURL string
 	Timeout time.Duration
 	client  *http.Client
+
+	capsMu sync.Mutex
+	caps   map[string]interface{}
 }
 
 // ClientOptions configuration for creating a new client
@@ -91,6 +98,85 @@ func (c *Client) Info() (map[string]interface{}, error) {
 	return result, nil
 }
 
+// Capabilities gets the optional features supported by the server.
+// Servers that predate capability discovery report no features.
+// The result is fetched once and cached; use RefreshCapabilities to
+// fetch it again, e.g. after the server was upgraded.
+func (c *Client) Capabilities() (map[string]interface{}, error) {
+	c.capsMu.Lock()
+	defer c.capsMu.Unlock()
+	if c.caps != nil {
+		return c.caps, nil
+	}
+	caps, err := c.fetchCapabilities()
+	if err != nil {
+		return nil, err
+	}
+	c.caps = caps
+	return caps, nil
+}
+
+// RefreshCapabilities fetches the server's capabilities again and
+// replaces the cached result
+func (c *Client) RefreshCapabilities() (map[string]interface{}, error) {
+	c.capsMu.Lock()
+	c.caps = nil
+	c.capsMu.Unlock()
+	return c.Capabilities()
+}
+
+func (c *Client) fetchCapabilities() (map[string]interface{}, error) {
+	resp, err := c.client.Get(c.BaseURL + "/capabilities")
+	if err != nil {
+		return nil, fmt.Errorf("capabilities request failed: %w", err)
+	}
+	defer resp.Body.Close()
+
+	if resp.StatusCode == http.StatusNotFound {
+		return map[string]interface{}{
+			"api_version": float64(0),
+			"features":    map[string]interface{}{},
+		}, nil
+	}
+	if resp.StatusCode < 200 || resp.StatusCode >= 300 {
+		return nil, fmt.Errorf("capabilities request failed: %s", resp.Status)
+	}
+
+	var result map[string]interface{}
+	if err := json.NewDecoder(resp.Body).Decode(&result); err != nil {
+		return nil, fmt.Errorf("failed to decode capabilities response: %w", err)
+	}
+
+	return result, nil
+}
+
+// Supports reports whether the server advertises the given feature,
+// using the cached capabilities
+func (c *Client) Supports(feature string) bool {
+	caps, err := c.Capabilities()
+	if err != nil {
+		return false
+	}
+	features, ok := caps["features"].(map[string]interface{})
+	if !ok {
+		return false
+	}
+	enabled, ok := features[feature].(bool)
+	return ok && enabled
+}
+
+// Compatible reports whether the server still accepts APIVersion.
+// Servers that predate capability discovery report no minimum and
+// are treated as compatible.
+func (c *Client) Compatible() (bool, error) {
+	caps, err := c.Capabilities()
+	if err != nil {
+		return false, err
+	}
+	minVersion, _ := caps["min_api_version"].(float64)
+	return minVersion <= APIVersion, nil
+}
+
 // request makes an HTTP request
 func (c *Client) request(method, path string, body interface{}) (*http.Response, error) {
 	url := c.BaseURL + path
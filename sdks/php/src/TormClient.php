@@ -11,8 +11,12 @@ use Exception;
  */
 class TormClient
 {
+    /** Version of the server API this client speaks */
+    public const API_VERSION = 1;
+    
     private string $baseUrl;
     private int $timeout;
+    private ?array $capabilities = null;
     
     /**
      * Create a new TormClient instance
@@ -62,6 +66,58 @@ class TormClient
         return $this->request('GET', '/');
     }
     
+    /**
+     * Get the features supported by the server
+     * 
+     * Servers that predate capability discovery report no optional
+     * features, so callers can fall back instead of failing.
+     * 
+     * @param bool $refresh Re-fetch instead of using the cached result
+     * @return array
+     * @throws Exception
+     */
+    public function capabilities(bool $refresh = false): array
+    {
+        if ($this->capabilities !== null && !$refresh) {
+            return $this->capabilities;
+        }
+        
+        [$statusCode, $response] = $this->send('GET', '/capabilities');
+        if ($statusCode === 404) {
+            $this->capabilities = ['api_version' => 0, 'features' => []];
+        } else {
+            $this->capabilities = $this->decode($statusCode, $response);
+        }
+        
+        return $this->capabilities;
+    }
+    
+    /**
+     * Check whether the server supports an optional feature
+     * 
+     * @param string $feature Feature name (e.g. 'search', 'transactions', 'watch')
+     * @return bool
+     * @throws Exception
+     */
+    public function supports(string $feature): bool
+    {
+        return (bool)($this->capabilities()['features'][$feature] ?? false);
+    }
+    
+    /**
+     * Check whether the server still accepts this client's API version
+     * 
+     * Servers that predate capability discovery report no minimum and
+     * are treated as compatible.
+     * 
+     * @return bool
+     * @throws Exception
+     */
+    public function isCompatible(): bool
+    {
+        return ($this->capabilities()['min_api_version'] ?? 0) <= self::API_VERSION;
+    }
+    
     /**
      * Make HTTP request
      * 
@@ -72,6 +128,22 @@ class TormClient
      * @throws Exception
      */
     public function request(string $method, string $path, ?array $data = null): array
+    {
+        [$statusCode, $response] = $this->send($method, $path, $data);
+        
+        return $this->decode($statusCode, $response);
+    }
+    
+    /**
+     * Send HTTP request
+     * 
+     * @param string $method HTTP method
+     * @param string $path Request path
+     * @param array|null $data Request body
+     * @return array{int, string} Status code and raw response body
+     * @throws Exception
+     */
+    private function send(string $method, string $path, ?array $data = null): array
     {
         $url = $this->baseUrl . $path;
         
@@ -104,6 +176,19 @@ class TormClient
             }
         }
         
+        return [$statusCode, $response];
+    }
+    
+    /**
+     * Decode a JSON response, throwing on error statuses
+     * 
+     * @param int $statusCode HTTP status code
+     * @param string $response Raw response body
+     * @return array
+     * @throws Exception
+     */
+    private function decode(int $statusCode, string $response): array
+    {
         $result = json_decode($response, true);
         if ($result === null && json_last_error() !== JSON_ERROR_NONE) {
             throw new Exception("Failed to decode JSON response: " . json_last_error_msg());
@@ -5,6 +5,9 @@ from typing import Optional, Dict, Any
 from .model import Model
 from .exceptions import ConnectionError
 
+# Version of the server API this client speaks
+API_VERSION = 1
+
 
 class TormClient:
     """
@@ -30,6 +33,7 @@ class TormClient:
         self.timeout = timeout
         self.session = requests.Session()
         self.session.headers.update({'Content-Type': 'application/json'})
+        self._capabilities: Optional[Dict[str, Any]] = None
     
     def model(self, name: str, schema: Optional[Dict[str, Any]] = None, 
               collection: Optional[str] = None, validate: bool = True) -> Model:
@@ -75,6 +79,56 @@ class TormClient:
         except requests.RequestException as e:
             raise ConnectionError(f"Failed to get server info: {e}")
     
+    def capabilities(self, refresh: bool = False) -> Dict[str, Any]:
+        """
+        Get the features supported by the server
+        
+        Servers that predate capability discovery report no optional
+        features, so callers can fall back instead of failing.
+        
+        Args:
+            refresh: Re-fetch instead of using the cached result
+        
+        Returns:
+            Capabilities dictionary
+        """
+        if self._capabilities is not None and not refresh:
+            return self._capabilities
+        try:
+            response = self.session.get(f'{self.base_url}/capabilities', timeout=self.timeout)
+            if response.status_code == 404:
+                self._capabilities = {'api_version': 0, 'features': {}}
+            else:
+                response.raise_for_status()
+                self._capabilities = response.json()
+        except requests.RequestException as e:
+            raise ConnectionError(f"Failed to get server capabilities: {e}")
+        return self._capabilities
+    
+    def supports(self, feature: str) -> bool:
+        """
+        Check whether the server supports an optional feature
+        
+        Args:
+            feature: Feature name (e.g. 'search', 'transactions', 'watch')
+        
+        Returns:
+            True if the feature is available
+        """
+        return bool(self.capabilities().get('features', {}).get(feature, False))
+    
+    def is_compatible(self) -> bool:
+        """
+        Check whether the server still accepts this client's API version
+        
+        Servers that predate capability discovery report no minimum and
+        are treated as compatible.
+        
+        Returns:
+            True if the server's min_api_version is at most API_VERSION
+        """
+        return self.capabilities().get('min_api_version', 0) <= API_VERSION
+    
     def close(self):
         """Close the client session"""
         self.session.close()